@@ -0,0 +1,58 @@
+use clap::Args;
+use std::fs;
+use std::path::PathBuf;
+
+use beam::logic::coverage::Coverage;
+
+use super::{load_spec, read, OutputFormat};
+
+#[derive(Args, Debug, Clone)]
+pub struct CoverageArgs {
+    /// Path to grammar specification file
+    #[arg(short = 's', long = "spec", value_name = "FILE")]
+    pub spec_path: PathBuf,
+
+    /// Program files, or directories whose files are all read
+    #[arg(value_name = "FILE", required = true)]
+    pub inputs: Vec<PathBuf>,
+
+    /// Output format: a text report, or the report as JSON
+    #[arg(long = "format", value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+
+    /// Explicit start symbol override
+    #[arg(long = "start")]
+    pub start: Option<String>,
+}
+
+pub fn run_coverage(args: &CoverageArgs) {
+    args.format.ensure_available();
+    let grammar = load_spec(&args.spec_path, args.start.as_deref());
+
+    let mut coverage = Coverage::new(&grammar);
+    for path in &args.inputs {
+        if path.is_dir() {
+            let mut entries: Vec<PathBuf> = match fs::read_dir(path) {
+                Ok(rd) => rd.filter_map(|e| e.ok()).map(|e| e.path()).filter(|p| p.is_file()).collect(),
+                Err(e) => {
+                    eprintln!("error: failed to read directory '{}': {}", path.display(), e);
+                    std::process::exit(2);
+                }
+            };
+            entries.sort();
+            for entry in entries {
+                coverage.add_source(&grammar, &read(&entry));
+            }
+        } else {
+            coverage.add_source(&grammar, &read(path));
+        }
+    }
+
+    match args.format {
+        OutputFormat::Text => print!("{}", coverage.to_text(&grammar)),
+        OutputFormat::Json => {
+            #[cfg(feature = "json")]
+            println!("{}", coverage.to_json());
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use clap::Args;
+use std::fs;
+use std::path::PathBuf;
+
+use beam::logic::ast::{ASTNode, TriviaKind};
+use beam::logic::parser::Parser;
+
+use super::{load_spec, read};
+
+#[derive(Args, Debug, Clone)]
+pub struct FmtArgs {
+    /// Path to grammar specification file
+    #[arg(short = 's', long = "spec", value_name = "FILE")]
+    pub spec_path: PathBuf,
+
+    /// Source files to format
+    #[arg(value_name = "FILE", required = true)]
+    pub files: Vec<PathBuf>,
+
+    /// Rewrite the files in place instead of printing them
+    #[arg(short = 'w', long = "write", action = clap::ArgAction::SetTrue)]
+    pub write: bool,
+
+    /// Explicit start symbol override
+    #[arg(long = "start")]
+    pub start: Option<String>,
+}
+
+pub fn run_fmt(args: &FmtArgs) {
+    let grammar = load_spec(&args.spec_path, args.start.as_deref());
+
+    // Trivia only serves to spot comments, which the formatted text does not keep
+    let mut parser = Parser::new(grammar.clone()).with_trivia();
+    let mut failed = false;
+    for path in &args.files {
+        let ast = match parser.parse(&read(path)) {
+            Ok(ast) => ast,
+            Err(e) => {
+                eprintln!("{}: parse error: {}", path.display(), e);
+                failed = true;
+                continue;
+            }
+        };
+        if args.write && has_comments(&ast) {
+            eprintln!("{}: formatting would drop its comments; left unchanged", path.display());
+            failed = true;
+            continue;
+        }
+        let text = grammar.unparse(&ast);
+        // Never write something that reads back differently
+        if !parser.parse(&text).is_ok_and(|again| again.syneq(&ast)) {
+            eprintln!("{}: formatted text does not re-parse to the same tree; left unchanged", path.display());
+            failed = true;
+            continue;
+        }
+        if args.write {
+            if let Err(e) = fs::write(path, format!("{}\n", text)) {
+                eprintln!("error: failed to write '{}': {}", path.display(), e);
+                std::process::exit(2);
+            }
+        } else {
+            println!("{}", text);
+        }
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+fn has_comments(ast: &ASTNode) -> bool {
+    ast.terminals()
+        .filter_map(|t| t.trivia.as_deref())
+        .flat_map(|trivia| trivia.leading.iter().chain(&trivia.trailing))
+        .any(|piece| piece.kind == TriviaKind::Comment)
+}
@@ -0,0 +1,86 @@
+use clap::Args;
+use std::path::PathBuf;
+
+use beam::engine::{FuzzConfig, Fuzzer};
+use anstyle::{AnsiColor, Style};
+
+use super::{load_spec, read};
+
+#[derive(Args, Debug, Clone)]
+pub struct FuzzArgs {
+    /// Path to grammar specification file
+    #[arg(short = 's', long = "spec", value_name = "FILE")]
+    pub spec_path: PathBuf,
+
+    /// Number of mutated inputs to run
+    #[arg(short = 'n', long = "iterations", default_value_t = 10_000)]
+    pub iterations: usize,
+
+    /// Random seed (random if omitted)
+    #[arg(long = "seed")]
+    pub seed: Option<u64>,
+
+    /// Corpus directory: loaded as seeds, updated with interesting inputs
+    #[arg(long = "corpus", value_name = "DIR")]
+    pub corpus: Option<PathBuf>,
+
+    /// Directory for minimized crashing inputs
+    #[arg(long = "crashes", value_name = "DIR")]
+    pub crashes: Option<PathBuf>,
+
+    /// Seed files to start from (in addition to the corpus)
+    #[arg(value_name = "SEED_FILE")]
+    pub seeds: Vec<PathBuf>,
+
+    /// Skip the type checker
+    #[arg(long = "no-check", action = clap::ArgAction::SetTrue)]
+    pub no_check: bool,
+
+    /// Explicit start symbol override
+    #[arg(long = "start")]
+    pub start: Option<String>,
+}
+
+pub fn run_fuzz(args: &FuzzArgs) {
+    let grammar = load_spec(&args.spec_path, args.start.as_deref());
+
+    let config = FuzzConfig {
+        seed: args.seed.unwrap_or_else(rand::random),
+        iterations: args.iterations,
+        check_types: !args.no_check,
+        corpus_dir: args.corpus.clone(),
+        crash_dir: args.crashes.clone(),
+        ..Default::default()
+    };
+    let mut fuzzer = match Fuzzer::new(&grammar, config) {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(2);
+        }
+    };
+    for path in &args.seeds {
+        fuzzer.add_seed(&read(path));
+    }
+    let report = match fuzzer.run() {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(2);
+        }
+    };
+
+    println!("{} executions, {} features, corpus of {}", report.executions, report.features, report.corpus.len());
+    if report.crashes.is_empty() {
+        let ok = Style::new().fg_color(Some(AnsiColor::Green.into()));
+        println!("{ok}No crashes{ok:#}");
+        return;
+    }
+    let err = Style::new().fg_color(Some(AnsiColor::Red.into()));
+    for crash in &report.crashes {
+        println!("{err}{} panic:{err:#} {}", crash.stage, crash.message);
+        println!("  input:     {:?}", crash.input);
+        println!("  minimized: {:?}", crash.minimized);
+    }
+    std::process::exit(1);
+}
@@ -0,0 +1,68 @@
+use clap::Args;
+use std::path::PathBuf;
+
+use beam::engine::{GenerateConfig, Generator};
+use anstyle::{AnsiColor, Style};
+
+use super::load_spec;
+
+#[derive(Args, Debug, Clone)]
+pub struct GenArgs {
+    /// Path to grammar specification file
+    #[arg(short = 's', long = "spec", value_name = "FILE")]
+    pub spec_path: PathBuf,
+
+    /// Number of programs to print
+    #[arg(short = 'n', long = "count", default_value_t = 10)]
+    pub count: usize,
+
+    /// Random seed (random if omitted)
+    #[arg(long = "seed")]
+    pub seed: Option<u64>,
+
+    /// Largest program size, in tokens
+    #[arg(long = "max-size", default_value_t = 20)]
+    pub max_size: usize,
+
+    /// Sample uniformly over sizes and over programs of each size
+    #[arg(long = "uniform", action = clap::ArgAction::SetTrue)]
+    pub uniform: bool,
+
+    /// Keep only programs the type checker assigns a type
+    #[arg(long = "typed", action = clap::ArgAction::SetTrue)]
+    pub typed: bool,
+
+    /// Explicit start symbol override
+    #[arg(long = "start")]
+    pub start: Option<String>,
+}
+
+pub fn run_gen(args: &GenArgs) {
+    let grammar = load_spec(&args.spec_path, args.start.as_deref());
+
+    let config = GenerateConfig {
+        seed: args.seed.unwrap_or_else(rand::random),
+        max_size: args.max_size,
+        uniform: args.uniform,
+        typed: args.typed,
+        ..Default::default()
+    };
+    let generator = match Generator::new(&grammar, config) {
+        Ok(g) => g,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(2);
+        }
+    };
+
+    let mut found = 0;
+    for (source, _ast) in generator.take(args.count) {
+        println!("{}", source);
+        found += 1;
+    }
+    if found < args.count {
+        let warn = Style::new().fg_color(Some(AnsiColor::Yellow.into()));
+        eprintln!("{warn}Only generated{warn:#} {} of {} programs up to size {}", found, args.count, args.max_size);
+        std::process::exit(1);
+    }
+}
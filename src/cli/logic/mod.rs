@@ -1,6 +1,14 @@
-use clap::{Args, Subcommand};
+pub mod generate;
+pub mod fuzz;
+pub mod reduce;
+pub mod test;
+pub mod coverage;
+pub mod fmt;
+pub mod parse;
+
+use clap::{Args, Subcommand, ValueEnum};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use beam::logic::{check::TypeChecker, grammar::Grammar, parser::Parser};
 use beam::logic::debug::{DebugLevel, set_debug_level, add_module_filter, set_debug_input};
@@ -16,6 +24,20 @@ pub struct LogicCmd {
 pub enum LogicSubcommand {
     /// Typecheck a source file given a grammar spec
     Check(CheckArgs),
+    /// Parse a source file and print its AST
+    Parse(parse::ParseArgs),
+    /// Generate random programs from a grammar spec
+    Gen(generate::GenArgs),
+    /// Fuzz the parser and type checker, reporting panics
+    Fuzz(fuzz::FuzzArgs),
+    /// Shrink a failing program while it fails the same way
+    Reduce(reduce::ReduceArgs),
+    /// Run the `test` declarations of a spec
+    Test(test::TestArgs),
+    /// Report which productions and typing rules a set of programs exercises
+    Coverage(coverage::CoverageArgs),
+    /// Reformat source files using the spec's layout hints
+    Fmt(fmt::FmtArgs),
 }
 
 #[derive(Args, Debug, Clone)]
@@ -31,10 +53,82 @@ pub struct CheckArgs {
     /// Explicit start symbol override
     #[arg(long = "start")] 
     pub start: Option<String>,
+
+    /// Output format; `json` prints the type (or error) and the AST, see json.md
+    #[arg(long = "format", value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+
+    /// Print the type of every node instead of the root's: the AST annotated with types
+    /// and binder contexts (`sexpr`), the source with typed spans underlined (`source`),
+    /// or the typed AST as JSON (`json`)
+    #[arg(long = "typed", value_enum)]
+    pub typed: Option<TypedFormat>,
+}
+
+/// Output of `check --typed`
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TypedFormat {
+    Sexpr,
+    Source,
+    Json,
+}
+
+/// Output format of `parse`, `check` and `coverage`
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
 }
 
-pub fn dispatch(cli: &crate::cli::Cli) {
-    // Wire verbosity to debug level, with --trace overriding verbose count
+impl OutputFormat {
+    /// Exit early when JSON is asked for but the binary was built without the `json` feature
+    pub fn ensure_available(self) {
+        if self == OutputFormat::Json && !cfg!(feature = "json") {
+            eprintln!("error: --format json needs beam built with the `json` feature");
+            std::process::exit(2);
+        }
+    }
+}
+
+// JSON output only exists with the `json` feature; the code paths using it run after
+// `ensure_available` and are compiled out without it
+
+/// JSON value of a library type, see json.md
+#[cfg(feature = "json")]
+pub fn json_of<T: serde::Serialize>(value: &T) -> serde_json::Value {
+    serde_json::to_value(value).expect("JSON export cannot fail for these types")
+}
+
+/// Print a JSON report and exit with `code`
+#[cfg(feature = "json")]
+pub fn exit_json(report: serde_json::Value, code: i32) -> ! {
+    println!("{}", serde_json::to_string_pretty(&report).expect("JSON report serializes"));
+    std::process::exit(code)
+}
+
+/// Contents of a file a command was given; exits with status 2 if it cannot be read
+pub fn read(path: &Path) -> String {
+    fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("error: failed to read '{}': {}", path.display(), e);
+        std::process::exit(2);
+    })
+}
+
+/// Load the grammar spec at `path`, with `start` overriding its start symbol; exits with
+/// status 2 if it cannot be read or parsed
+pub fn load_spec(path: &Path, start: Option<&str>) -> Grammar {
+    let mut grammar = Grammar::load(&read(path)).unwrap_or_else(|e| {
+        eprintln!("error: failed to parse grammar spec: {}", e);
+        std::process::exit(2);
+    });
+    if let Some(start) = start {
+        grammar.set_start(start.to_string());
+    }
+    grammar
+}
+
+/// Wire verbosity flags to the debug level, with --trace overriding the verbose count
+pub fn configure_debug(cli: &crate::cli::Cli) -> DebugLevel {
     let level = if cli.trace {
         DebugLevel::Trace
     } else {
@@ -53,42 +147,30 @@ pub fn dispatch(cli: &crate::cli::Cli) {
             add_module_filter(m);
         }
     }
+    level
+}
 
-    match &cli.command {
-        crate::cli::Commands::Logic(cmd) => match &cmd.command {
-            LogicSubcommand::Check(args) => run_check(args, cli.with_input, level),
-        },
+pub fn dispatch(cmd: &LogicCmd, with_input: bool, level: DebugLevel) {
+    match &cmd.command {
+        LogicSubcommand::Check(args) => run_check(args, with_input, level),
+        LogicSubcommand::Parse(args) => parse::run_parse(args),
+        LogicSubcommand::Gen(args) => generate::run_gen(args),
+        LogicSubcommand::Fuzz(args) => fuzz::run_fuzz(args),
+        LogicSubcommand::Reduce(args) => reduce::run_reduce(args),
+        LogicSubcommand::Test(args) => test::run_test(args),
+        LogicSubcommand::Coverage(args) => coverage::run_coverage(args),
+        LogicSubcommand::Fmt(args) => fmt::run_fmt(args),
     }
 }
 
 fn run_check(args: &CheckArgs, with_input: bool, debug_level: DebugLevel) {
-    // Load grammar spec
-    let spec = match fs::read_to_string(&args.spec_path) {
-        Ok(s) => s,
-        Err(e) => {
-            eprintln!("error: failed to read spec '{}': {}", args.spec_path.display(), e);
-            std::process::exit(2);
-        }
-    };
-    let mut grammar = match Grammar::load(&spec) {
-        Ok(g) => g,
-        Err(e) => {
-            eprintln!("error: failed to parse grammar spec: {}", e);
-            std::process::exit(2);
-        }
-    };
-    if let Some(start) = &args.start {
-        grammar.set_start(start.clone());
+    args.format.ensure_available();
+    if args.typed == Some(TypedFormat::Json) {
+        OutputFormat::Json.ensure_available();
     }
+    let grammar = load_spec(&args.spec_path, args.start.as_deref());
 
-    // Load code
-    let code = match fs::read_to_string(&args.code_path) {
-        Ok(s) => s,
-        Err(e) => {
-            eprintln!("error: failed to read code '{}': {}", args.code_path.display(), e);
-            std::process::exit(2);
-        }
-    };
+    let code = read(&args.code_path);
     if with_input { set_debug_input(Some(code.clone())); }
 
     // Parse
@@ -96,11 +178,28 @@ fn run_check(args: &CheckArgs, with_input: bool, debug_level: DebugLevel) {
     let ast = match parser.parse(&code) {
         Ok(ast) => ast,
         Err(e) => {
+            if args.format == OutputFormat::Json {
+                #[cfg(feature = "json")]
+                exit_json(serde_json::json!({ "error": { "stage": "parse", "message": e } }), 1);
+            }
             eprintln!("parse error: {}", e);
             std::process::exit(1);
         }
     };
 
+    // Desugar before checking; rewritten nodes keep the spans of the sugar they replace
+    let ast = match parser.grammar().desugar(ast) {
+        Ok(ast) => ast,
+        Err(e) => {
+            if args.format == OutputFormat::Json {
+                #[cfg(feature = "json")]
+                exit_json(serde_json::json!({ "error": { "stage": "desugar", "message": e } }), 1);
+            }
+            eprintln!("desugar error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
     // if debug level is trace, print the AST
     if matches!(debug_level, DebugLevel::Trace) {
         println!("AST: {}", ast.show_simple());
@@ -110,9 +209,39 @@ fn run_check(args: &CheckArgs, with_input: bool, debug_level: DebugLevel) {
     // Typecheck
     let mut checker = TypeChecker::new();
 
-    checker.debug_at_span(&ast, "typechecking...");
+    // keep stdout parseable in JSON and typed output
+    if args.format == OutputFormat::Text && args.typed.is_none() {
+        checker.debug_at_span(&ast, "typechecking...");
+    }
+
+    if let Some(format) = args.typed {
+        let typed = checker.check_typed(&ast);
+        match format {
+            TypedFormat::Sexpr => println!("{}", typed.to_sexpr()),
+            TypedFormat::Source => print!("{}", typed.to_annotated_source(&code)),
+            TypedFormat::Json => {
+                #[cfg(feature = "json")]
+                println!("{}", serde_json::to_string_pretty(&json_of(&typed)).expect("JSON report serializes"));
+            }
+        }
+        if let Some(e) = &typed.error {
+            if format == TypedFormat::Sexpr {
+                eprintln!("Type error: {}", e);
+            }
+            std::process::exit(1);
+        }
+        std::process::exit(0);
+    }
 
-    match checker.check(&ast) {
+    let result = checker.check(&ast);
+    #[cfg(feature = "json")]
+    if args.format == OutputFormat::Json {
+        match result {
+            Ok(ty) => exit_json(serde_json::json!({ "type": ty.as_ref().map(json_of), "ast": json_of(&ast) }), 0),
+            Err(e) => exit_json(serde_json::json!({ "error": { "stage": "type", "message": e } }), 1),
+        }
+    }
+    match result {
         Ok(Some(ty)) => {
             let ok = Style::new().fg_color(Some(AnsiColor::Green.into()));
             println!("{ok}Type:{ok:#} {:?}", ty);
@@ -0,0 +1,98 @@
+use clap::{Args, ValueEnum};
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use beam::logic::ast::ASTNode;
+use beam::logic::check::TypeChecker;
+use beam::logic::check::derivation::DerivationRecorder;
+use beam::logic::parser::Parser;
+
+use super::{load_spec, read, OutputFormat};
+#[cfg(feature = "json")]
+use super::{exit_json, json_of};
+
+#[derive(Args, Debug, Clone)]
+pub struct ParseArgs {
+    /// Path to grammar specification file
+    #[arg(short = 's', long = "spec", value_name = "FILE")]
+    pub spec_path: PathBuf,
+
+    /// Source file to parse
+    #[arg(value_name = "CODE_FILE")]
+    pub code_path: PathBuf,
+
+    /// Output format: indented S-expression, or the AST as JSON (see json.md)
+    #[arg(long = "format", value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+
+    /// Print a Graphviz graph instead: the AST (`--dot`), or the typing derivation (`--dot=derivation`)
+    #[arg(long = "dot", value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "ast", conflicts_with = "format")]
+    pub dot: Option<DotView>,
+
+    /// Apply the spec's `desugar` rules before printing (always done for `--dot=derivation`)
+    #[arg(long = "desugar")]
+    pub desugar: bool,
+
+    /// Print the abstract tree: without `%inline` nonterminals, `%hide` tokens and group nodes
+    #[arg(long = "abstract")]
+    pub abstract_syntax: bool,
+
+    /// Explicit start symbol override
+    #[arg(long = "start")]
+    pub start: Option<String>,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DotView {
+    /// The parse tree, nodes labelled with rule names and bindings
+    Ast,
+    /// Rule applications, with the child and type each premise checked
+    Derivation,
+}
+
+pub fn run_parse(args: &ParseArgs) {
+    args.format.ensure_available();
+    let grammar = load_spec(&args.spec_path, args.start.as_deref());
+
+    let mut ast = match Parser::new(grammar.clone()).parse(&read(&args.code_path)) {
+        Ok(ast) => ast,
+        Err(e) => fail(args, "parse", e),
+    };
+    if args.desugar || args.dot == Some(DotView::Derivation) {
+        ast = grammar.desugar(ast).unwrap_or_else(|e| fail(args, "desugar", e));
+    }
+    if args.abstract_syntax {
+        ast = ast.to_abstract(&grammar);
+    }
+    print_ast(args, &ast);
+}
+
+fn fail(args: &ParseArgs, stage: &str, message: String) -> ! {
+    if args.format == OutputFormat::Json {
+        #[cfg(feature = "json")]
+        exit_json(serde_json::json!({ "error": { "stage": stage, "message": message } }), 1);
+    }
+    eprintln!("{} error: {}", stage, message);
+    std::process::exit(1);
+}
+
+fn print_ast(args: &ParseArgs, ast: &ASTNode) {
+    match (args.dot, args.format) {
+        (Some(DotView::Ast), _) => print!("{}", ast.to_dot()),
+        (Some(DotView::Derivation), _) => {
+            let recorder = Rc::new(RefCell::new(DerivationRecorder::new()));
+            let result = TypeChecker::new().with_derivation(Rc::clone(&recorder)).check(ast);
+            print!("{}", recorder.borrow().to_dot());
+            if let Err(e) = result {
+                eprintln!("type error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        (None, OutputFormat::Text) => println!("{}", ast.pretty()),
+        (None, OutputFormat::Json) => {
+            #[cfg(feature = "json")]
+            exit_json(json_of(ast), 0);
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use clap::Args;
+use std::fs;
+use std::path::PathBuf;
+
+use beam::engine::Reducer;
+use anstyle::{AnsiColor, Style};
+
+use super::{load_spec, read};
+
+#[derive(Args, Debug, Clone)]
+pub struct ReduceArgs {
+    /// Path to grammar specification file
+    #[arg(short = 's', long = "spec", value_name = "FILE")]
+    pub spec_path: PathBuf,
+
+    /// Path to the failing program
+    #[arg(value_name = "CODE_FILE")]
+    pub code_path: PathBuf,
+
+    /// Write the reduced program here instead of stdout
+    #[arg(short = 'o', long = "output", value_name = "FILE")]
+    pub output: Option<PathBuf>,
+
+    /// Explicit start symbol override
+    #[arg(long = "start")]
+    pub start: Option<String>,
+}
+
+pub fn run_reduce(args: &ReduceArgs) {
+    let grammar = load_spec(&args.spec_path, args.start.as_deref());
+    let code = read(&args.code_path);
+
+    let reduction = match Reducer::new(&grammar).reduce(&code) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let info = Style::new().fg_color(Some(AnsiColor::Cyan.into()));
+    eprintln!(
+        "{info}Reduced{info:#} {} to {} characters ({}) after {} tests",
+        code.trim_end().chars().count(),
+        reduction.reduced.chars().count(),
+        reduction.kind,
+        reduction.tests
+    );
+    match &args.output {
+        Some(path) => {
+            if let Err(e) = fs::write(path, format!("{}\n", reduction.reduced)) {
+                eprintln!("error: failed to write '{}': {}", path.display(), e);
+                std::process::exit(2);
+            }
+        }
+        None => println!("{}", reduction.reduced),
+    }
+}
@@ -0,0 +1,109 @@
+use clap::Args;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use beam::logic::bind::BoundType;
+use beam::logic::grammar::spec_test::{parse_tests, Expectation, Outcome, SpecTest, TestResult};
+use anstyle::{AnsiColor, Style};
+
+use super::{load_spec, read};
+
+#[derive(Args, Debug, Clone)]
+pub struct TestArgs {
+    /// Path to grammar specification file
+    #[arg(short = 's', long = "spec", value_name = "FILE")]
+    pub spec_path: PathBuf,
+
+    /// Extra test file (defaults to the spec path with a `.tests` extension, if present)
+    #[arg(long = "tests", value_name = "FILE")]
+    pub tests_path: Option<PathBuf>,
+
+    /// Record the actual type of every test that type checks as its expected type
+    #[arg(long = "update", action = clap::ArgAction::SetTrue)]
+    pub update: bool,
+
+    /// Explicit start symbol override
+    #[arg(long = "start")]
+    pub start: Option<String>,
+}
+
+pub fn run_test(args: &TestArgs) {
+    let grammar = load_spec(&args.spec_path, args.start.as_deref());
+
+    // (file, tests) pairs so updates go back where each test came from
+    let mut files = vec![(args.spec_path.clone(), grammar.tests.clone())];
+    let sidecar = args.tests_path.clone().or_else(|| Some(args.spec_path.with_extension("tests")).filter(|p| p.exists()));
+    if let Some(path) = sidecar {
+        match parse_tests(&read(&path)) {
+            Ok(tests) => files.push((path, tests)),
+            Err(e) => {
+                eprintln!("error: invalid test file '{}': {}", path.display(), e);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    let ok = Style::new().fg_color(Some(AnsiColor::Green.into()));
+    let err = Style::new().fg_color(Some(AnsiColor::Red.into()));
+    let (mut passed, mut failed, mut updated) = (0, 0, 0);
+    for (path, tests) in &files {
+        let results = grammar.run_tests(tests);
+        for r in &results {
+            if r.passed {
+                passed += 1;
+                println!("{ok}PASS{ok:#} {}:{} {}", path.display(), r.test.line, r.test.input);
+            } else if !args.update || golden(r).is_none() {
+                failed += 1;
+                println!("{err}FAIL{err:#} {}:{} {}", path.display(), r.test.line, r.test.input);
+                println!("  - expected: {}", r.test.expect);
+                println!("  + actual:   {}", r.outcome.to_string().replace('\n', "\n              "));
+            }
+        }
+        if args.update {
+            updated += update_file(path, &results);
+        }
+    }
+
+    if updated > 0 {
+        println!("updated {} expected type(s)", updated);
+    }
+    println!("{} passed, {} failed", passed, failed);
+    if failed > 0 {
+        std::process::exit(1);
+    }
+}
+
+/// The test line to write in `--update` mode, if the expected type should change
+fn golden(result: &TestResult) -> Option<SpecTest> {
+    let Outcome::Type(Some(ty)) = &result.outcome else { return None };
+    let current = match &result.test.expect {
+        Expectation::Checks => None,
+        Expectation::Type(t) => BoundType::parse(t).ok(),
+        _ => return None,
+    };
+    if current.as_ref() == Some(ty) {
+        return None;
+    }
+    Some(SpecTest { expect: Expectation::Type(ty.to_string()), ..result.test.clone() })
+}
+
+fn update_file(path: &Path, results: &[TestResult]) -> usize {
+    let text = read(path);
+    let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+    let mut count = 0;
+    for test in results.iter().filter_map(golden) {
+        let Some(line) = lines.get_mut(test.line - 1) else { continue };
+        let indent: String = line.chars().take_while(|c| c.is_whitespace()).collect();
+        *line = format!("{}{}", indent, test.to_line());
+        count += 1;
+    }
+    if count > 0 {
+        let mut out = lines.join("\n");
+        if text.ends_with('\n') { out.push('\n'); }
+        if let Err(e) = fs::write(path, out) {
+            eprintln!("error: failed to write '{}': {}", path.display(), e);
+            std::process::exit(2);
+        }
+    }
+    count
+}
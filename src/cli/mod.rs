@@ -1,4 +1,5 @@
 pub mod logic;
+pub mod synth;
 
 use clap::{ArgAction, Parser, Subcommand};
 
@@ -30,11 +31,17 @@ pub struct Cli {
 pub enum Commands {
     /// Logic-related commands
     Logic(self::logic::LogicCmd),
+    /// Enumerate programs of a given type
+    Synth(self::synth::SynthArgs),
 }
 
 pub fn run() {
     let cli = Cli::parse();
-    self::logic::dispatch(&cli);
+    let level = self::logic::configure_debug(&cli);
+    match &cli.command {
+        Commands::Logic(cmd) => self::logic::dispatch(cmd, cli.with_input, level),
+        Commands::Synth(args) => self::synth::run_synth(args),
+    }
 }
 
 
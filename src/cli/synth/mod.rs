@@ -0,0 +1,82 @@
+use clap::Args;
+use std::path::PathBuf;
+
+use beam::engine::{EnumerateConfig, Enumerator};
+use beam::logic::bind::BoundType;
+use anstyle::{AnsiColor, Style};
+
+use crate::cli::logic::load_spec;
+
+#[derive(Args, Debug, Clone)]
+pub struct SynthArgs {
+    /// Path to grammar specification file
+    #[arg(short = 's', long = "spec", value_name = "FILE")]
+    pub spec_path: PathBuf,
+
+    /// Goal type, e.g. "a -> a"
+    #[arg(short = 't', long = "type", value_name = "TYPE")]
+    pub goal: String,
+
+    /// Context entries the programs may use (repeatable), e.g. --var "f:a->b"
+    #[arg(long = "var", value_name = "NAME:TYPE")]
+    pub vars: Vec<String>,
+
+    /// Largest program size, in tokens
+    #[arg(long = "max-size", default_value_t = 10)]
+    pub max_size: usize,
+
+    /// Deepest AST accepted
+    #[arg(long = "max-depth", default_value_t = 64)]
+    pub max_depth: usize,
+
+    /// Number of programs to print
+    #[arg(short = 'n', long = "count", default_value_t = 10)]
+    pub count: usize,
+
+    /// Explicit start symbol override
+    #[arg(long = "start")]
+    pub start: Option<String>,
+}
+
+pub fn run_synth(args: &SynthArgs) {
+    let grammar = load_spec(&args.spec_path, args.start.as_deref());
+
+    let goal = BoundType::parse(&args.goal).unwrap_or_else(|e| {
+        eprintln!("error: invalid goal type '{}': {}", args.goal, e);
+        std::process::exit(2);
+    });
+    let mut context = Vec::new();
+    for var in &args.vars {
+        let parsed = var
+            .split_once(':')
+            .ok_or_else(|| "expected NAME:TYPE".to_string())
+            .and_then(|(name, ty)| Ok((name.trim().to_string(), BoundType::parse(ty)?)));
+        match parsed {
+            Ok(entry) => context.push(entry),
+            Err(e) => {
+                eprintln!("error: invalid --var '{}': {}", var, e);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    let config = EnumerateConfig { max_size: args.max_size, max_depth: args.max_depth, max_count: args.count, context, names: Vec::new() };
+    let enumerator = match Enumerator::new(&grammar, goal.clone(), config) {
+        Ok(e) => e,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(2);
+        }
+    };
+
+    let mut found = 0;
+    for (source, _ast) in enumerator {
+        println!("{}", source);
+        found += 1;
+    }
+    if found == 0 {
+        let warn = Style::new().fg_color(Some(AnsiColor::Yellow.into()));
+        eprintln!("{warn}No programs of type{warn:#} {} up to size {}", goal, args.max_size);
+        std::process::exit(1);
+    }
+}
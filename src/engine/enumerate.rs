@@ -0,0 +1,781 @@
+// Enumeration of programs, smallest first, directed by a goal type.
+//
+// Sentences are built by size (number of tokens), with a small pool of candidate strings
+// for regex terminals. The goal is pushed through the typing rules backwards at every
+// nonterminal: a rule's conclusion is unified with the goal, the positions it binds to
+// premise terms are searched with the premise's type (in the context extended by the
+// premise's setting), and every other position (annotations, binders) is enumerated
+// freely but must agree with the types and names the rule reads from it. So `lambda`
+// splits `τ₁ → τ₂` and searches its body under `x:τ₁`, `app` picks an argument type and
+// searches for a function to the goal, and `var` only offers context entries of the goal
+// type. Type variables no position determines (the argument type of `app`) range over the
+// subformulas of the root goal and the initial context. Rule-less productions pass the
+// goal to their only nonterminal (one occurrence of it, if repeated), and are enumerated
+// untyped otherwise. The type checker validates every candidate, so the search only has
+// to be complete, never sound; memo entries are capped, so large sizes are not even that.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use regex::Regex;
+
+use crate::logic::ast::ASTNode;
+use crate::logic::bind::BoundType;
+use crate::logic::check::context::TypingContext;
+use crate::logic::check::TypeChecker;
+use crate::logic::grammar::utils::is_regex;
+use crate::logic::grammar::{Grammar, Production, RepetitionKind, Symbol};
+use crate::logic::parser::Parser;
+use crate::logic::typing::rule::ConclusionKind;
+use crate::logic::typing::{Type, TypeAscription, TypingJudgment, TypingRule};
+
+/// Upper bound on sentences kept per memo entry and per split; keeps large sizes
+/// tractable at the price of completeness.
+const SENTENCE_CAP: usize = 4096;
+
+/// Names tried for regex terminals besides the context and goal names
+const DEFAULT_NAMES: [&str; 4] = ["x", "y", "f", "0"];
+
+type Sentence = Vec<String>;
+type Context = Vec<(String, BoundType)>;
+type Subst = HashMap<String, BoundType>;
+/// Goal and context of a slot, or `None` to enumerate it untyped
+type SlotGoal = Option<(BoundType, Context)>;
+
+/// Limits and typing context for an enumeration
+#[derive(Debug, Clone)]
+pub struct EnumerateConfig {
+    /// Largest program size, in tokens
+    pub max_size: usize,
+    /// Deepest AST accepted
+    pub max_depth: usize,
+    /// Stop after this many programs
+    pub max_count: usize,
+    /// Typing context the programs are checked in
+    pub context: Vec<(String, BoundType)>,
+    /// Extra candidate strings for regex terminals (e.g. constants from examples)
+    pub names: Vec<String>,
+}
+
+impl Default for EnumerateConfig {
+    fn default() -> Self {
+        Self { max_size: 10, max_depth: 64, max_count: 10, context: Vec::new(), names: Vec::new() }
+    }
+}
+
+enum TerminalClass {
+    Nonterminal,
+    Literal(String),
+    Pattern(String),
+}
+
+/// Tokens of one derivation, with the tokens under each binding it contains in depth-first order
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+struct Derivation {
+    tokens: Sentence,
+    bindings: Vec<(String, Sentence)>,
+}
+
+impl Derivation {
+    fn token(token: String) -> Self {
+        Self { tokens: vec![token], bindings: Vec::new() }
+    }
+
+    fn then(&self, other: &Derivation) -> Self {
+        Self {
+            tokens: [self.tokens.as_slice(), other.tokens.as_slice()].concat(),
+            bindings: [self.bindings.as_slice(), other.bindings.as_slice()].concat(),
+        }
+    }
+}
+
+/// A production position searched with a goal: `ty` is the premise type, or `None` for
+/// the goal of the production itself (a rule-less pass-through)
+struct Slot {
+    index: usize,
+    ty: Option<Type>,
+    extensions: Vec<TypeAscription>,
+}
+
+/// Iterator over `(source, ast)` pairs whose type is the goal, in order of size.
+/// Without a goal every program that parses is yielded.
+pub struct Enumerator {
+    grammar: Grammar,
+    parser: Parser,
+    goal: Option<BoundType>,
+    config: EnumerateConfig,
+    start: String,
+    pool: HashMap<String, Vec<String>>,
+    /// Types an undetermined type variable may take: subformulas of the goal and the context
+    candidates: Vec<BoundType>,
+    /// (nt, size) -> derivations of `nt` with exactly `size` tokens
+    table: HashMap<(String, usize), Vec<Derivation>>,
+    /// (nt, size, goal, context) -> sentences of `nt` searched for `goal`
+    typed_table: HashMap<String, Vec<Sentence>>,
+    /// Entries being computed, to cut unit cycles; `low` is the shallowest one a cycle reached
+    stack: Vec<String>,
+    low: usize,
+    size: usize,
+    pending: VecDeque<Sentence>,
+    seen: HashSet<String>,
+    emitted: usize,
+}
+
+impl Enumerator {
+    pub fn new(grammar: &Grammar, goal: BoundType, config: EnumerateConfig) -> Result<Self, String> {
+        Self::build(grammar, Some(goal), config)
+    }
+
+    /// Enumerate every parseable program, smallest first, without type filtering
+    pub fn untyped(grammar: &Grammar, config: EnumerateConfig) -> Result<Self, String> {
+        Self::build(grammar, None, config)
+    }
+
+    fn build(grammar: &Grammar, goal: Option<BoundType>, config: EnumerateConfig) -> Result<Self, String> {
+        let start = grammar
+            .start_nonterminal()
+            .cloned()
+            .ok_or_else(|| "No start nonterminal defined in grammar".to_string())?;
+        let mut names: Vec<String> = config.context.iter().map(|(n, _)| n.clone()).collect();
+        if let Some(goal) = &goal {
+            collect_atoms(goal, &mut names);
+        }
+        names.extend(config.names.iter().cloned());
+        names.extend(DEFAULT_NAMES.iter().map(|s| s.to_string()));
+
+        let mut pool = HashMap::new();
+        for prods in grammar.productions.values() {
+            for prod in prods {
+                collect_patterns(grammar, &prod.rhs, &names, &mut pool)?;
+            }
+        }
+
+        let mut candidates = Vec::new();
+        goal.iter().for_each(|ty| subformulas(ty, &mut candidates));
+        config.context.iter().for_each(|(_, ty)| subformulas(ty, &mut candidates));
+
+        Ok(Self {
+            grammar: grammar.clone(),
+            parser: Parser::new(grammar.clone()),
+            goal,
+            config,
+            start,
+            pool,
+            candidates,
+            table: HashMap::new(),
+            typed_table: HashMap::new(),
+            stack: Vec::new(),
+            low: usize::MAX,
+            size: 0,
+            pending: VecDeque::new(),
+            seen: HashSet::new(),
+            emitted: 0,
+        })
+    }
+
+    /// Index of the single nonterminal a rule-less production passes its type through, if any.
+    /// A repeated one (`Program ::= Term+`) counts too: only a single occurrence has a type,
+    /// since a node with several nonterminal children has none.
+    fn unit_child(&self, prod: &Production) -> Option<usize> {
+        let nts: Vec<usize> = (0..prod.rhs.len())
+            .filter(|&i| prod.rhs[i].is_group() || self.grammar.productions.contains_key(prod.rhs[i].value()))
+            .collect();
+        match nts.as_slice() {
+            [i] if matches!(prod.rhs[*i], Symbol::Simple { .. }) => Some(*i),
+            _ => None,
+        }
+    }
+
+    fn classify(&self, value: &str) -> TerminalClass {
+        if self.grammar.productions.contains_key(value) {
+            TerminalClass::Nonterminal
+        } else if is_regex(value) {
+            TerminalClass::Pattern(value.to_string())
+        } else {
+            TerminalClass::Literal(value.trim_matches('\'').to_string())
+        }
+    }
+
+    /// Enter a memo entry; false if it is already being computed (a unit cycle, which
+    /// adds nothing the entry does not derive by itself)
+    fn enter(&mut self, mark: &str) -> Option<usize> {
+        if let Some(depth) = self.stack.iter().position(|m| m == mark) {
+            self.low = self.low.min(depth);
+            return None;
+        }
+        self.stack.push(mark.to_string());
+        Some(std::mem::replace(&mut self.low, usize::MAX))
+    }
+
+    /// Leave a memo entry; true if its result is complete (no cycle reached an outer entry)
+    fn leave(&mut self, outer_low: usize) -> bool {
+        let depth = self.stack.len() - 1;
+        self.stack.pop();
+        let complete = self.low >= depth;
+        self.low = self.low.min(outer_low);
+        complete
+    }
+
+    /// All derivations of `nt` with exactly `size` tokens
+    fn derivations(&mut self, nt: &str, size: usize) -> Vec<Derivation> {
+        let key = (nt.to_string(), size);
+        if let Some(found) = self.table.get(&key) {
+            return found.clone();
+        }
+        let mark = format!("{}#{}", nt, size);
+        let Some(outer_low) = self.enter(&mark) else { return Vec::new() };
+        let mut out = Vec::new();
+        let mut seen = HashSet::new();
+        for prod in self.grammar.productions[nt].clone() {
+            for d in self.sequence(&prod.rhs, size) {
+                if out.len() < SENTENCE_CAP && seen.insert(d.clone()) {
+                    out.push(d);
+                }
+            }
+        }
+        if self.leave(outer_low) {
+            self.table.insert(key, out.clone());
+        }
+        out
+    }
+
+    fn sequence(&mut self, symbols: &[Symbol], size: usize) -> Vec<Derivation> {
+        self.product(symbols, size).iter().map(|parts| concat(parts)).collect()
+    }
+
+    /// One derivation per symbol, for every split of `size` tokens among them
+    fn product(&mut self, symbols: &[Symbol], size: usize) -> Vec<Vec<Derivation>> {
+        let Some((first, rest)) = symbols.split_first() else {
+            return if size == 0 { vec![Vec::new()] } else { Vec::new() };
+        };
+        let mut out = Vec::new();
+        for k in 0..=size {
+            let heads = self.symbol(first, k);
+            if heads.is_empty() { continue; }
+            let tails = self.product(rest, size - k);
+            for h in &heads {
+                for t in &tails {
+                    if out.len() >= SENTENCE_CAP { return out; }
+                    out.push([std::slice::from_ref(h), t.as_slice()].concat());
+                }
+            }
+        }
+        out
+    }
+
+    fn symbol(&mut self, symbol: &Symbol, size: usize) -> Vec<Derivation> {
+        match symbol.repetition() {
+            None => self.base(symbol, size),
+            Some(RepetitionKind::ZeroOrOne) => {
+                let mut out = self.base(symbol, size);
+                if size == 0 { out.push(Derivation::default()); }
+                out
+            }
+            Some(RepetitionKind::ZeroOrMore) => self.star(symbol, size),
+            Some(RepetitionKind::OneOrMore) => {
+                let mut out = self.base(symbol, size);
+                out.extend(self.one_then_star(symbol, size));
+                out
+            }
+        }
+    }
+
+    fn star(&mut self, symbol: &Symbol, size: usize) -> Vec<Derivation> {
+        if size == 0 { return vec![Derivation::default()]; }
+        self.one_then_star(symbol, size)
+    }
+
+    /// One non-empty occurrence followed by any number of occurrences
+    fn one_then_star(&mut self, symbol: &Symbol, size: usize) -> Vec<Derivation> {
+        let mut out = Vec::new();
+        for k in 1..=size {
+            let heads = self.base(symbol, k);
+            if heads.is_empty() { continue; }
+            let tails = self.star(symbol, size - k);
+            for h in &heads {
+                for t in &tails {
+                    if out.len() >= SENTENCE_CAP { return out; }
+                    out.push(h.then(t));
+                }
+            }
+        }
+        out
+    }
+
+    fn base(&mut self, symbol: &Symbol, size: usize) -> Vec<Derivation> {
+        match symbol {
+            Symbol::Group { symbols, .. } => self.sequence(symbols, size),
+            Symbol::Simple { value, binding, .. } => {
+                let mut out = match self.classify(value) {
+                    TerminalClass::Nonterminal => self.derivations(value, size),
+                    TerminalClass::Literal(lit) if size == 1 => vec![Derivation::token(lit)],
+                    TerminalClass::Pattern(p) if size == 1 => {
+                        self.pool[&p].iter().map(|s| Derivation::token(s.clone())).collect()
+                    }
+                    _ => Vec::new(),
+                };
+                if let Some(binding) = binding {
+                    for d in &mut out {
+                        d.bindings.insert(0, (binding.clone(), d.tokens.clone()));
+                    }
+                }
+                out
+            }
+        }
+    }
+
+    /// Sentences of `nt` with exactly `size` tokens that may have type `goal` in `context`
+    fn typed(&mut self, nt: &str, goal: &BoundType, context: &Context, size: usize) -> Vec<Sentence> {
+        let mark = format!("{}#{}:{:?}@{:?}", nt, size, goal, context);
+        if let Some(found) = self.typed_table.get(&mark) {
+            return found.clone();
+        }
+        let Some(outer_low) = self.enter(&mark) else { return Vec::new() };
+        let mut out = Vec::new();
+        let mut seen = HashSet::new();
+        for prod in self.grammar.productions[nt].clone() {
+            for sentence in self.typed_production(&prod, goal, context, size) {
+                if out.len() < SENTENCE_CAP && seen.insert(sentence.clone()) {
+                    out.push(sentence);
+                }
+            }
+        }
+        if self.leave(outer_low) {
+            self.typed_table.insert(mark, out.clone());
+        }
+        out
+    }
+
+    fn typed_production(&mut self, prod: &Production, goal: &BoundType, context: &Context, size: usize) -> Vec<Sentence> {
+        let rule = prod.rule.as_ref().and_then(|name| self.grammar.typing_rules.get(name).cloned());
+        let slots = match (&rule, self.unit_child(prod)) {
+            (Some(rule), _) => rule_slots(rule, &prod.rhs, &self.grammar),
+            (None, Some(index)) if prod.rule.is_none() => vec![Slot { index, ty: None, extensions: Vec::new() }],
+            _ => return self.sequence(&prod.rhs, size).into_iter().map(|d| d.tokens).collect(),
+        };
+        let mut subst = Subst::new();
+        if let Some(rule) = &rule
+            && let ConclusionKind::Type(ty) = &rule.conclusion.kind
+            && !conclusion_fits(ty, goal, &mut subst)
+        {
+            return Vec::new();
+        }
+
+        let free: Vec<usize> = (0..prod.rhs.len()).filter(|i| slots.iter().all(|s| s.index != *i)).collect();
+        let free_symbols: Vec<Symbol> = free.iter().map(|&i| prod.rhs[i].clone()).collect();
+        let mut out = Vec::new();
+        for f in 0..=size {
+            for parts in self.product(&free_symbols, f) {
+                let lookup = |var: &str| binding_value(&free_symbols, &parts, var);
+                let Some(choices) = slot_goals(rule.as_ref(), &slots, &lookup, subst.clone(), goal, context, &self.candidates) else {
+                    continue;
+                };
+                for goals in choices {
+                    for terms in self.typed_slots(prod, &slots, &goals, size - f) {
+                        let mut sentence = Vec::new();
+                        let (mut free_parts, mut terms) = (parts.iter(), terms.into_iter());
+                        for i in 0..prod.rhs.len() {
+                            match free.contains(&i) {
+                                true => sentence.extend(free_parts.next().unwrap().tokens.iter().cloned()),
+                                false => sentence.extend(terms.next().unwrap()),
+                            }
+                        }
+                        out.push(sentence);
+                        if out.len() >= SENTENCE_CAP { return out; }
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    /// One sentence per slot, for every split of `size` tokens among them
+    fn typed_slots(&mut self, prod: &Production, slots: &[Slot], goals: &[SlotGoal], size: usize) -> Vec<Vec<Sentence>> {
+        let Some((slot, rest)) = slots.split_first() else {
+            return if size == 0 { vec![Vec::new()] } else { Vec::new() };
+        };
+        let mut out = Vec::new();
+        for k in 0..=size {
+            let heads = match &goals[0] {
+                Some((goal, context)) => self.typed(prod.rhs[slot.index].value(), goal, context, k),
+                None => self.symbol(&prod.rhs[slot.index], k).into_iter().map(|d| d.tokens).collect(),
+            };
+            if heads.is_empty() { continue; }
+            let tails = self.typed_slots(prod, rest, &goals[1..], size - k);
+            for h in &heads {
+                for t in &tails {
+                    if out.len() >= SENTENCE_CAP { return out; }
+                    out.push([std::slice::from_ref(h), t.as_slice()].concat());
+                }
+            }
+        }
+        out
+    }
+
+    /// Parse and type-check one candidate; returns the AST if its type is the goal.
+    fn accept(&mut self, source: &str) -> Option<ASTNode> {
+        let ast = self.parser.parse(source).ok()?;
+        if ast.depth() > self.config.max_depth { return None; }
+        let Some(goal) = &self.goal else { return Some(ast) };
+        let context = TypingContext::with_references(self.config.context.clone());
+        let mut checker = TypeChecker::with_context(context);
+        match self.grammar.check_parsed(&ast, &mut checker) {
+            Ok(Some(ty)) if &ty == goal => Some(ast),
+            _ => None,
+        }
+    }
+}
+
+impl Iterator for Enumerator {
+    type Item = (String, ASTNode);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.emitted < self.config.max_count {
+            let Some(sentence) = self.pending.pop_front() else {
+                if self.size >= self.config.max_size { return None; }
+                self.size += 1;
+                let (start, size) = (self.start.clone(), self.size);
+                self.pending = match self.goal.clone() {
+                    Some(goal) => self.typed(&start, &goal, &self.config.context.clone(), size).into(),
+                    None => self.derivations(&start, size).into_iter().map(|d| d.tokens).collect(),
+                };
+                crate::debug_debug!("synthesizer", "enumerate: size {} has {} candidates", self.size, self.pending.len());
+                continue;
+            };
+            let source = self.grammar.render_tokens(&sentence);
+            if !self.seen.insert(source.clone()) { continue; }
+            if let Some(ast) = self.accept(&source) {
+                self.emitted += 1;
+                return Some((source, ast));
+            }
+        }
+        None
+    }
+}
+
+/// Positions of a rule's production bound to the terms of its typing premises
+fn rule_slots(rule: &TypingRule, rhs: &[Symbol], grammar: &Grammar) -> Vec<Slot> {
+    let mut slots = Vec::new();
+    for premise in &rule.premises {
+        let Some(TypingJudgment::Ascription((term, ty))) = &premise.judgment else { continue };
+        let index = rhs.iter().position(|s| {
+            matches!(s, Symbol::Simple { repetition: None, .. })
+                && s.binding() == Some(term)
+                && grammar.productions.contains_key(s.value())
+        });
+        if let Some(index) = index
+            && slots.iter().all(|s: &Slot| s.index != index)
+        {
+            let extensions = premise.setting.as_ref().map(|s| s.extensions.clone()).unwrap_or_default();
+            slots.push(Slot { index, ty: Some(ty.clone()), extensions });
+        }
+    }
+    slots.sort_by_key(|s| s.index);
+    slots
+}
+
+/// The goal and context of each slot, once per choice of the type variables nothing
+/// determines; `None` if the free positions contradict the rule. A slot whose type
+/// cannot be instantiated gets no goal and is enumerated untyped.
+fn slot_goals(
+    rule: Option<&TypingRule>,
+    slots: &[Slot],
+    lookup: &dyn Fn(&str) -> Option<Sentence>,
+    mut subst: Subst,
+    goal: &BoundType,
+    context: &Context,
+    candidates: &[BoundType],
+) -> Option<Vec<Vec<SlotGoal>>> {
+    let Some(rule) = rule else {
+        return Some(vec![vec![Some((goal.clone(), context.clone()))]]);
+    };
+    let name = |var: &str| lookup(var).map(|tokens| terminal_value(&tokens));
+
+    // Types and names the rule reads from annotations and binders must agree with the goal
+    let mut vars = Vec::new();
+    if let ConclusionKind::Type(ty) = &rule.conclusion.kind {
+        type_vars(ty, &mut vars);
+    }
+    for slot in slots {
+        slot.ty.iter().for_each(|ty| type_vars(ty, &mut vars));
+        slot.extensions.iter().for_each(|(_, ty)| type_vars(ty, &mut vars));
+    }
+    for var in &vars {
+        let Some(value) = name(var) else { continue };
+        let bound = BoundType::Atom(value?);
+        match subst.get(var) {
+            Some(existing) if *existing != bound => return None,
+            _ => { subst.insert(var.clone(), bound); }
+        }
+    }
+    if let ConclusionKind::ContextLookup(_, x) = &rule.conclusion.kind
+        && let Some(value) = name(x)
+        && lookup_context(context, &value?) != Some(goal)
+    {
+        return None;
+    }
+    for premise in &rule.premises {
+        if let Some(TypingJudgment::Membership(x, _)) = &premise.judgment
+            && let Some(value) = name(x)
+            && lookup_context(context, &value?).is_none()
+        {
+            return None;
+        }
+    }
+
+    // Whatever is still open ranges over the candidate types
+    let open: Vec<String> = vars.into_iter().filter(|v| !subst.contains_key(v)).collect();
+    let mut choices = vec![subst];
+    for var in &open {
+        choices = choices
+            .into_iter()
+            .flat_map(|s| candidates.iter().map(move |c| {
+                let mut s = s.clone();
+                s.insert(var.clone(), c.clone());
+                s
+            }))
+            .collect();
+    }
+
+    Some(choices.into_iter().map(|subst| {
+        slots.iter().map(|slot| {
+            let Some(ty) = &slot.ty else { return Some((goal.clone(), context.clone())) };
+            let target = instantiate(ty, &subst)?;
+            let mut extended = context.clone();
+            for (x, ty) in &slot.extensions {
+                if let (Some(Some(value)), Some(ty)) = (name(x), instantiate(ty, &subst)) {
+                    extended.push((value, ty));
+                }
+            }
+            Some((target, extended))
+        }).collect()
+    }).collect())
+}
+
+/// Tokens under `var` in the free positions, found the way the binder does: a direct
+/// child carrying the binding first, then depth-first
+fn binding_value(symbols: &[Symbol], parts: &[Derivation], var: &str) -> Option<Sentence> {
+    symbols
+        .iter()
+        .zip(parts)
+        .find(|(s, _)| s.binding().map(String::as_str) == Some(var))
+        .map(|(_, d)| d.tokens.clone())
+        .or_else(|| parts.iter().flat_map(|d| &d.bindings).find(|(b, _)| b == var).map(|(_, t)| t.clone()))
+}
+
+/// The single non-parenthesis token of a bound subtree, as the binder reads names and types
+fn terminal_value(tokens: &[String]) -> Option<String> {
+    match tokens.iter().filter(|t| *t != "(" && *t != ")").collect::<Vec<_>>().as_slice() {
+        [one] => Some((*one).clone()),
+        _ => None,
+    }
+}
+
+fn lookup_context<'c>(context: &'c Context, name: &str) -> Option<&'c BoundType> {
+    context.iter().rev().find(|(n, _)| n == name).map(|(_, ty)| ty)
+}
+
+fn concat(parts: &[Derivation]) -> Derivation {
+    parts.iter().fold(Derivation::default(), |acc, d| acc.then(d))
+}
+
+/// Does a rule conclusion type (with atoms as meta-variables) unify with the goal?
+fn conclusion_fits(pattern: &Type, goal: &BoundType, subst: &mut HashMap<String, BoundType>) -> bool {
+    match (pattern, goal) {
+        (Type::Atom(v), g) => match subst.get(v) {
+            Some(bound) => bound == g,
+            None => { subst.insert(v.clone(), g.clone()); true }
+        },
+        (Type::Raw(name), BoundType::Atom(g)) => name == g,
+        (Type::Raw(_), _) => false,
+        (Type::Arrow(p1, p2), BoundType::Arrow(g1, g2)) => conclusion_fits(p1, g1, subst) && conclusion_fits(p2, g2, subst),
+        (Type::Pointer(p), BoundType::Pointer(g)) => conclusion_fits(p, g, subst),
+        (Type::Array(p, _), BoundType::Array(g, _)) => conclusion_fits(p, g, subst),
+        (Type::Arrow(..) | Type::Pointer(_) | Type::Array(..), _) => false,
+        _ => true,
+    }
+}
+
+/// A rule type under a substitution; `None` while a variable is open or the form has no
+/// concrete counterpart
+fn instantiate(ty: &Type, subst: &Subst) -> Option<BoundType> {
+    let boxed = |t: &Type| instantiate(t, subst).map(Box::new);
+    Some(match ty {
+        Type::Atom(v) if v.starts_with('\'') && v.ends_with('\'') && v.len() > 1 => BoundType::Atom(v[1..v.len() - 1].to_string()),
+        Type::Atom(v) => subst.get(v)?.clone(),
+        Type::Raw(name) => BoundType::Atom(name.clone()),
+        Type::Arrow(l, r) => BoundType::Arrow(boxed(l)?, boxed(r)?),
+        Type::Pointer(t) => BoundType::Pointer(boxed(t)?),
+        Type::Array(t, n) => BoundType::Array(boxed(t)?, n.parse().ok()?),
+        Type::Not(t) => BoundType::Not(boxed(t)?),
+        Type::Intersection(l, r) => BoundType::Intersection(boxed(l)?, boxed(r)?),
+        Type::Union(l, r) => BoundType::Union(boxed(l)?, boxed(r)?),
+        Type::Universe => BoundType::Universe,
+        Type::Empty => BoundType::Empty,
+        Type::Tuple(_) | Type::ContextCall(..) => return None,
+    })
+}
+
+/// Type variables of a rule type (unquoted atoms), in order of appearance
+fn type_vars(ty: &Type, out: &mut Vec<String>) {
+    match ty {
+        Type::Atom(v) if !v.starts_with('\'') && !out.contains(v) => out.push(v.clone()),
+        Type::Arrow(l, r) | Type::Intersection(l, r) | Type::Union(l, r) => {
+            type_vars(l, out);
+            type_vars(r, out);
+        }
+        Type::Pointer(t) | Type::Array(t, _) | Type::Not(t) => type_vars(t, out),
+        _ => {}
+    }
+}
+
+fn subformulas(ty: &BoundType, out: &mut Vec<BoundType>) {
+    if out.contains(ty) { return; }
+    out.push(ty.clone());
+    match ty {
+        BoundType::Arrow(l, r) | BoundType::Intersection(l, r) | BoundType::Union(l, r) => {
+            subformulas(l, out);
+            subformulas(r, out);
+        }
+        BoundType::Pointer(t) | BoundType::Array(t, _) | BoundType::Not(t) => subformulas(t, out),
+        BoundType::Tuple(ts) => ts.iter().for_each(|t| subformulas(t, out)),
+        _ => {}
+    }
+}
+
+fn collect_atoms(ty: &BoundType, out: &mut Vec<String>) {
+    match ty {
+        BoundType::Atom(a) if !out.contains(a) => out.push(a.clone()),
+        BoundType::Arrow(l, r) | BoundType::Intersection(l, r) | BoundType::Union(l, r) => {
+            collect_atoms(l, out);
+            collect_atoms(r, out);
+        }
+        BoundType::Pointer(t) | BoundType::Array(t, _) | BoundType::Not(t) => collect_atoms(t, out),
+        BoundType::Tuple(ts) => ts.iter().for_each(|t| collect_atoms(t, out)),
+        _ => {}
+    }
+}
+
+/// Candidate strings for each regex terminal: the names that fully match it
+fn collect_patterns(grammar: &Grammar, symbols: &[Symbol], names: &[String], pool: &mut HashMap<String, Vec<String>>) -> Result<(), String> {
+    for symbol in symbols {
+        match symbol {
+            Symbol::Group { symbols, .. } => collect_patterns(grammar, symbols, names, pool)?,
+            Symbol::Simple { value, .. } => {
+                if grammar.productions.contains_key(value) || !is_regex(value) || pool.contains_key(value) {
+                    continue;
+                }
+                let re = Regex::new(&format!("^(?:{})$", &value[1..value.len() - 1]))
+                    .map_err(|e| format!("Invalid terminal regex {}: {}", value, e))?;
+                let mut matching: Vec<String> = Vec::new();
+                for n in names {
+                    if re.is_match(n) && !grammar.special_tokens.contains(n) && !matching.contains(n) {
+                        matching.push(n.clone());
+                    }
+                }
+                pool.insert(value.clone(), matching);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::grammar::tests::STLC_SPEC;
+
+    fn run(goal: &str, config: EnumerateConfig) -> Vec<String> {
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        let goal = BoundType::parse(goal).unwrap();
+        Enumerator::new(&grammar, goal, config).unwrap().map(|(src, _)| src).collect()
+    }
+
+    #[test]
+    fn finds_identity_for_arrow_goal() {
+        let found = run("a → a", EnumerateConfig { max_count: 3, ..Default::default() });
+        assert!(!found.is_empty());
+        assert!(found[0].starts_with('λ'), "got {:?}", found);
+        // results come smallest first, counted in tokens
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        let mut tokenizer = grammar.tokenizer();
+        let sizes: Vec<usize> = found.iter().map(|s| tokenizer.tokenize(s.clone()).unwrap().len()).collect();
+        assert!(sizes.windows(2).all(|w| w[0] <= w[1]), "got {:?}", found);
+    }
+
+    #[test]
+    fn uses_context_variables() {
+        let config = EnumerateConfig {
+            max_count: 1,
+            context: vec![("g".to_string(), BoundType::parse("b").unwrap())],
+            ..Default::default()
+        };
+        assert_eq!(run("b", config), vec!["g".to_string()]);
+    }
+
+    #[test]
+    fn finds_requested_count() {
+        assert_eq!(run("a → a", EnumerateConfig { max_count: 5, ..Default::default() }).len(), 5);
+    }
+
+    fn enumerator(goal: &str, context: &Context) -> (Enumerator, BoundType) {
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        let goal = BoundType::parse(goal).unwrap();
+        let config = EnumerateConfig { context: context.clone(), ..Default::default() };
+        (Enumerator::new(&grammar, goal.clone(), config).unwrap(), goal)
+    }
+
+    fn entry(name: &str, ty: &str) -> (String, BoundType) {
+        (name.to_string(), BoundType::parse(ty).unwrap())
+    }
+
+    #[test]
+    fn var_offers_only_context_entries_of_the_goal_type() {
+        let context = vec![entry("g", "a"), entry("h", "b")];
+        let (mut e, goal) = enumerator("a", &context);
+        assert_eq!(e.typed("Variable", &goal, &context, 1), vec![vec!["g".to_string()]]);
+        assert!(e.typed("Variable", &goal, &Vec::new(), 1).is_empty());
+    }
+
+    #[test]
+    fn lambda_searches_its_body_under_the_parameter() {
+        let (mut e, goal) = enumerator("a → a", &Vec::new());
+        let found = e.typed("Lambda", &goal, &Vec::new(), 8);
+        let identity: Sentence = ["λ", "x", ":", "a", "->", "a", ".", "x"].iter().map(|t| t.to_string()).collect();
+        assert!(found.contains(&identity), "got {:?}", found);
+        // the body can only be the parameter, and annotations never contradict the goal
+        for s in &found {
+            assert_eq!(s.iter().rev().find(|t| *t != ")"), Some(&s[1]), "got {:?}", s);
+            if s[4] == "->" {
+                assert_eq!(&s[3..6], ["a", "->", "a"], "got {:?}", s);
+            }
+        }
+    }
+
+    #[test]
+    fn application_searches_for_a_function_to_the_goal() {
+        let context = vec![entry("f", "a → b"), entry("z", "a")];
+        let (mut e, _) = enumerator("b", &context);
+        let goal = BoundType::parse("b").unwrap();
+        let found = e.typed("Application", &goal, &context, 2);
+        assert_eq!(found, vec![vec!["f".to_string(), "z".to_string()]]);
+    }
+
+    #[test]
+    fn example_spec_finds_identity_at_default_size() {
+        // `Program ::= Term+` at the root, `let` declarations and parenthesized types
+        let grammar = Grammar::load(include_str!("../../examples/stlc.spec")).unwrap();
+        let goal = BoundType::parse("a -> a").unwrap();
+        let mut e = Enumerator::new(&grammar, goal, EnumerateConfig { max_count: 3, ..Default::default() }).unwrap();
+        let found: Vec<String> = e.by_ref().map(|(src, _)| src).collect();
+        assert_eq!(found.len(), 3, "got {:?}", found);
+        assert!(found.iter().all(|s| s.starts_with('λ')), "got {:?}", found);
+        assert!(e.table.values().all(|v| v.len() <= SENTENCE_CAP));
+        assert!(e.typed_table.values().all(|v| v.len() <= SENTENCE_CAP));
+    }
+
+    #[test]
+    fn respects_size_limit() {
+        let found = run("a → a", EnumerateConfig { max_size: 4, ..Default::default() });
+        assert!(found.is_empty());
+    }
+}
@@ -0,0 +1,448 @@
+// Coverage-guided fuzzing of the parser and type checker.
+//
+// Inputs are mutated at three levels: tokens (delete, duplicate, swap, insert or
+// replace with a grammar literal), characters, and subtrees (a nonterminal's tokens
+// replaced by a fresh random sentence of the same nonterminal). Each input runs
+// through `Parser::parse`, `Parser::partial`, `Grammar::check_parsed` (desugaring,
+// then the type checker) and an optional oracle (e.g. an evaluator) under `catch_unwind`. An input joins the corpus when it reaches a new coverage feature:
+// a production alternative used, the kind of partial outcome, or a (digit-free)
+// error message. Panicking inputs are minimized with ddmin and reported as crashes.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::logic::ast::ASTNode;
+use crate::logic::check::TypeChecker;
+use crate::logic::grammar::Grammar;
+use crate::logic::grammar::desugar::SourceError;
+use crate::logic::parser::Parser;
+use crate::logic::partial::PartialOutcome;
+use crate::logic::tokenizer::Tokenizer;
+use super::generate::{GenerateConfig, Generator};
+use super::minimize::{ddmin, guarded, normalize, token_ranges, tokenize};
+
+/// Programs generated to seed an empty corpus
+const SEED_PROGRAMS: usize = 16;
+/// Largest replacement subtree, in tokens
+const SUBTREE_SIZE: usize = 8;
+
+#[derive(Debug, Clone)]
+pub struct FuzzConfig {
+    pub seed: u64,
+    /// Mutated inputs to run
+    pub iterations: usize,
+    /// Inputs longer than this (in characters) are not mutated further
+    pub max_len: usize,
+    /// Also run the type checker on inputs that parse
+    pub check_types: bool,
+    /// Where interesting inputs are saved (and loaded from on start)
+    pub corpus_dir: Option<PathBuf>,
+    /// Where minimized crashing inputs are saved
+    pub crash_dir: Option<PathBuf>,
+}
+
+impl Default for FuzzConfig {
+    fn default() -> Self {
+        Self { seed: 0, iterations: 10_000, max_len: 256, check_types: true, corpus_dir: None, crash_dir: None }
+    }
+}
+
+/// Which entry point panicked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stage {
+    Parse,
+    Partial,
+    Check,
+    Oracle,
+}
+
+impl std::fmt::Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stage::Parse => write!(f, "parse"),
+            Stage::Partial => write!(f, "partial"),
+            Stage::Check => write!(f, "check"),
+            Stage::Oracle => write!(f, "oracle"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Crash {
+    pub stage: Stage,
+    pub message: String,
+    pub input: String,
+    pub minimized: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FuzzReport {
+    pub executions: usize,
+    /// Coverage features reached
+    pub features: usize,
+    pub corpus: Vec<String>,
+    /// One crash per distinct (stage, message)
+    pub crashes: Vec<Crash>,
+}
+
+/// Extra stage run on every input, with its tree if it parsed; panicking is a crash
+pub type Oracle = Box<dyn FnMut(&str, Option<&ASTNode>)>;
+
+pub struct Fuzzer {
+    grammar: Grammar,
+    config: FuzzConfig,
+    rng: StdRng,
+    parser: Parser,
+    tokenizer: Tokenizer,
+    /// Literal terminals of the grammar, used for token insertion
+    literals: Vec<String>,
+    /// Random sentence generators per nonterminal, for subtree replacement
+    generators: HashMap<String, Option<Generator>>,
+    corpus: Vec<String>,
+    features: HashSet<String>,
+    crashes: Vec<Crash>,
+    executions: usize,
+    oracle: Option<Oracle>,
+}
+
+/// Result of running one input through every stage
+struct Execution {
+    features: Vec<String>,
+    ast: Option<ASTNode>,
+    panic: Option<(Stage, String)>,
+}
+
+impl Fuzzer {
+    pub fn new(grammar: &Grammar, config: FuzzConfig) -> Result<Self, String> {
+        Ok(Self {
+            grammar: grammar.clone(),
+            rng: StdRng::seed_from_u64(config.seed),
+            parser: Parser::new(grammar.clone()),
+            tokenizer: grammar.tokenizer(),
+            literals: grammar.literals(),
+            generators: HashMap::new(),
+            config,
+            corpus: Vec::new(),
+            features: HashSet::new(),
+            crashes: Vec::new(),
+            executions: 0,
+            oracle: None,
+        })
+    }
+
+    /// Also run `oracle` on every input, after the type checker
+    pub fn with_oracle(mut self, oracle: impl FnMut(&str, Option<&ASTNode>) + 'static) -> Self {
+        self.oracle = Some(Box::new(oracle));
+        self
+    }
+
+    /// Add a starting input
+    pub fn add_seed(&mut self, input: &str) {
+        self.consider(input.to_string());
+    }
+
+    /// Fuzz for `config.iterations` executions
+    pub fn run(&mut self) -> Result<FuzzReport, String> {
+        if let Some(dir) = self.config.corpus_dir.clone() {
+            for input in load_dir(&dir)? { self.add_seed(&input); }
+        }
+        // Random programs give every production a chance, whatever the seeds cover
+        let config = GenerateConfig { seed: self.rng.r#gen(), max_size: 12, ..Default::default() };
+        match Generator::new(&self.grammar, config) {
+            Ok(generator) => {
+                let seeds: Vec<String> = generator.take(SEED_PROGRAMS).map(|(s, _)| s).collect();
+                for s in seeds { self.add_seed(&s); }
+            }
+            Err(e) => crate::debug_warn!("synthesizer", "fuzz: no random seeds: {}", e),
+        }
+        if self.corpus.is_empty() {
+            self.add_seed("");
+        }
+        // Every seed crashed, so there is nothing to mutate; report the crashes
+        let iterations = if self.corpus.is_empty() {
+            crate::debug_warn!("synthesizer", "fuzz: every seed crashed; stopping early");
+            0
+        } else {
+            self.config.iterations
+        };
+
+        for _ in 0..iterations {
+            let parent = self.corpus[self.rng.gen_range(0..self.corpus.len())].clone();
+            let child = self.mutate(&parent);
+            if child.chars().count() > self.config.max_len { continue; }
+            self.consider(child);
+        }
+
+        if let Some(dir) = &self.config.corpus_dir {
+            save_dir(dir, "", &self.corpus)?;
+        }
+        if let Some(dir) = &self.config.crash_dir {
+            let inputs: Vec<String> = self.crashes.iter().map(|c| c.minimized.clone()).collect();
+            save_dir(dir, "crash-", &inputs)?;
+        }
+        Ok(FuzzReport { executions: self.executions, features: self.features.len(), corpus: self.corpus.clone(), crashes: self.crashes.clone() })
+    }
+
+    /// Run `input`; keep it if it reaches new coverage, record it if it crashes
+    fn consider(&mut self, input: String) {
+        let exec = self.execute(&input);
+        if let Some((stage, message)) = exec.panic {
+            if self.crashes.iter().any(|c| c.stage == stage && c.message == message) { return; }
+            crate::debug_warn!("synthesizer", "fuzz: {} panicked on '{}': {}", stage, input, message);
+            let minimized = self.minimize(&input, stage);
+            self.crashes.push(Crash { stage, message, input, minimized });
+            return;
+        }
+        let mut new = false;
+        for f in exec.features {
+            new |= self.features.insert(f);
+        }
+        if new || self.corpus.is_empty() {
+            self.corpus.push(input);
+        }
+    }
+
+    fn execute(&mut self, input: &str) -> Execution {
+        self.executions += 1;
+        let mut features = Vec::new();
+
+        let ast = match guarded(|| self.parser.parse(input)) {
+            Err(msg) => return self.crashed(Stage::Parse, msg),
+            Ok(Ok(ast)) => {
+                alternatives(&self.grammar, &ast, &mut features);
+                Some(ast)
+            }
+            Ok(Err(e)) => {
+                features.push(format!("parse-error:{}", normalize(&e, input)));
+                None
+            }
+        };
+
+        match guarded(|| self.parser.partial(input)) {
+            Err(msg) => return self.crashed(Stage::Partial, msg),
+            Ok(outcome) => features.push(match outcome {
+                Ok(PartialOutcome::Complete { .. }) => "partial:complete".to_string(),
+                Ok(PartialOutcome::Incomplete { states }) => format!("partial:incomplete:{}", states.len().min(4)),
+                Ok(PartialOutcome::Error(e)) | Err(e) => format!("partial-error:{}", normalize(&e, input)),
+            }),
+        }
+
+        if self.config.check_types && let Some(ast) = &ast {
+            match guarded(|| self.grammar.check_parsed(ast, &mut TypeChecker::new())) {
+                Err(msg) => return self.crashed(Stage::Check, msg),
+                Ok(Ok(Some(_))) => features.push("type:some".to_string()),
+                Ok(Ok(None)) => features.push("type:none".to_string()),
+                Ok(Err(SourceError::Desugar(e))) => features.push(format!("desugar-error:{}", normalize(&e, input))),
+                Ok(Err(SourceError::Type(e) | SourceError::Parse(e))) => features.push(format!("type-error:{}", normalize(&e, input))),
+            }
+        }
+
+        if let Some(oracle) = &mut self.oracle
+            && let Err(msg) = guarded(|| oracle(input, ast.as_ref()))
+        {
+            return self.crashed(Stage::Oracle, msg);
+        }
+        Execution { features, ast, panic: None }
+    }
+
+    /// A panic may leave the parser mid-update, so it is rebuilt
+    fn crashed(&mut self, stage: Stage, message: String) -> Execution {
+        self.parser = Parser::new(self.grammar.clone());
+        Execution { features: Vec::new(), ast: None, panic: Some((stage, message)) }
+    }
+
+    /// Smallest input (by ddmin over tokens, then characters) that still panics in `stage`
+    fn minimize(&mut self, input: &str, stage: Stage) -> String {
+        let tokens = tokenize(&mut self.tokenizer, input).unwrap_or_else(|| chars(input));
+        let mut still_crashes = |units: &[String], sep: &str| {
+            let candidate = units.join(sep);
+            matches!(self.execute(&candidate).panic, Some((s, _)) if s == stage)
+        };
+        let tokens = ddmin(tokens, |u| still_crashes(u, " "));
+        let text = tokens.join(" ");
+        ddmin(chars(&text), |u| still_crashes(u, "")).concat()
+    }
+
+    fn mutate(&mut self, input: &str) -> String {
+        let tokens = tokenize(&mut self.tokenizer, input).unwrap_or_default();
+        match self.rng.gen_range(0..10) {
+            0..=5 if !tokens.is_empty() || !self.literals.is_empty() => self.mutate_tokens(tokens),
+            6..=7 => self.mutate_subtree(input, &tokens).unwrap_or_else(|| self.mutate_chars(input)),
+            _ => self.mutate_chars(input),
+        }
+    }
+
+    fn mutate_tokens(&mut self, mut tokens: Vec<String>) -> String {
+        let n = tokens.len();
+        let op = if n == 0 { 3 } else { self.rng.gen_range(0..5) };
+        match op {
+            0 => { tokens.remove(self.rng.gen_range(0..n)); }
+            1 => {
+                let i = self.rng.gen_range(0..n);
+                let tok = tokens[i].clone();
+                tokens.insert(i, tok);
+            }
+            2 if n > 1 => {
+                let i = self.rng.gen_range(0..n - 1);
+                tokens.swap(i, i + 1);
+            }
+            4 if !self.literals.is_empty() => {
+                let i = self.rng.gen_range(0..n);
+                tokens[i] = self.literals[self.rng.gen_range(0..self.literals.len())].clone();
+            }
+            _ if !self.literals.is_empty() => {
+                let lit = self.literals[self.rng.gen_range(0..self.literals.len())].clone();
+                tokens.insert(self.rng.gen_range(0..=n), lit);
+            }
+            _ => {}
+        }
+        self.grammar.render_tokens(&tokens)
+    }
+
+    fn mutate_chars(&mut self, input: &str) -> String {
+        let mut cs: Vec<char> = input.chars().collect();
+        let n = cs.len();
+        let pick = |rng: &mut StdRng| match rng.gen_range(0..4) {
+            0 => ['(', ')', ' ', '\n', '.', ':'][rng.gen_range(0..6)],
+            1 => char::from_u32(rng.gen_range(0x80..0x3000)).unwrap_or('λ'),
+            _ => rng.gen_range(b'!'..=b'~') as char,
+        };
+        match self.rng.gen_range(0..3) {
+            0 if n > 0 => { cs.remove(self.rng.gen_range(0..n)); }
+            1 if n > 0 => {
+                let i = self.rng.gen_range(0..n);
+                cs[i] = pick(&mut self.rng);
+            }
+            _ => {
+                let c = pick(&mut self.rng);
+                cs.insert(self.rng.gen_range(0..=n), c);
+            }
+        }
+        cs.into_iter().collect()
+    }
+
+    /// Replace the tokens of a random nonterminal with a new sentence of the same nonterminal
+    fn mutate_subtree(&mut self, input: &str, tokens: &[String]) -> Option<String> {
+        let ast = self.execute(input).ast?;
+        let mut nodes = Vec::new();
+        token_ranges(&ast, 0, &mut nodes);
+        let nodes: Vec<(String, usize, usize)> = nodes.into_iter().filter(|(nt, _, _)| self.grammar.productions.contains_key(nt)).collect();
+        if nodes.is_empty() || tokens.is_empty() { return None; }
+        let (nt, start, end) = nodes[self.rng.gen_range(0..nodes.len())].clone();
+        let seed = self.rng.r#gen();
+        let grammar = &self.grammar;
+        let generator = self.generators.entry(nt.clone()).or_insert_with(|| {
+            let mut g = grammar.clone();
+            g.set_start(nt);
+            Generator::new(&g, GenerateConfig { seed, max_size: SUBTREE_SIZE, ..Default::default() }).ok()
+        });
+        let replacement = generator.as_mut()?.sample_tokens()?;
+        let end = end.min(tokens.len());
+        let mut out: Vec<String> = tokens[..start.min(end)].to_vec();
+        out.extend(replacement);
+        out.extend_from_slice(&tokens[end..]);
+        Some(self.grammar.render_tokens(&out))
+    }
+}
+
+/// Coverage keys of the production alternatives used in `node`
+fn alternatives(grammar: &Grammar, node: &ASTNode, out: &mut Vec<String>) {
+    if let ASTNode::Nonterminal(nt) = node {
+        if let Some(alt) = grammar.match_alternative(nt) {
+            out.push(format!("alt:{}:{}", nt.value, alt));
+        }
+        for child in &nt.children { alternatives(grammar, child, out); }
+    }
+}
+
+fn chars(input: &str) -> Vec<String> {
+    input.chars().map(String::from).collect()
+}
+
+/// Hash used for corpus file names (FNV-1a, stable across runs)
+fn fnv1a(s: &str) -> u64 {
+    s.bytes().fold(0xcbf29ce484222325, |h, b| (h ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+fn load_dir(dir: &Path) -> Result<Vec<String>, String> {
+    if !dir.exists() { return Ok(Vec::new()); }
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+        .map_err(|e| format!("failed to read {}: {}", dir.display(), e))?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.is_file())
+        .collect();
+    paths.sort();
+    Ok(paths.iter().filter_map(|p| std::fs::read_to_string(p).ok()).collect())
+}
+
+fn save_dir(dir: &Path, prefix: &str, inputs: &[String]) -> Result<(), String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("failed to create {}: {}", dir.display(), e))?;
+    for input in inputs {
+        let path = dir.join(format!("{}{:016x}.txt", prefix, fnv1a(input)));
+        std::fs::write(&path, input).map_err(|e| format!("failed to write {}: {}", path.display(), e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::grammar::tests::STLC_SPEC;
+
+    #[test]
+    fn fuzzing_grows_the_corpus_and_saves_it() {
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        let dir = std::env::temp_dir().join(format!("beam-fuzz-{}", std::process::id()));
+        let config = FuzzConfig { seed: 5, iterations: 300, corpus_dir: Some(dir.join("corpus")), ..Default::default() };
+        let mut fuzzer = Fuzzer::new(&grammar, config).unwrap();
+        fuzzer.add_seed("λx:a->a.x");
+        let report = fuzzer.run().unwrap();
+        assert!(report.executions >= 301);
+        assert!(report.features > 3);
+        assert!(report.corpus.len() > 1);
+        let saved = load_dir(&dir.join("corpus")).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+        assert_eq!(saved.len(), report.corpus.iter().collect::<HashSet<_>>().len());
+    }
+
+    #[test]
+    fn crashes_are_deduplicated_minimized_and_saved() {
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        let dir = std::env::temp_dir().join(format!("beam-fuzz-crash-{}", std::process::id()));
+        let config = FuzzConfig { seed: 5, iterations: 200, crash_dir: Some(dir.clone()), ..Default::default() };
+        let mut fuzzer = Fuzzer::new(&grammar, config).unwrap().with_oracle(|input, _| {
+            if input.contains("->") { panic!("injected fault"); }
+        });
+        fuzzer.add_seed("λx:a->a.x");
+        fuzzer.add_seed("(λy:b->b.y) z");
+        let report = fuzzer.run().unwrap();
+        let saved = load_dir(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+
+        // every input with an arrow panics the same way, so there is one crash
+        assert_eq!(report.crashes.len(), 1, "{:?}", report.crashes);
+        let crash = &report.crashes[0];
+        assert_eq!(crash.stage, Stage::Oracle);
+        assert_eq!(crash.message, "injected fault");
+        assert_eq!(crash.input, "λx:a->a.x");
+        assert_eq!(crash.minimized, "->");
+        assert_eq!(saved, vec!["->".to_string()]);
+        assert!(report.corpus.iter().all(|input| !input.contains("->")));
+    }
+
+    #[test]
+    fn stops_early_when_every_seed_crashes() {
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        let mut fuzzer = Fuzzer::new(&grammar, FuzzConfig { iterations: 1000, ..Default::default() })
+            .unwrap()
+            .with_oracle(|_, _| panic!("injected fault"));
+        let report = fuzzer.run().unwrap();
+        assert!(report.corpus.is_empty());
+        assert_eq!(report.crashes.len(), 1);
+        assert_eq!(report.crashes[0].minimized, "");
+        assert!(report.executions < 1000, "{}", report.executions);
+    }
+}
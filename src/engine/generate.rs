@@ -0,0 +1,462 @@
+// Random program generation.
+//
+// Sentences are sampled from the flattened BNF of a spec (the same view the grammar
+// mask uses), so repetitions and groups need no special casing. Two modes:
+// - size-bounded: alternatives are picked uniformly among those that can still finish
+//   within the remaining token budget;
+// - uniform: a size is picked uniformly among the reachable ones, then a derivation of
+//   exactly that size is drawn uniformly using derivation counts per size. Counts are
+//   kept as logarithms, since they grow exponentially with the size.
+// Regex terminals get a random string matching the regex. Each sample is joined with
+// `Grammar::render_tokens` and re-parsed, since the parser's ordered choice does not
+// accept every sentence of the context-free language; optionally the type checker
+// must accept it too.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use regex_syntax::hir::{Class, Hir, HirKind};
+
+use crate::logic::ast::ASTNode;
+use crate::logic::check::TypeChecker;
+use crate::logic::grammar::Grammar;
+use crate::logic::parser::Parser;
+use super::mask::{Bnf, BnfSymbol};
+
+/// Extra iterations drawn for unbounded regex repetitions (geometric, capped)
+const MAX_EXTRA_REPEAT: u32 = 6;
+/// Attempts at drawing a string for one regex terminal
+const TOKEN_ATTEMPTS: usize = 32;
+/// Log of a count of zero
+const ZERO: f64 = f64::NEG_INFINITY;
+
+#[derive(Debug, Clone)]
+pub struct GenerateConfig {
+    pub seed: u64,
+    /// Largest program, in tokens
+    pub max_size: usize,
+    /// Deepest nesting of BNF rules before only the smallest alternatives are taken
+    pub max_depth: usize,
+    /// Uniform over sizes and over derivations of each size, instead of uniform per choice
+    pub uniform: bool,
+    /// Keep only programs the type checker assigns a type
+    pub typed: bool,
+    /// Samples drawn per returned program before giving up
+    pub max_attempts: usize,
+}
+
+impl Default for GenerateConfig {
+    fn default() -> Self {
+        Self { seed: 0, max_size: 20, max_depth: 32, uniform: false, typed: false, max_attempts: 1000 }
+    }
+}
+
+/// Iterator over random programs `(source, ast)`; ends when `max_attempts` samples in
+/// a row are rejected.
+pub struct Generator {
+    grammar: Grammar,
+    bnf: Bnf,
+    parser: Parser,
+    rng: StdRng,
+    config: GenerateConfig,
+    /// Regex of each pattern terminal, `None` for literals
+    patterns: Vec<Option<Hir>>,
+    /// Fewest tokens each nonterminal derives (`usize::MAX` if none)
+    min_size: Vec<usize>,
+    /// A rule of each nonterminal deriving `min_size` tokens whose expansion terminates
+    min_rule: Vec<Option<usize>>,
+    /// counts[nt][n]: log of the number of derivations of `nt` with exactly n tokens
+    counts: Vec<Vec<f64>>,
+}
+
+impl Generator {
+    pub fn new(grammar: &Grammar, config: GenerateConfig) -> Result<Self, String> {
+        let bnf = Bnf::from_grammar(grammar)?;
+        let patterns = bnf
+            .sources
+            .iter()
+            .map(|src| match crate::logic::grammar::utils::is_regex(src) {
+                true => regex_syntax::parse(&src[1..src.len() - 1])
+                    .map(Some)
+                    .map_err(|e| format!("Invalid terminal regex {}: {}", src, e)),
+                false => Ok(None),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let min_size = min_sizes(&bnf);
+        if min_size[bnf.start] == usize::MAX {
+            return Err("The start nonterminal derives no finite sentence".to_string());
+        }
+        if min_size[bnf.start] > config.max_size {
+            return Err(format!("The smallest program has {} tokens, more than the maximum size {}", min_size[bnf.start], config.max_size));
+        }
+        let min_rule = min_rules(&bnf, &min_size);
+        let counts = if config.uniform { derivation_counts(&bnf, config.max_size) } else { Vec::new() };
+        Ok(Self {
+            grammar: grammar.clone(),
+            parser: Parser::new(grammar.clone()),
+            rng: StdRng::seed_from_u64(config.seed),
+            bnf,
+            config,
+            patterns,
+            min_size,
+            min_rule,
+            counts,
+        })
+    }
+
+    /// Draw one token sequence of the grammar (not yet checked by the parser)
+    pub fn sample_tokens(&mut self) -> Option<Vec<String>> {
+        let mut out = Vec::new();
+        if self.config.uniform {
+            let sizes: Vec<usize> = (1..=self.config.max_size).filter(|&n| self.counts[self.bnf.start][n] > ZERO).collect();
+            if sizes.is_empty() { return None; }
+            let n = sizes[self.rng.gen_range(0..sizes.len())];
+            self.exact(BnfSymbol::N(self.bnf.start), n, &mut out)?;
+        } else {
+            self.bounded(self.bnf.start, self.config.max_size, 0, &mut out)?;
+        }
+        Some(out)
+    }
+
+    /// Expand `nt` using at most `budget` tokens; returns the number used
+    fn bounded(&mut self, nt: usize, budget: usize, depth: usize, out: &mut Vec<String>) -> Option<usize> {
+        let rules: Vec<usize> = self.bnf.by_lhs[nt].iter().copied().filter(|&r| self.rule_min(r) <= budget).collect();
+        let rule = if depth >= self.config.max_depth {
+            self.min_rule[nt].filter(|&r| self.rule_min(r) <= budget)?
+        } else if rules.is_empty() {
+            return None;
+        } else {
+            rules[self.rng.gen_range(0..rules.len())]
+        };
+        let rhs = self.bnf.rules[rule].rhs.clone();
+        let mut used = 0;
+        for (i, sym) in rhs.iter().enumerate() {
+            // Leave enough budget for the symbols still to come
+            let reserved = rhs[i + 1..].iter().fold(0usize, |acc, s| acc.saturating_add(self.symbol_min(*s)));
+            let available = budget.checked_sub(reserved.saturating_add(used))?;
+            used += match *sym {
+                BnfSymbol::T(t) => { out.push(self.terminal(t)?); 1 }
+                BnfSymbol::N(n) => self.bounded(n, available, depth + 1, out)?,
+            };
+        }
+        Some(used)
+    }
+
+    /// Expand `sym` into exactly `n` tokens, uniformly among its derivations of that size
+    fn exact(&mut self, sym: BnfSymbol, n: usize, out: &mut Vec<String>) -> Option<()> {
+        match sym {
+            BnfSymbol::T(t) => (n == 1).then(|| self.terminal(t)).flatten().map(|tok| out.push(tok)),
+            BnfSymbol::N(nt) => {
+                let weights: Vec<(usize, f64)> = self.bnf.by_lhs[nt].iter().map(|&r| (r, self.sequence_count(&self.bnf.rules[r].rhs, n))).collect();
+                let rule = self.pick(&weights)?;
+                let rhs = self.bnf.rules[rule].rhs.clone();
+                self.exact_sequence(&rhs, n, out)
+            }
+        }
+    }
+
+    fn exact_sequence(&mut self, rhs: &[BnfSymbol], n: usize, out: &mut Vec<String>) -> Option<()> {
+        let Some((first, rest)) = rhs.split_first() else { return (n == 0).then_some(()) };
+        let weights: Vec<(usize, f64)> = (0..=n).map(|k| (k, self.symbol_count(*first, k) + self.sequence_count(rest, n - k))).collect();
+        let k = self.pick(&weights)?;
+        self.exact(*first, k, out)?;
+        self.exact_sequence(rest, n - k, out)
+    }
+
+    /// Weighted choice by log-weights; `None` if every weight is zero
+    fn pick(&mut self, weights: &[(usize, f64)]) -> Option<usize> {
+        let max = weights.iter().map(|(_, w)| *w).fold(ZERO, f64::max);
+        if max == ZERO { return None; }
+        // Relative to the largest, so the biggest weight is 1 and none overflows
+        let weights: Vec<(usize, f64)> = weights.iter().map(|&(item, w)| (item, (w - max).exp())).collect();
+        let total: f64 = weights.iter().map(|(_, w)| w).sum();
+        let mut x = self.rng.gen_range(0.0..total);
+        for &(item, w) in &weights {
+            if x < w { return Some(item); }
+            x -= w;
+        }
+        weights.iter().rev().find(|(_, w)| *w > 0.0).map(|(item, _)| *item)
+    }
+
+    fn symbol_count(&self, sym: BnfSymbol, n: usize) -> f64 {
+        symbol_count(&self.counts, sym, n)
+    }
+
+    fn sequence_count(&self, rhs: &[BnfSymbol], n: usize) -> f64 {
+        sequence_count(&self.counts, rhs, n)
+    }
+
+    fn symbol_min(&self, sym: BnfSymbol) -> usize {
+        match sym {
+            BnfSymbol::T(_) => 1,
+            BnfSymbol::N(n) => self.min_size[n],
+        }
+    }
+
+    fn rule_min(&self, rule: usize) -> usize {
+        self.bnf.rules[rule].rhs.iter().fold(0usize, |acc, s| acc.saturating_add(self.symbol_min(*s)))
+    }
+
+    /// A value for terminal `t`: the literal itself, or a random match of the regex
+    fn terminal(&mut self, t: usize) -> Option<String> {
+        let Some(hir) = self.patterns[t].clone() else {
+            return Some(self.bnf.sources[t].trim_matches('\'').to_string());
+        };
+        (0..TOKEN_ATTEMPTS).find_map(|_| {
+            let mut s = String::new();
+            sample_hir(&hir, &mut self.rng, &mut s);
+            (!s.is_empty() && self.bnf.matches(t, &s)).then_some(s)
+        })
+    }
+}
+
+impl Iterator for Generator {
+    type Item = (String, ASTNode);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for _ in 0..self.config.max_attempts {
+            let Some(tokens) = self.sample_tokens() else { continue };
+            let source = self.grammar.render_tokens(&tokens);
+            let Ok(ast) = self.parser.parse(&source) else {
+                crate::debug_trace!("synthesizer", "generate: '{}' does not parse", source);
+                continue;
+            };
+            if self.config.typed && !matches!(self.grammar.check_parsed(&ast, &mut TypeChecker::new()), Ok(Some(_))) {
+                crate::debug_trace!("synthesizer", "generate: '{}' does not type check", source);
+                continue;
+            }
+            return Some((source, ast));
+        }
+        None
+    }
+}
+
+/// Fewest tokens each nonterminal derives, by fixpoint
+fn min_sizes(bnf: &Bnf) -> Vec<usize> {
+    let mut min = vec![usize::MAX; bnf.by_lhs.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for rule in &bnf.rules {
+            let size = rule.rhs.iter().fold(0usize, |acc, s| {
+                acc.saturating_add(match s {
+                    BnfSymbol::T(_) => 1,
+                    BnfSymbol::N(n) => min[*n],
+                })
+            });
+            if size < min[rule.lhs] {
+                min[rule.lhs] = size;
+                changed = true;
+            }
+        }
+    }
+    min
+}
+
+/// For each nonterminal, a smallest rule whose nonterminals all have strictly shorter
+/// smallest derivations, so following these rules always terminates. Picking any rule of
+/// minimal size is not enough: with `A ::= B | 'a'` and `B ::= A` both of A's rules
+/// have size 1, and taking `A ::= B` loops forever.
+fn min_rules(bnf: &Bnf, min: &[usize]) -> Vec<Option<usize>> {
+    let mut height = vec![usize::MAX; bnf.by_lhs.len()];
+    let mut choice = vec![None; bnf.by_lhs.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (r, rule) in bnf.rules.iter().enumerate() {
+            let size = rule.rhs.iter().fold(0usize, |acc, s| {
+                acc.saturating_add(match s {
+                    BnfSymbol::T(_) => 1,
+                    BnfSymbol::N(n) => min[*n],
+                })
+            });
+            if size == usize::MAX || size != min[rule.lhs] { continue; }
+            // Tallest nonterminal child, or none if some child has no chosen rule yet
+            let h = rule.rhs.iter().try_fold(0usize, |acc, s| match s {
+                BnfSymbol::N(n) if height[*n] == usize::MAX => None,
+                BnfSymbol::N(n) => Some(acc.max(height[*n])),
+                BnfSymbol::T(_) => Some(acc),
+            });
+            let Some(h) = h else { continue };
+            if h + 1 < height[rule.lhs] {
+                height[rule.lhs] = h + 1;
+                choice[rule.lhs] = Some(r);
+                changed = true;
+            }
+        }
+    }
+    choice
+}
+
+/// Log of the number of derivations of each nonterminal per exact size, up to `max_size`. Sizes are filled
+/// in increasing order; nullable symbols make a size depend on itself, so each size is
+/// iterated to a fixpoint (bounded, since cyclic unit or empty rules are ambiguous).
+fn derivation_counts(bnf: &Bnf, max_size: usize) -> Vec<Vec<f64>> {
+    let mut counts = vec![vec![ZERO; max_size + 1]; bnf.by_lhs.len()];
+    for n in 0..=max_size {
+        for _ in 0..=bnf.by_lhs.len() {
+            let mut changed = false;
+            for nt in 0..bnf.by_lhs.len() {
+                let total = bnf.by_lhs[nt].iter().map(|&r| sequence_count(&counts, &bnf.rules[r].rhs, n)).fold(ZERO, log_add);
+                if total != counts[nt][n] {
+                    counts[nt][n] = total;
+                    changed = true;
+                }
+            }
+            if !changed { break; }
+        }
+    }
+    counts
+}
+
+/// ln(a + b) from ln(a) and ln(b)
+fn log_add(a: f64, b: f64) -> f64 {
+    let (hi, lo) = if a >= b { (a, b) } else { (b, a) };
+    if lo == ZERO { return hi; }
+    hi + (lo - hi).exp().ln_1p()
+}
+
+fn symbol_count(counts: &[Vec<f64>], sym: BnfSymbol, n: usize) -> f64 {
+    match sym {
+        BnfSymbol::T(_) => if n == 1 { 0.0 } else { ZERO },
+        BnfSymbol::N(nt) => counts[nt][n],
+    }
+}
+
+fn sequence_count(counts: &[Vec<f64>], rhs: &[BnfSymbol], n: usize) -> f64 {
+    match rhs {
+        [] => if n == 0 { 0.0 } else { ZERO },
+        [only] => symbol_count(counts, *only, n),
+        [first, rest @ ..] => (0..=n).map(|k| symbol_count(counts, *first, k) + sequence_count(counts, rest, n - k)).fold(ZERO, log_add),
+    }
+}
+
+/// Append a random string matching `hir`. Classes prefer printable ASCII when they
+/// contain any, so identifiers stay readable.
+fn sample_hir(hir: &Hir, rng: &mut StdRng, out: &mut String) {
+    match hir.kind() {
+        HirKind::Empty | HirKind::Look(_) => {}
+        HirKind::Literal(lit) => out.push_str(&String::from_utf8_lossy(&lit.0)),
+        HirKind::Class(Class::Unicode(cls)) => {
+            let ranges: Vec<(char, char)> = cls.ranges().iter().map(|r| (r.start(), r.end())).collect();
+            if let Some(c) = sample_ranges(&ranges, rng) { out.push(c); }
+        }
+        HirKind::Class(Class::Bytes(cls)) => {
+            let ranges: Vec<(char, char)> = cls.ranges().iter().map(|r| (r.start() as char, r.end() as char)).collect();
+            if let Some(c) = sample_ranges(&ranges, rng) { out.push(c); }
+        }
+        HirKind::Repetition(rep) => {
+            let mut times = rep.min;
+            let max = rep.max.unwrap_or(u32::MAX);
+            while times < max && times - rep.min < MAX_EXTRA_REPEAT && rng.gen_bool(0.5) {
+                times += 1;
+            }
+            for _ in 0..times { sample_hir(&rep.sub, rng, out); }
+        }
+        HirKind::Capture(cap) => sample_hir(&cap.sub, rng, out),
+        HirKind::Concat(parts) => parts.iter().for_each(|p| sample_hir(p, rng, out)),
+        HirKind::Alternation(alts) => {
+            if !alts.is_empty() { sample_hir(&alts[rng.gen_range(0..alts.len())], rng, out); }
+        }
+    }
+}
+
+fn sample_ranges(ranges: &[(char, char)], rng: &mut StdRng) -> Option<char> {
+    let printable: Vec<(char, char)> = ranges
+        .iter()
+        .filter_map(|&(lo, hi)| {
+            let (lo, hi) = (lo.max('!'), hi.min('~'));
+            (lo <= hi).then_some((lo, hi))
+        })
+        .collect();
+    let pool = if printable.is_empty() { ranges } else { &printable[..] };
+    let (lo, hi) = *pool.get(rng.gen_range(0..pool.len().max(1)))?;
+    // Skip over the surrogate gap if the range spans it
+    (0..8).find_map(|_| char::from_u32(rng.gen_range(lo as u32..=hi as u32)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::grammar::tests::STLC_SPEC;
+
+    fn generate(spec: &str, config: GenerateConfig, n: usize) -> Vec<String> {
+        let grammar = Grammar::load(spec).unwrap();
+        Generator::new(&grammar, config).unwrap().take(n).map(|(src, _)| src).collect()
+    }
+
+    #[test]
+    fn samples_parse_and_respect_the_size_bound() {
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        let config = GenerateConfig { seed: 7, max_size: 12, ..Default::default() };
+        let programs: Vec<(String, ASTNode)> = Generator::new(&grammar, config).unwrap().take(20).collect();
+        assert_eq!(programs.len(), 20);
+        let mut parser = Parser::new(grammar.clone());
+        for (src, ast) in &programs {
+            assert_eq!(&parser.parse(src).unwrap(), ast);
+            assert!(parser.tokens().len() <= 12, "{} is too long", src);
+        }
+    }
+
+    #[test]
+    fn depth_limit_escapes_unit_cycles() {
+        let grammar = Grammar::load("A ::= B | 'a'\nB ::= A").unwrap();
+        for seed in 0..20 {
+            let config = GenerateConfig { seed, max_depth: 4, ..Default::default() };
+            let mut generator = Generator::new(&grammar, config).unwrap();
+            assert_eq!(generator.sample_tokens(), Some(vec!["a".to_string()]));
+        }
+    }
+
+    #[test]
+    fn same_seed_same_programs() {
+        let config = GenerateConfig { seed: 42, ..Default::default() };
+        let a = generate(STLC_SPEC, config.clone(), 10);
+        assert_eq!(a, generate(STLC_SPEC, config, 10));
+        assert_ne!(a, generate(STLC_SPEC, GenerateConfig { seed: 43, ..Default::default() }, 10));
+    }
+
+    #[test]
+    fn uniform_sampling_covers_sizes() {
+        let spec = "Digit ::= /[0-9]/\nList ::= '[' Digit* ']'";
+        let config = GenerateConfig { seed: 1, max_size: 6, uniform: true, ..Default::default() };
+        let programs = generate(spec, config, 200);
+        let mut parser = Parser::new(Grammar::load(spec).unwrap());
+        let lengths: Vec<usize> = programs.iter().map(|p| { parser.parse(p).unwrap(); parser.tokens().len() }).collect();
+        // Sizes 2..=6 are equally likely, so every length shows up
+        for len in 2..=6 {
+            assert!(lengths.contains(&len), "no list of {} tokens", len);
+        }
+        assert!(programs.iter().all(|p| p.starts_with('[') && p.ends_with(']')));
+    }
+
+    #[test]
+    fn rejects_a_size_bound_below_the_smallest_program() {
+        let grammar = Grammar::load("Pair ::= '(' 'a' 'a' ')'").unwrap();
+        let config = GenerateConfig { max_size: 3, ..Default::default() };
+        let err = Generator::new(&grammar, config).err().unwrap();
+        assert!(err.contains("4 tokens"), "{}", err);
+    }
+
+    #[test]
+    fn uniform_sampling_survives_counts_beyond_floats() {
+        let grammar = Grammar::load("E ::= 'a' | 'b' | 'c' | 'd' | 'e' | 'f' | 'g' | 'h' | E E").unwrap();
+        let config = GenerateConfig { seed: 5, max_size: 300, uniform: true, ..Default::default() };
+        let mut generator = Generator::new(&grammar, config).unwrap();
+        // More than 8^300 programs of 300 tokens: far past f64::MAX
+        let top = generator.counts[generator.bnf.start][300];
+        assert!(top.is_finite() && top > f64::MAX.ln());
+        let lengths: Vec<usize> = (0..20).map(|_| generator.sample_tokens().unwrap().len()).collect();
+        assert!(lengths.iter().all(|&n| (1..=300).contains(&n)));
+        // Sizes are drawn uniformly, so long programs show up
+        assert!(lengths.iter().any(|&n| n > 100), "{:?}", lengths);
+    }
+
+    #[test]
+    fn typed_filter_keeps_well_typed_programs() {
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        let config = GenerateConfig { seed: 3, max_size: 12, typed: true, ..Default::default() };
+        for (src, ast) in Generator::new(&grammar, config).unwrap().take(5) {
+            assert!(matches!(TypeChecker::new().check(&ast), Ok(Some(_))), "{} does not type check", src);
+        }
+    }
+}
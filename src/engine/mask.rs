@@ -0,0 +1,585 @@
+// Grammar-constrained vocabulary masking for rankers.
+//
+// The grammar is flattened into plain BNF (repetitions and groups become fresh
+// nonterminals) and recognized with an Earley chart, which has the correct-prefix
+// property: a chart set is empty exactly when the tokens read so far cannot start
+// any sentence of the grammar. The chart for the committed part of the prefix is
+// cached, so masking a whole vocabulary only re-scans the last token or two.
+//
+// Note: this recognizes the context-free language of the spec. Tokens are split by
+// `Grammar::tokenizer` and regex terminals must match a whole token, exactly as in the
+// parser, so the mask never removes a token the parser could use. The recursive-descent
+// `Parser` commits to the first successful alternative, so it may still reject a few
+// programs the mask lets through.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+use regex_automata::hybrid::dfa::{Cache, DFA};
+use regex_automata::hybrid::LazyStateID;
+use regex_automata::{Anchored, Input};
+
+use crate::logic::grammar::{Grammar, RepetitionKind, Symbol, TOKEN_DELIMITERS};
+use crate::logic::grammar::utils::is_regex;
+use crate::logic::tokenizer::Tokenizer;
+use super::rank::Ranker;
+
+
+/// A terminal of the flattened grammar
+pub(crate) enum TerminalKind {
+    Literal(String),
+    Pattern(Box<(DFA, RefCell<Cache>)>),
+}
+
+/// Symbol on the right-hand side of a flattened rule
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum BnfSymbol {
+    N(usize),
+    T(usize),
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct BnfRule {
+    pub(crate) lhs: usize,
+    pub(crate) rhs: Vec<BnfSymbol>,
+}
+
+/// An Earley item: rule index, dot position and origin chart set
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Item {
+    rule: usize,
+    dot: usize,
+    origin: usize,
+}
+
+/// Plain BNF view of a `Grammar` (no repetition, no groups)
+pub(crate) struct Bnf {
+    pub(crate) rules: Vec<BnfRule>,
+    /// Spec nonterminal and alternative index each rule lowers, `None` for helper rules
+    pub(crate) origins: Vec<Option<(String, usize)>>,
+    pub(crate) by_lhs: Vec<Vec<usize>>,
+    pub(crate) nullable: Vec<bool>,
+    pub(crate) terminals: Vec<TerminalKind>,
+    /// Terminal values as written in the spec (`'x'` or `/re/`), by terminal index
+    pub(crate) sources: Vec<String>,
+    pub(crate) special_tokens: HashSet<String>,
+    pub(crate) start: usize,
+}
+
+impl Bnf {
+    pub(crate) fn from_grammar(grammar: &Grammar) -> Result<Self, String> {
+        let start_nt = grammar
+            .start_nonterminal()
+            .ok_or_else(|| "No start nonterminal defined in grammar".to_string())?;
+
+        let mut builder = BnfBuilder { grammar, nt_ids: HashMap::new(), nt_count: 0, rules: Vec::new(), origins: Vec::new(), terminals: Vec::new(), sources: Vec::new(), terminal_ids: HashMap::new() };
+        // Declared nonterminals first so their ids are stable
+        let mut names: Vec<&String> = grammar.production_order.iter().collect();
+        for k in grammar.productions.keys() {
+            if !names.contains(&k) { names.push(k); }
+        }
+        for name in &names { builder.nt_id(name); }
+        for name in names {
+            let lhs = builder.nt_id(name);
+            for (alt, production) in grammar.productions[name].iter().enumerate() {
+                let rhs = builder.sequence(&production.rhs)?;
+                builder.push(BnfRule { lhs, rhs }, Some((name.to_string(), alt)));
+            }
+        }
+        let start = builder.nt_id(start_nt);
+
+        let nt_count = builder.nt_count;
+        let mut by_lhs = vec![Vec::new(); nt_count];
+        for (i, rule) in builder.rules.iter().enumerate() {
+            by_lhs[rule.lhs].push(i);
+        }
+
+        // Nullable nonterminals by fixpoint
+        let mut nullable = vec![false; nt_count];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for rule in &builder.rules {
+                if nullable[rule.lhs] { continue; }
+                if rule.rhs.iter().all(|s| matches!(s, BnfSymbol::N(n) if nullable[*n])) {
+                    nullable[rule.lhs] = true;
+                    changed = true;
+                }
+            }
+        }
+
+        Ok(Self {
+            rules: builder.rules,
+            origins: builder.origins,
+            by_lhs,
+            nullable,
+            terminals: builder.terminals,
+            sources: builder.sources,
+            special_tokens: grammar.special_tokens.iter().cloned().collect(),
+            start,
+        })
+    }
+
+    /// Does `token` fully match terminal `t`?
+    pub(crate) fn matches(&self, t: usize, token: &str) -> bool {
+        match &self.terminals[t] {
+            TerminalKind::Literal(lit) => lit == token,
+            TerminalKind::Pattern(p) => {
+                let (dfa, cache) = &**p;
+                !self.special_tokens.contains(token)
+                    && run_dfa(dfa, &mut cache.borrow_mut(), token).is_some_and(|(_, full)| full)
+            }
+        }
+    }
+
+    /// Can `fragment` be extended into a token that matches terminal `t`?
+    fn prefix_matches(&self, t: usize, fragment: &str) -> bool {
+        match &self.terminals[t] {
+            TerminalKind::Literal(lit) => lit.starts_with(fragment),
+            TerminalKind::Pattern(p) => run_dfa(&p.0, &mut p.1.borrow_mut(), fragment).is_some(),
+        }
+    }
+}
+
+/// Feed `text` through an anchored lazy DFA. Returns `None` once the DFA dies,
+/// otherwise `Some((state, is_full_match))`.
+fn run_dfa(dfa: &DFA, cache: &mut Cache, text: &str) -> Option<(LazyStateID, bool)> {
+    let input = Input::new(text).anchored(Anchored::Yes);
+    let mut sid = dfa.start_state_forward(cache, &input).ok()?;
+    for &b in text.as_bytes() {
+        sid = dfa.next_state(cache, sid, b).ok()?;
+        if sid.is_dead() || sid.is_quit() { return None; }
+    }
+    let eoi = dfa.next_eoi_state(cache, sid).ok()?;
+    Some((sid, eoi.is_match()))
+}
+
+struct BnfBuilder<'g> {
+    grammar: &'g Grammar,
+    nt_ids: HashMap<String, usize>,
+    nt_count: usize,
+    rules: Vec<BnfRule>,
+    origins: Vec<Option<(String, usize)>>,
+    terminals: Vec<TerminalKind>,
+    sources: Vec<String>,
+    terminal_ids: HashMap<String, usize>,
+}
+
+impl BnfBuilder<'_> {
+    fn nt_id(&mut self, name: &str) -> usize {
+        if let Some(id) = self.nt_ids.get(name) { return *id; }
+        let id = self.fresh();
+        self.nt_ids.insert(name.to_string(), id);
+        id
+    }
+
+    fn push(&mut self, rule: BnfRule, origin: Option<(String, usize)>) {
+        self.rules.push(rule);
+        self.origins.push(origin);
+    }
+
+    fn fresh(&mut self) -> usize {
+        self.nt_count += 1;
+        self.nt_count - 1
+    }
+
+    fn terminal_id(&mut self, value: &str) -> Result<usize, String> {
+        if let Some(id) = self.terminal_ids.get(value) { return Ok(*id); }
+        let kind = if is_regex(value) {
+            let pattern = &value[1..value.len() - 1];
+            // Anchored at both ends, like `Grammar::regex_matches`: the whole token must match
+            let dfa = DFA::new(&format!("(?:{})$", pattern)).map_err(|e| format!("Invalid terminal regex {}: {}", value, e))?;
+            let cache = RefCell::new(dfa.create_cache());
+            TerminalKind::Pattern(Box::new((dfa, cache)))
+        } else {
+            TerminalKind::Literal(value.trim_matches('\'').to_string())
+        };
+        self.terminals.push(kind);
+        self.sources.push(value.to_string());
+        self.terminal_ids.insert(value.to_string(), self.terminals.len() - 1);
+        Ok(self.terminals.len() - 1)
+    }
+
+    fn sequence(&mut self, symbols: &[Symbol]) -> Result<Vec<BnfSymbol>, String> {
+        symbols.iter().map(|s| self.symbol(s)).collect()
+    }
+
+    /// Lower one symbol, introducing helper nonterminals for groups and repetitions
+    fn symbol(&mut self, symbol: &Symbol) -> Result<BnfSymbol, String> {
+        let base = match symbol {
+            Symbol::Group { symbols, .. } => {
+                let id = self.fresh();
+                let rhs = self.sequence(symbols)?;
+                self.push(BnfRule { lhs: id, rhs }, None);
+                BnfSymbol::N(id)
+            }
+            Symbol::Simple { value, .. } => {
+                if self.grammar.productions.contains_key(value) {
+                    BnfSymbol::N(self.nt_id(value))
+                } else {
+                    BnfSymbol::T(self.terminal_id(value)?)
+                }
+            }
+        };
+        let Some(rep) = symbol.repetition() else { return Ok(base) };
+        let id = self.fresh();
+        match rep {
+            RepetitionKind::ZeroOrOne => {
+                self.push(BnfRule { lhs: id, rhs: vec![] }, None);
+                self.push(BnfRule { lhs: id, rhs: vec![base] }, None);
+            }
+            RepetitionKind::ZeroOrMore => {
+                self.push(BnfRule { lhs: id, rhs: vec![] }, None);
+                self.push(BnfRule { lhs: id, rhs: vec![base, BnfSymbol::N(id)] }, None);
+            }
+            RepetitionKind::OneOrMore => {
+                let star = self.fresh();
+                self.push(BnfRule { lhs: star, rhs: vec![] }, None);
+                self.push(BnfRule { lhs: star, rhs: vec![base, BnfSymbol::N(star)] }, None);
+                self.push(BnfRule { lhs: id, rhs: vec![base, BnfSymbol::N(star)] }, None);
+            }
+        }
+        Ok(BnfSymbol::N(id))
+    }
+}
+
+/// Earley chart for a committed token prefix, reused across calls
+#[derive(Default)]
+struct Chart {
+    tokens: Vec<String>,
+    sets: Vec<Vec<Item>>,
+}
+
+/// Decides which strings can extend a prefix towards a sentence of the grammar.
+pub struct GrammarMask {
+    bnf: Bnf,
+    tokenizer: RefCell<Tokenizer>,
+    chart: RefCell<Chart>,
+}
+
+impl GrammarMask {
+    pub fn new(grammar: &Grammar) -> Result<Self, String> {
+        let bnf = Bnf::from_grammar(grammar)?;
+        let tokenizer = grammar.tokenizer();
+        Ok(Self { bnf, tokenizer: RefCell::new(tokenizer), chart: RefCell::new(Chart::default()) })
+    }
+
+    /// Is `text` a prefix of some sentence of the grammar?
+    pub fn is_viable(&self, text: &str) -> bool {
+        let occ = match self.tokenizer.borrow_mut().tokenize_with_spans(text) {
+            Ok(v) => v,
+            Err(_) => return false,
+        };
+        let tokenizer = self.tokenizer.borrow();
+        let mut tokens: Vec<String> = occ.iter().filter_map(|(id, _, _)| tokenizer.str(*id)).collect();
+        drop(tokenizer);
+
+        // The last token is still open unless a delimiter follows it
+        let open = match (occ.last(), text.chars().last()) {
+            (Some((_, _, end)), Some(c)) => *end == text.chars().count() && !TOKEN_DELIMITERS.contains(&c),
+            _ => false,
+        };
+        let fragment = if open { tokens.pop() } else { None };
+
+        let mut chart = self.chart.borrow_mut();
+        if !self.extend(&mut chart, &tokens) { return false; }
+        let Some(frag) = fragment else { return true };
+
+        // A special token is split off by the tokenizer no matter what follows, so it
+        // can only grow into a longer literal; otherwise it must be accepted as is.
+        let special = self.bnf.special_tokens.contains(&frag);
+        let last = chart.sets.last().expect("chart always has an initial set");
+        let extends = last.iter().any(|item| match self.next_symbol(item) {
+            Some(BnfSymbol::T(t)) => match &self.bnf.terminals[t] {
+                TerminalKind::Literal(lit) if special => lit.len() > frag.len() && lit.starts_with(&frag),
+                _ if special => false,
+                _ => self.bnf.prefix_matches(t, &frag),
+            },
+            _ => false,
+        });
+        if extends { return true; }
+        if !special {
+            drop(chart);
+            return self.splits_before_special(text, &frag);
+        }
+        tokens.push(frag);
+        self.extend(&mut chart, &tokens)
+    }
+
+    /// `a-` can still become `a` `->`: end the open token before a tail that may grow
+    /// into a special token, and check that instead
+    fn splits_before_special(&self, text: &str, frag: &str) -> bool {
+        let Some(head) = text.strip_suffix(frag) else { return false };
+        frag.char_indices().skip(1).any(|(k, _)| {
+            let tail = &frag[k..];
+            self.bnf.special_tokens.iter().any(|s| s.len() > tail.len() && s.starts_with(tail))
+                && self.is_viable(&format!("{}{} {}", head, &frag[..k], tail))
+        })
+    }
+
+    /// Filter `vocab` down to the items that keep `prefix` viable
+    pub fn allowed(&self, prefix: &str, vocab: &[String]) -> Vec<String> {
+        vocab.iter().filter(|v| self.is_viable(&format!("{}{}", prefix, v))).cloned().collect()
+    }
+
+    /// Weight of each of `vocab` as the next token after the tokens of `prefix`: the best
+    /// product of `weight(nonterminal, alternative)` over the alternatives predicted at
+    /// this position on the way to a terminal it matches, 1 for an alternative already
+    /// under way, 0 if nothing accepts it. `None` if `prefix` is not a viable prefix.
+    pub fn choice_weights(&self, prefix: &str, vocab: &[String], weight: &dyn Fn(&str, usize) -> f64) -> Option<Vec<f64>> {
+        let occ = self.tokenizer.borrow_mut().tokenize_with_spans(prefix).ok()?;
+        let tokenizer = self.tokenizer.borrow();
+        let tokens: Vec<String> = occ.iter().filter_map(|(id, _, _)| tokenizer.str(*id)).collect();
+        drop(tokenizer);
+        let mut chart = self.chart.borrow_mut();
+        if !self.extend(&mut chart, &tokens) { return None; }
+
+        let k = chart.sets.len() - 1;
+        let set = &chart.sets[k];
+        let rule_weight = |rule: usize| self.bnf.origins[rule].as_ref().map_or(1.0, |(nt, alt)| weight(nt, *alt));
+        // Predicted items take the best weight of an item predicting them; repeat until
+        // stable (weights only grow and never exceed 1, so at most one pass per item)
+        let predicted = |item: &Item| item.origin == k && item.dot == 0;
+        let mut best: Vec<f64> = set
+            .iter()
+            .map(|item| match predicted(item) {
+                // the start symbol's alternatives are predicted by nothing
+                true if k == 0 && self.bnf.rules[item.rule].lhs == self.bnf.start => rule_weight(item.rule),
+                true => 0.0,
+                false => 1.0,
+            })
+            .collect();
+        for _ in 0..set.len() {
+            let mut changed = false;
+            for (i, item) in set.iter().enumerate() {
+                if !predicted(item) { continue; }
+                let lhs = self.bnf.rules[item.rule].lhs;
+                let via = set
+                    .iter()
+                    .zip(&best)
+                    .filter(|(parent, _)| self.next_symbol(parent) == Some(BnfSymbol::N(lhs)))
+                    .map(|(_, w)| w * rule_weight(item.rule))
+                    .fold(0.0, f64::max);
+                if via > best[i] {
+                    best[i] = via;
+                    changed = true;
+                }
+            }
+            if !changed { break; }
+        }
+
+        let mut terminals = vec![0.0f64; self.bnf.terminals.len()];
+        for (item, w) in set.iter().zip(&best) {
+            if let Some(BnfSymbol::T(t)) = self.next_symbol(item) {
+                terminals[t] = terminals[t].max(*w);
+            }
+        }
+        Some(vocab.iter().map(|tok| {
+            (0..terminals.len()).filter(|&t| terminals[t] > 0.0 && self.bnf.matches(t, tok)).map(|t| terminals[t]).fold(0.0, f64::max)
+        }).collect())
+    }
+
+    /// Vocabulary derived from the grammar: every literal terminal, plus each printable
+    /// ASCII or Greek character that can start or continue a regex terminal, plus a space.
+    pub fn derive_vocab(&self) -> Vec<String> {
+        let mut vocab: Vec<String> = Vec::new();
+        let push = |s: String, vocab: &mut Vec<String>| if !vocab.contains(&s) { vocab.push(s) };
+        for t in &self.bnf.terminals {
+            if let TerminalKind::Literal(lit) = t { push(lit.clone(), &mut vocab); }
+        }
+        let chars = (32u8..127).map(|c| c as char).chain('α'..='ω');
+        for c in chars {
+            let s = c.to_string();
+            let usable = (0..self.bnf.terminals.len()).any(|t| match &self.bnf.terminals[t] {
+                TerminalKind::Pattern(p) => {
+                    let (dfa, cache) = &**p;
+                    let mut cache = cache.borrow_mut();
+                    run_dfa(dfa, &mut cache, &s).is_some() || run_dfa(dfa, &mut cache, &format!("{0}{0}", c)).is_some()
+                }
+                TerminalKind::Literal(_) => false,
+            });
+            if usable { push(s, &mut vocab); }
+        }
+        push(" ".to_string(), &mut vocab);
+        vocab
+    }
+
+    fn next_symbol(&self, item: &Item) -> Option<BnfSymbol> {
+        self.bnf.rules[item.rule].rhs.get(item.dot).copied()
+    }
+
+    /// Bring the cached chart in line with `tokens`, reusing the common prefix.
+    /// Returns false if the tokens are not a viable prefix.
+    fn extend(&self, chart: &mut Chart, tokens: &[String]) -> bool {
+        if chart.sets.is_empty() {
+            let mut initial: Vec<Item> = self.bnf.by_lhs[self.bnf.start].iter().map(|&rule| Item { rule, dot: 0, origin: 0 }).collect();
+            self.closure(&mut initial, &[], 0);
+            chart.sets.push(initial);
+        }
+        let common = chart.tokens.iter().zip(tokens).take_while(|(a, b)| a == b).count();
+        chart.tokens.truncate(common);
+        chart.sets.truncate(common + 1);
+        for token in &tokens[common..] {
+            if chart.sets.last().is_none_or(|s| s.is_empty()) { return false; }
+            let k = chart.sets.len() - 1;
+            let mut next: Vec<Item> = chart.sets[k]
+                .iter()
+                .filter(|item| matches!(self.next_symbol(item), Some(BnfSymbol::T(t)) if self.bnf.matches(t, token)))
+                .map(|item| Item { dot: item.dot + 1, ..*item })
+                .collect();
+            self.closure(&mut next, &chart.sets, k + 1);
+            chart.tokens.push(token.clone());
+            chart.sets.push(next);
+        }
+        chart.sets.last().is_some_and(|s| !s.is_empty())
+    }
+
+    /// Predict/complete until `set` (chart position `k`) is closed
+    fn closure(&self, set: &mut Vec<Item>, previous: &[Vec<Item>], k: usize) {
+        let mut seen: HashSet<Item> = set.iter().copied().collect();
+        let mut i = 0;
+        while i < set.len() {
+            let item = set[i];
+            i += 1;
+            match self.next_symbol(&item) {
+                Some(BnfSymbol::N(n)) => {
+                    for &rule in &self.bnf.by_lhs[n] {
+                        let predicted = Item { rule, dot: 0, origin: k };
+                        if seen.insert(predicted) { set.push(predicted); }
+                    }
+                    if self.bnf.nullable[n] {
+                        let advanced = Item { dot: item.dot + 1, ..item };
+                        if seen.insert(advanced) { set.push(advanced); }
+                    }
+                }
+                Some(BnfSymbol::T(_)) => {}
+                None => {
+                    let lhs = self.bnf.rules[item.rule].lhs;
+                    let parents: Vec<Item> = if item.origin == k { set.clone() } else { previous[item.origin].clone() };
+                    for parent in parents {
+                        if self.next_symbol(&parent) == Some(BnfSymbol::N(lhs)) {
+                            let advanced = Item { dot: parent.dot + 1, ..parent };
+                            if seen.insert(advanced) { set.push(advanced); }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Wraps any ranker and removes vocabulary items the grammar cannot accept next.
+pub struct MaskedRanker<R: Ranker> {
+    inner: R,
+    mask: GrammarMask,
+    /// The inner ranker's vocabulary, fetched once since it is read on every `rank`
+    vocab: Vec<String>,
+}
+
+impl<R: Ranker> MaskedRanker<R> {
+    pub fn new(grammar: &Grammar, inner: R) -> Result<Self, String> {
+        let vocab = inner.vocab();
+        Ok(Self { inner, mask: GrammarMask::new(grammar)?, vocab })
+    }
+
+    /// Access the underlying mask (e.g. to derive a grammar vocabulary)
+    pub fn mask(&self) -> &GrammarMask {
+        &self.mask
+    }
+}
+
+impl<R: Ranker> Ranker for MaskedRanker<R> {
+    fn vocab(&self) -> Vec<String> {
+        self.vocab.clone()
+    }
+
+    fn rank(&self, input: &str) -> Vec<(String, f32)> {
+        let allowed = self.mask.allowed(input, &self.vocab);
+        self.inner.rank_constrained(input, &allowed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::rank::DefaultRanker;
+    use crate::logic::grammar::tests::STLC_SPEC;
+
+    fn mask() -> GrammarMask {
+        GrammarMask::new(&Grammar::load(STLC_SPEC).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn viable_prefixes() {
+        let m = mask();
+        assert!(m.is_viable(""));
+        assert!(m.is_viable("λ"));
+        assert!(m.is_viable("λx"));
+        assert!(m.is_viable("λx:a-"));
+        assert!(m.is_viable("λx:a->a.x"));
+        assert!(m.is_viable("(λx:a->a.x) y"));
+        assert!(!m.is_viable(")"));
+        assert!(!m.is_viable("λ."));
+        assert!(!m.is_viable("λx:a->a.x)"));
+    }
+
+    #[test]
+    fn masks_vocabulary_after_lambda() {
+        let m = mask();
+        let vocab: Vec<String> = ["x", ")", ".", "λ", "(", ":"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(m.allowed("λ", &vocab), vec!["x".to_string()]);
+        assert_eq!(m.allowed("λx", &vocab), vec!["x".to_string(), ":".to_string()]);
+        assert_eq!(m.allowed("λx:a.", &vocab), vec!["x".to_string(), "λ".to_string(), "(".to_string()]);
+    }
+
+    #[test]
+    fn masked_ranker_only_returns_allowed_tokens() {
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        let ranker = MaskedRanker::new(&grammar, DefaultRanker).unwrap();
+        let ranked = ranker.rank("λx:a");
+        assert!(!ranked.is_empty());
+        for (tok, _) in &ranked {
+            assert!(ranker.mask().is_viable(&format!("λx:a{}", tok)), "token {:?} should be masked", tok);
+        }
+        assert!(!ranked.iter().any(|(t, _)| t == "λ"));
+    }
+
+    #[test]
+    fn masked_ranker_fetches_the_vocab_once() {
+        struct Counting(std::cell::Cell<usize>);
+        impl Ranker for Counting {
+            fn vocab(&self) -> Vec<String> {
+                self.0.set(self.0.get() + 1);
+                vec!["x".to_string(), ":".to_string()]
+            }
+            fn rank(&self, _input: &str) -> Vec<(String, f32)> {
+                vec![("x".to_string(), 0.5), (":".to_string(), 0.5)]
+            }
+        }
+        let ranker = MaskedRanker::new(&Grammar::load(STLC_SPEC).unwrap(), Counting(std::cell::Cell::new(0))).unwrap();
+        for input in ["λ", "λx", "λx:a"] {
+            ranker.rank(input);
+        }
+        assert_eq!(ranker.inner.0.get(), 1);
+    }
+
+    #[test]
+    fn derived_vocab_contains_literals_and_identifier_chars() {
+        let vocab = mask().derive_vocab();
+        for tok in ["λ", "->", "(", ")", ":", ".", "x", "τ", " "] {
+            assert!(vocab.contains(&tok.to_string()), "missing {:?}", tok);
+        }
+        assert!(!vocab.contains(&"#".to_string()));
+    }
+
+    #[test]
+    fn regex_terminals_match_whole_tokens_like_the_parser() {
+        let grammar = Grammar::load("Name ::= /[a-z]+/").unwrap();
+        let m = GrammarMask::new(&grammar).unwrap();
+        let mut parser = crate::logic::parser::Parser::new(grammar);
+        for (input, ok) in [("xy", true), ("1x", false), ("x1", false)] {
+            assert_eq!(m.is_viable(input), ok, "{}", input);
+            assert_eq!(parser.parse(input).is_ok(), ok, "{}", input);
+        }
+    }
+}
@@ -0,0 +1,120 @@
+// Helpers shared by the fuzzer and the reducer: running a stage with panics caught,
+// normalizing error messages so that equal failures compare equal, and shrinking
+// token lists with delta debugging.
+
+use std::cell::Cell;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Once;
+
+use crate::logic::ast::ASTNode;
+use crate::logic::tokenizer::Tokenizer;
+
+thread_local! {
+    /// Set while a guarded call runs, so the panic hook stays quiet for it only
+    static GUARDED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Run `f`, turning a panic into its message. The default panic output is suppressed
+/// for these calls without affecting panics elsewhere.
+pub(crate) fn guarded<T>(f: impl FnOnce() -> T) -> Result<T, String> {
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if !GUARDED.with(|g| g.get()) { previous(info); }
+        }));
+    });
+    GUARDED.with(|g| g.set(true));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    GUARDED.with(|g| g.set(false));
+    result.map_err(|payload| {
+        payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "panic".to_string())
+    })
+}
+
+/// Delta debugging: a 1-minimal subsequence of `units` for which `test` still holds
+pub fn ddmin(mut units: Vec<String>, mut test: impl FnMut(&[String]) -> bool) -> Vec<String> {
+    let mut n = 2;
+    while units.len() >= 2 {
+        let chunk = units.len().div_ceil(n);
+        let mut reduced = false;
+        for start in (0..units.len()).step_by(chunk) {
+            let complement: Vec<String> = units[..start].iter().chain(&units[(start + chunk).min(units.len())..]).cloned().collect();
+            if test(&complement) {
+                units = complement;
+                n = (n - 1).max(2);
+                reduced = true;
+                break;
+            }
+        }
+        if !reduced {
+            if n >= units.len() { break; }
+            n = (n * 2).min(units.len());
+        }
+    }
+    if units.len() == 1 && test(&[]) { units.clear(); }
+    units
+}
+
+/// (nonterminal, first token, end token) of every nonterminal node, by terminal count
+pub(crate) fn token_ranges(node: &ASTNode, start: usize, out: &mut Vec<(String, usize, usize)>) -> usize {
+    match node {
+        ASTNode::Terminal(_) => 1,
+        ASTNode::Nonterminal(nt) => {
+            let mut len = 0;
+            for child in &nt.children { len += token_ranges(child, start + len, out); }
+            out.push((nt.value.clone(), start, start + len));
+            len
+        }
+    }
+}
+
+/// Error message reduced to its fixed words: anything quoted, containing a digit or
+/// spelled like a piece of the input is dropped, so each kind of error counts once
+pub(crate) fn normalize(msg: &str, input: &str) -> String {
+    let mut words = Vec::new();
+    let mut quote = None;
+    for word in msg.split_whitespace() {
+        if let Some(q) = quote {
+            if word.ends_with(q) { quote = None; }
+            continue;
+        }
+        if let Some(q) = word.chars().next().filter(|c| matches!(c, '\'' | '"' | '`')) {
+            if word.len() == 1 || !word.ends_with(q) { quote = Some(q); }
+            continue;
+        }
+        let bare = word.trim_matches(|c: char| !c.is_alphanumeric());
+        if bare.is_empty() || bare.chars().any(|c| c.is_ascii_digit()) || input.contains(bare) { continue; }
+        words.push(word);
+        if words.len() == 8 { break; }
+    }
+    words.join(" ")
+}
+
+pub(crate) fn tokenize(tokenizer: &mut Tokenizer, input: &str) -> Option<Vec<String>> {
+    let occ = tokenizer.tokenize_with_spans(input).ok()?;
+    Some(occ.iter().filter_map(|(id, _, _)| tokenizer.str(*id)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ddmin_finds_minimal_failing_subset() {
+        let units: Vec<String> = "a b c x d e y f".split(' ').map(String::from).collect();
+        let min = ddmin(units, |u| u.iter().any(|s| s == "x") && u.iter().any(|s| s == "y"));
+        assert_eq!(min, vec!["x", "y"]);
+    }
+
+    #[test]
+    fn guarded_reports_panics_quietly() {
+        assert_eq!(guarded(|| 1 + 1), Ok(2));
+        let err = guarded(|| -> i32 { panic!("boom {}", 7) }).unwrap_err();
+        assert_eq!(err, "boom 7");
+    }
+}
@@ -11,8 +11,28 @@
 
 pub mod synthesizer;
 pub mod rank;
+pub mod mask;
+pub mod enumerate;
+pub mod pbe;
+pub mod ngram;
+// the ranker protocol is JSON lines
+#[cfg(feature = "json")]
+pub mod process;
+pub mod generate;
+pub mod minimize;
+pub mod fuzz;
+pub mod reduce;
 
 pub mod tests;
 
 // Re-export main types for convenience
-pub use synthesizer::Synthesizer;
+pub use synthesizer::{Candidates, StreamConfig, StreamState, Synthesizer, SynthesizerState};
+pub use mask::{GrammarMask, MaskedRanker};
+pub use enumerate::{Enumerator, EnumerateConfig};
+pub use ngram::NgramRanker;
+#[cfg(feature = "json")]
+pub use process::{ProcessConfig, ProcessRanker};
+pub use generate::{GenerateConfig, Generator};
+pub use fuzz::{Crash, FuzzConfig, FuzzReport, Fuzzer, Oracle, Stage};
+pub use reduce::{FailureKind, Reducer, Reduction};
+pub use pbe::{Evaluator, Example, PbeConfig, RankBy, Solution};
@@ -0,0 +1,423 @@
+// Trainable ranker: token n-grams plus production probabilities (PCFG), learnt from a
+// directory of programs written against one spec.
+//
+// Continuations are scored with an n-gram model smoothed with Witten-Bell
+// interpolation down to an add-one unigram, times the PCFG weight of the token: the
+// most likely chain of alternatives the grammar predicts at that point to reach a
+// terminal the token matches (see `GrammarMask::choice_weights`). The PCFG counts
+// which alternative each nonterminal used and also scores whole programs
+// (`program_log_prob`). Models are saved as plain text, tagged with the fingerprint
+// of the spec they were trained on, so synthesis can be reproduced offline.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::logic::ast::ASTNode;
+use crate::logic::grammar::Grammar;
+use crate::logic::parser::Parser;
+use crate::logic::tokenizer::Tokenizer;
+use super::mask::GrammarMask;
+use super::rank::Ranker;
+
+const HEADER: &str = ";!ngram 2";
+const BOS: &str = "<s>";
+const EOS: &str = "</s>";
+
+pub struct NgramRanker {
+    grammar: Grammar,
+    order: usize,
+    /// Continuations offered by `vocab`, in first-seen order
+    vocab: Vec<String>,
+    /// counts[context][token]; contexts of length 0..order
+    counts: BTreeMap<Vec<String>, BTreeMap<String, u64>>,
+    /// rules[nonterminal][alternative] = times the parser chose that alternative
+    rules: BTreeMap<String, Vec<u64>>,
+    tokenizer: RefCell<Tokenizer>,
+    /// Earley view of the grammar, to find the alternatives predicted after a prefix
+    mask: GrammarMask,
+}
+
+impl NgramRanker {
+    /// An untrained model of the given order (2 = bigrams); grammar literals seed the vocabulary.
+    pub fn new(grammar: &Grammar, order: usize) -> Result<Self, String> {
+        if order == 0 {
+            return Err("n-gram order must be at least 1".to_string());
+        }
+        let vocab = grammar.literals();
+        let rules = grammar
+            .productions
+            .iter()
+            .map(|(nt, prods)| (nt.clone(), vec![0; prods.len()]))
+            .collect();
+        let tokenizer = grammar.tokenizer();
+        let mask = GrammarMask::new(grammar)?;
+        Ok(Self { grammar: grammar.clone(), order, vocab, counts: BTreeMap::new(), rules, tokenizer: RefCell::new(tokenizer), mask })
+    }
+
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    /// Learn from one program
+    pub fn train_source(&mut self, source: &str) -> Result<(), String> {
+        let mut parser = Parser::new(self.grammar.clone());
+        let ast = parser.parse(source)?;
+        let tokens = parser.tokens().to_vec();
+        self.count_tokens(&tokens);
+        self.count_rules(&ast);
+        Ok(())
+    }
+
+    /// Learn from every file under `dir` (recursively, in path order). Files that do not
+    /// parse are skipped. Returns the number of programs learnt.
+    pub fn train_dir<P: AsRef<Path>>(&mut self, dir: P) -> Result<usize, String> {
+        let mut files = Vec::new();
+        collect_files(dir.as_ref(), &mut files)?;
+        files.sort();
+        let mut trained = 0;
+        for file in files {
+            let source = match fs::read_to_string(&file) {
+                Ok(s) => s,
+                Err(e) => {
+                    crate::debug_warn!("synthesizer", "ngram: skipping {}: {}", file.display(), e);
+                    continue;
+                }
+            };
+            match self.train_source(&source) {
+                Ok(()) => trained += 1,
+                Err(e) => crate::debug_warn!("synthesizer", "ngram: skipping {}: {}", file.display(), e),
+            }
+        }
+        Ok(trained)
+    }
+
+    fn count_tokens(&mut self, tokens: &[String]) {
+        let mut padded: Vec<String> = vec![BOS.to_string(); self.order - 1];
+        padded.extend(tokens.iter().cloned());
+        padded.push(EOS.to_string());
+        for tok in tokens {
+            if !self.vocab.contains(tok) {
+                self.vocab.push(tok.clone());
+            }
+        }
+        for i in self.order - 1..padded.len() {
+            for n in 0..self.order {
+                let context = padded[i - n..i].to_vec();
+                *self.counts.entry(context).or_default().entry(padded[i].clone()).or_insert(0) += 1;
+            }
+        }
+    }
+
+    fn count_rules(&mut self, node: &ASTNode) {
+        let ASTNode::Nonterminal(nt) = node else { return };
+        if let Some(alt) = self.grammar.match_alternative(nt)
+            && let Some(slots) = self.rules.get_mut(&nt.value)
+        {
+            slots[alt] += 1;
+        }
+        for child in &nt.children {
+            self.count_rules(child);
+        }
+    }
+
+    /// P(token | context), Witten-Bell interpolated; only the last `order - 1` context tokens matter
+    pub fn probability(&self, context: &[String], token: &str) -> f64 {
+        let keep = context.len().min(self.order - 1);
+        self.interpolate(&context[context.len() - keep..], token)
+    }
+
+    fn interpolate(&self, context: &[String], token: &str) -> f64 {
+        if context.is_empty() {
+            let unigrams = self.counts.get(&Vec::new());
+            let total: u64 = unigrams.map_or(0, |m| m.values().sum());
+            let count = unigrams.and_then(|m| m.get(token)).copied().unwrap_or(0);
+            // +1 for the end marker
+            let size = self.vocab.len() as f64 + 1.0;
+            return (count as f64 + 1.0) / (total as f64 + size);
+        }
+        let lower = self.interpolate(&context[1..], token);
+        let Some(followers) = self.counts.get(context) else { return lower };
+        let total: u64 = followers.values().sum();
+        let distinct = followers.len() as f64;
+        let count = followers.get(token).copied().unwrap_or(0);
+        (count as f64 + distinct * lower) / (total as f64 + distinct)
+    }
+
+    /// Probability of choosing alternative `alt` for `nonterminal` (add-one smoothed)
+    pub fn production_probability(&self, nonterminal: &str, alt: usize) -> f64 {
+        let Some(slots) = self.rules.get(nonterminal) else { return 0.0 };
+        if alt >= slots.len() {
+            return 0.0;
+        }
+        let total: u64 = slots.iter().sum();
+        (slots[alt] as f64 + 1.0) / (total as f64 + slots.len() as f64)
+    }
+
+    /// Log-probability of a parsed program's derivation under the PCFG
+    pub fn program_log_prob(&self, ast: &ASTNode) -> f64 {
+        let ASTNode::Nonterminal(nt) = ast else { return 0.0 };
+        let here = match self.grammar.match_alternative(nt) {
+            Some(alt) => self.production_probability(&nt.value, alt).ln(),
+            // synthetic group nodes carry no choice of their own
+            None => 0.0,
+        };
+        here + nt.children.iter().map(|c| self.program_log_prob(c)).sum::<f64>()
+    }
+
+    /// Write the model as text
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        fs::write(path, self.to_model_string())
+    }
+
+    pub fn to_model_string(&self) -> String {
+        let mut out = format!("{}\nspec\t{}\norder\t{}\n", HEADER, self.grammar.fingerprint(), self.order);
+        for tok in &self.vocab {
+            out.push_str(&format!("vocab\t{}\n", escape(tok)));
+        }
+        for (context, followers) in &self.counts {
+            for (tok, count) in followers {
+                let mut fields: Vec<String> = context.iter().map(|t| escape(t)).collect();
+                fields.push(escape(tok));
+                out.push_str(&format!("gram\t{}\t{}\n", count, fields.join("\t")));
+            }
+        }
+        for (nt, slots) in &self.rules {
+            for (alt, count) in slots.iter().enumerate() {
+                if *count > 0 {
+                    out.push_str(&format!("rule\t{}\t{}\t{}\n", escape(nt), alt, count));
+                }
+            }
+        }
+        out
+    }
+
+    /// Read a model written by `save`; `grammar` must be the spec it was trained on, which
+    /// the model's `spec` line checks
+    pub fn load<P: AsRef<Path>>(path: P, grammar: &Grammar) -> Result<Self, String> {
+        let text = fs::read_to_string(path.as_ref()).map_err(|e| format!("failed to read model '{}': {}", path.as_ref().display(), e))?;
+        Self::from_model_string(&text, grammar)
+    }
+
+    pub fn from_model_string(text: &str, grammar: &Grammar) -> Result<Self, String> {
+        let mut lines = text.lines();
+        if lines.next() != Some(HEADER) {
+            return Err(format!("not an n-gram model (expected '{}' header)", HEADER));
+        }
+        let mut model: Option<Self> = None;
+        let mut spec = None;
+        for (i, line) in lines.enumerate() {
+            let line_no = i + 2;
+            let fields: Vec<&str> = line.split('\t').collect();
+            let bad = || format!("line {}: malformed '{}'", line_no, line);
+            match fields.as_slice() {
+                ["spec", hash] => {
+                    let expected = grammar.fingerprint();
+                    if *hash != expected {
+                        return Err(format!("model was trained on spec {}, but the grammar has spec {}; retrain it with this grammar", hash, expected));
+                    }
+                    spec = Some(());
+                }
+                ["order", n] => model = Some(Self::new(grammar, n.parse().map_err(|_| bad())?)?),
+                ["vocab", tok] => {
+                    let m = model.as_mut().ok_or_else(bad)?;
+                    let tok = unescape(tok);
+                    if !m.vocab.contains(&tok) { m.vocab.push(tok); }
+                }
+                ["gram", count, rest @ ..] if !rest.is_empty() => {
+                    let m = model.as_mut().ok_or_else(bad)?;
+                    let count: u64 = count.parse().map_err(|_| bad())?;
+                    let (tok, context) = rest.split_last().unwrap();
+                    if context.len() >= m.order { return Err(bad()); }
+                    let context: Vec<String> = context.iter().map(|t| unescape(t)).collect();
+                    m.counts.entry(context).or_default().insert(unescape(tok), count);
+                }
+                ["rule", nt, alt, count] => {
+                    let m = model.as_mut().ok_or_else(bad)?;
+                    let nt = unescape(nt);
+                    let alt: usize = alt.parse().map_err(|_| bad())?;
+                    let slots = m.rules.get_mut(&nt).ok_or_else(|| format!("line {}: unknown nonterminal '{}'", line_no, nt))?;
+                    let slot = slots.get_mut(alt).ok_or_else(|| format!("line {}: {} has no alternative {}", line_no, nt, alt))?;
+                    *slot = count.parse().map_err(|_| bad())?;
+                }
+                [""] => {}
+                _ => return Err(bad()),
+            }
+        }
+        if spec.is_none() {
+            return Err("model has no spec line".to_string());
+        }
+        model.ok_or_else(|| "model has no order line".to_string())
+    }
+
+    /// `tokens` ordered by n-gram probability times PCFG weight of following `input`.
+    /// Where `input` is not a grammatical prefix the n-gram model ranks alone.
+    fn score(&self, input: &str, tokens: &[String]) -> Vec<(String, f32)> {
+        let context = self.context_of(input);
+        let weights = self.mask.choice_weights(input, tokens, &|nt, alt| self.production_probability(nt, alt));
+        let mut ranked: Vec<(String, f32)> = tokens
+            .iter()
+            .enumerate()
+            .map(|(i, tok)| {
+                let weight = weights.as_ref().map_or(1.0, |w| w[i]);
+                (tok.clone(), (self.probability(&context, tok) * weight) as f32)
+            })
+            .collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+        ranked
+    }
+
+    fn context_of(&self, input: &str) -> Vec<String> {
+        let mut tokenizer = self.tokenizer.borrow_mut();
+        let occ = tokenizer.tokenize_with_spans(input).unwrap_or_default();
+        let mut context: Vec<String> = vec![BOS.to_string(); self.order - 1];
+        context.extend(occ.iter().filter_map(|(id, _, _)| tokenizer.str(*id)));
+        context
+    }
+}
+
+impl Ranker for NgramRanker {
+    fn vocab(&self) -> Vec<String> {
+        self.vocab.clone()
+    }
+
+    /// Vocabulary ordered by n-gram likelihood of following `input`, weighted by the PCFG
+    fn rank(&self, input: &str) -> Vec<(String, f32)> {
+        self.score(input, &self.vocab)
+    }
+
+    fn rank_constrained(&self, input: &str, allowed: &[String]) -> Vec<(String, f32)> {
+        self.score(input, allowed)
+    }
+}
+
+fn collect_files(dir: &Path, out: &mut Vec<std::path::PathBuf>) -> Result<(), String> {
+    let entries = fs::read_dir(dir).map_err(|e| format!("failed to read corpus '{}': {}", dir.display(), e))?;
+    for entry in entries {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        if path.is_dir() {
+            collect_files(&path, out)?;
+        } else {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n").replace('\r', "\\r")
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::grammar::tests::STLC_SPEC;
+
+    fn trained() -> NgramRanker {
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        let mut model = NgramRanker::new(&grammar, 3).unwrap();
+        for src in ["λx:a->a.x", "λy:a->a.y", "λx:a->a.x", "λf:a->a.f x"] {
+            model.train_source(src).unwrap();
+        }
+        model
+    }
+
+    #[test]
+    fn ranks_seen_continuations_first() {
+        let model = trained();
+        let ranked = model.rank("λx");
+        assert_eq!(ranked[0].0, ":");
+        let ranked = model.rank("λx:a");
+        assert_eq!(ranked[0].0, "->");
+        // probabilities over the vocabulary (plus end marker) sum to at most one
+        let total: f32 = model.rank("λ").iter().map(|(_, p)| p).sum();
+        assert!(total <= 1.0 + 1e-4, "{}", total);
+    }
+
+    #[test]
+    fn learns_production_probabilities() {
+        let model = trained();
+        // Term ::= Application[e] | BaseTerm[e]; only one program was an application
+        let app = model.production_probability("Term", 0);
+        let base = model.production_probability("Term", 1);
+        assert!(base > app, "{} vs {}", base, app);
+        let mut parser = Parser::new(model.grammar.clone());
+        let common = parser.parse("λx:a->a.x").unwrap();
+        let rare = parser.parse("λf:a->a.f x").unwrap();
+        assert!(model.program_log_prob(&common) > model.program_log_prob(&rare));
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let model = trained();
+        let text = model.to_model_string();
+        let loaded = NgramRanker::from_model_string(&text, &model.grammar).unwrap();
+        assert_eq!(loaded.to_model_string(), text);
+        assert_eq!(loaded.rank("λx:a"), model.rank("λx:a"));
+        assert!(NgramRanker::from_model_string("order\t2\n", &model.grammar).is_err());
+    }
+
+    #[test]
+    fn production_probabilities_weight_the_ranking() {
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        // No n-grams, so only the PCFG tells `λ`, `(` and identifiers apart after the dot
+        let with_rules = |rules: &str| {
+            let text = format!("{}\nspec\t{}\norder\t2\n{}", HEADER, grammar.fingerprint(), rules);
+            NgramRanker::from_model_string(&text, &grammar).unwrap()
+        };
+        let lambdas = with_rules("rule\tBaseTerm\t1\t50\n");
+        assert_eq!(lambdas.rank("λx:a.")[0].0, "λ");
+        let parens = with_rules("rule\tBaseTerm\t2\t50\n");
+        assert_eq!(parens.rank("λx:a.")[0].0, "(");
+        let ranked = with_rules("").rank("λx:a.");
+        let score = |tok: &str| ranked.iter().find(|(t, _)| t == tok).unwrap().1;
+        assert_eq!(score("λ"), score("("));
+        // a token the grammar cannot take next gets no weight
+        assert_eq!(score(")"), 0.0);
+    }
+
+    #[test]
+    fn load_rejects_models_of_another_spec() {
+        let model = trained();
+        let other = Grammar::load("Name ::= /[a-z]+/").unwrap();
+        let err = NgramRanker::from_model_string(&model.to_model_string(), &other).err().unwrap();
+        assert!(err.contains("trained on spec"), "{}", err);
+        let unsigned = model.to_model_string().lines().filter(|l| !l.starts_with("spec\t")).collect::<Vec<_>>().join("\n");
+        assert!(NgramRanker::from_model_string(&unsigned, &model.grammar).is_err());
+    }
+
+    #[test]
+    fn train_dir_skips_unparsable_files() {
+        let dir = std::env::temp_dir().join(format!("beam-ngram-{}", std::process::id()));
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        fs::write(dir.join("a.stlc"), "λx:a->a.x").unwrap();
+        fs::write(dir.join("nested/b.stlc"), "λy:a->a.y").unwrap();
+        fs::write(dir.join("broken.stlc"), ") (").unwrap();
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        let mut model = NgramRanker::new(&grammar, 2).unwrap();
+        let trained = model.train_dir(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(trained, 2);
+        assert!(model.vocab().contains(&"y".to_string()));
+    }
+}
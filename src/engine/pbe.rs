@@ -0,0 +1,218 @@
+// Programming by example.
+//
+// Candidates come from the enumerator (grammar first, then the goal type if one is
+// given) and are run on every input/output example through a user-supplied
+// `Evaluator`, since specs only describe syntax and typing. The consistent programs
+// are ranked by size or by a `Ranker`'s score. Ranking by score looks at every
+// consistent program within the size limit before keeping the best ones, so a
+// larger program the ranker prefers can beat a smaller one.
+
+use crate::logic::ast::ASTNode;
+use crate::logic::bind::utils::extract_terminals;
+use crate::logic::bind::BoundType;
+use crate::logic::grammar::Grammar;
+use super::enumerate::{EnumerateConfig, Enumerator};
+use super::rank::Ranker;
+
+/// One input/output pair the program must reproduce
+#[derive(Debug, Clone, PartialEq)]
+pub struct Example {
+    pub input: String,
+    pub output: String,
+}
+
+impl Example {
+    pub fn new(input: impl Into<String>, output: impl Into<String>) -> Self {
+        Self { input: input.into(), output: output.into() }
+    }
+}
+
+/// Evaluation semantics for a spec: run `program` on `input` and render the result.
+pub trait Evaluator {
+    fn eval(&self, program: &ASTNode, input: &str) -> Result<String, String>;
+}
+
+/// How consistent programs are ordered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RankBy {
+    /// Fewest tokens first
+    #[default]
+    Size,
+    /// Highest mean `Ranker` score per token first
+    Ranker,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PbeConfig {
+    /// Search limits; `max_count` is the number of consistent programs to return
+    pub search: EnumerateConfig,
+    pub rank_by: RankBy,
+}
+
+/// A program consistent with all examples
+#[derive(Debug, Clone)]
+pub struct Solution {
+    pub source: String,
+    pub ast: ASTNode,
+    /// Number of tokens
+    pub size: usize,
+    /// Mean ranker score (0 when ranking by size)
+    pub score: f32,
+}
+
+/// Search for programs consistent with every example
+pub fn synthesize(
+    grammar: &Grammar,
+    goal: Option<BoundType>,
+    examples: &[Example],
+    evaluator: &dyn Evaluator,
+    ranker: &dyn Ranker,
+    config: &PbeConfig,
+) -> Result<Vec<Solution>, String> {
+    let mut search = config.search.clone();
+    // Constants appearing in the examples are good candidates for literals
+    for ex in examples {
+        for word in ex.input.split_whitespace().chain(ex.output.split_whitespace()) {
+            if !search.names.iter().any(|n| n == word) {
+                search.names.push(word.to_string());
+            }
+        }
+    }
+    let wanted = search.max_count;
+    search.max_count = usize::MAX;
+    let candidates = match goal {
+        Some(goal) => Enumerator::new(grammar, goal, search)?,
+        None => Enumerator::untyped(grammar, search)?,
+    };
+
+    let mut solutions = Vec::new();
+    for (source, ast) in candidates {
+        // Candidates arrive smallest first, so size ranking can stop early
+        if config.rank_by == RankBy::Size && solutions.len() >= wanted { break; }
+        let consistent = examples
+            .iter()
+            .all(|ex| evaluator.eval(&ast, &ex.input).is_ok_and(|out| out == ex.output));
+        if !consistent { continue; }
+        crate::debug_debug!("synthesizer", "pbe: consistent candidate {}", source);
+        let tokens = extract_terminals(&ast);
+        let score = match config.rank_by {
+            RankBy::Size => 0.0,
+            RankBy::Ranker => score_tokens(grammar, ranker, &tokens),
+        };
+        solutions.push(Solution { source, ast, size: tokens.len(), score });
+    }
+
+    match config.rank_by {
+        RankBy::Size => solutions.sort_by_key(|s| s.size),
+        RankBy::Ranker => solutions.sort_by(|a, b| b.score.total_cmp(&a.score)),
+    }
+    solutions.truncate(wanted);
+    Ok(solutions)
+}
+
+/// Mean score the ranker gives each token after the program prefix before it
+fn score_tokens(grammar: &Grammar, ranker: &dyn Ranker, tokens: &[String]) -> f32 {
+    if tokens.is_empty() { return 0.0; }
+    let total: f32 = (0..tokens.len())
+        .map(|i| {
+            let prefix = grammar.render_tokens(&tokens[..i]);
+            ranker
+                .rank(&prefix)
+                .into_iter()
+                .find(|(tok, _)| *tok == tokens[i])
+                .map_or(0.0, |(_, s)| s)
+        })
+        .sum();
+    total / tokens.len() as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::tests::Prefer;
+    use std::collections::HashMap;
+
+    const ARITH_SPEC: &str = r#"
+    Number ::= /[0-9]+/
+    Input ::= 'x'
+    Atom ::= Number | Input | '(' Expr ')'
+    Expr ::= Atom '+' Expr | Atom '*' Expr | Atom
+    "#;
+
+    /// Integer arithmetic where `x` is the example input
+    struct Arith;
+
+    impl Arith {
+        fn value(&self, node: &ASTNode, x: i64) -> Result<i64, String> {
+            let ASTNode::Nonterminal(nt) = node else {
+                let t = node.as_terminal().unwrap();
+                return if t.value == "x" { Ok(x) } else { t.value.parse().map_err(|_| format!("bad literal {}", t.value)) };
+            };
+            let kids = &nt.children;
+            match kids.as_slice() {
+                [a, op, b] if op.value() == "+" => Ok(self.value(a, x)? + self.value(b, x)?),
+                [a, op, b] if op.value() == "*" => Ok(self.value(a, x)? * self.value(b, x)?),
+                [open, e, _] if open.value() == "(" => self.value(e, x),
+                [single] => self.value(single, x),
+                _ => Err(format!("cannot evaluate {}", nt.value)),
+            }
+        }
+    }
+
+    impl Evaluator for Arith {
+        fn eval(&self, program: &ASTNode, input: &str) -> Result<String, String> {
+            let x = input.parse().map_err(|_| "input must be an integer".to_string())?;
+            self.value(program, x).map(|v| v.to_string())
+        }
+    }
+
+    fn examples() -> Vec<Example> {
+        vec![Example::new("1", "3"), Example::new("2", "5"), Example::new("5", "11")]
+    }
+
+    #[test]
+    fn finds_smallest_consistent_program() {
+        let grammar = Grammar::load(ARITH_SPEC).unwrap();
+        let config = PbeConfig { search: EnumerateConfig { max_size: 5, max_count: 2, ..Default::default() }, rank_by: RankBy::Size };
+        let found = synthesize(&grammar, None, &examples(), &Arith, &Prefer(vec![]), &config).unwrap();
+        assert!(!found.is_empty());
+        assert_eq!(found[0].size, 5);
+        for sol in &found {
+            for ex in examples() {
+                assert_eq!(Arith.eval(&sol.ast, &ex.input).unwrap(), ex.output, "{}", sol.source);
+            }
+        }
+    }
+
+    #[test]
+    fn ranker_orders_solutions() {
+        let grammar = Grammar::load(ARITH_SPEC).unwrap();
+        let config = PbeConfig { search: EnumerateConfig { max_size: 3, max_count: 5, ..Default::default() }, rank_by: RankBy::Ranker };
+        let doubled = [Example::new("1", "2"), Example::new("3", "6")];
+        let ranker = Prefer(vec!["x", "+", "*", "2"]);
+        let found = synthesize(&grammar, None, &doubled, &Arith, &ranker, &config).unwrap();
+        let sources: Vec<&str> = found.iter().map(|s| s.source.as_str()).collect();
+        assert_eq!(sources.first(), Some(&"x+x"), "got {:?}", sources);
+        let scores: HashMap<&str, f32> = found.iter().map(|s| (s.source.as_str(), s.score)).collect();
+        assert!(scores["x+x"] > scores["2*x"]);
+    }
+
+    #[test]
+    fn ranker_can_prefer_a_larger_program() {
+        let grammar = Grammar::load(ARITH_SPEC).unwrap();
+        let config = PbeConfig { search: EnumerateConfig { max_size: 5, max_count: 1, ..Default::default() }, rank_by: RankBy::Ranker };
+        let doubled = [Example::new("1", "2"), Example::new("3", "6")];
+        let ranker = Prefer(vec!["(", ")", "x", "+"]);
+        let found = synthesize(&grammar, None, &doubled, &Arith, &ranker, &config).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].size, 5, "got {}", found[0].source);
+    }
+
+    #[test]
+    fn no_solution_within_limits() {
+        let grammar = Grammar::load(ARITH_SPEC).unwrap();
+        let config = PbeConfig { search: EnumerateConfig { max_size: 1, ..Default::default() }, rank_by: RankBy::Size };
+        let found = synthesize(&grammar, None, &examples(), &Arith, &Prefer(vec![]), &config).unwrap();
+        assert!(found.is_empty());
+    }
+}
@@ -0,0 +1,323 @@
+// Ranker backed by an external process speaking line-delimited JSON over stdio.
+//
+// Protocol (one JSON object per line, one response line per request):
+//   -> {"op":"vocab"}                                  <- {"vocab":["x","λ",...]}
+//   -> {"op":"rank","prefix":"λx","allowed":[":"]}     <- {"scores":[[":",0.9],...]}
+// `allowed` is omitted for unconstrained ranking. Any response may instead be
+// {"error":"message"}. A worker that times out, exits or answers garbage is killed,
+// along with anything it started, and respawned, up to `max_restarts` times over the ranker's lifetime. Once those are
+// spent, every later request fails straight away with the last error.
+
+use std::cell::{Cell, RefCell};
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::rank::Ranker;
+
+#[derive(Debug, Clone)]
+pub struct ProcessConfig {
+    pub program: String,
+    pub args: Vec<String>,
+    /// How long to wait for each response line
+    pub timeout: Duration,
+    /// Respawns allowed after the first start
+    pub max_restarts: usize,
+}
+
+impl ProcessConfig {
+    pub fn new(program: impl Into<String>) -> Self {
+        Self { program: program.into(), args: Vec::new(), timeout: Duration::from_secs(5), max_restarts: 3 }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum Request<'a> {
+    Vocab,
+    Rank {
+        prefix: &'a str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        allowed: Option<&'a [String]>,
+    },
+}
+
+#[derive(Deserialize)]
+struct Response {
+    #[serde(default)]
+    vocab: Option<Vec<String>>,
+    #[serde(default)]
+    scores: Option<Vec<(String, f32)>>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// A running model process
+struct Worker {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+}
+
+impl Worker {
+    fn spawn(config: &ProcessConfig) -> Result<Self, String> {
+        let mut command = Command::new(&config.program);
+        command.args(&config.args).stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::inherit());
+        // In its own process group, so killing the worker also reaches its children
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut command, 0);
+        let mut child = command
+            .spawn()
+            .map_err(|e| format!("failed to start '{}': {}", config.program, e))?;
+        let stdin = child.stdin.take().ok_or("child has no stdin")?;
+        let stdout = child.stdout.take().ok_or("child has no stdout")?;
+        let (tx, lines) = mpsc::channel();
+        // Reader thread: ends when the process closes stdout or the receiver is dropped
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if tx.send(line).is_err() { break; }
+            }
+        });
+        Ok(Self { child, stdin, lines })
+    }
+
+    fn call(&mut self, request: &str, timeout: Duration) -> Result<String, String> {
+        writeln!(self.stdin, "{}", request)
+            .and_then(|_| self.stdin.flush())
+            .map_err(|e| format!("failed to write request: {}", e))?;
+        self.lines.recv_timeout(timeout).map_err(|e| match e {
+            mpsc::RecvTimeoutError::Timeout => format!("no response within {:?}", timeout),
+            mpsc::RecvTimeoutError::Disconnected => "process exited".to_string(),
+        })
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        // A child left behind (e.g. the `sleep` of a shell script) would keep stdout
+        // open and outlive the ranker
+        #[cfg(unix)]
+        // SAFETY: kill has no memory-safety preconditions; the group id is the worker's pid
+        unsafe {
+            libc::kill(-(self.child.id() as libc::pid_t), libc::SIGKILL);
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+pub struct ProcessRanker {
+    config: ProcessConfig,
+    worker: RefCell<Option<Worker>>,
+    restarts: Cell<usize>,
+    /// Set once the restart budget is spent; no worker is started after that
+    failed: RefCell<Option<String>>,
+    vocab: RefCell<Option<Vec<String>>>,
+}
+
+impl ProcessRanker {
+    /// The process is started lazily, on the first request.
+    pub fn new(config: ProcessConfig) -> Self {
+        Self { config, worker: RefCell::new(None), restarts: Cell::new(0), failed: RefCell::new(None), vocab: RefCell::new(None) }
+    }
+
+    /// Number of times the worker had to be respawned
+    pub fn restarts(&self) -> usize {
+        self.restarts.get()
+    }
+
+    /// Ask the process for its vocabulary (cached after the first success)
+    pub fn try_vocab(&self) -> Result<Vec<String>, String> {
+        if let Some(v) = self.vocab.borrow().as_ref() {
+            return Ok(v.clone());
+        }
+        let response = self.request(&Request::Vocab)?;
+        if let Some(msg) = response.error { return Err(msg); }
+        let vocab = response.vocab.ok_or("response has no \"vocab\" field")?;
+        *self.vocab.borrow_mut() = Some(vocab.clone());
+        Ok(vocab)
+    }
+
+    /// Ask the process to score continuations of `prefix`, optionally only `allowed` ones
+    pub fn try_rank(&self, prefix: &str, allowed: Option<&[String]>) -> Result<Vec<(String, f32)>, String> {
+        let response = self.request(&Request::Rank { prefix, allowed })?;
+        // A well-formed error is the model's answer, not a broken worker
+        if let Some(msg) = response.error { return Err(msg); }
+        let mut scores = response.scores.ok_or("response has no \"scores\" field")?;
+        if let Some(allowed) = allowed {
+            scores.retain(|(tok, _)| allowed.contains(tok));
+        }
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+        Ok(scores)
+    }
+
+    /// Send one request, respawning the worker and retrying while restarts remain
+    fn request(&self, request: &Request) -> Result<Response, String> {
+        if let Some(e) = self.failed.borrow().as_ref() {
+            return Err(e.clone());
+        }
+        let line = serde_json::to_string(request).map_err(|e| e.to_string())?;
+        loop {
+            match self.call_once(&line) {
+                Ok(response) => return Ok(response),
+                Err(e) => {
+                    // Whatever state the worker is in, it cannot be trusted any more
+                    self.worker.borrow_mut().take();
+                    if self.restarts.get() >= self.config.max_restarts {
+                        let e = format!("ranker process failed: {}", e);
+                        *self.failed.borrow_mut() = Some(e.clone());
+                        return Err(e);
+                    }
+                    self.restarts.set(self.restarts.get() + 1);
+                    crate::debug_warn!("synthesizer", "ranker process failed ({}), restarting", e);
+                }
+            }
+        }
+    }
+
+    fn call_once(&self, line: &str) -> Result<Response, String> {
+        let mut slot = self.worker.borrow_mut();
+        if slot.is_none() {
+            *slot = Some(Worker::spawn(&self.config)?);
+        }
+        let reply = slot.as_mut().unwrap().call(line, self.config.timeout)?;
+        serde_json::from_str(&reply).map_err(|e| format!("invalid response '{}': {}", reply, e))
+    }
+}
+
+impl Ranker for ProcessRanker {
+    fn vocab(&self) -> Vec<String> {
+        self.try_vocab().unwrap_or_else(|e| {
+            crate::debug_error!("synthesizer", "{}", e);
+            Vec::new()
+        })
+    }
+
+    fn rank(&self, input: &str) -> Vec<(String, f32)> {
+        self.try_rank(input, None).unwrap_or_else(|e| {
+            crate::debug_error!("synthesizer", "{}", e);
+            Vec::new()
+        })
+    }
+
+    fn rank_constrained(&self, input: &str, allowed: &[String]) -> Vec<(String, f32)> {
+        self.try_rank(input, Some(allowed)).unwrap_or_else(|e| {
+            crate::debug_error!("synthesizer", "{}", e);
+            Vec::new()
+        })
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Stand-in model script in its own temp dir, removed on drop
+    struct Stub {
+        dir: PathBuf,
+    }
+
+    impl Stub {
+        fn path(&self) -> PathBuf {
+            self.dir.join("model.sh")
+        }
+    }
+
+    impl Drop for Stub {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn stub(name: &str, body: &str) -> Stub {
+        let dir = std::env::temp_dir().join(format!("beam-process-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let stub = Stub { dir };
+        std::fs::write(stub.path(), body).unwrap();
+        stub
+    }
+
+    fn spawn_ranker(script: &Stub, timeout_ms: u64, max_restarts: usize) -> ProcessRanker {
+        let mut config = ProcessConfig::new("sh");
+        config.args = vec![script.path().to_string_lossy().into_owned()];
+        config.timeout = Duration::from_millis(timeout_ms);
+        config.max_restarts = max_restarts;
+        ProcessRanker::new(config)
+    }
+
+    const ECHO_MODEL: &str = r#"
+while IFS= read -r line; do
+  case "$line" in
+    *'"op":"vocab"'*) echo '{"vocab":["x","y","λ"]}' ;;
+    *'"allowed"'*) echo '{"scores":[["x",0.2],["y",0.7],["λ",0.1]]}' ;;
+    *'"prefix":"bad"'*) echo '{"error":"unknown prefix"}' ;;
+    *) echo '{"scores":[["x",0.6],["y",0.3],["λ",0.1]]}' ;;
+  esac
+done
+"#;
+
+    #[test]
+    fn speaks_the_protocol() {
+        let script = stub("echo", ECHO_MODEL);
+        let ranker = spawn_ranker(&script, 2000, 0);
+        assert_eq!(ranker.vocab(), vec!["x", "y", "λ"]);
+        let ranked = ranker.rank("λ");
+        assert_eq!(ranked[0], ("x".to_string(), 0.6));
+        let allowed = vec!["y".to_string(), "λ".to_string()];
+        let constrained = ranker.rank_constrained("λ", &allowed);
+        assert_eq!(constrained, vec![("y".to_string(), 0.7), ("λ".to_string(), 0.1)]);
+        assert_eq!(ranker.try_rank("bad", None), Err("unknown prefix".to_string()));
+        // A model-level error does not count as a crash
+        assert_eq!(ranker.restarts(), 0);
+    }
+
+    #[test]
+    fn restarts_after_timeout_and_exit() {
+        // Every process logs its start, reads one request and sleeps past the timeout
+        // without answering; each timeout costs a restart until the budget of 2 runs out
+        let script = stub("flaky", r#"
+echo started >> "$(dirname "$0")/starts"
+read -r line
+sleep 5
+"#);
+        let starts = || std::fs::read_to_string(script.dir.join("starts")).unwrap().lines().count();
+        let ranker = spawn_ranker(&script, 100, 2);
+        assert!(ranker.try_vocab().is_err());
+        assert_eq!(ranker.restarts(), 2);
+        assert_eq!(starts(), 3);
+        // With the budget spent, later calls fail without starting another process
+        assert!(ranker.try_rank("", None).unwrap_err().contains("no response"));
+        assert_eq!(starts(), 3);
+
+        let script = stub("once", r#"
+read -r line
+echo '{"scores":[["x",1.0]]}'
+"#);
+        let ranker = spawn_ranker(&script, 2000, 1);
+        assert_eq!(ranker.rank("").len(), 1);
+        // The process exited after its only answer; the next call respawns it
+        assert_eq!(ranker.rank("").len(), 1);
+        assert_eq!(ranker.restarts(), 1);
+    }
+
+    #[test]
+    fn killing_a_worker_kills_what_it_started() {
+        // The script answers nothing and leaves a background job that would write a
+        // marker file after the worker has already been given up on
+        let script = stub("orphan", r#"
+read -r line
+(sleep 1; echo alive > "$(dirname "$0")/alive") &
+wait
+"#);
+        let ranker = spawn_ranker(&script, 100, 0);
+        assert!(ranker.try_vocab().is_err());
+        thread::sleep(Duration::from_millis(1500));
+        assert!(!script.dir.join("alive").exists());
+    }
+}
@@ -3,6 +3,12 @@
 pub trait Ranker {
     fn vocab(&self) -> Vec<String>;
     fn rank(&self, input: &str) -> Vec<(String, f32)>;
+
+    /// Rank only the given continuations (e.g. the ones a grammar mask allows).
+    /// The default filters `rank`; rankers that can do better should override it.
+    fn rank_constrained(&self, input: &str, allowed: &[String]) -> Vec<(String, f32)> {
+        self.rank(input).into_iter().filter(|(tok, _)| allowed.contains(tok)).collect()
+    }
 }
 
 // default ranker thats random with a vocab of all ascii chars + some stuff
@@ -0,0 +1,218 @@
+// Test-case reduction for programs that fail to parse or type check.
+//
+// The failure is classified once (parse error, desugaring error, type error or panic,
+// with the message normalized as in the fuzzer) and every candidate must reproduce the
+// same kind. Candidates are desugared before checking, as `logic check` does.
+// Passes, repeated until none makes progress:
+// - drop the tokens of a whole nonterminal (e.g. one item of `Stmt*`);
+// - hoist a smaller descendant of the same nonterminal into its place;
+// - replace a nonterminal with one of its smallest derivations;
+// - ddmin over the remaining tokens.
+// The grammar-aware passes need an AST, so they only apply once the input parses;
+// parse errors are reduced by the token pass alone.
+
+use crate::logic::ast::ASTNode;
+use crate::logic::check::TypeChecker;
+use crate::logic::grammar::Grammar;
+use crate::logic::grammar::desugar::SourceError;
+use crate::logic::parser::Parser;
+use crate::logic::tokenizer::Tokenizer;
+use super::enumerate::{EnumerateConfig, Enumerator};
+use super::minimize::{ddmin, guarded, normalize, token_ranges, tokenize};
+
+/// Smallest derivations tried per nonterminal
+const REPLACEMENTS: usize = 3;
+/// Largest replacement enumerated, in tokens
+const MAX_REPLACEMENT: usize = 6;
+
+/// What went wrong, with names and positions stripped from the message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FailureKind {
+    Parse(String),
+    Desugar(String),
+    Type(String),
+    Panic(String),
+}
+
+impl std::fmt::Display for FailureKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FailureKind::Parse(m) => write!(f, "parse error: {}", m),
+            FailureKind::Desugar(m) => write!(f, "desugar error: {}", m),
+            FailureKind::Type(m) => write!(f, "type error: {}", m),
+            FailureKind::Panic(m) => write!(f, "panic: {}", m),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Reduction {
+    pub kind: FailureKind,
+    pub original: String,
+    pub reduced: String,
+    /// Candidates run through the parser and checker
+    pub tests: usize,
+}
+
+pub struct Reducer {
+    grammar: Grammar,
+    parser: Parser,
+    tokenizer: Tokenizer,
+    tests: usize,
+}
+
+impl Reducer {
+    pub fn new(grammar: &Grammar) -> Self {
+        Self {
+            grammar: grammar.clone(),
+            parser: Parser::new(grammar.clone()),
+            tokenizer: grammar.tokenizer(),
+            tests: 0,
+        }
+    }
+
+    /// How `input` fails, or `None` if it parses, desugars and type checks
+    pub fn classify(&mut self, input: &str) -> Option<FailureKind> {
+        self.tests += 1;
+        let ast = match guarded(|| self.parser.parse(input)) {
+            Err(msg) => return Some(self.panicked(&msg, input)),
+            Ok(Err(e)) => return Some(FailureKind::Parse(normalize(&e, input))),
+            Ok(Ok(ast)) => ast,
+        };
+        match guarded(|| self.grammar.check_parsed(&ast, &mut TypeChecker::new())) {
+            Err(msg) => Some(self.panicked(&msg, input)),
+            Ok(Err(SourceError::Desugar(e))) => Some(FailureKind::Desugar(normalize(&e, input))),
+            Ok(Err(SourceError::Type(e) | SourceError::Parse(e))) => Some(FailureKind::Type(normalize(&e, input))),
+            Ok(Ok(_)) => None,
+        }
+    }
+
+    fn panicked(&mut self, msg: &str, input: &str) -> FailureKind {
+        self.parser = Parser::new(self.grammar.clone());
+        FailureKind::Panic(normalize(msg, input))
+    }
+
+    /// Shrink `input` while it keeps failing the same way
+    pub fn reduce(&mut self, input: &str) -> Result<Reduction, String> {
+        self.tests = 0;
+        let kind = self.classify(input).ok_or("input parses and type checks; nothing to reduce")?;
+        let mut tokens = tokenize(&mut self.tokenizer, input).ok_or("input does not tokenize")?;
+        let original = tokens.len();
+        loop {
+            let before = tokens.len();
+            tokens = self.reduce_tree(tokens, &kind);
+            tokens = ddmin(tokens, |t| {
+                let candidate = self.grammar.render_tokens(t);
+                self.classify(&candidate).as_ref() == Some(&kind)
+            });
+            if tokens.len() >= before { break; }
+        }
+        crate::debug_info!("synthesizer", "reduce: {} -> {} tokens in {} tests", original, tokens.len(), self.tests);
+        Ok(Reduction { kind, original: input.to_string(), reduced: self.grammar.render_tokens(&tokens), tests: self.tests })
+    }
+
+    /// Grammar-aware passes; each accepted change restarts from the new AST
+    fn reduce_tree(&mut self, mut tokens: Vec<String>, kind: &FailureKind) -> Vec<String> {
+        'outer: loop {
+            let source = self.grammar.render_tokens(&tokens);
+            let Ok(ast) = self.parser.parse(&source) else { return tokens };
+            let mut nodes = Vec::new();
+            token_ranges(&ast, 0, &mut nodes);
+            // Largest subtrees first: removing them saves the most
+            nodes.sort_by_key(|(_, s, e)| std::cmp::Reverse(e - s));
+
+            for (nt, start, end) in &nodes {
+                let mut candidates: Vec<Vec<String>> = vec![Vec::new()];
+                candidates.extend(hoistable(&ast, nt, *start, *end).into_iter().map(|(s, e)| tokens[s..e].to_vec()));
+                candidates.extend(self.smallest_derivations(nt, end - start));
+                for replacement in candidates {
+                    if replacement.len() >= end - start { continue; }
+                    let mut candidate = tokens[..*start].to_vec();
+                    candidate.extend(replacement);
+                    candidate.extend_from_slice(&tokens[*end..]);
+                    if self.classify(&self.grammar.render_tokens(&candidate)).as_ref() == Some(kind) {
+                        tokens = candidate;
+                        continue 'outer;
+                    }
+                }
+            }
+            return tokens;
+        }
+    }
+
+    /// A few of the smallest sentences `nt` derives, shorter than `limit` tokens
+    fn smallest_derivations(&self, nt: &str, limit: usize) -> Vec<Vec<String>> {
+        if limit <= 1 { return Vec::new(); }
+        let mut grammar = self.grammar.clone();
+        grammar.set_start(nt.to_string());
+        let config = EnumerateConfig { max_size: (limit - 1).min(MAX_REPLACEMENT), max_count: REPLACEMENTS, ..Default::default() };
+        let Ok(enumerator) = Enumerator::untyped(&grammar, config) else { return Vec::new() };
+        let mut tokenizer = grammar.tokenizer();
+        enumerator.filter_map(|(source, _)| tokenize(&mut tokenizer, &source)).collect()
+    }
+}
+
+/// Token ranges of strict descendants of the node at `start..end` labelled `nt`
+fn hoistable(ast: &ASTNode, nt: &str, start: usize, end: usize) -> Vec<(usize, usize)> {
+    let mut nodes = Vec::new();
+    token_ranges(ast, 0, &mut nodes);
+    nodes
+        .into_iter()
+        .filter(|(v, s, e)| v == nt && *s >= start && *e <= end && (*s, *e) != (start, end) && e > s)
+        .map(|(_, s, e)| (s, e))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::grammar::tests::{let_spec, STLC_SPEC};
+
+    #[test]
+    fn reduces_type_error_to_the_culprit() {
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        let mut reducer = Reducer::new(&grammar);
+        let input = "λf:a->a.λg:b->b.λh:c->c.y";
+        let kind = reducer.classify(input).unwrap();
+        assert!(matches!(kind, FailureKind::Type(_)), "{}", kind);
+        let reduction = reducer.reduce(input).unwrap();
+        assert_eq!(reduction.kind, kind);
+        assert_eq!(reducer.classify(&reduction.reduced), Some(kind));
+        assert!(reduction.reduced.len() < input.len() / 2, "{}", reduction.reduced);
+    }
+
+    #[test]
+    fn reduces_parse_errors_and_rejects_passing_input() {
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        let mut reducer = Reducer::new(&grammar);
+        let input = "λf:a->a.λg:b->b.f )";
+        let reduction = reducer.reduce(input).unwrap();
+        assert!(matches!(reduction.kind, FailureKind::Parse(_)));
+        assert!(reduction.reduced.len() < input.len());
+        assert_eq!(reducer.classify(&reduction.reduced), Some(reduction.kind));
+
+        assert!(reducer.classify("λx:a->a.x").is_none());
+        assert!(reducer.reduce("λx:a->a.x").is_err());
+    }
+
+    #[test]
+    fn checks_the_desugared_program() {
+        let mut reducer = Reducer::new(&Grammar::load(&let_spec()).unwrap());
+        // `g` is only in scope once the `let` becomes a lambda
+        assert_eq!(reducer.classify("λz:a->a.let g : a->a = z in g"), None);
+        assert!(matches!(reducer.classify("λz:a->a.let g : a->a = z in g z"), Some(FailureKind::Type(_))));
+    }
+
+    #[test]
+    fn keeps_desugaring_failures_apart() {
+        let mut reducer = Reducer::new(&Grammar::load(&let_spec()).unwrap());
+        // the lambda rule cannot bind a parameter annotated with a bare base type
+        let input = "let g : a = λy:a->a.(y) in (g)";
+        let kind = reducer.classify(input).unwrap();
+        assert!(matches!(kind, FailureKind::Desugar(_)), "{}", kind);
+        let reduction = reducer.reduce(input).unwrap();
+        assert_eq!(reduction.kind, kind);
+        assert!(reduction.reduced.starts_with("let"), "{}", reduction.reduced);
+        assert!(reduction.reduced.len() < input.len(), "{}", reduction.reduced);
+    }
+}
@@ -2,7 +2,15 @@ use crate::logic::ast::ASTNode;
 use crate::logic::check::TypeChecker;
 use crate::logic::grammar::Grammar;
 use crate::logic::parser::Parser;
+use crate::logic::bind::BoundType;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashSet};
+#[cfg(feature = "json")]
+use std::path::Path;
+use std::rc::Rc;
 use super::rank::{Ranker, DefaultRanker};
+use super::mask::GrammarMask;
+use super::pbe::{self, Evaluator, Example, PbeConfig, Solution};
 
 /// The main synthesizer that performs partial parsing and caches results
 pub struct Synthesizer {
@@ -10,9 +18,10 @@ pub struct Synthesizer {
     pub(crate) parser: Parser,
     pub(crate) type_checker: TypeChecker,
     pub(crate) code: String,
-    pub(crate) ranker: Box<dyn Ranker>,
+    pub(crate) ranker: Rc<dyn Ranker>,
+    pub(crate) mask: GrammarMask,
     pub(crate) cache: Option<ASTNode>,
-    pub(crate) remaining_tokens: Vec<String>, // tokens not yet consumed (for future synthesis expansion)
+    pub(crate) history: Vec<SynthesizerState>, // undo stack, most recent last
 }
 
 // I wanted to do an album with the sounds of the '50s
@@ -25,17 +34,113 @@ impl Synthesizer {
         let grammar = Grammar::load(grammar_spec)?;
         let parser = Parser::new(grammar.clone());
         let type_checker = TypeChecker::new();
+        let mask = GrammarMask::new(&grammar)?;
         Ok(Self { 
             grammar, 
             parser, 
             type_checker, 
             code: String::new(), 
             cache: None, 
-            ranker,
-            remaining_tokens: Vec::new() 
+            ranker: Rc::from(ranker),
+            mask,
+            history: Vec::new(),
         })
     }
 
+    /// Code written so far
+    pub fn code(&self) -> &str { &self.code }
+
+    /// AST of the code so far, if it is a complete program
+    pub fn ast(&self) -> Option<&ASTNode> { self.cache.as_ref() }
+
+    /// Append `token` to the code. Fails (leaving the state untouched) if no sentence
+    /// of the grammar starts with the result.
+    pub fn step(&mut self, token: &str) -> Result<(), String> {
+        let code = format!("{}{}", self.code, token);
+        if !self.mask.is_viable(&code) {
+            return Err(format!("'{}' cannot continue '{}'", token, self.code));
+        }
+        self.history.push(self.checkpoint());
+        self.cache = self.parser.parse(&code).ok();
+        self.code = code;
+        crate::debug_debug!("synthesizer", "step: '{}'", self.code);
+        Ok(())
+    }
+
+    /// Back up one step. Returns false if there is nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        let Some(state) = self.history.pop() else { return false };
+        self.code = state.code;
+        self.cache = state.cache;
+        true
+    }
+
+    /// Snapshot of the current state
+    pub fn checkpoint(&self) -> SynthesizerState {
+        SynthesizerState {
+            code: self.code.clone(),
+            cache: self.cache.clone(),
+        }
+    }
+
+    /// Jump to a saved state. The current state goes on the undo stack; a missing
+    /// cache (e.g. from a state loaded from disk) is re-derived by parsing.
+    pub fn restore(&mut self, state: SynthesizerState) -> Result<(), String> {
+        if !state.code.is_empty() && !self.mask.is_viable(&state.code) {
+            return Err(format!("saved code '{}' is not valid for this grammar", state.code));
+        }
+        self.history.push(self.checkpoint());
+        self.cache = match state.cache {
+            Some(ast) => Some(ast),
+            None => self.parser.parse(&state.code).ok(),
+        };
+        self.code = state.code;
+        Ok(())
+    }
+
+    /// Independent copy of this synthesizer (sharing the ranker), to explore another path
+    pub fn branch(&self) -> Result<Self, String> {
+        Ok(Self {
+            grammar: self.grammar.clone(),
+            parser: Parser::new(self.grammar.clone()),
+            type_checker: TypeChecker::new(),
+            code: self.code.clone(),
+            ranker: Rc::clone(&self.ranker),
+            mask: GrammarMask::new(&self.grammar)?,
+            cache: self.cache.clone(),
+            history: self.history.clone(),
+        })
+    }
+
+    /// Lazily stream complete programs extending the current code, best ranked first.
+    /// Each continuation is scored by the ranker (restricted to what the grammar allows)
+    /// and the frontier is expanded cheapest-first by summed negative log score.
+    pub fn candidates(&self, config: StreamConfig) -> Candidates<'_> {
+        self.resume(StreamState {
+            frontier: vec![Frontier { cost: 0.0, code: self.code.clone(), steps: 0 }],
+            seen: Vec::new(),
+            expansions: 0,
+            config,
+        })
+    }
+
+    /// Continue a candidate stream from a state saved with `Candidates::state`
+    pub fn resume(&self, state: StreamState) -> Candidates<'_> {
+        let mut vocab = self.ranker.vocab();
+        if vocab.is_empty() {
+            vocab = self.mask.derive_vocab();
+        }
+        Candidates {
+            synth: self,
+            parser: Parser::new(self.grammar.clone()),
+            vocab,
+            frontier: state.frontier.into(),
+            seen: state.seen.into_iter().collect(),
+            expansions: state.expansions,
+            config: state.config,
+        }
+    }
+
     pub fn run(&mut self, input: &str, k:i32) -> Result<String, String> {
         crate::debug_info!("synthesizer", "Running synthesizer on input: {}", input);
         let ranked = self.ranker.rank(input);
@@ -51,13 +156,261 @@ impl Synthesizer {
         Ok(String::new()) // placeholder
     }
 
+    /// Find programs consistent with all `examples`, optionally of type `goal`.
+    /// Ranking by `RankBy::Ranker` uses this synthesizer's ranker.
+    pub fn synthesize_from_examples(
+        &self,
+        goal: Option<BoundType>,
+        examples: &[Example],
+        evaluator: &dyn Evaluator,
+        config: &PbeConfig,
+    ) -> Result<Vec<Solution>, String> {
+        pbe::synthesize(&self.grammar, goal, examples, evaluator, self.ranker.as_ref(), config)
+    }
+
 
 }
 
 /// Represents a saved state of the synthesizer
 #[derive(Debug, Clone)]
-pub(crate) struct SynthesizerState {
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub struct SynthesizerState {
     pub code: String,
+    /// Not saved to disk; `Synthesizer::restore` re-parses the code instead
+    #[cfg_attr(feature = "json", serde(skip))]
     pub cache: Option<ASTNode>,
-    pub remaining_tokens: Vec<String>,
+}
+
+/// Where a candidate stream stands: its frontier, the programs already yielded and the
+/// expansions spent, so `Synthesizer::resume` picks a long search up where it stopped
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub struct StreamState {
+    frontier: Vec<Frontier>,
+    seen: Vec<String>,
+    expansions: usize,
+    config: StreamConfig,
+}
+
+#[cfg(feature = "json")]
+macro_rules! json_file {
+    ($ty:ty, $what:literal) => {
+        impl $ty {
+            pub fn to_json(&self) -> Result<String, String> {
+                serde_json::to_string_pretty(self).map_err(|e| e.to_string())
+            }
+
+            pub fn from_json(json: &str) -> Result<Self, String> {
+                serde_json::from_str(json).map_err(|e| format!(concat!("invalid ", $what, ": {}"), e))
+            }
+
+            pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+                let path = path.as_ref();
+                std::fs::write(path, self.to_json()?).map_err(|e| format!("failed to write {}: {}", path.display(), e))
+            }
+
+            pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+                let path = path.as_ref();
+                let json = std::fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+                Self::from_json(&json)
+            }
+        }
+    };
+}
+
+#[cfg(feature = "json")]
+json_file!(SynthesizerState, "synthesizer state");
+#[cfg(feature = "json")]
+json_file!(StreamState, "candidate stream state");
+
+/// Limits for `Synthesizer::candidates`
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub struct StreamConfig {
+    /// Most continuations appended to the starting code
+    pub max_steps: usize,
+    /// Most frontier entries expanded before the stream ends
+    pub max_expansions: usize,
+    /// Continuations of each entry kept, best first
+    pub beam_width: usize,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        Self { max_steps: 16, max_expansions: 10_000, beam_width: 8 }
+    }
+}
+
+/// Partial program waiting to be expanded
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+struct Frontier {
+    cost: f32,
+    code: String,
+    steps: usize,
+}
+
+impl PartialEq for Frontier {
+    fn eq(&self, other: &Self) -> bool { self.cmp(other) == Ordering::Equal }
+}
+
+impl Eq for Frontier {}
+
+impl PartialOrd for Frontier {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+impl Ord for Frontier {
+    // Reversed so the max-heap pops the cheapest entry; shorter code breaks ties
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost).then_with(|| other.code.len().cmp(&self.code.len()))
+    }
+}
+
+/// Iterator returned by `Synthesizer::candidates`, yielding `(source, ast)` pairs
+pub struct Candidates<'a> {
+    synth: &'a Synthesizer,
+    parser: Parser,
+    vocab: Vec<String>,
+    frontier: BinaryHeap<Frontier>,
+    seen: HashSet<String>,
+    expansions: usize,
+    config: StreamConfig,
+}
+
+impl Candidates<'_> {
+    /// Snapshot of the stream, to continue later with `Synthesizer::resume`
+    pub fn state(&self) -> StreamState {
+        let mut seen: Vec<String> = self.seen.iter().cloned().collect();
+        seen.sort();
+        StreamState {
+            frontier: self.frontier.clone().into_sorted_vec(),
+            seen,
+            expansions: self.expansions,
+            config: self.config,
+        }
+    }
+
+    fn expand(&mut self, entry: &Frontier) {
+        if entry.steps >= self.config.max_steps { return; }
+        let allowed = self.synth.mask.allowed(&entry.code, &self.vocab);
+        let mut ranked = self.synth.ranker.rank_constrained(&entry.code, &allowed);
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+        let trailing_space = entry.code.is_empty() || entry.code.ends_with(char::is_whitespace);
+        for (token, score) in ranked.into_iter().take(self.config.beam_width) {
+            // Runs of whitespace only produce duplicates
+            if trailing_space && token.trim().is_empty() { continue; }
+            self.frontier.push(Frontier {
+                cost: entry.cost - score.max(1e-6).ln(),
+                code: format!("{}{}", entry.code, token),
+                steps: entry.steps + 1,
+            });
+        }
+    }
+}
+
+impl Iterator for Candidates<'_> {
+    type Item = (String, ASTNode);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.expansions < self.config.max_expansions {
+            let entry = self.frontier.pop()?;
+            self.expansions += 1;
+            self.expand(&entry);
+            let source = entry.code.trim().to_string();
+            if source.is_empty() || self.seen.contains(&source) { continue; }
+            if let Ok(ast) = self.parser.parse(&source) {
+                self.seen.insert(source.clone());
+                crate::debug_debug!("synthesizer", "candidate '{}' (cost {:.3})", source, entry.cost);
+                return Some((source, ast));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::tests::Prefer;
+    use crate::logic::grammar::tests::STLC_SPEC;
+
+    fn synth() -> Synthesizer {
+        Synthesizer::new(STLC_SPEC, Box::new(Prefer(vec!["x", "λ", ":", "a", "->", ".", "(", ")"]))).unwrap()
+    }
+
+    #[test]
+    fn step_undo_and_restore() {
+        let mut s = synth();
+        assert!(s.step("λ").is_ok());
+        assert!(s.step(")").is_err());
+        assert_eq!(s.code(), "λ");
+        let saved = s.checkpoint();
+        s.step("x").unwrap();
+        s.step(":").unwrap();
+        assert_eq!(s.code(), "λx:");
+        assert!(s.undo());
+        assert_eq!(s.code(), "λx");
+        s.restore(saved).unwrap();
+        assert_eq!(s.code(), "λ");
+        // Restoring is itself undoable
+        assert!(s.undo());
+        assert_eq!(s.code(), "λx");
+
+        let mut other = s.branch().unwrap();
+        other.step(":").unwrap();
+        assert_eq!(s.code(), "λx");
+        assert_eq!(other.code(), "λx:");
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn state_round_trips_through_json() {
+        let mut s = synth();
+        s.step("x").unwrap();
+        assert!(s.ast().is_some());
+        let path = std::env::temp_dir().join(format!("beam-synth-state-{}.json", std::process::id()));
+        s.checkpoint().save(&path).unwrap();
+        let loaded = SynthesizerState::load(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert!(loaded.cache.is_none());
+
+        let mut fresh = synth();
+        fresh.restore(loaded).unwrap();
+        assert_eq!(fresh.code(), "x");
+        assert_eq!(fresh.ast(), s.ast());
+    }
+
+    #[test]
+    fn candidates_stream_lazily_in_rank_order() {
+        let s = synth();
+        let mut stream = s.candidates(StreamConfig { max_steps: 8, ..Default::default() });
+        let (first, ast) = stream.next().unwrap();
+        assert_eq!(first, "x");
+        assert_eq!(ast.as_nonterminal().unwrap().value, "Term");
+        let more: Vec<String> = stream.take(2).map(|(src, _)| src).collect();
+        assert_eq!(more.len(), 2);
+        assert!(more.iter().all(|src| src != "x"));
+
+        // Streaming continues from the current code
+        let mut s = synth();
+        s.step("(").unwrap();
+        let (first, _) = s.candidates(StreamConfig::default()).next().unwrap();
+        assert_eq!(first, "(x)");
+    }
+
+    #[test]
+    fn candidate_streams_resume_where_they_stopped() {
+        let s = synth();
+        let config = StreamConfig { max_steps: 8, ..Default::default() };
+        let expected: Vec<String> = s.candidates(config).take(4).map(|(src, _)| src).collect();
+
+        let mut stream = s.candidates(config);
+        let first: Vec<String> = stream.by_ref().take(2).map(|(src, _)| src).collect();
+        let state = stream.state();
+        #[cfg(feature = "json")]
+        let state = StreamState::from_json(&state.to_json().unwrap()).unwrap();
+        let rest: Vec<String> = synth().resume(state).take(2).map(|(src, _)| src).collect();
+        assert_eq!([first, rest].concat(), expected);
+    }
 }
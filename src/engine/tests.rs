@@ -0,0 +1,17 @@
+//! Helpers shared by the engine's unit tests
+#![cfg(test)]
+
+use super::rank::Ranker;
+
+/// Ranker over a fixed vocabulary that scores tokens in the given order
+pub(crate) struct Prefer(pub Vec<&'static str>);
+
+impl Ranker for Prefer {
+    fn vocab(&self) -> Vec<String> {
+        self.0.iter().map(|s| s.to_string()).collect()
+    }
+    fn rank(&self, _input: &str) -> Vec<(String, f32)> {
+        let n = self.0.len() as f32;
+        self.0.iter().enumerate().map(|(i, s)| (s.to_string(), (n - i as f32) / (n + 1.0))).collect()
+    }
+}
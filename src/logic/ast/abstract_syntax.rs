@@ -0,0 +1,198 @@
+// Abstract syntax: the parse tree without the nodes only the concrete syntax needs.
+//
+// `%inline` nonterminals and the parser's synthetic wrappers (`<group>`, `Item*`,
+// `Item+`, `Item?`) are replaced by their children, and `%hide` terminals are left
+// out. A spliced node's binding moves onto its single remaining child; where that is
+// not possible the node stays. A node a bound rule references is only spliced into a
+// single nonterminal, which the reference then points to, and every bound rule is
+// renumbered for the new pre-order.
+
+use std::collections::{HashMap, HashSet};
+
+use super::arena::NodeKind;
+use super::visit::{VisitorMut, walk_node_mut};
+use super::{ASTNode, AstArena, NodeId, NonTerminal};
+use crate::logic::grammar::Grammar;
+
+/// Nonterminals the parser creates for groups and repetitions
+fn is_synthetic(name: &str) -> bool {
+    name == "<group>" || name.ends_with(['*', '+', '?'])
+}
+
+/// An abstract node with the old pre-order id of each node in it, in its pre-order
+type Piece = (ASTNode, Vec<usize>);
+
+struct Abstraction<'a> {
+    arena: AstArena,
+    grammar: &'a Grammar,
+    referenced: HashSet<usize>,
+    /// Spliced node -> old id of the node that took its place
+    replaced: HashMap<usize, usize>,
+}
+
+impl Abstraction<'_> {
+    fn build(&mut self, id: NodeId, root: bool) -> Vec<Piece> {
+        let node = &self.arena[id];
+        let keep = self.referenced.contains(&id.0) || node.binding.is_some();
+        if node.kind == NodeKind::Terminal {
+            if self.grammar.is_hidden(&node.value) && !keep {
+                return vec![];
+            }
+            return vec![(self.arena.to_ast(id), vec![id.0])];
+        }
+
+        let pieces: Vec<Piece> = self.arena.children(id).to_vec().into_iter().flat_map(|c| self.build(c, false)).collect();
+        let node = &self.arena[id];
+        let single = match pieces.as_slice() {
+            [(only, _)] => Some(only),
+            _ => None,
+        };
+        let splice = (self.grammar.is_inline(&node.value) || is_synthetic(&node.value))
+            && node.bound_typing_rule.is_none()
+            && (!root || single.is_some())
+            && (node.binding.is_none() || single.is_some_and(|n| n.binding().is_none()))
+            && (!self.referenced.contains(&id.0) || single.is_some_and(|n| matches!(n, ASTNode::Nonterminal(_))));
+        if splice {
+            let mut pieces = pieces;
+            if let [(only, origins)] = pieces.as_mut_slice() {
+                self.replaced.insert(id.0, origins[0]);
+                if node.binding.is_some() {
+                    only.set_binding(node.binding.clone());
+                }
+            }
+            return pieces;
+        }
+
+        let mut origins = vec![id.0];
+        let mut children = Vec::with_capacity(pieces.len());
+        for (child, child_origins) in pieces {
+            children.push(child);
+            origins.extend(child_origins);
+        }
+        let nt = NonTerminal {
+            value: node.value.clone(),
+            span: node.span.clone(),
+            children,
+            binding: node.binding.clone(),
+            bound_typing_rule: node.bound_typing_rule.clone(),
+        };
+        vec![(ASTNode::Nonterminal(nt), origins)]
+    }
+}
+
+/// Rewrites bound rules, whose references still hold old relative ids
+struct Renumber<'a> {
+    origins: &'a [usize],
+    new_of: HashMap<usize, usize>,
+    replaced: &'a HashMap<usize, usize>,
+    next: usize,
+}
+
+impl Renumber<'_> {
+    fn new_id(&self, mut old: usize) -> usize {
+        // a spliced reference was replaced by a single nonterminal, so the chain ends at a kept node
+        while !self.new_of.contains_key(&old) {
+            old = self.replaced[&old];
+        }
+        self.new_of[&old]
+    }
+}
+
+impl VisitorMut for Renumber<'_> {
+    fn visit_node_mut(&mut self, node: &mut ASTNode) {
+        let id = self.next;
+        self.next += 1;
+        if let ASTNode::Nonterminal(nt) = node
+            && let Some(rule) = &nt.bound_typing_rule
+        {
+            let owner = self.origins[id];
+            let rule = rule.map_nodes(|rel| NodeId(self.new_id(owner + rel.0) - id));
+            nt.bound_typing_rule = Some(Box::new(rule));
+        }
+        walk_node_mut(self, node)
+    }
+}
+
+impl ASTNode {
+    /// The abstract tree under the `%inline` and `%hide` declarations of `grammar`,
+    /// without synthetic group and repetition nodes. Bindings and bound rules are kept,
+    /// with rule references renumbered; spans of the remaining nodes are unchanged.
+    pub fn to_abstract(&self, grammar: &Grammar) -> ASTNode {
+        let arena = AstArena::from(self.clone());
+        let mut referenced = HashSet::new();
+        for id in arena.ids() {
+            if let Some(rule) = &arena[id].bound_typing_rule {
+                referenced.extend(rule.referenced_nodes().into_iter().filter_map(|rel| arena.resolve(id, rel)).map(|n| n.0));
+            }
+        }
+        let mut abstraction = Abstraction { arena, grammar, referenced, replaced: HashMap::new() };
+        let root = abstraction.arena.root();
+        let (mut ast, origins) = abstraction.build(root, true).pop().expect("the root is never spliced away");
+
+        let new_of = origins.iter().enumerate().map(|(new, old)| (*old, new)).collect();
+        let mut renumber = Renumber { origins: &origins, new_of, replaced: &abstraction.replaced, next: 0 };
+        renumber.visit_node_mut(&mut ast);
+        ast
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::logic::ast::ASTNode;
+    use crate::logic::bind::BoundType;
+    use crate::logic::check::TypeChecker;
+    use crate::logic::grammar::Grammar;
+    use crate::logic::grammar::tests::STLC_SPEC;
+    use crate::logic::parser::Parser;
+
+    #[test]
+    fn drops_chains_and_punctuation_but_keeps_rules() {
+        let spec = format!("{}\n\n%inline Term BaseTerm Type BaseType TypeName TypedParam\n%hide '(' ')' '.' ':' 'λ'\n", STLC_SPEC);
+        let grammar = Grammar::load(&spec).unwrap();
+        let ast = Parser::new(grammar.clone()).parse("(λx:a->a.x) z").unwrap();
+        let abs = ast.to_abstract(&grammar);
+        assert!(abs.node_count() < ast.node_count());
+
+        // Term -> Application: the root chain collapses, its binding moves down
+        let ASTNode::Nonterminal(app) = &abs else { panic!("expected a nonterminal") };
+        assert_eq!((app.value.as_str(), app.rule_name(), app.binding.as_deref()), ("Application", Some("app"), Some("e")));
+        assert_eq!(app.children.iter().map(|c| c.value()).collect::<Vec<_>>(), vec!["BaseTerm", "Variable"]);
+        // `(λ...)` keeps its BaseTerm[f]: the Lambda below already carries [e] from the inner chain
+        let f = &app.children[0];
+        assert_eq!(f.binding().map(String::as_str), Some("f"));
+        assert_eq!(f.children().unwrap()[0].binding().map(String::as_str), Some("e"));
+        assert_eq!(f.children().unwrap()[0].value(), "Lambda");
+        assert!(abs.terminals().all(|t| !["(", ")", ".", ":", "λ"].contains(&t.value.as_str())));
+
+        // bound rules still point at the right nodes: the abstract tree checks the same
+        let check = |ast: &ASTNode| {
+            let mut tc = TypeChecker::new();
+            tc.add("z".into(), BoundType::Atom("a".into()));
+            tc.check(ast)
+        };
+        assert_eq!(check(&abs), check(&ast));
+        assert_eq!(check(&abs), Ok(Some(BoundType::Atom("a".into()))));
+        for nt in abs.nonterminals() {
+            if let Some(rule) = &nt.bound_typing_rule {
+                assert!(rule.referenced_nodes().iter().all(|rel| nt.descendant(*rel).is_some()));
+            }
+        }
+    }
+
+    #[test]
+    fn without_declarations_only_synthetic_nodes_go() {
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        let ast = Parser::new(grammar.clone()).parse("(λx:a->a.x) z").unwrap();
+        assert_eq!(ast.to_abstract(&grammar), ast);
+    }
+
+    #[test]
+    fn groups_are_spliced_into_their_parent() {
+        let spec = "Item ::= /[a-z]+/\nList ::= Item ( ',' Item )*\n\n%hide ','\n";
+        let grammar = Grammar::load(spec).unwrap();
+        let ast = Parser::new(grammar.clone()).parse("a, b, c").unwrap();
+        assert!(ast.nonterminals().any(|nt| nt.value == "<group>"));
+        let abs = ast.to_abstract(&grammar);
+        assert_eq!(abs.children().unwrap().iter().map(|c| c.value()).collect::<Vec<_>>(), vec!["Item"; 3]);
+    }
+}
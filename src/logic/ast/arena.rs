@@ -0,0 +1,306 @@
+// Arena-backed AST: every node lives in one Vec, addressed by a `NodeId`, with
+// parent links and sibling navigation, so lookups never clone subtrees.
+//
+// Nodes are laid out in pre-order, which makes a subtree a contiguous id range.
+// Bound typing rules use the same numbering relative to the node they are bound
+// to (0 = that node), so a rule reference resolves with `AstArena::resolve` here
+// and with `NonTerminal::descendant` on a plain `ASTNode` tree. Repeated lookups
+// in one tree should use `descendant_sized` with `ASTNode::subtree_sizes`, which
+// skips whole siblings instead of walking every node before the target.
+
+use std::ops::Index;
+
+use super::{ASTNode, NonTerminal, SourceSpan, Terminal, Trivia};
+use crate::logic::bind::BoundTypingRule;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json", serde(transparent))]
+pub struct NodeId(pub usize);
+
+impl std::fmt::Display for NodeId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    Terminal,
+    Nonterminal,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArenaNode {
+    pub kind: NodeKind,
+    pub value: String,
+    pub span: Option<SourceSpan>,
+    pub binding: Option<String>,
+    /// Node ids inside are relative to this node, see `AstArena::resolve`
+    pub bound_typing_rule: Option<Box<BoundTypingRule>>,
+    /// Terminals only, see `Terminal::trivia`
+    pub trivia: Option<Box<Trivia>>,
+    pub parent: Option<NodeId>,
+    pub children: Vec<NodeId>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AstArena {
+    nodes: Vec<ArenaNode>,
+}
+
+impl AstArena {
+    /// The root is always `NodeId(0)`
+    pub fn root(&self) -> NodeId {
+        NodeId(0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn get(&self, id: NodeId) -> Option<&ArenaNode> {
+        self.nodes.get(id.0)
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes[id.0].parent
+    }
+
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        &self.nodes[id.0].children
+    }
+
+    /// Position of `id` among its parent's children
+    pub fn index_in_parent(&self, id: NodeId) -> Option<usize> {
+        let parent = self.parent(id)?;
+        self.children(parent).iter().position(|c| *c == id)
+    }
+
+    pub fn next_sibling(&self, id: NodeId) -> Option<NodeId> {
+        let i = self.index_in_parent(id)?;
+        self.children(self.parent(id)?).get(i + 1).copied()
+    }
+
+    pub fn prev_sibling(&self, id: NodeId) -> Option<NodeId> {
+        let i = self.index_in_parent(id)?.checked_sub(1)?;
+        self.children(self.parent(id)?).get(i).copied()
+    }
+
+    /// Parent, grandparent, ... up to the root
+    pub fn ancestors(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        std::iter::successors(self.parent(id), |p| self.parent(*p))
+    }
+
+    /// `id` and everything below it, in pre-order
+    pub fn subtree(&self, id: NodeId) -> impl Iterator<Item = NodeId> {
+        (id.0..self.subtree_end(id)).map(NodeId)
+    }
+
+    /// Absolute id of a node referenced by the bound rule of `owner`
+    pub fn resolve(&self, owner: NodeId, relative: NodeId) -> Option<NodeId> {
+        let id = NodeId(owner.0 + relative.0);
+        (id.0 < self.subtree_end(owner)).then_some(id)
+    }
+
+    /// Every node id in pre-order
+    pub fn ids(&self) -> impl Iterator<Item = NodeId> {
+        (0..self.nodes.len()).map(NodeId)
+    }
+
+    fn subtree_end(&self, id: NodeId) -> usize {
+        let mut last = id;
+        while let Some(child) = self.children(last).last() {
+            last = *child;
+        }
+        last.0 + 1
+    }
+
+    fn push(&mut self, node: ASTNode, parent: Option<NodeId>) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        let (kind, value, span, binding, rule, trivia, children) = match node {
+            ASTNode::Terminal(t) => (NodeKind::Terminal, t.value, t.span, t.binding, None, t.trivia, vec![]),
+            ASTNode::Nonterminal(nt) => {
+                (NodeKind::Nonterminal, nt.value, nt.span, nt.binding, nt.bound_typing_rule, None, nt.children)
+            }
+        };
+        self.nodes.push(ArenaNode { kind, value, span, binding, bound_typing_rule: rule, trivia, parent, children: vec![] });
+        for child in children {
+            let c = self.push(child, Some(id));
+            self.nodes[id.0].children.push(c);
+        }
+        id
+    }
+
+    /// Rebuild the owned tree rooted at `id`
+    pub fn to_ast(&self, id: NodeId) -> ASTNode {
+        let n = &self.nodes[id.0];
+        match n.kind {
+            NodeKind::Terminal => ASTNode::Terminal(Terminal {
+                value: n.value.clone(),
+                span: n.span.clone(),
+                binding: n.binding.clone(),
+                trivia: n.trivia.clone(),
+            }),
+            NodeKind::Nonterminal => ASTNode::Nonterminal(NonTerminal {
+                value: n.value.clone(),
+                span: n.span.clone(),
+                children: n.children.iter().map(|c| self.to_ast(*c)).collect(),
+                binding: n.binding.clone(),
+                bound_typing_rule: n.bound_typing_rule.clone(),
+            }),
+        }
+    }
+}
+
+impl From<ASTNode> for AstArena {
+    fn from(root: ASTNode) -> Self {
+        let mut arena = AstArena { nodes: Vec::with_capacity(root.node_count()) };
+        arena.push(root, None);
+        arena
+    }
+}
+
+impl From<&AstArena> for ASTNode {
+    fn from(arena: &AstArena) -> Self {
+        arena.to_ast(arena.root())
+    }
+}
+
+impl Index<NodeId> for AstArena {
+    type Output = ArenaNode;
+
+    fn index(&self, id: NodeId) -> &ArenaNode {
+        &self.nodes[id.0]
+    }
+}
+
+impl ASTNode {
+    /// Size of the subtree rooted at each node, indexed by pre-order position (0 = self)
+    pub fn subtree_sizes(&self) -> Vec<usize> {
+        match self {
+            ASTNode::Terminal(_) => vec![1],
+            ASTNode::Nonterminal(nt) => nt.subtree_sizes(),
+        }
+    }
+}
+
+impl NonTerminal {
+    /// See `ASTNode::subtree_sizes`
+    pub fn subtree_sizes(&self) -> Vec<usize> {
+        fn fill(nt: &NonTerminal, sizes: &mut Vec<usize>) -> usize {
+            let at = sizes.len();
+            sizes.push(1);
+            for child in &nt.children {
+                sizes[at] += match child {
+                    ASTNode::Terminal(_) => { sizes.push(1); 1 }
+                    ASTNode::Nonterminal(c) => fill(c, sizes),
+                };
+            }
+            sizes[at]
+        }
+        let mut sizes = Vec::new();
+        fill(self, &mut sizes);
+        sizes
+    }
+
+    /// `descendant`, given this subtree's sizes (see `ASTNode::subtree_sizes`): only
+    /// the children along the path to `id` are visited
+    pub fn descendant_sized(&self, id: NodeId, sizes: &[usize]) -> Option<&NonTerminal> {
+        let (mut node, mut rel, mut at) = (self, id.0, 0);
+        'down: while rel > 0 {
+            let mut offset = 1;
+            for child in &node.children {
+                let size = *sizes.get(at + offset)?;
+                if rel < offset + size {
+                    let ASTNode::Nonterminal(nt) = child else { return None };
+                    (node, rel, at) = (nt, rel - offset, at + offset);
+                    continue 'down;
+                }
+                offset += size;
+            }
+            return None;
+        }
+        Some(node)
+    }
+
+    /// The nonterminal at pre-order position `id` within this subtree (0 = self)
+    pub fn descendant(&self, id: NodeId) -> Option<&NonTerminal> {
+        if id.0 == 0 {
+            return Some(self);
+        }
+        match self.descendants().nth(id.0 - 1)? {
+            ASTNode::Nonterminal(nt) => Some(nt),
+            ASTNode::Terminal(_) => None,
+        }
+    }
+
+    /// Pre-order position of `target` within this subtree, by identity
+    pub fn id_of(&self, target: &NonTerminal) -> Option<NodeId> {
+        if std::ptr::eq(self, target) {
+            return Some(NodeId(0));
+        }
+        self.descendants()
+            .position(|n| matches!(n, ASTNode::Nonterminal(nt) if std::ptr::eq(nt, target)))
+            .map(|i| NodeId(i + 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::grammar::Grammar;
+    use crate::logic::grammar::tests::STLC_SPEC;
+    use crate::logic::parser::Parser;
+
+    #[test]
+    fn round_trip_and_navigation() {
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        let ast = Parser::new(grammar).parse("(λx:a->a.x) y").unwrap();
+        let arena = AstArena::from(ast.clone());
+        assert_eq!(arena.len(), ast.node_count());
+        assert_eq!(ASTNode::from(&arena), ast);
+
+        let root = arena.root();
+        assert_eq!(arena.parent(root), None);
+        for id in arena.ids().skip(1) {
+            let parent = arena.parent(id).unwrap();
+            assert!(arena.children(parent).contains(&id));
+            assert_eq!(arena.ancestors(id).last(), Some(root));
+        }
+
+        let app = arena.ids().find(|id| arena[*id].value == "Application").unwrap();
+        let [f, e] = arena.children(app) else { panic!("app has two children") };
+        assert_eq!(arena.next_sibling(*f), Some(*e));
+        assert_eq!(arena.prev_sibling(*e), Some(*f));
+        assert_eq!(arena.next_sibling(*e), None);
+
+        // bound references resolve to the same node in the tree and in the arena
+        let rule = arena[app].bound_typing_rule.as_ref().unwrap();
+        let tree_app = ast.find_by_rule("app")[0];
+        for rel in rule.referenced_nodes() {
+            let abs = arena.resolve(app, rel).unwrap();
+            assert_eq!(arena.to_ast(abs), tree_app.descendant(rel).unwrap().as_node());
+        }
+        assert!(arena.subtree(app).all(|id| id == app || arena.ancestors(id).any(|a| a == app)));
+    }
+
+    #[test]
+    fn sized_lookup_agrees_with_the_walk() {
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        let ast = Parser::new(grammar).parse("(λx:a->a.(λy:a->a.y) x) (λz:a->a.z)").unwrap();
+        let ASTNode::Nonterminal(root) = &ast else { panic!("root is a nonterminal") };
+        let sizes = ast.subtree_sizes();
+        assert_eq!(sizes.len(), ast.node_count());
+        assert_eq!(sizes[0], ast.node_count());
+        for id in (0..=sizes.len()).map(NodeId) {
+            let walked = root.descendant(id);
+            let sized = root.descendant_sized(id, &sizes);
+            assert_eq!(walked.map(|nt| nt as *const _), sized.map(|nt| nt as *const _), "at {}", id);
+        }
+    }
+}
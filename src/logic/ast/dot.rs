@@ -0,0 +1,86 @@
+// Graphviz DOT export. Node ids are pre-order positions (`n0` is the root), the same
+// numbering as `NodeId`, so ids in bound rules can be matched against the graph.
+
+use super::ASTNode;
+
+pub(crate) const NONTERMINAL_FILL: &str = "#dbe9f6";
+pub(crate) const TERMINAL_FILL: &str = "#fde9c9";
+
+/// Escape `s` for a double-quoted DOT string
+pub(crate) fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Opening lines of a digraph with the shared node style
+pub(crate) fn header(name: &str) -> String {
+    format!("digraph {} {{\n  node [fontname=\"monospace\", style=filled];\n  edge [fontname=\"monospace\"];\n", name)
+}
+
+impl ASTNode {
+    /// The tree as a Graphviz digraph: nonterminals are blue boxes labelled with their
+    /// name, `(rule)` and `[binding]`; terminals are orange ellipses with their text.
+    pub fn to_dot(&self) -> String {
+        let mut out = header("ast");
+        let mut next = 0usize;
+        write_node(self, &mut next, &mut out);
+        out.push_str("}\n");
+        out
+    }
+}
+
+/// Write `node` and its subtree, returning its id
+fn write_node(node: &ASTNode, next: &mut usize, out: &mut String) -> usize {
+    let id = *next;
+    *next += 1;
+    let (mut label, shape, fill) = match node {
+        ASTNode::Nonterminal(nt) => {
+            let mut label = nt.value.clone();
+            if let Some(rule) = nt.rule_name() {
+                label.push_str(&format!("\n({})", rule));
+            }
+            (label, "box", NONTERMINAL_FILL)
+        }
+        ASTNode::Terminal(t) => (format!("'{}'", t.value), "ellipse", TERMINAL_FILL),
+    };
+    if let Some(binding) = node.binding() {
+        label.push_str(&format!("\n[{}]", binding));
+    }
+    out.push_str(&format!("  n{} [label=\"{}\", shape={}, fillcolor=\"{}\"];\n", id, escape(&label), shape, fill));
+    if let ASTNode::Nonterminal(nt) = node {
+        for child in &nt.children {
+            let c = write_node(child, next, out);
+            out.push_str(&format!("  n{} -> n{};\n", id, c));
+        }
+    }
+    id
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::logic::grammar::Grammar;
+    use crate::logic::grammar::tests::STLC_SPEC;
+    use crate::logic::parser::Parser;
+
+    #[test]
+    fn dot_lists_every_node_in_pre_order() {
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        let ast = Parser::new(grammar).parse("(λx:a->a.x) z").unwrap();
+        let dot = ast.to_dot();
+        assert!(dot.starts_with("digraph ast {") && dot.trim_end().ends_with('}'));
+        assert_eq!(dot.matches("[label=").count(), ast.node_count());
+        assert_eq!(dot.matches(" -> ").count(), ast.node_count() - 1);
+        assert!(dot.contains("n0 [label=\"Term\", shape=box"));
+        assert!(dot.contains("Application\\n(app)"));
+        assert!(dot.contains("label=\"'λ'\", shape=ellipse"));
+        assert!(dot.contains("Variable\\n(var)\\n[x]"));
+    }
+}
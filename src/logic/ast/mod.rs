@@ -7,8 +7,18 @@ use std::collections::HashSet;
 pub mod serialize;
 use serialize::*;
 pub mod utils;
+pub mod visit;
+pub mod arena;
+pub use arena::{AstArena, NodeId};
+pub mod trivia;
+pub use trivia::{Trivia, TriviaKind, TriviaPiece};
+pub mod dot;
+pub mod abstract_syntax;
+pub mod rewrite;
+pub mod scope;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
 pub struct SourceSpan {
     pub start: usize,
     pub end: usize,
@@ -16,11 +26,15 @@ pub struct SourceSpan {
 
 /// Nonterminal-specific data from an ASTNode
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
 pub struct NonTerminal {
     pub value: String,
+    #[cfg_attr(feature = "json", serde(default, skip_serializing_if = "Option::is_none"))]
     pub span: Option<SourceSpan>,
     pub children: Vec<ASTNode>,
+    #[cfg_attr(feature = "json", serde(default, skip_serializing_if = "Option::is_none"))]
     pub binding: Option<String>,
+    #[cfg_attr(feature = "json", serde(default, skip_serializing_if = "Option::is_none"))]
     pub bound_typing_rule: Option<Box<BoundTypingRule>>,
 }
 
@@ -36,25 +50,13 @@ impl NonTerminal {
     }
     
     /// Get terminal children of this nonterminal
-    pub fn terminal_children(&self) -> Vec<Terminal> {
-        self.children.iter().filter_map(|c| {
-            if let ASTNode::Terminal(t) = c {
-                Some(t.clone())
-            } else {
-                None
-            }
-        }).collect()
+    pub fn terminal_children(&self) -> impl Iterator<Item = &Terminal> {
+        self.children.iter().filter_map(ASTNode::as_terminal)
     }
     
     /// Get nonterminal children of this nonterminal  
-    pub fn nonterminal_children(&self) -> Vec<NonTerminal> {
-        self.children.iter().filter_map(|c| {
-            if let ASTNode::Nonterminal(nt) = c {
-                Some(nt.clone())
-            } else {
-                None
-            }
-        }).collect()
+    pub fn nonterminal_children(&self) -> impl Iterator<Item = &NonTerminal> {
+        self.children.iter().filter_map(ASTNode::as_nonterminal)
     }
 
     /// Get the binding if present
@@ -69,10 +71,16 @@ impl NonTerminal {
 
 /// Terminal-specific data from an ASTNode
 #[derive(Debug, Clone,PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
 pub struct Terminal {
     pub value: String,
+    #[cfg_attr(feature = "json", serde(default, skip_serializing_if = "Option::is_none"))]
     pub span: Option<SourceSpan>,
+    #[cfg_attr(feature = "json", serde(default, skip_serializing_if = "Option::is_none"))]
     pub binding: Option<String>,
+    /// Surrounding whitespace and comments, only kept by `Parser::with_trivia`
+    #[cfg_attr(feature = "json", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub trivia: Option<Box<Trivia>>,
 }
 
 impl Terminal {
@@ -87,6 +95,8 @@ impl Terminal {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json", serde(tag = "kind", rename_all = "snake_case"))]
 pub enum ASTNode {
     Terminal(Terminal),
     Nonterminal(NonTerminal)
@@ -122,62 +132,34 @@ impl ASTNode {
     }
 
     pub fn rules(&self) -> HashSet<String> {
-        let mut out = HashSet::new();
-        match self {
-            ASTNode::Nonterminal(nt) => {
-                if let Some(r) = &nt.bound_typing_rule {
-                    out.insert(r.name.clone());
-                }
-                for child in &nt.children {
-                    out.extend(child.rules());
-                }
-            }
-            _ => {}
-        }
-        out
+        self.nonterminals()
+            .filter_map(|nt| nt.rule_name().map(str::to_string))
+            .collect()
     }
 
-    pub fn terminal_children(&self) -> Vec<Terminal> {
-        match self {
-            ASTNode::Nonterminal(nt) => {
-                nt.children.iter().filter_map(|c| {
-                    if let ASTNode::Terminal(t) = c {
-                        Some(t.clone())
-                    } else {
-                        None
-                    }
-                }).collect()
-            }
-            _ => vec![],
-        }
+    /// Terminal children, if this is a nonterminal
+    pub fn terminal_children(&self) -> impl Iterator<Item = &Terminal> {
+        self.as_nonterminal().into_iter().flat_map(NonTerminal::terminal_children)
     }
-    
-    pub fn nonterminal_children(&self) -> Vec<NonTerminal> {
-        match self {
-            ASTNode::Nonterminal(nt) => {
-                nt.children.iter().filter_map(|c| {
-                    if let ASTNode::Nonterminal(n) = c {
-                        Some(n.clone())
-                    } else {
-                        None
-                    }
-                }).collect()
-            }
-            _ => vec![],
-        }
-    }    /// Get a reference to this node as a Terminal if it is one
-    pub fn as_terminal(&self) -> Option<Terminal> {
+
+    /// Nonterminal children, if this is a nonterminal
+    pub fn nonterminal_children(&self) -> impl Iterator<Item = &NonTerminal> {
+        self.as_nonterminal().into_iter().flat_map(NonTerminal::nonterminal_children)
+    }
+
+    /// Get a reference to this node as a Terminal if it is one
+    pub fn as_terminal(&self) -> Option<&Terminal> {
         if let ASTNode::Terminal(t) = self {
-            Some(t.clone())
+            Some(t)
         } else {
             None
         }
     }
 
     /// Get a reference to this node as a NonTerminal if it is one
-    pub fn as_nonterminal(&self) -> Option<NonTerminal> {
+    pub fn as_nonterminal(&self) -> Option<&NonTerminal> {
         if let ASTNode::Nonterminal(nt) = self {
-            Some(nt.clone())
+            Some(nt)
         } else {
             None
         }
@@ -235,6 +217,9 @@ impl ASTNode {
                     if let Some(b) = &t.binding {
                         out.push_str(&format!("(b {})", b));
                     }
+                    if let Some(s) = &t.span {
+                        out.push_str(&format!("(s {} {})", s.start, s.end));
+                    }
                     out.push(')');
                 }
                 ASTNode::Nonterminal(nt) => {
@@ -245,6 +230,9 @@ impl ASTNode {
                     if let Some(b) = &nt.binding {
                         out.push_str(&format!("(b {})", b));
                     }
+                    if let Some(s) = &nt.span {
+                        out.push_str(&format!("(s {} {})", s.start, s.end));
+                    }
                     for ch in &nt.children {
                         go(ch, out);
                     }
@@ -301,33 +289,46 @@ impl ASTNode {
         s
     }
 
-    pub fn save<P: AsRef<Path>>(self, path: P) -> io::Result<()> {
-        let rules = self.rules();
-        let mut header = String::new();
-        header.push_str(";!ast 1\n");
-        if !rules.is_empty() {
-            let mut v: Vec<_> = rules.into_iter().collect();
-            v.sort();
-            header.push_str(&format!(";!rules: {}\n", v.join(", ")));
-        }
-        header.push('\n');
-        let body = self.serialize();
-        fs::write(path, format!("{}{}\n", header, body))
+    /// Write the AST file (current format version): headers, then the S-expression body.
+    /// `source` is the text the tree was parsed from, if any, and only its hash is stored.
+    pub fn save<P: AsRef<Path>>(&self, path: P, grammar: &Grammar, source: Option<&str>) -> io::Result<()> {
+        let header = AstHeader::new(self, grammar, source);
+        fs::write(path, format!("{}{}\n", header.to_lines(), self.serialize()))
     }
 
-    /// Parse an AST S-expression with the help of a Grammar (for rule name resolution).
+    /// Parse an AST file's content with the Grammar it was produced with. The header is
+    /// validated against the grammar before the body is read.
     pub fn parse(input: &str, grammar: &Grammar) -> Result<ASTNode, String> {
-        let body = strip_headers(input);
+        let (header, body) = AstHeader::read(input)?;
+        header.validate(grammar)?;
         let sexpr = parse_sexpr(body)?;
+        header.check_rules(&sexpr)?;
         sexpr_to_ast(&sexpr, grammar)
     }
 
-    /// Load an AST from a file that includes headers, resolving rule names with the provided Grammar.
+    /// Load an AST from a file that includes headers, see `parse`.
     pub fn load<P: AsRef<Path>>(path: P, grammar: &Grammar) -> Result<ASTNode, String> {
         let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
         Self::parse(&content, grammar)
     }
 
+    /// Like `parse`, also re-binding the typing rule named by every `(rule X)` marker, so the
+    /// tree type-checks like one fresh from the parser. Fails with one `path: reason` line per
+    /// node that could not be bound.
+    pub fn parse_bound(input: &str, grammar: &Grammar) -> Result<ASTNode, String> {
+        let (header, body) = AstHeader::read(input)?;
+        header.validate(grammar)?;
+        let sexpr = parse_sexpr(body)?;
+        header.check_rules(&sexpr)?;
+        sexpr_to_bound_ast(&sexpr, grammar)
+    }
+
+    /// Like `load`, re-binding typing rules as `parse_bound` does
+    pub fn load_bound<P: AsRef<Path>>(path: P, grammar: &Grammar) -> Result<ASTNode, String> {
+        let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Self::parse_bound(&content, grammar)
+    }
+
     // Syntactic equality
     pub fn syneq(&self, other: &ASTNode) -> bool {
         match (self, other) {
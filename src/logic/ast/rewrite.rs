@@ -0,0 +1,506 @@
+// Tree patterns and rewrite rules, written like the S-expression format.
+//
+// A pattern is an `(N ...)` or `(T ...)` list in which parts may be metavariables:
+// `?E` matches any one node, `?Es...` any run of siblings (at most one per list) and
+// `_` any node without capturing it; `(N _ ...)` matches any nonterminal name. In an
+// `(N ...)` list, `(b x)` gives the binding of the child after it and `(rule r)` the
+// node's typing rule. Whatever a pattern leaves out is not checked.
+//
+// A rule `pattern => template` replaces a match by the template with the captures
+// filled in. Rules apply innermost first until none matches; the result is then
+// re-bound, since a rewrite shifts the `NodeId`s of every rule above it.
+
+use std::collections::HashMap;
+
+use super::serialize::{SExpr, parse_sexpr, rebind, sexpr_atom, sexpr_atom_or_str, sexpr_span};
+use super::visit::{Fold, VisitorMut, fold_children, walk_nonterminal_mut};
+use super::{ASTNode, NonTerminal, SourceSpan, Terminal};
+use crate::logic::grammar::Grammar;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    /// `?name`, or `_` when `name` is `None`
+    Var { name: Option<String>, binding: Option<String> },
+    /// `?name...`
+    Rest(String),
+    Terminal { value: String, binding: Option<String> },
+    /// `name` is `None` for `(N _ ...)`
+    Nonterminal { name: Option<String>, rule: Option<String>, binding: Option<String>, children: Vec<Pattern> },
+}
+
+/// What the metavariables of a pattern matched
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Captures {
+    nodes: HashMap<String, ASTNode>,
+    runs: HashMap<String, Vec<ASTNode>>,
+}
+
+impl Captures {
+    /// The node `?name` matched
+    pub fn node(&self, name: &str) -> Option<&ASTNode> {
+        self.nodes.get(name)
+    }
+
+    /// The siblings `?name...` matched
+    pub fn run(&self, name: &str) -> Option<&[ASTNode]> {
+        self.runs.get(name).map(Vec::as_slice)
+    }
+}
+
+/// Same text and shape; bindings differ when a repeated metavariable fills two slots
+fn same_shape(a: &ASTNode, b: &ASTNode) -> bool {
+    match (a, b) {
+        (ASTNode::Terminal(x), ASTNode::Terminal(y)) => x.value == y.value,
+        (ASTNode::Nonterminal(x), ASTNode::Nonterminal(y)) => {
+            x.value == y.value
+                && x.children.len() == y.children.len()
+                && x.children.iter().zip(&y.children).all(|(a, b)| same_shape(a, b))
+        }
+        _ => false,
+    }
+}
+
+impl Pattern {
+    /// Parse a pattern; a bare `?X` is allowed on its own, e.g. as a template
+    pub fn parse(text: &str) -> Result<Pattern, String> {
+        let text = text.trim();
+        let sexpr = if text.starts_with('(') {
+            parse_sexpr(text)?
+        } else if !text.is_empty() && !text.contains(char::is_whitespace) {
+            SExpr::Atom(text.to_string())
+        } else {
+            return Err(format!("expected a pattern, found '{}'", text));
+        };
+        match Self::from_sexpr(&sexpr)? {
+            Pattern::Rest(name) => Err(format!("'?{}...' can only stand among siblings", name)),
+            pattern => Ok(pattern),
+        }
+    }
+
+    fn from_sexpr(sexpr: &SExpr) -> Result<Pattern, String> {
+        let items = match sexpr {
+            SExpr::Atom(a) if a == "_" => return Ok(Pattern::Var { name: None, binding: None }),
+            SExpr::Atom(a) if a.starts_with('?') && a.len() > 1 => {
+                return Ok(match a[1..].strip_suffix("...") {
+                    Some(name) if !name.is_empty() => Pattern::Rest(name.to_string()),
+                    Some(_) => return Err(format!("metavariable needs a name: '{}'", a)),
+                    None => Pattern::Var { name: Some(a[1..].to_string()), binding: None },
+                });
+            }
+            SExpr::List(items) if !items.is_empty() => items,
+            other => return Err(format!("unexpected {:?} in pattern", other)),
+        };
+        let tag = sexpr_atom(&items[0])?;
+        if items.len() < 2 {
+            return Err(format!("({} ...) requires a {}", tag, if tag == "T" { "value" } else { "name" }));
+        }
+        match tag.as_str() {
+            "T" => {
+                let value = sexpr_atom_or_str(&items[1])?;
+                let mut binding = None;
+                for extra in &items[2..] {
+                    match meta(extra)? {
+                        Some(("b", b)) => binding = Some(b),
+                        Some(_) => {}
+                        None => return Err(format!("unexpected {:?} in (T ...)", extra)),
+                    }
+                }
+                Ok(Pattern::Terminal { value, binding })
+            }
+            "N" => {
+                let name = sexpr_atom(&items[1])?;
+                let mut rule = None;
+                let mut pending: Option<String> = None;
+                let mut children = Vec::new();
+                for extra in &items[2..] {
+                    match meta(extra)? {
+                        Some(("b", b)) if pending.is_none() => pending = Some(b),
+                        Some(("b", _)) => return Err(format!("two bindings for one child in (N {} ...)", name)),
+                        Some(("rule", r)) => rule = Some(r),
+                        Some(_) => {}
+                        None => {
+                            let mut child = Self::from_sexpr(extra)?;
+                            if let Some(b) = pending.take() {
+                                match &mut child {
+                                    Pattern::Var { binding, .. }
+                                    | Pattern::Terminal { binding, .. }
+                                    | Pattern::Nonterminal { binding, .. } => *binding = Some(b),
+                                    Pattern::Rest(n) => return Err(format!("'?{}...' cannot take a binding", n)),
+                                }
+                            }
+                            children.push(child);
+                        }
+                    }
+                }
+                if pending.is_some() {
+                    return Err(format!("(b ...) must come before a child in (N {} ...)", name));
+                }
+                if children.iter().filter(|c| matches!(c, Pattern::Rest(_))).count() > 1 {
+                    return Err(format!("at most one '?X...' per list in (N {} ...)", name));
+                }
+                let name = (name != "_").then_some(name);
+                Ok(Pattern::Nonterminal { name, rule, binding: None, children })
+            }
+            other => Err(format!("unknown pattern tag '{}'", other)),
+        }
+    }
+
+    /// The captures, if `node` matches
+    pub fn matches(&self, node: &ASTNode) -> Option<Captures> {
+        let mut captures = Captures::default();
+        self.match_into(node, &mut captures).then_some(captures)
+    }
+
+    fn match_into(&self, node: &ASTNode, captures: &mut Captures) -> bool {
+        match (self, node) {
+            (Pattern::Var { name, binding }, _) => {
+                if binding.is_some() && node.binding() != binding.as_ref() {
+                    return false;
+                }
+                let Some(name) = name else { return true };
+                match captures.nodes.get(name) {
+                    Some(seen) => same_shape(seen, node),
+                    None => {
+                        captures.nodes.insert(name.clone(), node.clone());
+                        true
+                    }
+                }
+            }
+            (Pattern::Terminal { value, binding }, ASTNode::Terminal(t)) => {
+                t.value == *value && (binding.is_none() || t.binding == *binding)
+            }
+            (Pattern::Nonterminal { name, rule, binding, children }, ASTNode::Nonterminal(nt)) => {
+                name.as_ref().is_none_or(|n| *n == nt.value)
+                    && rule.as_deref().is_none_or(|r| nt.rule_name() == Some(r))
+                    && (binding.is_none() || nt.binding == *binding)
+                    && match_children(children, &nt.children, captures)
+            }
+            _ => false,
+        }
+    }
+
+    /// Metavariables in the pattern, with whether each is a run
+    fn variables(&self, out: &mut Vec<(String, bool)>) {
+        match self {
+            Pattern::Var { name: Some(n), .. } => out.push((n.clone(), false)),
+            Pattern::Rest(n) => out.push((n.clone(), true)),
+            Pattern::Nonterminal { children, .. } => children.iter().for_each(|c| c.variables(out)),
+            Pattern::Var { name: None, .. } | Pattern::Terminal { .. } => {}
+        }
+    }
+
+    /// Fill in the template; new nodes take `span` and are bound to their rule, given
+    /// by `(rule r)` or else by the grammar's productions for the nonterminal
+    fn instantiate(&self, captures: &Captures, span: Option<&SourceSpan>, grammar: &Grammar) -> Result<Vec<ASTNode>, String> {
+        match self {
+            Pattern::Var { name: Some(name), binding } => {
+                let mut node = captures.nodes.get(name).cloned().ok_or_else(|| format!("unbound metavariable '?{}'", name))?;
+                if binding.is_some() {
+                    node.set_binding(binding.clone());
+                }
+                Ok(vec![node])
+            }
+            Pattern::Var { name: None, .. } => Err("'_' cannot appear in a template".into()),
+            Pattern::Rest(name) => captures.runs.get(name).cloned().ok_or_else(|| format!("unbound metavariable '?{}...'", name)),
+            Pattern::Terminal { value, binding } => {
+                Ok(vec![ASTNode::Terminal(Terminal { value: value.clone(), span: span.cloned(), binding: binding.clone(), trivia: None })])
+            }
+            Pattern::Nonterminal { name, rule, binding, children } => {
+                let name = name.as_ref().ok_or("(N _ ...) cannot appear in a template")?;
+                let mut filled = Vec::new();
+                for child in children {
+                    filled.extend(child.instantiate(captures, span, grammar)?);
+                }
+                let mut nt = NonTerminal { value: name.clone(), span: span.cloned(), children: filled, binding: binding.clone(), bound_typing_rule: None };
+                let rule = rule.clone().or_else(|| grammar.productions.get(name)?.iter().find_map(|p| p.rule.clone()));
+                if let Some(rule) = rule {
+                    rebind(&mut nt, &rule, grammar).map_err(|e| format!("{}: {}", name, e))?;
+                }
+                Ok(vec![ASTNode::Nonterminal(nt)])
+            }
+        }
+    }
+}
+
+/// `(b x)`, `(rule r)` or `(s a b)` as (key, value); `None` for anything else
+fn meta(sexpr: &SExpr) -> Result<Option<(&'static str, String)>, String> {
+    if let Some(span) = sexpr_span(sexpr) {
+        span?;
+        return Ok(Some(("s", String::new())));
+    }
+    let SExpr::List(pair) = sexpr else { return Ok(None) };
+    match pair.as_slice() {
+        [SExpr::Atom(k), v] if k == "b" => Ok(Some(("b", sexpr_atom_or_str(v)?))),
+        [SExpr::Atom(k), v] if k == "rule" => Ok(Some(("rule", sexpr_atom_or_str(v)?))),
+        _ => Ok(None),
+    }
+}
+
+fn match_children(patterns: &[Pattern], nodes: &[ASTNode], captures: &mut Captures) -> bool {
+    let Some(rest) = patterns.iter().position(|p| matches!(p, Pattern::Rest(_))) else {
+        return patterns.len() == nodes.len() && patterns.iter().zip(nodes).all(|(p, n)| p.match_into(n, captures));
+    };
+    if nodes.len() + 1 < patterns.len() {
+        return false;
+    }
+    let end = nodes.len() - (patterns.len() - rest - 1);
+    let fixed = patterns[..rest].iter().zip(&nodes[..rest]).chain(patterns[rest + 1..].iter().zip(&nodes[end..]));
+    if !fixed.into_iter().all(|(p, n)| p.match_into(n, captures)) {
+        return false;
+    }
+    let Pattern::Rest(name) = &patterns[rest] else { unreachable!() };
+    let run = &nodes[rest..end];
+    match captures.runs.get(name) {
+        Some(seen) => seen.len() == run.len() && seen.iter().zip(run).all(|(a, b)| same_shape(a, b)),
+        None => {
+            captures.runs.insert(name.clone(), run.to_vec());
+            true
+        }
+    }
+}
+
+/// `pattern => template`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RewriteRule {
+    pub pattern: Pattern,
+    pub template: Pattern,
+}
+
+impl RewriteRule {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let arrow = top_level_arrow(text).ok_or_else(|| format!("expected 'pattern => template': {}", text.trim()))?;
+        let pattern = Pattern::parse(&text[..arrow])?;
+        let template = Pattern::parse(&text[arrow + 2..])?;
+        if matches!(pattern, Pattern::Var { .. }) {
+            return Err("the pattern must be an (N ...) or (T ...) list".into());
+        }
+        let (mut bound, mut used) = (Vec::new(), Vec::new());
+        pattern.variables(&mut bound);
+        template.variables(&mut used);
+        if let Some((name, run)) = used.iter().find(|v| !bound.contains(v)) {
+            return Err(format!("template uses '?{}{}', which the pattern does not bind", name, if *run { "..." } else { "" }));
+        }
+        Ok(RewriteRule { pattern, template })
+    }
+
+    /// One rule per line; blank lines and `//` comments are skipped
+    pub fn parse_all(text: &str) -> Result<Vec<Self>, String> {
+        text.lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with("//"))
+            .map(|(i, line)| Self::parse(line).map_err(|e| format!("line {}: {}", i + 1, e)))
+            .collect()
+    }
+
+    /// The replacement for `node`, if the rule matches it. It keeps the node's span, and
+    /// its binding unless the template gives one.
+    pub fn apply(&self, node: &ASTNode, grammar: &Grammar) -> Result<Option<ASTNode>, String> {
+        let Some(captures) = self.pattern.matches(node) else { return Ok(None) };
+        let mut out = self.template.instantiate(&captures, node.span(), grammar)?;
+        let mut new = out.pop().ok_or("template produced no node")?;
+        if new.binding().is_none() {
+            new.set_binding(node.binding().cloned());
+        }
+        Ok(Some(new))
+    }
+}
+
+/// Byte offset of the `=>` outside any list or string
+fn top_level_arrow(text: &str) -> Option<usize> {
+    let (mut depth, mut in_str, mut escaped) = (0i32, false, false);
+    for (i, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_str => escaped = true,
+            '"' => in_str = !in_str,
+            '(' if !in_str => depth += 1,
+            ')' if !in_str => depth -= 1,
+            '=' if !in_str && depth == 0 && text[i..].starts_with("=>") => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Applies rewrite rules to a fixpoint
+pub struct Rewriter<'a> {
+    grammar: &'a Grammar,
+    rules: Vec<RewriteRule>,
+    max_steps: usize,
+}
+
+impl<'a> Rewriter<'a> {
+    pub const DEFAULT_MAX_STEPS: usize = 10_000;
+
+    pub fn new(grammar: &'a Grammar, rules: Vec<RewriteRule>) -> Self {
+        Rewriter { grammar, rules, max_steps: Self::DEFAULT_MAX_STEPS }
+    }
+
+    /// Give up after this many rewrites, e.g. for rules that undo each other
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Rewrite innermost-first until no rule matches, trying rules in order at each
+    /// node, then re-bind every typing rule in the tree
+    pub fn rewrite(&self, ast: ASTNode) -> Result<ASTNode, String> {
+        let mut normalize = Normalize { rewriter: self, steps: 0, error: None };
+        let mut ast = ast.fold(&mut normalize);
+        if let Some(e) = normalize.error {
+            return Err(e);
+        }
+        rebind_all(&mut ast, self.grammar)?;
+        Ok(ast)
+    }
+}
+
+struct Normalize<'r, 'a> {
+    rewriter: &'r Rewriter<'a>,
+    steps: usize,
+    error: Option<String>,
+}
+
+impl Fold for Normalize<'_, '_> {
+    fn fold_node(&mut self, mut node: ASTNode) -> ASTNode {
+        // Iterate rather than recurse on the rewritten node, so long rewrite chains at
+        // one node don't grow the stack
+        loop {
+            if self.error.is_some() {
+                return node;
+            }
+            node = match node {
+                ASTNode::Nonterminal(nt) => ASTNode::Nonterminal(fold_children(self, nt)),
+                t => t,
+            };
+            let mut rewritten = None;
+            for rule in &self.rewriter.rules {
+                match rule.apply(&node, self.rewriter.grammar) {
+                    Ok(None) => {}
+                    Ok(Some(new)) => {
+                        self.steps += 1;
+                        if self.steps > self.rewriter.max_steps {
+                            self.error = Some(format!("no fixpoint after {} rewrites", self.rewriter.max_steps));
+                            return node;
+                        }
+                        rewritten = Some(new);
+                        break;
+                    }
+                    Err(e) => {
+                        self.error = Some(e);
+                        return node;
+                    }
+                }
+            }
+            match rewritten {
+                Some(new) => node = new,
+                None => return node,
+            }
+        }
+    }
+}
+
+/// Re-resolves each bound rule by name against the node's current subtree
+struct Rebind<'a> {
+    grammar: &'a Grammar,
+    error: Option<String>,
+}
+
+impl VisitorMut for Rebind<'_> {
+    fn visit_nonterminal_mut(&mut self, nt: &mut NonTerminal) {
+        walk_nonterminal_mut(self, nt);
+        if let Some(rule) = nt.rule_name().map(str::to_string)
+            && let Err(e) = rebind(nt, &rule, self.grammar)
+        {
+            self.error.get_or_insert(format!("{}: {}", nt.value, e));
+        }
+    }
+}
+
+/// Re-bind every bound rule in `ast`, after edits that moved nodes around
+pub(crate) fn rebind_all(ast: &mut ASTNode, grammar: &Grammar) -> Result<(), String> {
+    let mut rebinder = Rebind { grammar, error: None };
+    ast.accept_mut(&mut rebinder);
+    rebinder.error.map_or(Ok(()), Err)
+}
+
+impl ASTNode {
+    /// Nodes matching `pattern`, pre-order
+    pub fn find_matches(&self, pattern: &Pattern) -> Vec<&ASTNode> {
+        self.pre_order().filter(|n| pattern.matches(n).is_some()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::bind::BoundType;
+    use crate::logic::check::TypeChecker;
+    use crate::logic::grammar::tests::STLC_SPEC;
+    use crate::logic::parser::Parser;
+
+    fn check(ast: &ASTNode) -> Result<Option<BoundType>, String> {
+        let mut tc = TypeChecker::new();
+        tc.add("z".into(), BoundType::Atom("a".into()));
+        tc.check(ast)
+    }
+
+    #[test]
+    fn patterns_capture_metavariables() {
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        let ast = Parser::new(grammar).parse("(λx:a->a.x) z").unwrap();
+        let pattern = Pattern::parse("(N Application (b f) ?F (b e) ?E)").unwrap();
+        let [app] = ast.find_matches(&pattern)[..] else { panic!("one application") };
+        let captures = pattern.matches(app).unwrap();
+        assert_eq!(captures.node("E"), Some(&app.children().unwrap()[1]));
+        assert_eq!(captures.node("F").unwrap().binding().map(String::as_str), Some("f"));
+
+        // wrong binding, rule or arity
+        for text in ["(N Application (b e) ?F ?E)", "(N _ (rule var) ?F ?E)", "(N Application ?F)"] {
+            assert!(ast.find_matches(&Pattern::parse(text).unwrap()).is_empty(), "{}", text);
+        }
+        // runs and repeated metavariables
+        let rest = Pattern::parse("(N Application ?Fs... (N BaseTerm ?X))").unwrap();
+        assert_eq!(rest.matches(app).unwrap().run("Fs").map(<[_]>::len), Some(1));
+        assert!(Pattern::parse("(N Application ?X ?X)").unwrap().matches(app).is_none());
+    }
+
+    #[test]
+    fn rewrites_to_fixpoint_and_rebinds() {
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        // drop redundant parentheses around a base term
+        let rules = RewriteRule::parse_all(
+            "// (( t )) => t\n(N BaseTerm (T \"(\") (N Term (b e) (N BaseTerm ?X)) (T \")\")) => (N BaseTerm ?X)\n",
+        )
+        .unwrap();
+        let rewriter = Rewriter::new(&grammar, rules);
+        let parse = |src: &str| Parser::new(grammar.clone()).parse(src).unwrap();
+
+        let ast = parse("((λx:a->a.x)) z");
+        let out = rewriter.rewrite(ast.clone()).unwrap();
+        assert!(out.node_count() < ast.node_count());
+        assert!(out.terminals().all(|t| t.value != "("));
+        assert!(out.syneq(&rewriter.rewrite(parse("(λx:a->a.x) z")).unwrap()));
+        // the app rule above the rewrite points at the new children
+        assert_eq!(check(&out), check(&ast));
+        assert_eq!(check(&out), Ok(Some(BoundType::Atom("a".into()))));
+    }
+
+    #[test]
+    fn reports_bad_rules_and_divergence() {
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        assert!(RewriteRule::parse("(N Variable ?X) => (N Variable ?Y)").unwrap_err().contains("?Y"));
+        assert!(RewriteRule::parse("(N Variable ?X)").is_err());
+        assert!(RewriteRule::parse("?X => ?X").is_err());
+        assert!(Pattern::parse("(N Application ?A... ?B...)").is_err());
+        assert_eq!(top_level_arrow("(T \"=>\") => _"), Some(9));
+
+        let looping = vec![RewriteRule::parse("(N Variable ?X) => (N Variable ?X)").unwrap()];
+        let ast = Parser::new(grammar.clone()).parse("z").unwrap();
+        let err = Rewriter::new(&grammar, looping.clone()).with_max_steps(10).rewrite(ast.clone()).unwrap_err();
+        assert!(err.contains("no fixpoint"), "{}", err);
+        // the default limit is reached without exhausting the stack
+        let err = Rewriter::new(&grammar, looping).rewrite(ast).unwrap_err();
+        assert!(err.contains(&Rewriter::DEFAULT_MAX_STEPS.to_string()), "{}", err);
+    }
+}
@@ -0,0 +1,392 @@
+// Scoping from the spec's `binds` declarations: each variable occurrence is resolved
+// to the nearest enclosing binder of the same name, or is free. On top of that:
+// free variables, capture-avoiding substitution, alpha-equivalence and de Bruijn
+// indices.
+//
+// Variables are the nodes of the nonterminals the declarations bind (`Variable` for
+// `Lambda binds x in e` when Lambda's `x` is a `Variable`); a variable's name is its
+// text. A binder's own variable is not an occurrence.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use super::arena::NodeKind;
+use super::rewrite::rebind_all;
+use super::{ASTNode, AstArena, NodeId, NonTerminal, Terminal};
+use crate::logic::grammar::Grammar;
+use crate::logic::parser::Parser;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Reference {
+    /// The binder's variable node, and how many binders lie between (0 = innermost)
+    Bound { site: NodeId, index: usize },
+    Free(String),
+}
+
+struct Scopes {
+    arena: AstArena,
+    /// Variable node of each binder -> its name
+    sites: HashMap<NodeId, String>,
+    /// Binder variable -> roots of the subtrees it scopes over
+    scope_roots: HashMap<NodeId, Vec<NodeId>>,
+    references: HashMap<NodeId, Reference>,
+}
+
+impl Scopes {
+    fn new(grammar: &Grammar, ast: &ASTNode) -> Scopes {
+        let arena = AstArena::from(ast.clone());
+        let vars = grammar.variable_nonterminals();
+        let mut sites = HashMap::new();
+        let mut scope_roots: HashMap<NodeId, Vec<NodeId>> = HashMap::new();
+        let mut opens: HashMap<NodeId, Vec<NodeId>> = HashMap::new();
+        for id in arena.ids() {
+            for decl in grammar.binders.iter().filter(|d| arena[id].kind == NodeKind::Nonterminal && d.nonterminal == arena[id].value) {
+                let Some(site) = nearest_bound(&arena, id, &decl.var) else { continue };
+                sites.insert(site, text(&arena, site));
+                for root in decl.scopes.iter().filter_map(|s| nearest_bound(&arena, id, s)) {
+                    scope_roots.entry(site).or_default().push(root);
+                    opens.entry(root).or_default().push(site);
+                }
+            }
+        }
+        let mut scopes = Scopes { arena, sites, scope_roots, references: HashMap::new() };
+        let mut env = Vec::new();
+        scopes.resolve(scopes.arena.root(), &vars, &opens, &mut env);
+        scopes
+    }
+
+    fn resolve(&mut self, id: NodeId, vars: &HashSet<&str>, opens: &HashMap<NodeId, Vec<NodeId>>, env: &mut Vec<NodeId>) {
+        let depth = env.len();
+        env.extend(opens.get(&id).into_iter().flatten());
+        let node = &self.arena[id];
+        if self.sites.contains_key(&id) {
+            // a binder's own variable
+        } else if node.kind == NodeKind::Nonterminal && vars.contains(node.value.as_str()) {
+            let name = text(&self.arena, id);
+            let reference = match env.iter().rev().enumerate().find(|(_, site)| self.sites[*site] == name) {
+                Some((index, site)) => Reference::Bound { site: *site, index },
+                None => Reference::Free(name),
+            };
+            self.references.insert(id, reference);
+        } else {
+            for child in self.arena.children(id).to_vec() {
+                self.resolve(child, vars, opens, env);
+            }
+        }
+        env.truncate(depth);
+    }
+
+    fn free(&self) -> impl Iterator<Item = (NodeId, &str)> {
+        self.references.iter().filter_map(|(id, r)| match r {
+            Reference::Free(name) => Some((*id, name.as_str())),
+            Reference::Bound { .. } => None,
+        })
+    }
+
+    /// Node to replace for occurrence `id`, and what to put there. Where the occurrence
+    /// sits in a chain of single-child nodes (`Term > BaseTerm > Variable`), the highest
+    /// node of the chain that also starts the replacement's own chain is replaced. With
+    /// `parenthesize`, the replacement is first put between the literals of a wrapping
+    /// production of a chain node (`BaseTerm ::= '(' Term ')'`) where there is one.
+    fn splice_point(&self, grammar: &Grammar, id: NodeId, replacement: &ASTNode, parenthesize: bool) -> Option<(NodeId, ASTNode)> {
+        let mut down = vec![replacement];
+        while let Some([only]) = down.last().and_then(|n| n.children().map(Vec::as_slice)) {
+            down.push(only);
+        }
+        let mut up = vec![id];
+        while let Some(parent) = self.arena.parent(*up.last().unwrap()).filter(|p| self.arena.children(*p).len() == 1) {
+            up.push(parent);
+        }
+        let direct = || {
+            up.iter().rev().find_map(|n| {
+                let r = down.iter().find(|r| r.value() == self.arena[*n].value)?;
+                let mut r = (*r).clone();
+                r.set_binding(self.arena[*n].binding.clone());
+                Some((*n, r))
+            })
+        };
+        let wrapped = || {
+            up.iter().rev().find_map(|n| {
+                let outer = &self.arena[*n];
+                let (r, production) = down.iter().find_map(|r| Some((*r, grammar.wrapping_production(&outer.value, r.value())?)))?;
+                let children = production
+                    .rhs
+                    .iter()
+                    .map(|s| match s.value() == r.value() {
+                        true => {
+                            let mut r = r.clone();
+                            r.set_binding(s.binding().cloned());
+                            r
+                        }
+                        false => ASTNode::Terminal(Terminal {
+                            value: s.value().trim_matches('\'').to_string(),
+                            span: None,
+                            binding: s.binding().cloned(),
+                            trivia: None,
+                        }),
+                    })
+                    .collect();
+                let node = NonTerminal { value: outer.value.clone(), span: None, children, binding: outer.binding.clone(), bound_typing_rule: None };
+                Some((*n, ASTNode::Nonterminal(node)))
+            })
+        };
+        match parenthesize {
+            true => wrapped().or_else(direct),
+            false => direct().or_else(wrapped),
+        }
+    }
+
+    /// Whether `id` lies in a subtree `site` scopes over
+    fn in_scope_of(&self, id: NodeId, site: NodeId) -> bool {
+        let roots = &self.scope_roots[&site];
+        std::iter::once(id).chain(self.arena.ancestors(id)).any(|n| roots.contains(&n))
+    }
+
+    fn same(&self, a: NodeId, other: &Scopes, b: NodeId) -> bool {
+        let (x, y) = (&self.arena[a], &other.arena[b]);
+        if x.kind != y.kind || x.value != y.value || x.binding != y.binding {
+            return false;
+        }
+        match (self.sites.contains_key(&a), other.sites.contains_key(&b)) {
+            (true, true) => return true,
+            (false, false) => {}
+            _ => return false,
+        }
+        match (self.references.get(&a), other.references.get(&b)) {
+            (Some(Reference::Bound { index: i, .. }), Some(Reference::Bound { index: j, .. })) => return i == j,
+            (Some(Reference::Free(m)), Some(Reference::Free(n))) => return m == n,
+            (None, None) => {}
+            _ => return false,
+        }
+        let (xs, ys) = (self.arena.children(a), other.arena.children(b));
+        xs.len() == ys.len() && xs.iter().zip(ys).all(|(c, d)| self.same(*c, other, *d))
+    }
+}
+
+/// The nearest node below `id` bound to `binding`
+fn nearest_bound(arena: &AstArena, id: NodeId, binding: &str) -> Option<NodeId> {
+    let mut queue: std::collections::VecDeque<NodeId> = arena.children(id).iter().copied().collect();
+    while let Some(n) = queue.pop_front() {
+        if arena[n].binding.as_deref() == Some(binding) {
+            return Some(n);
+        }
+        queue.extend(arena.children(n));
+    }
+    None
+}
+
+fn text(arena: &AstArena, id: NodeId) -> String {
+    arena.subtree(id).filter(|n| arena[*n].kind == NodeKind::Terminal).map(|n| arena[n].value.as_str()).collect()
+}
+
+/// `node` spelling `name` instead, for variables of a single token
+fn renamed(node: &ASTNode, name: &str) -> Result<ASTNode, String> {
+    if node.terminals().filter(|t| !t.value.is_empty()).count() != 1 {
+        return Err(format!("cannot rename {}: it is not a single token", node.value()));
+    }
+    let mut node = node.clone();
+    fn go(node: &mut ASTNode, name: &str) {
+        match node {
+            ASTNode::Terminal(t) if !t.value.is_empty() => t.value = name.to_string(),
+            ASTNode::Terminal(_) => {}
+            ASTNode::Nonterminal(nt) => nt.children.iter_mut().for_each(|c| go(c, name)),
+        }
+    }
+    go(&mut node, name);
+    Ok(node)
+}
+
+/// Replacement for a node, by pre-order id
+type Replace<'a> = dyn FnMut(NodeId, &ASTNode) -> Result<Option<ASTNode>, String> + 'a;
+
+/// Rebuild `node`, replacing the nodes `f` returns a replacement for
+fn edit(node: &ASTNode, next: &mut usize, f: &mut Replace) -> Result<ASTNode, String> {
+    let id = NodeId(*next);
+    *next += 1;
+    if let Some(new) = f(id, node)? {
+        *next += node.node_count() - 1;
+        return Ok(new);
+    }
+    match node {
+        ASTNode::Terminal(_) => Ok(node.clone()),
+        ASTNode::Nonterminal(nt) => {
+            let mut nt = nt.clone();
+            nt.children = nt.children.iter().map(|c| edit(c, next, f)).collect::<Result<_, _>>()?;
+            Ok(ASTNode::Nonterminal(nt))
+        }
+    }
+}
+
+impl ASTNode {
+    /// Names of the variables not bound by any enclosing binder
+    pub fn free_vars(&self, grammar: &Grammar) -> BTreeSet<String> {
+        Scopes::new(grammar, self).free().map(|(_, name)| name.to_string()).collect()
+    }
+
+    /// Equal up to the names of bound variables; otherwise as `syneq`
+    pub fn alpha_eq(&self, other: &ASTNode, grammar: &Grammar) -> bool {
+        let (a, b) = (Scopes::new(grammar, self), Scopes::new(grammar, other));
+        a.same(a.arena.root(), &b, b.arena.root())
+    }
+
+    /// Replace the free occurrences of `var` by `replacement`, first renaming binders
+    /// that would capture one of its free variables. Where the occurrence sits in a chain
+    /// of single-child nodes (`Term > BaseTerm > Variable`), the highest node of the chain
+    /// that also starts the replacement's own chain is replaced. If that does not print
+    /// back to the same tree (or no node of the chain fits), the replacement is wrapped
+    /// in the grammar's parenthesizing production instead, e.g. `BaseTerm ::= '(' Term ')'`;
+    /// if that does not either, it is an error. Trees that do not print back to themselves
+    /// to begin with are not checked. Replacements keep the binding of the node they
+    /// replace, and bound rules are re-bound afterwards.
+    pub fn substitute(&self, grammar: &Grammar, var: &str, replacement: &ASTNode) -> Result<ASTNode, String> {
+        let scopes = Scopes::new(grammar, self);
+        let targets: HashSet<NodeId> = scopes.free().filter(|(_, name)| *name == var).map(|(id, _)| id).collect();
+        if targets.is_empty() {
+            return Ok(self.clone());
+        }
+        let incoming = replacement.free_vars(grammar);
+        let mut taken: HashSet<String> = scopes.sites.values().cloned().collect();
+        taken.extend(scopes.free().map(|(_, name)| name.to_string()));
+        taken.extend(incoming.iter().cloned());
+
+        let mut renames: HashMap<NodeId, String> = HashMap::new();
+        let mut captures: Vec<(&NodeId, &String)> = scopes.sites.iter().filter(|(_, name)| incoming.contains(*name)).collect();
+        captures.sort();
+        for (site, name) in captures {
+            if targets.iter().any(|t| scopes.in_scope_of(*t, *site)) {
+                let fresh = (1..).map(|n| format!("{}{}", name, n)).find(|f| !taken.contains(f)).expect("unbounded");
+                taken.insert(fresh.clone());
+                renames.insert(*site, fresh);
+            }
+        }
+
+        let mut parser = Parser::new(grammar.clone());
+        let mut reparses = |ast: &ASTNode| parser.parse(&grammar.unparse(ast)).is_ok_and(|p| p.syneq(ast));
+        let checked = reparses(self);
+        for parenthesize in [false, true] {
+            let mut points = HashMap::new();
+            for target in &targets {
+                let (id, node) = scopes.splice_point(grammar, *target, replacement, parenthesize).ok_or_else(|| {
+                    format!("cannot substitute a {} for '{}': nothing around the occurrence takes one", replacement.value(), var)
+                })?;
+                points.insert(id, node);
+            }
+            let mut out = edit(self, &mut 0, &mut |id, node| {
+                if let Some(new) = points.get(&id) {
+                    return Ok(Some(new.clone()));
+                }
+                let site = match scopes.references.get(&id) {
+                    Some(Reference::Bound { site, .. }) => *site,
+                    _ => id,
+                };
+                renames.get(&site).map(|name| renamed(node, name)).transpose()
+            })?;
+            rebind_all(&mut out, grammar)?;
+            if !checked || reparses(&out) {
+                return Ok(out);
+            }
+        }
+        Err(format!("substituting for '{}' gives a tree that does not print back to itself", var))
+    }
+
+    /// Bound occurrences spelled as their de Bruijn index (0 = innermost binder) and
+    /// binder variables as `_`, so alpha-equivalent trees become `syneq`
+    pub fn to_de_bruijn(&self, grammar: &Grammar) -> Result<ASTNode, String> {
+        let scopes = Scopes::new(grammar, self);
+        edit(self, &mut 0, &mut |id, node| match scopes.references.get(&id) {
+            Some(Reference::Bound { index, .. }) => renamed(node, &index.to_string()).map(Some),
+            _ if scopes.sites.contains_key(&id) => renamed(node, "_").map(Some),
+            _ => Ok(None),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::logic::ast::ASTNode;
+    use crate::logic::bind::BoundType;
+    use crate::logic::check::TypeChecker;
+    use crate::logic::grammar::Grammar;
+    use crate::logic::grammar::tests::STLC_SPEC;
+    use crate::logic::parser::Parser;
+
+    fn grammar() -> Grammar {
+        Grammar::load(&format!("{}\n\nLambda binds x in e\n", STLC_SPEC)).unwrap()
+    }
+
+    fn parse(grammar: &Grammar, src: &str) -> ASTNode {
+        Parser::new(grammar.clone()).parse(src).unwrap()
+    }
+
+    #[test]
+    fn free_vars_and_alpha_equivalence() {
+        let g = grammar();
+        assert_eq!(g.variable_nonterminals().into_iter().collect::<Vec<_>>(), vec!["Variable"]);
+        let fv = |src: &str| parse(&g, src).free_vars(&g).into_iter().collect::<Vec<_>>();
+        assert_eq!(fv("(λx:a->a.λy:a->a.x) y"), vec!["y"]);
+        assert_eq!(fv("λy:a->a.x"), vec!["x"]);
+        assert!(fv("λx:a->a.x").is_empty());
+
+        let alpha = |a: &str, b: &str| parse(&g, a).alpha_eq(&parse(&g, b), &g);
+        assert!(alpha("λx:a->a.x", "λy:a->a.y"));
+        assert!(alpha("λx:a->a.λy:a->a.x", "λy:a->a.λx:a->a.y"));
+        assert!(!alpha("λx:a->a.λy:a->a.x", "λx:a->a.λy:a->a.y"));
+        assert!(!alpha("λx:a->a.x", "λy:a->a.x"));
+        assert!(!alpha("λx:a->a.z", "λx:a->a.w"));
+        assert!(!alpha("λx:a->a.x", "λx:b->b.x"));
+
+        let db = |src: &str| parse(&g, src).to_de_bruijn(&g).unwrap();
+        assert_eq!(g.unparse(&db("λx:a->a.λy:a->a.x")), "λ_:a->a.λ_:a->a.1");
+        assert!(db("λx:a->a.λy:a->a.x").syneq(&db("λp:a->a.λq:a->a.p")));
+    }
+
+    #[test]
+    fn substitution_avoids_capture() {
+        let g = grammar();
+        let subst = |src: &str, var: &str, by: &str| parse(&g, src).substitute(&g, var, &parse(&g, by)).unwrap();
+
+        let out = subst("λy:a->a.x", "x", "y");
+        assert_eq!(g.unparse(&out), "λy1:a->a.y");
+        assert_eq!(out.free_vars(&g).into_iter().collect::<Vec<_>>(), vec!["y"]);
+        assert!(out.alpha_eq(&parse(&g, "λw:a->a.y"), &g));
+
+        // bound occurrences and unrelated binders are left alone
+        assert_eq!(g.unparse(&subst("λx:a->a.x", "x", "y")), "λx:a->a.x");
+        assert_eq!(g.unparse(&subst("(λx:a->a.x) x", "x", "z")), "(λx:a->a.x)z");
+
+        // typing rules are re-bound over the new tree
+        let out = subst("λy:a->a.x", "x", "z");
+        let mut tc = TypeChecker::new();
+        tc.add("z".into(), BoundType::Atom("a".into()));
+        assert_eq!(tc.check(&out), tc.check(&parse(&g, "λy:a->a.z")));
+        assert!(tc.check(&out).unwrap().is_some());
+    }
+
+    #[test]
+    fn compound_replacements_are_parenthesized() {
+        let g = grammar();
+        let mut parser = Parser::new(g.clone());
+        let out = parse(&g, "(λg:a->a.g) x").substitute(&g, "x", &parse(&g, "(λq:a->a.q) y")).unwrap();
+        let printed = g.unparse(&out);
+        assert_eq!(printed, "(λg:a->a.g)((λq:a->a.q)y)");
+        assert!(parser.parse(&printed).unwrap().syneq(&out));
+
+        // where the replacement fits as is, no parentheses are added
+        let out = parse(&g, "λy:a->a.x").substitute(&g, "x", &parse(&g, "(λq:a->a.q) y")).unwrap();
+        let printed = g.unparse(&out);
+        assert_eq!(printed, "λy1:a->a.(λq:a->a.q)y");
+        assert!(parser.parse(&printed).unwrap().syneq(&out));
+    }
+
+    #[test]
+    fn binds_lines_load_and_save() {
+        let g = grammar();
+        assert_eq!(g.binders[0].to_line(), "Lambda binds x in e");
+        assert_eq!(Grammar::load(&g.to_spec_string()).unwrap().binders[0].scopes, vec!["e"]);
+
+        let load = |line: &str| Grammar::load(&format!("{}\n\n{}\n", STLC_SPEC, line)).unwrap_err();
+        assert!(load("Nope binds x in e").contains("unknown nonterminal"));
+        assert!(load("Lambda binds q in e").contains("binds nothing to 'q'"));
+        assert!(load("Lambda binds x in q").contains("binds nothing to 'q'"));
+        assert!(load("Lambda binds x").contains("expected 'Nonterminal binds x in e'"));
+    }
+}
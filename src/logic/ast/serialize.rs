@@ -1,4 +1,5 @@
-use crate::logic::ast::{ASTNode, Terminal, NonTerminal};
+use crate::logic::ast::{ASTNode, Terminal, NonTerminal, SourceSpan};
+use crate::logic::bind::{BindingResolver, DefaultBindingResolver};
 use crate::logic::grammar::Grammar;
 // ---------------- S-expression parsing helpers ----------------
 
@@ -9,19 +10,163 @@ pub enum SExpr {
     List(Vec<SExpr>),
 }
 
-pub fn strip_headers(input: &str) -> &str {
-    let mut offset = 0usize;
-    for line in input.lines() {
-        let trimmed = line.trim_start();
-        if trimmed.starts_with(';') || trimmed.is_empty() {
+// ---------------- File headers ----------------
+
+/// Newest AST file format version written by `ASTNode::save`
+pub const AST_FORMAT_VERSION: u32 = 2;
+
+/// The `;!key value` lines at the top of an AST file.
+///
+/// v1 has `;!ast 1` and an optional `;!rules:` list. v2 adds `;!spec:` (required, see
+/// `Grammar::fingerprint`), an optional `;!source:` hash of the parsed text, and spans
+/// in the body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AstHeader {
+    pub version: u32,
+    pub spec: Option<String>,
+    pub source: Option<String>,
+    /// `None` when the file has no `;!rules:` line
+    pub rules: Option<Vec<String>>,
+}
+
+impl AstHeader {
+    /// Header for `ast` produced with `grammar`, optionally from source text `source`
+    pub fn new(ast: &ASTNode, grammar: &Grammar, source: Option<&str>) -> Self {
+        let mut rules: Vec<String> = ast.rules().into_iter().collect();
+        rules.sort();
+        AstHeader {
+            version: AST_FORMAT_VERSION,
+            spec: Some(grammar.fingerprint()),
+            source: source.map(content_hash),
+            rules: (!rules.is_empty()).then_some(rules),
+        }
+    }
+
+    /// Read the header lines of `input`, returning the header and the body after them.
+    /// Blank lines and plain `;` comments may sit between header lines; input without a
+    /// `;!ast` line is a bare v1 body.
+    pub fn read(input: &str) -> Result<(Self, &str), String> {
+        let mut header = AstHeader { version: 1, spec: None, source: None, rules: None };
+        let mut seen_version = false;
+        let mut offset = 0usize;
+        for (i, line) in input.split_inclusive('\n').enumerate() {
+            let trimmed = line.trim();
+            let err = |msg: String| format!("AST header line {}: {}", i + 1, msg);
+            if let Some(entry) = trimmed.strip_prefix(";!") {
+                let (key, value) = match entry.split_once(':') {
+                    Some((k, v)) => (k.trim(), v.trim()),
+                    None => entry.split_once(char::is_whitespace).map_or((entry, ""), |(k, v)| (k, v.trim())),
+                };
+                if key == "ast" && seen_version {
+                    return Err(err("duplicate ';!ast' line".into()));
+                }
+                if key != "ast" && !seen_version {
+                    return Err(err("expected ';!ast <version>' first".into()));
+                }
+                match key {
+                    "ast" => {
+                        header.version = value.parse().map_err(|_| err(format!("invalid version '{}'", value)))?;
+                        if !(1..=AST_FORMAT_VERSION).contains(&header.version) {
+                            return Err(err(format!(
+                                "unsupported AST format version {} (this build reads 1 to {})",
+                                header.version, AST_FORMAT_VERSION
+                            )));
+                        }
+                        seen_version = true;
+                    }
+                    "rules" => {
+                        header.rules = Some(value.split(',').map(str::trim).filter(|r| !r.is_empty()).map(str::to_string).collect());
+                    }
+                    "spec" | "source" if header.version >= 2 => {
+                        if value.is_empty() {
+                            return Err(err(format!("';!{}:' needs a hash", key)));
+                        }
+                        let slot = if key == "spec" { &mut header.spec } else { &mut header.source };
+                        *slot = Some(value.to_string());
+                    }
+                    _ => return Err(err(format!("unknown header ';!{}' in a v{} file", key, header.version))),
+                }
+            } else if !(trimmed.is_empty() || trimmed.starts_with(';')) {
+                break;
+            }
             offset += line.len();
-            offset += 1; // account for newline
-            continue;
-        } else {
-            return &input[offset..];
         }
+        if header.version >= 2 && header.spec.is_none() {
+            return Err(format!("AST format v{} requires a ';!spec:' header", header.version));
+        }
+        Ok((header, &input[offset..]))
     }
-    input
+
+    /// Check the header against the grammar the body is about to be read with
+    pub fn validate(&self, grammar: &Grammar) -> Result<(), String> {
+        if let Some(spec) = &self.spec {
+            let expected = grammar.fingerprint();
+            if *spec != expected {
+                return Err(format!(
+                    "AST was produced with spec {}, but the grammar has spec {}; re-parse the source with this grammar",
+                    spec, expected
+                ));
+            }
+        }
+        for rule in self.rules.iter().flatten() {
+            if !grammar.typing_rules.contains_key(rule) {
+                return Err(format!("AST header lists typing rule '{}', which the grammar does not define", rule));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that every `(rule X)` marker in `body` is listed in `;!rules:`, if present
+    pub fn check_rules(&self, body: &SExpr) -> Result<(), String> {
+        fn go<'a>(s: &'a SExpr, out: &mut Vec<&'a str>) {
+            if let SExpr::List(items) = s {
+                match items.as_slice() {
+                    [SExpr::Atom(k), v] if k == "rule" => out.extend(sexpr_str(v)),
+                    _ => items.iter().for_each(|i| go(i, out)),
+                }
+            }
+        }
+        let Some(listed) = &self.rules else { return Ok(()) };
+        let mut used = Vec::new();
+        go(body, &mut used);
+        match used.into_iter().find(|r| !listed.iter().any(|l| l == r)) {
+            Some(rule) => Err(format!("typing rule '{}' is used in the AST but missing from the ';!rules:' header", rule)),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether `source` is the text the AST was parsed from; `None` without a `;!source:` hash
+    pub fn matches_source(&self, source: &str) -> Option<bool> {
+        self.source.as_ref().map(|h| *h == content_hash(source))
+    }
+
+    /// The header lines, ending with the blank separator line
+    pub fn to_lines(&self) -> String {
+        let mut out = format!(";!ast {}\n", self.version);
+        if let Some(spec) = &self.spec {
+            out.push_str(&format!(";!spec: {}\n", spec));
+        }
+        if let Some(source) = &self.source {
+            out.push_str(&format!(";!source: {}\n", source));
+        }
+        if let Some(rules) = &self.rules {
+            out.push_str(&format!(";!rules: {}\n", rules.join(", ")));
+        }
+        out.push('\n');
+        out
+    }
+}
+
+/// Stable 64-bit FNV-1a hash of `text` as 16 hex digits; unlike `DefaultHasher` it
+/// does not change between Rust releases, so it can be stored in files
+pub fn content_hash(text: &str) -> String {
+    let hash = text.bytes().fold(0xcbf2_9ce4_8422_2325u64, |h, b| (h ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3));
+    format!("{:016x}", hash)
+}
+
+/// The body of `input` after its header lines (see `AstHeader::read`), unvalidated
+pub fn strip_headers(input: &str) -> &str {
+    AstHeader::read(input).map_or(input, |(_, body)| body)
 }
 
 pub fn parse_sexpr(input: &str) -> Result<SExpr, String> {
@@ -107,6 +252,36 @@ pub fn parse_sexpr(input: &str) -> Result<SExpr, String> {
 }
 
 pub fn sexpr_to_ast(sexpr: &SExpr, grammar: &Grammar) -> Result<ASTNode, String> {
+    build(sexpr, grammar, None, None)
+}
+
+/// Like `sexpr_to_ast`, re-binding every `(rule X)` marker against the grammar's typing
+/// rules. All binding failures are reported at once, one per line, prefixed by node path.
+pub fn sexpr_to_bound_ast(sexpr: &SExpr, grammar: &Grammar) -> Result<ASTNode, String> {
+    let mut failures = Vec::new();
+    let ast = build(sexpr, grammar, Some(&mut failures), None)?;
+    if failures.is_empty() { Ok(ast) } else { Err(failures.join("\n")) }
+}
+
+/// Resolve the typing rule `rule_name` for `nt`, as the parser does for a fresh node
+pub(crate) fn rebind(nt: &mut NonTerminal, rule_name: &str, grammar: &Grammar) -> Result<(), String> {
+    let rule = grammar.typing_rules.get(rule_name).ok_or_else(|| format!("unknown typing rule '{}'", rule_name))?;
+    let bound = DefaultBindingResolver.resolve_rule(rule, nt)?;
+    if !bound.is_well_formed() {
+        return Err(format!("bound rule '{}' is not well-formed", rule_name));
+    }
+    nt.bound_typing_rule = Some(Box::new(bound));
+    Ok(())
+}
+
+/// `parent` is the path of the parent node and this node's position among its children;
+/// paths read like `Expression/Application[0]/Expression[1]`
+fn build(
+    sexpr: &SExpr,
+    grammar: &Grammar,
+    mut failures: Option<&mut Vec<String>>,
+    parent: Option<(&str, usize)>,
+) -> Result<ASTNode, String> {
     match sexpr {
         SExpr::List(items) if !items.is_empty() => {
             match &items[0] {
@@ -118,8 +293,11 @@ pub fn sexpr_to_ast(sexpr: &SExpr, grammar: &Grammar) -> Result<ASTNode, String>
                         _ => return Err("terminal value must be atom or string".into()),
                     };
                     let mut binding: Option<String> = None;
+                    let mut span: Option<SourceSpan> = None;
                     for extra in &items[2..] {
-                        if let SExpr::List(pair) = extra {
+                        if let Some(s) = sexpr_span(extra) {
+                            span = Some(s?);
+                        } else if let SExpr::List(pair) = extra {
                             if pair.len() == 2 {
                                 if let SExpr::Atom(k) = &pair[0] {
                                     match k.as_str() {
@@ -130,37 +308,50 @@ pub fn sexpr_to_ast(sexpr: &SExpr, grammar: &Grammar) -> Result<ASTNode, String>
                             }
                         }
                     }
-                    Ok(ASTNode::Terminal(Terminal { value, span: None, binding }))
+                    Ok(ASTNode::Terminal(Terminal { value, span, binding, trivia: None }))
                 }
                 SExpr::Atom(tag) if tag == "N" => {
                     if items.len() < 2 { return Err("(N ...) requires a name".into()); }
                     let name = sexpr_atom(&items[1])?;
+                    let path = match parent {
+                        Some((p, i)) => format!("{}/{}[{}]", p, name, i),
+                        None => name.clone(),
+                    };
                     let mut binding: Option<String> = None;
+                    let mut rule_name: Option<String> = None;
+                    let mut span: Option<SourceSpan> = None;
                     let mut children: Vec<ASTNode> = Vec::new();
                     for extra in &items[2..] {
+                        if let Some(s) = sexpr_span(extra) {
+                            span = Some(s?);
+                            continue;
+                        }
                         match extra {
                             SExpr::List(pair) if pair.len() == 2 => {
                                 if let SExpr::Atom(k) = &pair[0] {
                                     match k.as_str() {
                                         "b" => { binding = Some(sexpr_atom_or_str(&pair[1])?); }
-                                        "rule" => {
-                                            // Rules will be resolved during parsing/binding phase
-                                            let _rname = sexpr_atom_or_str(&pair[1])?;
-                                        }
+                                        "rule" => { rule_name = Some(sexpr_atom_or_str(&pair[1])?); }
                                         _ => {
                                             // This is not a known metadata key, treat as child node
-                                            children.push(sexpr_to_ast(extra, grammar)?);
+                                            children.push(build(extra, grammar, failures.as_deref_mut(), Some((&path, children.len())))?);
                                         }
                                     }
                                 } else {
                                     // First element is not an atom, treat as child node
-                                    children.push(sexpr_to_ast(extra, grammar)?);
+                                    children.push(build(extra, grammar, failures.as_deref_mut(), Some((&path, children.len())))?);
                                 }
                             }
-                            other => { children.push(sexpr_to_ast(other, grammar)?); }
+                            other => { children.push(build(other, grammar, failures.as_deref_mut(), Some((&path, children.len())))?); }
                         }
                     }
-                    Ok(ASTNode::Nonterminal(NonTerminal { value: name, span: None, children, binding, bound_typing_rule: None }))
+                    let mut nt = NonTerminal { value: name, span, children, binding, bound_typing_rule: None };
+                    if let (Some(failures), Some(rule_name)) = (failures, rule_name)
+                        && let Err(e) = rebind(&mut nt, &rule_name, grammar)
+                    {
+                        failures.push(format!("{}: {}", path, e));
+                    }
+                    Ok(ASTNode::Nonterminal(nt))
                 }
                 other => Err(format!("Unknown node tag: {:?}", other)),
             }
@@ -169,6 +360,23 @@ pub fn sexpr_to_ast(sexpr: &SExpr, grammar: &Grammar) -> Result<ASTNode, String>
     }
 }
 
+/// `(s start end)` span metadata (character offsets); `None` for anything else
+pub fn sexpr_span(s: &SExpr) -> Option<Result<SourceSpan, String>> {
+    let SExpr::List(items) = s else { return None };
+    if !matches!(items.first(), Some(SExpr::Atom(k)) if k == "s") {
+        return None;
+    }
+    let offset = |s: &SExpr| sexpr_atom(s)?.parse::<usize>().map_err(|_| "span offsets must be integers".to_string());
+    Some(match items.as_slice() {
+        [_, start, end] => offset(start).and_then(|start| Ok(SourceSpan { start, end: offset(end)? })),
+        _ => Err("(s ...) takes a start and an end offset".into()),
+    })
+}
+
+fn sexpr_str(s: &SExpr) -> Option<&str> {
+    match s { SExpr::Atom(a) | SExpr::Str(a) => Some(a), SExpr::List(_) => None }
+}
+
 pub fn sexpr_atom(s: &SExpr) -> Result<String, String> {
     match s { SExpr::Atom(a) => Ok(a.clone()), _ => Err("expected atom".into()) }
 }
@@ -176,3 +384,105 @@ pub fn sexpr_atom(s: &SExpr) -> Result<String, String> {
 pub fn sexpr_atom_or_str(s: &SExpr) -> Result<String, String> {
     match s { SExpr::Atom(a) => Ok(a.clone()), SExpr::Str(t) => Ok(t.clone()), _ => Err("expected atom or string".into()) }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::AstHeader;
+    use crate::logic::ast::ASTNode;
+    use crate::logic::bind::BoundType;
+    use crate::logic::check::TypeChecker;
+    use crate::logic::grammar::Grammar;
+    use crate::logic::grammar::tests::STLC_SPEC;
+    use crate::logic::parser::Parser;
+
+    fn check(ast: &ASTNode) -> Result<Option<BoundType>, String> {
+        let mut tc = TypeChecker::new();
+        tc.add("z".into(), BoundType::Atom("a".into()));
+        tc.check(ast)
+    }
+
+    #[test]
+    fn rebinds_rules_after_load() {
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        let ast = Parser::new(grammar.clone()).parse("(λx:a->a.x)((λy:a->a.y)z)").unwrap();
+        let path = std::env::temp_dir().join(format!("beam-rebind-{}.ast", std::process::id()));
+        ast.save(&path, &grammar, None).unwrap();
+
+        let plain = ASTNode::load(&path, &grammar).unwrap();
+        assert!(plain.nonterminals().all(|nt| nt.bound_typing_rule.is_none()));
+
+        let bound = ASTNode::load_bound(&path, &grammar).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(bound.syneq(&ast));
+        assert_eq!(bound.rules(), ast.rules());
+        for (a, b) in bound.nonterminals().zip(ast.nonterminals()) {
+            assert_eq!(a.bound_typing_rule, b.bound_typing_rule);
+        }
+        assert_eq!(check(&bound), check(&ast));
+        assert_eq!(check(&bound), Ok(Some(BoundType::Atom("a".into()))));
+    }
+
+    #[test]
+    fn reports_binding_failures_by_path() {
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        let ast = Parser::new(grammar.clone()).parse("(λx:a->a.x) z").unwrap();
+        let text = ast.serialize().replace("(rule app)", "(rule apply)");
+        let err = ASTNode::parse_bound(&text, &grammar).unwrap_err();
+        let app = ast.find_by_rule("app")[0];
+        assert!(err.contains("unknown typing rule 'apply'"), "{}", err);
+        assert!(err.starts_with(&ast.as_nonterminal().unwrap().value), "{}", err);
+        assert!(err.contains(&format!("/{}[", app.value)), "{}", err);
+        assert!(ASTNode::parse(&text, &grammar).is_ok());
+    }
+
+    #[test]
+    fn v2_keeps_spans_and_checks_the_spec() {
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        let src = "(λx:a->a.x) z";
+        let ast = Parser::new(grammar.clone()).parse(src).unwrap();
+        let path = std::env::temp_dir().join(format!("beam-v2-{}.ast", std::process::id()));
+        ast.save(&path, &grammar, Some(src)).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let (header, _) = AstHeader::read(&content).unwrap();
+        assert_eq!(header.version, 2);
+        assert_eq!(header.spec, Some(grammar.fingerprint()));
+        assert_eq!(header.matches_source(src), Some(true));
+        assert_eq!(header.matches_source("z"), Some(false));
+
+        let loaded = ASTNode::parse_bound(&content, &grammar).unwrap();
+        assert_eq!(loaded, ast);
+        assert!(loaded.pre_order().all(|n| n.span().is_some()));
+
+        // same grammar spelled differently is fine, a different grammar is not
+        let respelled = Grammar::load(&grammar.to_spec_string()).unwrap();
+        assert!(ASTNode::parse(&content, &respelled).is_ok());
+        let annotated = Grammar::load(&format!("{}\n%space around '->'\nLambda binds x in e\nsyn size = 1\n", STLC_SPEC)).unwrap();
+        assert_eq!(annotated.fingerprint(), grammar.fingerprint());
+        let other = Grammar::load(&format!("{}\nUnit ::= 'unit'\n", STLC_SPEC)).unwrap();
+        let err = ASTNode::parse(&content, &other).unwrap_err();
+        assert!(err.contains("re-parse the source"), "{}", err);
+    }
+
+    #[test]
+    fn validates_headers() {
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        let v1 = ";!ast 1\n; written by hand\n;!rules: var\n\n(N Variable (rule var) (N Identifier (b x) (T \"x\")))\n";
+        let ast = ASTNode::parse_bound(v1, &grammar).unwrap();
+        assert_eq!(ast.rules().len(), 1);
+
+        let bad = [
+            (";!ast 1\n;!rules: nope\n\n(N Variable (T \"x\"))", "does not define"),
+            (";!ast 1\n;!rules: app\n\n(N Variable (rule var) (T \"x\"))", "missing from the ';!rules:' header"),
+            (";!ast 1\n;!spec: 00\n\n(N Variable (T \"x\"))", "unknown header"),
+            (";!ast 2\n\n(N Variable (T \"x\"))", "requires a ';!spec:'"),
+            (";!ast 3\n\n(N Variable (T \"x\"))", "unsupported AST format version 3"),
+            (";!rules: var\n;!ast 1\n\n(N Variable (T \"x\"))", "expected ';!ast <version>' first"),
+        ];
+        for (input, msg) in bad {
+            let err = ASTNode::parse(input, &grammar).unwrap_err();
+            assert!(err.contains(msg), "{}: {}", input, err);
+        }
+    }
+}
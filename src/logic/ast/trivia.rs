@@ -0,0 +1,151 @@
+// Whitespace and comments around terminals, so a tree parsed with
+// `Parser::with_trivia` prints back to its exact source text.
+//
+// A terminal owns the trivia after it up to and including the end of its line
+// (trailing); everything else between two tokens leads the next one. The first
+// token also leads with whatever precedes it, the last one trails with the rest.
+
+use super::visit::VisitorMut;
+use super::{ASTNode, SourceSpan, Terminal};
+use crate::logic::tokenizer::Tokenizer;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json", serde(rename_all = "snake_case"))]
+pub enum TriviaKind {
+    Whitespace,
+    Comment,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub struct TriviaPiece {
+    pub kind: TriviaKind,
+    pub text: String,
+    /// Character offsets, like token spans
+    pub span: SourceSpan,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub struct Trivia {
+    pub leading: Vec<TriviaPiece>,
+    pub trailing: Vec<TriviaPiece>,
+}
+
+impl ASTNode {
+    /// Source text of the tree: terminals with their trivia. Exact for trees parsed with
+    /// trivia; where two adjacent terminals both lack it (e.g. inserted by an edit), a
+    /// single space keeps them apart.
+    pub fn to_source(&self) -> String {
+        let mut out = String::new();
+        let mut prev_bare = false;
+        for t in self.terminals() {
+            match &t.trivia {
+                Some(trivia) => {
+                    trivia.leading.iter().for_each(|p| out.push_str(&p.text));
+                    out.push_str(&t.value);
+                    trivia.trailing.iter().for_each(|p| out.push_str(&p.text));
+                }
+                None => {
+                    if prev_bare {
+                        out.push(' ');
+                    }
+                    out.push_str(&t.value);
+                }
+            }
+            prev_bare = t.trivia.is_none();
+        }
+        out
+    }
+}
+
+/// Split `chars[start..end]` (text between tokens) into comment and whitespace pieces,
+/// ending a whitespace piece after each newline
+fn split_gap(chars: &[char], start: usize, end: usize, tokenizer: &Tokenizer) -> Vec<TriviaPiece> {
+    let mut pieces = Vec::new();
+    let mut i = start;
+    while i < end {
+        let (kind, j) = match tokenizer.comment_end(chars, i) {
+            // an unclosed comment never tokenizes, so it cannot sit between tokens
+            Some(j) => (TriviaKind::Comment, j.map_or(end, |j| j.min(end))),
+            None => {
+                let mut j = i;
+                while j < end && tokenizer.comment_end(chars, j).is_none() {
+                    j += 1;
+                    if chars[j - 1] == '\n' { break; }
+                }
+                (TriviaKind::Whitespace, j)
+            }
+        };
+        pieces.push(TriviaPiece { kind, text: chars[i..j].iter().collect(), span: SourceSpan { start: i, end: j } });
+        i = j;
+    }
+    pieces
+}
+
+/// Attach the trivia of `input` to the terminals of `ast`, which must be the tree
+/// parsed from the tokens at `spans`
+pub(crate) fn attach_trivia(ast: &mut ASTNode, input: &str, spans: &[(usize, usize)], tokenizer: &Tokenizer) {
+    let chars: Vec<char> = input.chars().collect();
+    let mut trivia: Vec<Trivia> = vec![Trivia::default(); spans.len()];
+    for i in 0..=spans.len() {
+        let start = if i == 0 { 0 } else { spans[i - 1].1 };
+        let end = spans.get(i).map_or(chars.len(), |s| s.0);
+        let mut pieces = split_gap(&chars, start, end, tokenizer).into_iter();
+        if i > 0 {
+            // trailing: up to the end of the line, or everything after the last token
+            let last = i == spans.len();
+            for piece in pieces.by_ref() {
+                let ends_line = piece.text.ends_with('\n');
+                trivia[i - 1].trailing.push(piece);
+                if ends_line && !last { break; }
+            }
+        }
+        if let Some(next) = trivia.get_mut(i) {
+            next.leading.extend(pieces);
+        }
+    }
+
+    struct Attach(std::vec::IntoIter<Trivia>);
+    impl VisitorMut for Attach {
+        fn visit_terminal_mut(&mut self, t: &mut Terminal) {
+            t.trivia = self.0.next().map(Box::new);
+        }
+    }
+    ast.accept_mut(&mut Attach(trivia.into_iter()));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::logic::ast::ASTNode;
+    use crate::logic::grammar::Grammar;
+    use crate::logic::grammar::tests::STLC_SPEC;
+    use crate::logic::parser::Parser;
+
+    #[test]
+    fn reconstructs_source_exactly() {
+        let spec = format!("{}\n\n%comment '//'\n%comment '(*' '*)'\n", STLC_SPEC);
+        let grammar = Grammar::load(&spec).unwrap();
+        let src = "  // identity\n(λx:a->a. (* body *) x)\n\n   y // arg\n";
+        let ast = Parser::new(grammar.clone()).with_trivia().parse(src).unwrap();
+        assert_eq!(ast.to_source(), src);
+
+        // comments are skipped either way, and trivia does not affect syntactic equality
+        let plain = Parser::new(grammar).parse(src).unwrap();
+        assert!(plain.syneq(&ast));
+        assert!(plain.terminals().all(|t| t.trivia.is_none()));
+
+        let first = ast.terminals().next().unwrap().trivia.as_ref().unwrap();
+        assert_eq!(first.leading.iter().map(|p| p.text.as_str()).collect::<Vec<_>>(), vec!["  ", "// identity", "\n"]);
+        let dot = ast.terminals().find(|t| t.value == ".").unwrap().trivia.as_ref().unwrap();
+        assert_eq!(dot.trailing.len(), 3);
+
+        // edited trees still print: a new bare terminal keeps its distance
+        let mut edited = ast.clone();
+        if let ASTNode::Nonterminal(nt) = &mut edited {
+            nt.children.push(Parser::new(Grammar::load(STLC_SPEC).unwrap()).parse("z").unwrap());
+        }
+        assert!(edited.to_source().starts_with(src));
+    }
+}
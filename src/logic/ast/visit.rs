@@ -0,0 +1,272 @@
+// Traversal over ASTNode: borrowing visitors, a rewriting fold, pre/post-order
+// iterators and the usual lookups, so callers don't re-implement the recursion.
+
+use super::{ASTNode, NonTerminal, Terminal};
+
+/// Read-only traversal. Override a method to act on a node; call the matching
+/// `walk_*` function from it to keep descending.
+pub trait Visitor<'a> {
+    fn visit_node(&mut self, node: &'a ASTNode) {
+        walk_node(self, node)
+    }
+
+    fn visit_nonterminal(&mut self, nt: &'a NonTerminal) {
+        walk_nonterminal(self, nt)
+    }
+
+    fn visit_terminal(&mut self, _t: &'a Terminal) {}
+}
+
+pub fn walk_node<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, node: &'a ASTNode) {
+    match node {
+        ASTNode::Terminal(t) => visitor.visit_terminal(t),
+        ASTNode::Nonterminal(nt) => visitor.visit_nonterminal(nt),
+    }
+}
+
+pub fn walk_nonterminal<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, nt: &'a NonTerminal) {
+    for child in &nt.children {
+        visitor.visit_node(child);
+    }
+}
+
+/// In-place traversal, same shape as `Visitor`
+pub trait VisitorMut {
+    fn visit_node_mut(&mut self, node: &mut ASTNode) {
+        walk_node_mut(self, node)
+    }
+
+    fn visit_nonterminal_mut(&mut self, nt: &mut NonTerminal) {
+        walk_nonterminal_mut(self, nt)
+    }
+
+    fn visit_terminal_mut(&mut self, _t: &mut Terminal) {}
+}
+
+pub fn walk_node_mut<V: VisitorMut + ?Sized>(visitor: &mut V, node: &mut ASTNode) {
+    match node {
+        ASTNode::Terminal(t) => visitor.visit_terminal_mut(t),
+        ASTNode::Nonterminal(nt) => visitor.visit_nonterminal_mut(nt),
+    }
+}
+
+pub fn walk_nonterminal_mut<V: VisitorMut + ?Sized>(visitor: &mut V, nt: &mut NonTerminal) {
+    for child in &mut nt.children {
+        visitor.visit_node_mut(child);
+    }
+}
+
+/// Bottom-up rewriting by value. Children are folded before their parent, and
+/// any node may be replaced by a node of the other kind.
+pub trait Fold {
+    fn fold_node(&mut self, node: ASTNode) -> ASTNode {
+        match node {
+            ASTNode::Terminal(t) => self.fold_terminal(t),
+            ASTNode::Nonterminal(nt) => self.fold_nonterminal(nt),
+        }
+    }
+
+    fn fold_nonterminal(&mut self, nt: NonTerminal) -> ASTNode {
+        ASTNode::Nonterminal(fold_children(self, nt))
+    }
+
+    fn fold_terminal(&mut self, t: Terminal) -> ASTNode {
+        ASTNode::Terminal(t)
+    }
+}
+
+/// Fold every child of `nt`, keeping the node itself
+pub fn fold_children<F: Fold + ?Sized>(folder: &mut F, mut nt: NonTerminal) -> NonTerminal {
+    nt.children = std::mem::take(&mut nt.children)
+        .into_iter()
+        .map(|c| folder.fold_node(c))
+        .collect();
+    nt
+}
+
+/// Parents before children, children left to right
+pub struct PreOrder<'a> {
+    stack: Vec<&'a ASTNode>,
+}
+
+impl<'a> Iterator for PreOrder<'a> {
+    type Item = &'a ASTNode;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        if let ASTNode::Nonterminal(nt) = node {
+            self.stack.extend(nt.children.iter().rev());
+        }
+        Some(node)
+    }
+}
+
+/// Children left to right, then their parent
+pub struct PostOrder<'a> {
+    /// Nodes still open, with the index of the next child to descend into
+    stack: Vec<(&'a ASTNode, usize)>,
+}
+
+impl<'a> Iterator for PostOrder<'a> {
+    type Item = &'a ASTNode;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (node, next_child) = self.stack.last_mut()?;
+            let node = *node;
+            match node.children().and_then(|c| c.get(*next_child)) {
+                Some(child) => {
+                    *next_child += 1;
+                    self.stack.push((child, 0));
+                }
+                None => {
+                    self.stack.pop();
+                    return Some(node);
+                }
+            }
+        }
+    }
+}
+
+impl ASTNode {
+    pub fn accept<'a, V: Visitor<'a> + ?Sized>(&'a self, visitor: &mut V) {
+        visitor.visit_node(self)
+    }
+
+    pub fn accept_mut<V: VisitorMut + ?Sized>(&mut self, visitor: &mut V) {
+        visitor.visit_node_mut(self)
+    }
+
+    pub fn fold<F: Fold + ?Sized>(self, folder: &mut F) -> ASTNode {
+        folder.fold_node(self)
+    }
+
+    pub fn pre_order(&self) -> PreOrder<'_> {
+        PreOrder { stack: vec![self] }
+    }
+
+    pub fn post_order(&self) -> PostOrder<'_> {
+        PostOrder { stack: vec![(self, 0)] }
+    }
+
+    /// Nonterminals in the subtree, pre-order
+    pub fn nonterminals(&self) -> impl Iterator<Item = &NonTerminal> {
+        self.pre_order().filter_map(|n| match n {
+            ASTNode::Nonterminal(nt) => Some(nt),
+            ASTNode::Terminal(_) => None,
+        })
+    }
+
+    /// Terminals in the subtree, left to right
+    pub fn terminals(&self) -> impl Iterator<Item = &Terminal> {
+        self.pre_order().filter_map(|n| match n {
+            ASTNode::Terminal(t) => Some(t),
+            ASTNode::Nonterminal(_) => None,
+        })
+    }
+
+    /// Nodes whose value (nonterminal name or terminal text) is `value`, pre-order
+    pub fn find_by_value(&self, value: &str) -> Vec<&ASTNode> {
+        self.pre_order().filter(|n| n.value() == value).collect()
+    }
+
+    /// Nonterminals bound to the typing rule `rule`, pre-order
+    pub fn find_by_rule(&self, rule: &str) -> Vec<&NonTerminal> {
+        self.nonterminals().filter(|nt| nt.has_rule(rule)).collect()
+    }
+
+    /// First node (pre-order) carrying the grammar binding `name`, as in `Variable[x]`
+    pub fn find_binding(&self, name: &str) -> Option<&ASTNode> {
+        self.pre_order().find(|n| n.binding().map(String::as_str) == Some(name))
+    }
+
+    /// Deepest node whose span contains the character `offset`
+    pub fn node_at_offset(&self, offset: usize) -> Option<&ASTNode> {
+        self.pre_order()
+            .filter(|n| n.span().is_some_and(|s| s.start <= offset && offset < s.end))
+            .last()
+    }
+}
+
+impl NonTerminal {
+    /// Every node below this one, pre-order (the node itself excluded)
+    pub fn descendants(&self) -> PreOrder<'_> {
+        PreOrder { stack: self.children.iter().rev().collect() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::grammar::Grammar;
+    use crate::logic::grammar::tests::STLC_SPEC;
+    use crate::logic::parser::Parser;
+
+    fn parse(src: &str) -> ASTNode {
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        Parser::new(grammar).parse(src).unwrap()
+    }
+
+    #[test]
+    fn traversals_and_queries() {
+        let src = "(λx:a->a.x) y";
+        let ast = parse(src);
+
+        // both orders visit every node exactly once
+        assert_eq!(ast.pre_order().count(), ast.node_count());
+        assert_eq!(ast.post_order().count(), ast.node_count());
+        assert!(std::ptr::eq(ast.pre_order().next().unwrap(), &ast));
+        assert!(std::ptr::eq(ast.post_order().last().unwrap(), &ast));
+
+        assert_eq!(ast.find_by_value("Lambda").len(), 1);
+        assert_eq!(ast.find_by_rule("app").len(), 1);
+        assert_eq!(ast.find_by_rule("var").len(), 3);
+        assert_eq!(ast.find_binding("x").unwrap().value(), "Variable");
+        assert!(ast.find_binding("nope").is_none());
+
+        let y = src.chars().position(|c| c == 'y').unwrap();
+        assert_eq!(ast.node_at_offset(y).unwrap().value(), "y");
+        assert!(ast.node_at_offset(src.len() + 10).is_none());
+    }
+
+    #[test]
+    fn visitor_fold_and_mut() {
+        struct Terminals<'a>(Vec<&'a str>);
+        impl<'a> Visitor<'a> for Terminals<'a> {
+            fn visit_terminal(&mut self, t: &'a Terminal) {
+                self.0.push(&t.value);
+            }
+        }
+
+        let mut ast = parse("λx:a->a.x");
+        let mut terms = Terminals(vec![]);
+        ast.accept(&mut terms);
+        assert_eq!(terms.0, vec!["λ", "x", ":", "a", "->", "a", ".", "x"]);
+
+        struct Rename;
+        impl VisitorMut for Rename {
+            fn visit_terminal_mut(&mut self, t: &mut Terminal) {
+                if t.value == "x" {
+                    t.value = "z".into();
+                }
+            }
+        }
+        ast.accept_mut(&mut Rename);
+        assert_eq!(ast.find_by_value("z").len(), 2);
+
+        // collapse every Variable into its identifier terminal
+        struct Collapse;
+        impl Fold for Collapse {
+            fn fold_nonterminal(&mut self, nt: NonTerminal) -> ASTNode {
+                let nt = fold_children(self, nt);
+                match nt.value.as_str() {
+                    "Variable" | "Identifier" if nt.children.len() == 1 => nt.children.into_iter().next().unwrap(),
+                    _ => ASTNode::Nonterminal(nt),
+                }
+            }
+        }
+        let folded = ast.fold(&mut Collapse);
+        assert!(folded.find_by_value("Variable").is_empty());
+        assert_eq!(folded.find_by_value("z").len(), 2);
+    }
+}
@@ -0,0 +1,439 @@
+// The expression language of attribute equations: integers, booleans, strings and
+// sets of strings, with arithmetic, comparisons, `if`, set literals and a few
+// aggregates over the children of a node.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttrValue {
+    Int(i64),
+    Bool(bool),
+    Str(String),
+    Set(BTreeSet<String>),
+    /// `children.a`, one value per child
+    List(Vec<AttrValue>),
+}
+
+impl fmt::Display for AttrValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AttrValue::Int(n) => write!(f, "{}", n),
+            AttrValue::Bool(b) => write!(f, "{}", b),
+            AttrValue::Str(s) => write!(f, "{:?}", s),
+            AttrValue::Set(s) => write!(f, "{{{}}}", s.iter().cloned().collect::<Vec<_>>().join(", ")),
+            AttrValue::List(l) => write!(f, "[{}]", l.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(", ")),
+        }
+    }
+}
+
+impl AttrValue {
+    fn kind(&self) -> &'static str {
+        match self {
+            AttrValue::Int(_) => "integer",
+            AttrValue::Bool(_) => "boolean",
+            AttrValue::Str(_) => "string",
+            AttrValue::Set(_) => "set",
+            AttrValue::List(_) => "list",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Int(i64),
+    Bool(bool),
+    Str(String),
+    /// `{e, ...}`: a set of strings
+    Set(Vec<Expr>),
+    /// `a` of the node itself, or `b.a` of the node bound to `b` below it;
+    /// the name `text` gives the source text instead
+    Attr { child: Option<String>, name: String },
+    /// `children.a`
+    Children(String),
+    Call(String, Vec<Expr>),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    If(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+const FUNCTIONS: &[&str] = &["sum", "max", "union", "len", "int"];
+
+const OVERFLOW: &str = "integer overflow or division by zero";
+
+impl Expr {
+    pub fn parse(text: &str) -> Result<Expr, String> {
+        let tokens = tokenize(text)?;
+        let mut p = ExprParser { tokens, pos: 0 };
+        let expr = p.expr()?;
+        match p.tokens.get(p.pos) {
+            None => Ok(expr),
+            Some(t) => Err(format!("unexpected '{}' in expression", t)),
+        }
+    }
+
+    /// Attribute names the expression reads (`text` excluded)
+    pub fn attributes(&self, out: &mut Vec<String>) {
+        match self {
+            Expr::Attr { name, .. } if name != "text" => out.push(name.clone()),
+            Expr::Children(name) => out.push(name.clone()),
+            Expr::Set(items) | Expr::Call(_, items) => items.iter().for_each(|e| e.attributes(out)),
+            Expr::Not(e) | Expr::Neg(e) => e.attributes(out),
+            Expr::Binary(_, a, b) => {
+                a.attributes(out);
+                b.attributes(out);
+            }
+            Expr::If(c, a, b) => [c, a, b].iter().for_each(|e| e.attributes(out)),
+            _ => {}
+        }
+    }
+
+    /// Evaluate with `lookup` resolving `Attr` and `Children` references
+    pub fn eval(&self, lookup: &mut dyn FnMut(&Expr) -> Result<AttrValue, String>) -> Result<AttrValue, String> {
+        Ok(match self {
+            Expr::Int(n) => AttrValue::Int(*n),
+            Expr::Bool(b) => AttrValue::Bool(*b),
+            Expr::Str(s) => AttrValue::Str(s.clone()),
+            Expr::Set(items) => {
+                let mut set = BTreeSet::new();
+                for item in items {
+                    match item.eval(lookup)? {
+                        AttrValue::Str(s) => set.insert(s),
+                        other => return Err(format!("set elements must be strings, found {}", other.kind())),
+                    };
+                }
+                AttrValue::Set(set)
+            }
+            Expr::Attr { .. } | Expr::Children(_) => lookup(self)?,
+            Expr::Call(f, args) => {
+                let args = args.iter().map(|a| a.eval(lookup)).collect::<Result<Vec<_>, _>>()?;
+                call(f, args)?
+            }
+            Expr::Not(e) => match e.eval(lookup)? {
+                AttrValue::Bool(b) => AttrValue::Bool(!b),
+                other => return Err(format!("'!' expects a boolean, found {}", other.kind())),
+            },
+            Expr::Neg(e) => match e.eval(lookup)? {
+                AttrValue::Int(n) => AttrValue::Int(n.checked_neg().ok_or(OVERFLOW)?),
+                other => return Err(format!("'-' expects an integer, found {}", other.kind())),
+            },
+            Expr::Binary(BinOp::And, a, b) => AttrValue::Bool(truth(a.eval(lookup)?)? && truth(b.eval(lookup)?)?),
+            Expr::Binary(BinOp::Or, a, b) => AttrValue::Bool(truth(a.eval(lookup)?)? || truth(b.eval(lookup)?)?),
+            Expr::Binary(op, a, b) => binary(*op, a.eval(lookup)?, b.eval(lookup)?)?,
+            Expr::If(c, a, b) => {
+                if truth(c.eval(lookup)?)? {
+                    a.eval(lookup)?
+                } else {
+                    b.eval(lookup)?
+                }
+            }
+        })
+    }
+}
+
+fn truth(v: AttrValue) -> Result<bool, String> {
+    match v {
+        AttrValue::Bool(b) => Ok(b),
+        other => Err(format!("expected a boolean, found {}", other.kind())),
+    }
+}
+
+fn binary(op: BinOp, a: AttrValue, b: AttrValue) -> Result<AttrValue, String> {
+    use AttrValue::*;
+    let int = |r: Option<i64>| r.map(Int).ok_or_else(|| OVERFLOW.to_string());
+    Ok(match (op, a, b) {
+        (BinOp::Eq, a, b) => Bool(a == b),
+        (BinOp::Ne, a, b) => Bool(a != b),
+        (BinOp::Add, Int(x), Int(y)) => int(x.checked_add(y))?,
+        (BinOp::Sub, Int(x), Int(y)) => int(x.checked_sub(y))?,
+        (BinOp::Mul, Int(x), Int(y)) => int(x.checked_mul(y))?,
+        (BinOp::Div, Int(x), Int(y)) => int(x.checked_div(y))?,
+        (BinOp::Rem, Int(x), Int(y)) => int(x.checked_rem(y))?,
+        (BinOp::Lt, Int(x), Int(y)) => Bool(x < y),
+        (BinOp::Le, Int(x), Int(y)) => Bool(x <= y),
+        (BinOp::Gt, Int(x), Int(y)) => Bool(x > y),
+        (BinOp::Ge, Int(x), Int(y)) => Bool(x >= y),
+        (BinOp::Add, Str(x), Str(y)) => Str(x + &y),
+        (BinOp::Add, Set(x), Set(y)) => Set(&x | &y),
+        (BinOp::Sub, Set(x), Set(y)) => Set(&x - &y),
+        (op, a, b) => return Err(format!("cannot apply {:?} to {} and {}", op, a.kind(), b.kind())),
+    })
+}
+
+fn call(f: &str, args: Vec<AttrValue>) -> Result<AttrValue, String> {
+    let list = |args: Vec<AttrValue>| match <[AttrValue; 1]>::try_from(args) {
+        Ok([AttrValue::List(items)]) => Ok(items),
+        _ => Err(format!("{}() takes one children.<attr> list", f)),
+    };
+    match f {
+        "sum" | "max" => {
+            let mut ints = Vec::new();
+            for v in list(args)? {
+                match v {
+                    AttrValue::Int(n) => ints.push(n),
+                    other => return Err(format!("{}() expects integers, found {}", f, other.kind())),
+                }
+            }
+            Ok(AttrValue::Int(match f {
+                "sum" => ints.into_iter().try_fold(0i64, i64::checked_add).ok_or(OVERFLOW)?,
+                _ => ints.into_iter().max().unwrap_or(0),
+            }))
+        }
+        "union" => {
+            let mut out = BTreeSet::new();
+            for v in list(args)? {
+                match v {
+                    AttrValue::Set(s) => out.extend(s),
+                    other => return Err(format!("union() expects sets, found {}", other.kind())),
+                }
+            }
+            Ok(AttrValue::Set(out))
+        }
+        "len" => match args.as_slice() {
+            [AttrValue::Str(s)] => Ok(AttrValue::Int(s.chars().count() as i64)),
+            [AttrValue::Set(s)] => Ok(AttrValue::Int(s.len() as i64)),
+            [AttrValue::List(l)] => Ok(AttrValue::Int(l.len() as i64)),
+            _ => Err("len() takes one string, set or list".into()),
+        },
+        "int" => match args.as_slice() {
+            [AttrValue::Str(s)] => s.trim().parse().map(AttrValue::Int).map_err(|_| format!("int(): '{}' is not an integer", s)),
+            _ => Err("int() takes one string".into()),
+        },
+        _ => Err(format!("unknown function '{}'", f)),
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<String>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '"' {
+            let mut s = String::from('"');
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err("unterminated string in expression".into()),
+                    Some('"') => break,
+                    Some('\\') => {
+                        s.push(*chars.get(i + 1).ok_or("unterminated string in expression")?);
+                        i += 2;
+                    }
+                    Some(c) => {
+                        s.push(*c);
+                        i += 1;
+                    }
+                }
+            }
+            tokens.push(s);
+            i += 1;
+        } else if c.is_alphanumeric() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(chars[start..i].iter().collect());
+        } else {
+            let two: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+            if ["==", "!=", "<=", ">=", "&&", "||"].contains(&two.as_str()) {
+                tokens.push(two);
+                i += 2;
+            } else if "+-*/%<>!(){},.".contains(c) {
+                tokens.push(c.to_string());
+                i += 1;
+            } else {
+                return Err(format!("unexpected '{}' in expression", c));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct ExprParser {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl ExprParser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Result<String, String> {
+        let t = self.tokens.get(self.pos).cloned().ok_or("unexpected end of expression")?;
+        self.pos += 1;
+        Ok(t)
+    }
+
+    fn expect(&mut self, tok: &str) -> Result<(), String> {
+        match self.next()? {
+            t if t == tok => Ok(()),
+            t => Err(format!("expected '{}', found '{}'", tok, t)),
+        }
+    }
+
+    fn eat(&mut self, tok: &str) -> bool {
+        let found = self.peek() == Some(tok);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        if self.eat("if") {
+            let c = self.expr()?;
+            self.expect("then")?;
+            let a = self.expr()?;
+            self.expect("else")?;
+            let b = self.expr()?;
+            return Ok(Expr::If(Box::new(c), Box::new(a), Box::new(b)));
+        }
+        self.binary(0)
+    }
+
+    /// Operators by precedence level, loosest first
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        const LEVELS: &[&[(&str, BinOp)]] = &[
+            &[("||", BinOp::Or)],
+            &[("&&", BinOp::And)],
+            &[("==", BinOp::Eq), ("!=", BinOp::Ne), ("<=", BinOp::Le), (">=", BinOp::Ge), ("<", BinOp::Lt), (">", BinOp::Gt)],
+            &[("+", BinOp::Add), ("-", BinOp::Sub)],
+            &[("*", BinOp::Mul), ("/", BinOp::Div), ("%", BinOp::Rem)],
+        ];
+        let Some(ops) = LEVELS.get(level) else { return self.unary() };
+        let mut left = self.binary(level + 1)?;
+        while let Some(&(_, op)) = ops.iter().find(|(t, _)| self.peek() == Some(*t)) {
+            self.pos += 1;
+            let right = self.binary(level + 1)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("-") {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        let tok = self.next()?;
+        if let Some(s) = tok.strip_prefix('"') {
+            return Ok(Expr::Str(s.to_string()));
+        }
+        if tok.chars().all(|c| c.is_ascii_digit()) {
+            return tok.parse().map(Expr::Int).map_err(|_| format!("integer '{}' is too large", tok));
+        }
+        match tok.as_str() {
+            "(" => {
+                let e = self.expr()?;
+                self.expect(")")?;
+                Ok(e)
+            }
+            "{" => {
+                let mut items = Vec::new();
+                if !self.eat("}") {
+                    loop {
+                        items.push(self.expr()?);
+                        if self.eat("}") {
+                            break;
+                        }
+                        self.expect(",")?;
+                    }
+                }
+                Ok(Expr::Set(items))
+            }
+            "true" => Ok(Expr::Bool(true)),
+            "false" => Ok(Expr::Bool(false)),
+            name if name.starts_with(|c: char| c.is_alphabetic() || c == '_') => {
+                if self.eat("(") {
+                    if !FUNCTIONS.contains(&name) {
+                        return Err(format!("unknown function '{}'", name));
+                    }
+                    let mut args = Vec::new();
+                    if !self.eat(")") {
+                        loop {
+                            args.push(self.expr()?);
+                            if self.eat(")") {
+                                break;
+                            }
+                            self.expect(",")?;
+                        }
+                    }
+                    return Ok(Expr::Call(name.to_string(), args));
+                }
+                if self.eat(".") {
+                    let attr = self.next()?;
+                    return Ok(if name == "children" {
+                        Expr::Children(attr)
+                    } else {
+                        Expr::Attr { child: Some(name.to_string()), name: attr }
+                    });
+                }
+                Ok(Expr::Attr { child: None, name: name.to_string() })
+            }
+            other => Err(format!("unexpected '{}' in expression", other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(text: &str) -> Result<AttrValue, String> {
+        Expr::parse(text)?.eval(&mut |e| match e {
+            Expr::Children(_) => Ok(AttrValue::List(vec![AttrValue::Int(2), AttrValue::Int(5)])),
+            Expr::Attr { child, .. } => Ok(AttrValue::Str(child.clone().unwrap_or_else(|| "self".into()))),
+            _ => unreachable!(),
+        })
+    }
+
+    #[test]
+    fn parses_and_evaluates() {
+        assert_eq!(eval("1 + 2 * 3 - -1"), Ok(AttrValue::Int(8)));
+        assert_eq!(eval("1 + sum(children.size)"), Ok(AttrValue::Int(8)));
+        assert_eq!(eval("max(children.size) >= 5 && !false"), Ok(AttrValue::Bool(true)));
+        assert_eq!(eval("if 1 < 2 then \"a\" + \"b\" else \"c\""), Ok(AttrValue::Str("ab".into())));
+        assert_eq!(eval("{x.text, \"y\"} - {\"y\"}").unwrap().to_string(), "{x}");
+        assert_eq!(eval("len(text) + int(\" 4 \")"), Ok(AttrValue::Int(8)));
+
+        assert!(eval("1 + \"a\"").unwrap_err().contains("cannot apply"));
+        assert!(eval("1 / 0").is_err());
+        assert_eq!(eval("-(-9223372036854775807 - 1)"), Err(OVERFLOW.to_string()));
+        let big = AttrValue::List(vec![AttrValue::Int(i64::MAX), AttrValue::Int(1)]);
+        assert_eq!(call("sum", vec![big]), Err(OVERFLOW.to_string()));
+        assert!(Expr::parse("frob(1)").unwrap_err().contains("unknown function"));
+        assert!(Expr::parse("1 +").is_err());
+        assert!(Expr::parse("(1").is_err());
+        let mut names = Vec::new();
+        Expr::parse("e.size + sum(children.depth) + len(text)").unwrap().attributes(&mut names);
+        assert_eq!(names, vec!["size", "depth"]);
+    }
+}
@@ -0,0 +1,346 @@
+// Attribute grammars: per-node values declared in the spec next to the typing rules.
+//
+//     syn size = 1 + sum(children.size)          default for every node
+//     syn size on Variable = 1                   override for one nonterminal
+//     inh depth = 0                              value at the root
+//     inh depth on Lambda.e = depth + 1          value for the child Lambda binds to `e`
+//
+// A synthesized attribute is computed from the node itself and what is below it; an
+// inherited one flows down, each node copying its parent's value unless an equation
+// for its binding in an ancestor says otherwise. Equations are evaluated on demand,
+// memoized per node, and a value that depends on itself is reported as a cycle.
+
+pub mod expr;
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::logic::ast::arena::NodeKind;
+use crate::logic::ast::{ASTNode, AstArena, NodeId};
+use crate::logic::grammar::{Grammar, Symbol};
+pub use expr::{AttrValue, Expr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json", serde(rename_all = "snake_case"))]
+pub enum AttrKind {
+    /// `syn`: computed from the node and its subtree
+    Synthesized,
+    /// `inh`: passed down from the parent
+    Inherited,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json", serde(tag = "kind", rename_all = "snake_case"))]
+pub enum AttrTarget {
+    /// Every node for `syn`, the root for `inh`
+    Default,
+    /// `on Name`, synthesized only
+    Nonterminal { name: String },
+    /// `on Name.b`, inherited only: the node `Name` binds to `b`
+    Child { parent: String, binding: String },
+}
+
+/// A `syn` or `inh` line of a spec
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub struct AttributeEquation {
+    pub kind: AttrKind,
+    pub name: String,
+    pub target: AttrTarget,
+    /// Source text of the right-hand side
+    pub expr: String,
+    /// 1-based line in the spec
+    pub line: usize,
+}
+
+impl AttributeEquation {
+    /// `syn` or `inh` followed by an equation
+    pub fn is_attribute_line(line: &str) -> bool {
+        let line = line.trim_start();
+        (line.starts_with("syn ") || line.starts_with("inh ")) && line.contains('=') && !line.contains("::=")
+    }
+
+    /// Parse one `syn name [on Target] = expr` or `inh name [on Parent.b] = expr` line
+    pub fn parse(line: &str, line_no: usize) -> Result<Self, String> {
+        let err = |msg: &str| format!("line {}: {}: {}", line_no, msg, line.trim());
+        let (lhs, expr) = line.trim().split_once('=').ok_or_else(|| err("expected '='"))?;
+        let words: Vec<&str> = lhs.split_whitespace().collect();
+        let kind = match words.first() {
+            Some(&"syn") => AttrKind::Synthesized,
+            Some(&"inh") => AttrKind::Inherited,
+            _ => return Err(err("expected 'syn' or 'inh'")),
+        };
+        let target = match words.as_slice() {
+            [_, _] => AttrTarget::Default,
+            [_, _, "on", target] => match (kind, target.split_once('.')) {
+                (AttrKind::Synthesized, None) => AttrTarget::Nonterminal { name: target.to_string() },
+                (AttrKind::Inherited, Some((parent, binding))) if !parent.is_empty() && !binding.is_empty() => {
+                    AttrTarget::Child { parent: parent.to_string(), binding: binding.to_string() }
+                }
+                (AttrKind::Synthesized, Some(_)) => return Err(err("synthesized attributes are defined 'on Nonterminal'")),
+                (AttrKind::Inherited, _) => return Err(err("inherited attributes are defined 'on Nonterminal.binding'")),
+            },
+            _ => return Err(err("expected 'syn name [on Target] = expression'")),
+        };
+        let name = words[1];
+        if !name.chars().all(|c| c.is_alphanumeric() || c == '_') || name == "text" || name == "children" {
+            return Err(err(&format!("invalid attribute name '{}'", name)));
+        }
+        let expr = expr.trim();
+        Expr::parse(expr).map_err(|e| err(&e))?;
+        Ok(AttributeEquation { kind, name: name.to_string(), target, expr: expr.to_string(), line: line_no })
+    }
+
+    pub fn to_line(&self) -> String {
+        let kind = match self.kind {
+            AttrKind::Synthesized => "syn",
+            AttrKind::Inherited => "inh",
+        };
+        match &self.target {
+            AttrTarget::Default => format!("{} {} = {}", kind, self.name, self.expr),
+            AttrTarget::Nonterminal { name } => format!("{} {} on {} = {}", kind, self.name, name, self.expr),
+            AttrTarget::Child { parent, binding } => format!("{} {} on {}.{} = {}", kind, self.name, parent, binding, self.expr),
+        }
+    }
+}
+
+fn binds(symbols: &[Symbol], binding: &str) -> bool {
+    symbols.iter().any(|s| s.binding().is_some_and(|b| b == binding) || s.group_symbols().is_some_and(|g| binds(g, binding)))
+}
+
+impl Grammar {
+    /// Check the attribute equations against each other and the productions
+    pub fn check_attributes(&self) -> Result<(), String> {
+        let mut kinds: HashMap<&str, AttrKind> = HashMap::new();
+        let mut seen = HashSet::new();
+        for eq in &self.attributes {
+            let err = |msg: String| format!("line {}: {}: {}", eq.line, msg, eq.to_line());
+            if kinds.insert(&eq.name, eq.kind).is_some_and(|k| k != eq.kind) {
+                return Err(err(format!("'{}' is declared both synthesized and inherited", eq.name)));
+            }
+            if !seen.insert((&eq.name, &eq.target)) {
+                return Err(err(format!("duplicate equation for '{}'", eq.name)));
+            }
+            match &eq.target {
+                AttrTarget::Default => {}
+                AttrTarget::Nonterminal { name } if !self.productions.contains_key(name) => {
+                    return Err(err(format!("unknown nonterminal '{}'", name)));
+                }
+                AttrTarget::Nonterminal { .. } => {}
+                AttrTarget::Child { parent, binding } => {
+                    let productions = self.productions.get(parent).ok_or_else(|| err(format!("unknown nonterminal '{}'", parent)))?;
+                    if !productions.iter().any(|p| binds(&p.rhs, binding)) {
+                        return Err(err(format!("{} binds nothing to '{}'", parent, binding)));
+                    }
+                }
+            }
+        }
+        for eq in &self.attributes {
+            let mut used = Vec::new();
+            Expr::parse(&eq.expr).map_err(|e| format!("line {}: {}", eq.line, e))?.attributes(&mut used);
+            if let Some(unknown) = used.iter().find(|a| !kinds.contains_key(a.as_str())) {
+                return Err(format!("line {}: unknown attribute '{}': {}", eq.line, unknown, eq.to_line()));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Evaluates attributes of one tree on demand, memoizing every value
+pub struct AttributeEvaluator {
+    arena: AstArena,
+    kinds: HashMap<String, AttrKind>,
+    equations: HashMap<(String, AttrTarget), Expr>,
+    memo: HashMap<(usize, String), AttrValue>,
+    /// Values being computed, for cycle detection
+    active: HashSet<(usize, String)>,
+}
+
+impl AttributeEvaluator {
+    pub fn new(grammar: &Grammar, ast: &ASTNode) -> Result<Self, String> {
+        let mut kinds = HashMap::new();
+        let mut equations = HashMap::new();
+        for eq in &grammar.attributes {
+            kinds.insert(eq.name.clone(), eq.kind);
+            let expr = Expr::parse(&eq.expr).map_err(|e| format!("line {}: {}", eq.line, e))?;
+            equations.insert((eq.name.clone(), eq.target.clone()), expr);
+        }
+        let arena = AstArena::from(ast.clone());
+        Ok(AttributeEvaluator { arena, kinds, equations, memo: HashMap::new(), active: HashSet::new() })
+    }
+
+    pub fn arena(&self) -> &AstArena {
+        &self.arena
+    }
+
+    /// Attribute `name` of the root
+    pub fn root(&mut self, name: &str) -> Result<AttrValue, String> {
+        self.get(self.arena.root(), name)
+    }
+
+    /// Attribute `name` of node `id`
+    pub fn get(&mut self, id: NodeId, name: &str) -> Result<AttrValue, String> {
+        let key = (id.0, name.to_string());
+        if let Some(value) = self.memo.get(&key) {
+            return Ok(value.clone());
+        }
+        let kind = *self.kinds.get(name).ok_or_else(|| format!("unknown attribute '{}'", name))?;
+        if !self.active.insert(key.clone()) {
+            return Err(format!("cyclic attribute '{}' at {}", name, self.describe(id)));
+        }
+        let value = match kind {
+            AttrKind::Synthesized => self.synthesized(id, name),
+            AttrKind::Inherited => self.inherited(id, name),
+        };
+        self.active.remove(&key);
+        let value = value?;
+        self.memo.insert(key, value.clone());
+        Ok(value)
+    }
+
+    fn synthesized(&mut self, id: NodeId, name: &str) -> Result<AttrValue, String> {
+        let node = &self.arena[id];
+        let own = (node.kind == NodeKind::Nonterminal)
+            .then(|| (name.to_string(), AttrTarget::Nonterminal { name: node.value.clone() }))
+            .filter(|key| self.equations.contains_key(key));
+        let key = own.unwrap_or((name.to_string(), AttrTarget::Default));
+        match self.equations.get(&key).cloned() {
+            Some(expr) => self.eval(id, &expr),
+            None => Err(format!("no equation for '{}' at {}", name, self.describe(id))),
+        }
+    }
+
+    fn inherited(&mut self, id: NodeId, name: &str) -> Result<AttrValue, String> {
+        let Some(parent) = self.arena.parent(id) else {
+            return match self.equations.get(&(name.to_string(), AttrTarget::Default)).cloned() {
+                Some(expr) => self.eval(id, &expr),
+                None => Err(format!("no root value for inherited '{}'", name)),
+            };
+        };
+        if let Some(binding) = self.arena[id].binding.clone() {
+            for owner in self.arena.ancestors(id).collect::<Vec<_>>() {
+                let target = AttrTarget::Child { parent: self.arena[owner].value.clone(), binding: binding.clone() };
+                if let Some(expr) = self.equations.get(&(name.to_string(), target)).cloned()
+                    && self.bound(owner, &binding) == Some(id)
+                {
+                    return self.eval(owner, &expr);
+                }
+            }
+        }
+        self.get(parent, name)
+    }
+
+    /// The nearest node below `id` bound to `binding`
+    fn bound(&self, id: NodeId, binding: &str) -> Option<NodeId> {
+        let mut queue: VecDeque<NodeId> = self.arena.children(id).iter().copied().collect();
+        while let Some(n) = queue.pop_front() {
+            if self.arena[n].binding.as_deref() == Some(binding) {
+                return Some(n);
+            }
+            queue.extend(self.arena.children(n));
+        }
+        None
+    }
+
+    /// Concatenated terminals of the subtree
+    fn text(&self, id: NodeId) -> String {
+        self.arena.subtree(id).filter(|n| self.arena[*n].kind == NodeKind::Terminal).map(|n| self.arena[n].value.as_str()).collect()
+    }
+
+    fn describe(&self, id: NodeId) -> String {
+        let node = &self.arena[id];
+        match &node.span {
+            Some(span) => format!("{} (span {}..{})", node.value, span.start, span.end),
+            None => node.value.clone(),
+        }
+    }
+
+    /// Evaluate `expr` with `scope` as the node it is about
+    fn eval(&mut self, scope: NodeId, expr: &Expr) -> Result<AttrValue, String> {
+        expr.eval(&mut |e| match e {
+            Expr::Attr { child, name } => {
+                let node = match child {
+                    None => scope,
+                    Some(b) => self.bound(scope, b).ok_or_else(|| format!("{} binds nothing to '{}'", self.describe(scope), b))?,
+                };
+                if name == "text" { Ok(AttrValue::Str(self.text(node))) } else { self.get(node, name) }
+            }
+            Expr::Children(name) => {
+                let children = self.arena.children(scope).to_vec();
+                children.into_iter().map(|c| self.get(c, name)).collect::<Result<_, _>>().map(AttrValue::List)
+            }
+            _ => unreachable!("only attribute references are looked up"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::grammar::tests::STLC_SPEC;
+    use crate::logic::parser::Parser;
+
+    const ATTRS: &str = "
+syn size = 1 + sum(children.size)
+syn fv = union(children.fv)
+syn fv on Variable = {text}
+syn fv on Lambda = e.fv - {x.text}
+inh depth = 0
+inh depth on Lambda.e = depth + 1
+syn maxdepth = max(children.maxdepth)
+syn maxdepth on Variable = depth
+";
+
+    fn evaluator(src: &str, attrs: &str) -> AttributeEvaluator {
+        let grammar = Grammar::load(&format!("{}\n{}", STLC_SPEC, attrs)).unwrap();
+        let ast = Parser::new(grammar.clone()).parse(src).unwrap();
+        AttributeEvaluator::new(&grammar, &ast).unwrap()
+    }
+
+    #[test]
+    fn evaluates_synthesized_and_inherited() {
+        let src = "(λx:a->a.λy:a->a.x) z";
+        let mut ev = evaluator(src, ATTRS);
+        let nodes = ev.arena().len() as i64;
+        assert_eq!(ev.root("size"), Ok(AttrValue::Int(nodes)));
+        assert_eq!(ev.root("fv").unwrap().to_string(), "{z}");
+        assert_eq!(ev.root("maxdepth"), Ok(AttrValue::Int(2)));
+        assert_eq!(ev.root("depth"), Ok(AttrValue::Int(0)));
+
+        let lambdas: Vec<NodeId> = ev.arena().ids().filter(|id| ev.arena()[*id].value == "Lambda").collect();
+        assert_eq!(ev.get(lambdas[1], "fv").unwrap().to_string(), "{x}");
+        assert_eq!(ev.get(lambdas[1], "depth"), Ok(AttrValue::Int(1)));
+    }
+
+    #[test]
+    fn reports_cycles_and_missing_equations() {
+        let mut ev = evaluator("(λx:a->a.x) z", "syn a = b\nsyn b on Variable = a\nsyn b = 0\n");
+        assert_eq!(ev.root("a"), Ok(AttrValue::Int(0)));
+        let var = ev.arena().ids().find(|id| ev.arena()[*id].value == "Variable").unwrap();
+        assert!(ev.get(var, "a").unwrap_err().contains("cyclic attribute"));
+
+        let mut ev = evaluator("(λx:a->a.x) z", "syn n on Lambda = 1\ninh d on Lambda.e = 0\n");
+        assert!(ev.root("n").unwrap_err().contains("no equation for 'n'"));
+        assert!(ev.root("d").unwrap_err().contains("no root value"));
+    }
+
+    #[test]
+    fn loads_checks_and_saves_equations() {
+        let grammar = Grammar::load(&format!("{}\n{}", STLC_SPEC, ATTRS)).unwrap();
+        assert_eq!(grammar.attributes.len(), 8);
+        let reloaded = Grammar::load(&grammar.to_spec_string()).unwrap();
+        let lines = |g: &Grammar| g.attributes.iter().map(AttributeEquation::to_line).collect::<Vec<_>>();
+        assert_eq!(lines(&reloaded), lines(&grammar));
+        assert_eq!(lines(&grammar)[3], "syn fv on Lambda = e.fv - {x.text}");
+
+        let load = |attrs: &str| Grammar::load(&format!("{}\n{}", STLC_SPEC, attrs)).unwrap_err();
+        assert!(load("syn a = b\n").contains("unknown attribute 'b'"));
+        assert!(load("syn a = 1\ninh a on Lambda.e = 1\n").contains("both synthesized and inherited"));
+        assert!(load("syn a = 1\nsyn a = 2\n").contains("duplicate"));
+        assert!(load("syn a on Nope = 1\n").contains("unknown nonterminal"));
+        assert!(load("inh a on Lambda.q = 1\n").contains("binds nothing to 'q'"));
+        assert!(load("inh a on Lambda = 1\n").contains("on Nonterminal.binding"));
+        assert!(load("syn a = 1 +\n").contains("line"));
+    }
+}
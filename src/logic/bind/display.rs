@@ -10,6 +10,7 @@ use super::{
     BoundTypingRule,
 };
 use super::utils::{extract_terminal_value,extract_terminals};
+use crate::logic::ast::{NodeId, NonTerminal};
 
 impl fmt::Display for BoundType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -32,79 +33,107 @@ impl fmt::Display for BoundType {
     }
 }
 
-impl fmt::Display for BoundTypeAscription {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let term = extract_terminals(&self.node.as_node()).join("");
-        write!(f, "{} : {}", term, self.ty)
+// Bound rules only hold node ids; given the node a rule is bound to, terms are
+// printed as source text, otherwise as the bare id (`#3`). `Display` and `Debug`
+// have no tree and print ids, so callers holding the node use `display_in`.
+
+/// Text of the node `id` under `root`: all terminals, or the single terminal value
+fn term(root: Option<&NonTerminal>, id: NodeId, all_terminals: bool) -> String {
+    let Some(nt) = root.and_then(|r| r.descendant(id)) else { return id.to_string() };
+    if all_terminals {
+        extract_terminals(&nt.as_node()).join("")
+    } else {
+        extract_terminal_value(&nt.as_node()).unwrap_or_else(|| nt.value.clone())
     }
 }
 
-impl fmt::Display for BoundTypingJudgment {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+impl BoundTypeAscription {
+    fn render(&self, root: Option<&NonTerminal>) -> String {
+        format!("{} : {}", term(root, self.node, true), self.ty)
+    }
+}
+
+impl BoundTypingJudgment {
+    fn render(&self, root: Option<&NonTerminal>) -> String {
         match self {
-            BoundTypingJudgment::Ascription(ascr) => write!(f, "{}", ascr),
-            BoundTypingJudgment::Membership(var_node, ctx) => {
-                let term = extract_terminal_value(&var_node.as_node()).unwrap_or_else(|| var_node.value.clone());
-                write!(f, "{} ∈ {}", term, ctx)
-            }
+            BoundTypingJudgment::Ascription(ascr) => ascr.render(root),
+            BoundTypingJudgment::Membership(var_node, ctx) => format!("{} ∈ {}", term(root, *var_node, false), ctx),
         }
     }
 }
 
-impl fmt::Display for BoundTypeSetting {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+impl BoundTypeSetting {
+    fn render(&self, root: Option<&NonTerminal>) -> String {
         if self.extensions.is_empty() {
-            write!(f, "{}", self.name)
+            self.name.clone()
         } else {
             let parts: Vec<String> = self.extensions.iter().map(|e| {
-                let term = extract_terminal_value(&e.node.as_node()).unwrap_or_else(|| e.node.value.clone());
-                format!("{}:{}", term, e.ty)
+                format!("{}:{}", term(root, e.node, false), e.ty)
             }).collect();
-            write!(f, "{}[{}]", self.name, parts.join(", "))
+            format!("{}[{}]", self.name, parts.join(", "))
         }
     }
 }
 
-impl fmt::Display for BoundPremise {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+impl BoundPremise {
+    fn render(&self, root: Option<&NonTerminal>) -> String {
         match (&self.setting, &self.judgment) {
-            (Some(setting), Some(judgment)) => write!(f, "{} ⊢ {}", setting, judgment),
-            (Some(setting), None) => write!(f, "{}", setting),
-            (None, Some(judgment)) => write!(f, "{}", judgment),
-            (None, None) => Ok(()),
+            (Some(setting), Some(judgment)) => format!("{} ⊢ {}", setting.render(root), judgment.render(root)),
+            (Some(setting), None) => setting.render(root),
+            (None, Some(judgment)) => judgment.render(root),
+            (None, None) => String::new(),
         }
     }
+
+    /// Like `Display`, with terms printed from `node`, the node the rule is bound to
+    pub fn display_in(&self, node: &NonTerminal) -> String {
+        self.render(Some(node))
+    }
 }
 
-impl fmt::Display for BoundConclusion {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+impl BoundConclusion {
+    fn render(&self, root: Option<&NonTerminal>) -> String {
         use super::rule::BoundConclusionKind;
         match &self.kind {
             BoundConclusionKind::Type(ty) => {
                 match (&self.context.input, &self.context.output) {
-                    (i, Some(o)) => write!(f, "{} -> {} ⊢ {}", i, o, ty),
-                    (i, None) => write!(f, "{}[] ⊢ {}", i, ty),
+                    (i, Some(o)) => format!("{} -> {} ⊢ {}", i, o.render(root), ty),
+                    (i, None) => format!("{}[] ⊢ {}", i, ty),
                 }
             }
-            BoundConclusionKind::ContextLookup(ctx, var_node) => {
-                let term = extract_terminal_value(&var_node.as_node()).unwrap_or_else(|| var_node.value.clone());
-                write!(f, "{}({})", ctx, term)
-            }
+            BoundConclusionKind::ContextLookup(ctx, var_node) => format!("{}({})", ctx, term(root, *var_node, false)),
         }
     }
 }
 
-impl fmt::Display for BoundTypingRule {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+impl BoundTypingRule {
+    fn render(&self, root: Option<&NonTerminal>) -> String {
         if self.premises.is_empty() {
-            write!(f, "{} ⇒ {}", self.name, self.conclusion)
+            format!("{} ⇒ {}", self.name, self.conclusion.render(root))
         } else {
-            let parts: Vec<String> = self.premises.iter().map(|p| p.to_string()).collect();
-            write!(f, "{} ⇒ {}", parts.join(", "), self.conclusion)
+            let parts: Vec<String> = self.premises.iter().map(|p| p.render(root)).collect();
+            format!("{} ⇒ {}", parts.join(", "), self.conclusion.render(root))
         }
     }
+
+    /// Like `Display`, with terms printed from `node`, the node this rule is bound to
+    pub fn display_in(&self, node: &NonTerminal) -> String {
+        self.render(Some(node))
+    }
 }
 
+macro_rules! display_via_render {
+    ($($ty:ty),*) => {$(
+        impl fmt::Display for $ty {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(&self.render(None))
+            }
+        }
+    )*};
+}
+
+display_via_render!(BoundTypeAscription, BoundTypingJudgment, BoundTypeSetting, BoundPremise, BoundConclusion, BoundTypingRule);
+
 impl fmt::Debug for BoundTypingRule {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let n = self.premises.len();
@@ -1,11 +1,14 @@
-use crate::logic::ast::{ASTNode, NonTerminal};
+use crate::logic::ast::{ASTNode, NodeId, NonTerminal};
 use crate::logic::typing::{TypingRule, Premise, TypingJudgment, TypeSetting, Conclusion};
 use crate::logic::typing::rule::ConclusionKind;
 use super::typing::BoundType;
-use super::utils::{get_nt_binding, bind_type, collect_nt_bindings_same_level};
+use super::utils::{find_nt_binding, bind_type, find_nt_bindings_same_level};
 
-/// A bound typing rule where all rule variables have been resolved to actual AST nodes
+/// A bound typing rule where all rule variables have been resolved to actual AST nodes.
+/// Nodes are referenced by their pre-order `NodeId` within the subtree the rule is
+/// bound to (see `NonTerminal::descendant` and `AstArena::resolve`).
 #[derive(Clone,PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
 pub struct BoundTypingRule {
     pub name: String,
     pub premises: Vec<BoundPremise>,
@@ -14,6 +17,7 @@ pub struct BoundTypingRule {
 
 /// A bound premise where rule variables are resolved to nodes
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
 pub struct BoundPremise {
     pub setting: Option<BoundTypeSetting>,
     pub judgment: Option<BoundTypingJudgment>,
@@ -21,6 +25,7 @@ pub struct BoundPremise {
 
 /// A bound type setting with resolved node references
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
 pub struct BoundTypeSetting {
     pub name: String,
     pub extensions: Vec<BoundTypeAscription>,
@@ -28,20 +33,24 @@ pub struct BoundTypeSetting {
 
 /// A bound type ascription linking a node to a type
 #[derive(Debug, Clone,PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
 pub struct BoundTypeAscription {
-    pub node: NonTerminal,  // The actual AST node (instead of rule variable)
+    pub node: NodeId,       // The actual AST node (instead of rule variable)
     pub ty: BoundType,          // Regular type, no need for special bound type
 }
 
 /// A bound typing judgment with resolved nodes
 #[derive(Debug, Clone,PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json", serde(tag = "kind", content = "args", rename_all = "snake_case"))]
 pub enum BoundTypingJudgment {
     Ascription(BoundTypeAscription),
-    Membership(NonTerminal, String), // (resolved node, context)
+    Membership(NodeId, String), // (resolved node, context)
 }
 
 /// Context specification for a bound conclusion (optional input/output context transforms)
 #[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
 pub struct BoundConclusionContext {
     pub input: String,
     pub output: Option<BoundTypeSetting>,
@@ -50,13 +59,16 @@ pub struct BoundConclusionContext {
 
 /// The kind of bound conclusion: either a type or a context lookup Γ(x)
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json", serde(tag = "kind", content = "args", rename_all = "snake_case"))]
 pub enum BoundConclusionKind {
     Type(BoundType),
-    ContextLookup(String, NonTerminal), // (context, resolved variable node)
+    ContextLookup(String, NodeId), // (context, resolved variable node)
 }
 
 /// A bound conclusion with resolved components
 #[derive(Debug, Clone,PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
 pub struct BoundConclusion {
     pub context: BoundConclusionContext,
     pub kind: BoundConclusionKind,
@@ -106,7 +118,7 @@ impl BindingResolver for DefaultBindingResolver {
         let bound_judgment = match &premise.judgment {
             Some(TypingJudgment::Ascription((term, ty))) => {
                 // Resolve the term variable to an actual node
-                let var_node = get_nt_binding(node, term.clone())
+                let var_node = locate(node, term)
                     .ok_or_else(|| format!("Could not resolve binding variable {} in ascription", term))?;
                 
                 // For types, try to resolve type variables but keep the type structure
@@ -120,7 +132,7 @@ impl BindingResolver for DefaultBindingResolver {
             }
             Some(TypingJudgment::Membership(var, ctx)) => {
                 // Resolve the variable to an actual node
-                let var_node = get_nt_binding(node, var.clone())
+                let var_node = locate(node, var)
                     .ok_or_else(|| format!("Could not resolve binding variable {} in membership", var))?;
                 
                 return Ok(BoundPremise { setting: bound_setting, judgment: Some(BoundTypingJudgment::Membership(var_node, ctx.clone())) });
@@ -152,7 +164,7 @@ impl BindingResolver for DefaultBindingResolver {
             }
             ConclusionKind::ContextLookup(context, var) => {
                 // Resolve the variable to an actual node
-                let var_node = get_nt_binding(node, var.clone())
+                let var_node = locate(node, var)
                     .ok_or_else(|| format!("Could not resolve binding variable {} in context lookup", var))?;
                 
                 Ok(BoundConclusion { context: BoundConclusionContext::default(), kind: BoundConclusionKind::ContextLookup(context.clone(), var_node) })
@@ -168,7 +180,7 @@ impl DefaultBindingResolver {
         
         for (term, ty) in &setting.extensions {
             // Resolve the term variable to an actual node (single binding in settings semantics)
-            let var_node = get_nt_binding(node, term.clone())
+            let var_node = locate(node, term)
                 .ok_or_else(|| format!("Could not resolve binding variable {} in type setting", term))?;
             
             // For types, try to resolve type variables but keep the type structure
@@ -197,7 +209,7 @@ impl DefaultBindingResolver {
         match &premise.judgment {
             Some(TypingJudgment::Ascription((term, ty))) => {
                 // Try to expand by repetition at same level
-                let reps = collect_nt_bindings_same_level(node, term);
+                let reps = locate_same_level(node, term);
                 let resolved_type = bind_type(node, ty.clone())
                     .ok_or_else(|| format!("Could not resolve type binding for type {:?} in ascription", ty))?;
                 if !reps.is_empty() {
@@ -207,7 +219,7 @@ impl DefaultBindingResolver {
                     }).collect())
                 } else {
                     // Fallback: attempt a single binding; if not found, treat as zero premises (e.g., zero repetitions)
-                    if let Some(var_node) = get_nt_binding(node, term.clone()) {
+                    if let Some(var_node) = locate(node, term) {
                         Ok(vec![BoundPremise { setting: bound_setting, judgment: Some(BoundTypingJudgment::Ascription(BoundTypeAscription { node: var_node, ty: resolved_type })) }])
                     } else {
                         Ok(vec![])
@@ -215,14 +227,14 @@ impl DefaultBindingResolver {
                 }
             }
             Some(TypingJudgment::Membership(var, ctx)) => {
-                let reps = collect_nt_bindings_same_level(node, var);
+                let reps = locate_same_level(node, var);
                 if !reps.is_empty() {
                     Ok(reps.into_iter().map(|var_node| BoundPremise {
                         setting: bound_setting.clone(),
                         judgment: Some(BoundTypingJudgment::Membership(var_node, ctx.clone())),
                     }).collect())
                 } else {
-                    if let Some(var_node) = get_nt_binding(node, var.clone()) {
+                    if let Some(var_node) = locate(node, var) {
                         Ok(vec![BoundPremise { setting: bound_setting, judgment: Some(BoundTypingJudgment::Membership(var_node, ctx.clone())) }])
                     } else {
                         Ok(vec![])
@@ -234,6 +246,16 @@ impl DefaultBindingResolver {
     }
 }
 
+/// Id (relative to `node`) of the node bound to `var`
+fn locate(node: &NonTerminal, var: &str) -> Option<NodeId> {
+    find_nt_binding(node, var).and_then(|found| node.id_of(found))
+}
+
+/// Ids (relative to `node`) of every node bound to `var` at the shallowest level it repeats
+fn locate_same_level(node: &NonTerminal, var: &str) -> Vec<NodeId> {
+    find_nt_bindings_same_level(node, var).into_iter().filter_map(|found| node.id_of(found)).collect()
+}
+
 /// Extension trait for NonTerminal to support binding resolution
 pub trait BindableNonTerminal {
     /// Resolve the typing rule of this nonterminal to create a bound rule
@@ -288,36 +310,61 @@ impl BindableASTNode for ASTNode {
 
 impl BoundTypingRule {
     /// Get all nodes referenced in this bound rule
-    pub fn referenced_nodes(&self) -> Vec<&NonTerminal> {
+    pub fn referenced_nodes(&self) -> Vec<NodeId> {
         let mut nodes = Vec::new();
         
         for premise in &self.premises {
             match &premise.judgment {
                 Some(BoundTypingJudgment::Ascription(ascr)) => {
-                    nodes.push(&ascr.node);
+                    nodes.push(ascr.node);
                 }
                 Some(BoundTypingJudgment::Membership(node, _)) => {
-                    nodes.push(node);
+                    nodes.push(*node);
                 }
                 None => {}
             }
             
             if let Some(setting) = &premise.setting {
                 for ext in &setting.extensions {
-                    nodes.push(&ext.node);
+                    nodes.push(ext.node);
                 }
             }
         }
         
         match &self.conclusion.kind {
-            BoundConclusionKind::ContextLookup(_, node) => nodes.push(node),
+            BoundConclusionKind::ContextLookup(_, node) => nodes.push(*node),
             BoundConclusionKind::Type(_) => {}
         }
         // Updated: input is now a plain String (no extensions to collect)
-        if let Some(s) = &self.conclusion.context.output { for ext in &s.extensions { nodes.push(&ext.node); } }
+        if let Some(s) = &self.conclusion.context.output { for ext in &s.extensions { nodes.push(ext.node); } }
         nodes
     }
-    
+
+    /// The same rule with every node reference replaced by `f(reference)`
+    pub fn map_nodes(&self, mut f: impl FnMut(NodeId) -> NodeId) -> BoundTypingRule {
+        let mut rule = self.clone();
+        let map_setting = |s: &mut BoundTypeSetting, f: &mut dyn FnMut(NodeId) -> NodeId| {
+            s.extensions.iter_mut().for_each(|ext| ext.node = f(ext.node));
+        };
+        for premise in &mut rule.premises {
+            match &mut premise.judgment {
+                Some(BoundTypingJudgment::Ascription(ascr)) => ascr.node = f(ascr.node),
+                Some(BoundTypingJudgment::Membership(node, _)) => *node = f(*node),
+                None => {}
+            }
+            if let Some(setting) = &mut premise.setting {
+                map_setting(setting, &mut f);
+            }
+        }
+        if let BoundConclusionKind::ContextLookup(_, node) = &mut rule.conclusion.kind {
+            *node = f(*node);
+        }
+        if let Some(setting) = &mut rule.conclusion.context.output {
+            map_setting(setting, &mut f);
+        }
+        rule
+    }
+
     /// Check if this bound rule is well-formed (all referenced nodes exist)
     pub fn is_well_formed(&self) -> bool {
         // A bound rule is well-formed if all its node references are valid
@@ -25,6 +25,7 @@ mod tests {
             value: value.to_string(),
             span: Some(SourceSpan { start: 0, end: 0 }),
             binding,
+            trivia: None,
         })
     }
 
@@ -193,7 +194,7 @@ mod tests {
         
         match bound_premise.judgment {
             Some(BoundTypingJudgment::Ascription(ascr)) => {
-                assert_eq!(ascr.node.value, "Variable");
+                assert_eq!(test_node.descendant(ascr.node).unwrap().value, "Variable");
                 assert_eq!(ascr.ty, BoundType::Atom("Int".to_string()));
             }
             _ => panic!("Expected ascription judgment"),
@@ -247,8 +248,8 @@ mod tests {
         debug_info!("test", "AST: {}", term.pretty());
 
         // assert is has one child and that child is a NonTerminal
-        assert_eq!(term.nonterminal_children().len(), 1);
-        let nt = &term.nonterminal_children()[0];
+        assert_eq!(term.nonterminal_children().count(), 1);
+        let nt = term.nonterminal_children().next().unwrap();
 
         let r = if let Some(r) = nt.bound_typing_rule.as_ref() {
             r
@@ -256,7 +257,7 @@ mod tests {
             panic!("Expected NonTerminal to have a bound typing rule");
         };
         
-        let typing_rule_str = format!("{}",r.as_ref());
+        let typing_rule_str = r.display_in(nt);
         println!("{}",typing_rule_str);
 
         assert_eq!(typing_rule_str,"Γ ⊢ (λy:a->a.y) : a → a, Γ ⊢ ((λx:a->a.x)z) : a ⇒ a")
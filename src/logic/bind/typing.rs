@@ -1,8 +1,12 @@
+use crate::logic::typing::Type;
+
 ///---------------
 /// Type Operations and Unification
 ///---------------
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json", serde(tag = "kind", content = "args", rename_all = "snake_case"))]
 pub enum BoundType {
     // Base types
     Atom(String),
@@ -29,6 +33,33 @@ pub enum BoundType {
 }
 
 impl BoundType {
+    /// Parse a closed type expression such as `a → a`. Type names are taken literally
+    /// (no binding against an AST), so this is meant for goals and context entries.
+    pub fn parse(type_str: &str) -> Result<Self, String> {
+        Self::from_type(&Type::parse(type_str)?)
+    }
+
+    /// Convert an unbound `Type` into a `BoundType`, treating every atom as a concrete type
+    pub fn from_type(ty: &Type) -> Result<Self, String> {
+        let boxed = |t: &Type| Self::from_type(t).map(Box::new);
+        Ok(match ty {
+            Type::Atom(name) | Type::Raw(name) => BoundType::Atom(name.clone()),
+            Type::Arrow(l, r) => BoundType::Arrow(boxed(l)?, boxed(r)?),
+            Type::Pointer(t) => BoundType::Pointer(boxed(t)?),
+            Type::Array(t, size) => {
+                let n = size.parse::<u64>().map_err(|_| format!("Array size must be a number, got '{}'", size))?;
+                BoundType::Array(boxed(t)?, n)
+            }
+            Type::Not(t) => BoundType::Not(boxed(t)?),
+            Type::Intersection(l, r) => BoundType::Intersection(boxed(l)?, boxed(r)?),
+            Type::Union(l, r) => BoundType::Union(boxed(l)?, boxed(r)?),
+            Type::ContextCall(ctx, var) => BoundType::ContextCall(ctx.clone(), var.clone()),
+            Type::Universe => BoundType::Universe,
+            Type::Empty => BoundType::Empty,
+            Type::Tuple(name) => return Err(format!("Tuple meta type ({}...) has no concrete form", name)),
+        })
+    }
+
     /// Check if this type is compatible with another type
     /// This implements basic subtyping and type compatibility rules
     pub fn is_compatible_with(&self, other: &BoundType) -> bool {
@@ -14,31 +14,34 @@ fn collect_terminals(node: &ASTNode, out: &mut Vec<String>) {
 
 /// Find the shallowest NonTerminal in the subtree of `root` that has one or more
 /// direct nonterminal children with the requested binding name. Returns that parent.
-fn find_parent_with_binding_level(root: &NonTerminal, var: &str) -> Option<NonTerminal> {
+fn find_parent_with_binding_level<'a>(root: &'a NonTerminal, var: &str) -> Option<&'a NonTerminal> {
     // If current node has any direct children with binding = var, return it
-    let direct = root.nonterminal_children();
-    let has_any = direct.iter().any(|ch| ch.binding.as_deref() == Some(var));
-    if has_any { return Some(root.clone()); }
+    let has_any = nt_children(root).any(|ch| ch.binding.as_deref() == Some(var));
+    if has_any { return Some(root); }
     // Otherwise recurse; prefer the first shallowest occurrence in preorder
-    for ch in direct {
-        if let Some(p) = find_parent_with_binding_level(&ch, var) { return Some(p); }
-    }
-    None
+    nt_children(root).find_map(|ch| find_parent_with_binding_level(ch, var))
 }
 
 /// Collect all direct child nonterminals of the discovered parent that share the same binding name.
-pub fn collect_nt_bindings_same_level(root: &NonTerminal, var: &str) -> Vec<NonTerminal> {
-    if let Some(parent) = find_parent_with_binding_level(root, var) {
-        parent
-            .nonterminal_children()
-            .into_iter()
-            .filter(|ch| ch.binding.as_deref() == Some(var))
-            .collect()
-    } else {
-        Vec::new()
+pub fn find_nt_bindings_same_level<'a>(root: &'a NonTerminal, var: &str) -> Vec<&'a NonTerminal> {
+    match find_parent_with_binding_level(root, var) {
+        Some(parent) => nt_children(parent).filter(|ch| ch.binding.as_deref() == Some(var)).collect(),
+        None => Vec::new(),
     }
 }
 
+/// Owned variant of `find_nt_bindings_same_level`
+pub fn collect_nt_bindings_same_level(root: &NonTerminal, var: &str) -> Vec<NonTerminal> {
+    find_nt_bindings_same_level(root, var).into_iter().cloned().collect()
+}
+
+fn nt_children(nt: &NonTerminal) -> impl Iterator<Item = &NonTerminal> {
+    nt.children.iter().filter_map(|c| match c {
+        ASTNode::Nonterminal(n) => Some(n),
+        ASTNode::Terminal(_) => None,
+    })
+}
+
 /// Collect and parse types from all nodes at the same AST level sharing the binding name.
 pub fn collect_types_same_level(root: &NonTerminal, var: &str) -> Vec<BoundType> {
     collect_nt_bindings_same_level(root, var)
@@ -63,8 +66,8 @@ pub fn bind_type(node: &NonTerminal, type_var: Type) -> Option<BoundType> {
             }
             
             // Single binding resolution path
-            if let Some(nt) = get_nt_binding(&node, var.clone()) {
-                if let Some(full_ty) = get_type_value(&nt) {
+            if let Some(nt) = find_nt_binding(node, &var) {
+                if let Some(full_ty) = get_type_value(nt) {
                     debug_trace!("bind::utils", "get_type_binding: found structured type={:?}", full_ty);
                     return Some(full_ty);
                 } else {
@@ -158,35 +161,31 @@ pub fn bind_type(node: &NonTerminal, type_var: Type) -> Option<BoundType> {
     }
 }
 
-/// Bind a variable to a type in the current context
-pub fn get_nt_binding(node: &NonTerminal,var: String) -> Option<NonTerminal> {
-    debug_trace!("bind::utils", "get_nt_binding: looking for {} in node {}", var, node.value);
+/// Find the node bound to `var` below `node`: direct children first, then deeper
+pub fn find_nt_binding<'a>(node: &'a NonTerminal, var: &str) -> Option<&'a NonTerminal> {
+    debug_trace!("bind::utils", "find_nt_binding: looking for {} in node {}", var, node.value);
     
     // First pass: check if any direct children have the binding we're looking for
-    for child in &node.nonterminal_children() {
-        debug_trace!("bind::utils", "get_nt_binding: checking direct child {} (binding: {:?})", child.value, child.binding);
-        if let Some(binding) = &child.binding {
-            if *binding == var {
-                debug_trace!("bind::utils", "get_nt_binding: DIRECT MATCH! Found {} in child {}", var, child.value);
-                return Some(child.clone());
-            }
-        }
+    if let Some(child) = nt_children(node).find(|ch| ch.binding.as_deref() == Some(var)) {
+        debug_trace!("bind::utils", "find_nt_binding: DIRECT MATCH! Found {} in child {}", var, child.value);
+        return Some(child);
     }
     
     // Second pass: if no direct match, recurse into children
-    for child in &node.nonterminal_children() {
-        debug_trace!("bind::utils", "get_nt_binding: recursing into child {}", child.value);
-        if let Some(binding) = child.bind(var.clone()) {
-            debug_trace!("bind::utils", "get_nt_binding: found binding in child {}", child.value);
-            return Some(binding);
-        }
+    let found = nt_children(node).find_map(|child| child.find_binding_ref(var));
+    if found.is_none() {
+        debug_trace!("bind::utils", "find_nt_binding: no binding found for {}", var);
     }
-    debug_trace!("bind::utils", "get_nt_binding: no binding found for {}", var);
-    None
+    found
+}
+
+/// Owned variant of `find_nt_binding`
+pub fn get_nt_binding(node: &NonTerminal,var: String) -> Option<NonTerminal> {
+    find_nt_binding(node, &var).cloned()
 }
 
 pub fn get_var_binding(node: &NonTerminal,var: String) -> Result<Option<String>, String> {
-    if let Some(binding) = get_nt_binding(node, var) {
+    if let Some(binding) = find_nt_binding(node, &var) {
         if let Some(value) = extract_terminal_value(&binding.as_node()) {
             return Ok(Some(value));
         } else {
@@ -197,20 +196,7 @@ pub fn get_var_binding(node: &NonTerminal,var: String) -> Result<Option<String>,
 }
 
 pub fn extract_terminals(node: &ASTNode) -> Vec<String> {
-    match node {
-        ASTNode::Terminal(t) => vec![t.value.clone()],
-        ASTNode::Nonterminal(nt) => {
-            
-            let mut terminals = vec![];
-            for child in &nt.children {
-                // all extract_terminals on all childrens and merge vecs
-                let extracted = extract_terminals(child);
-                terminals.extend(extracted);
-            }
-            
-            terminals
-        }
-    }
+    node.terminals().map(|t| t.value.clone()).collect()
 }
 
 // extract ONE terminal value. errors if more
@@ -229,20 +215,16 @@ pub fn extract_terminal_value(node: &ASTNode) -> Option<String> {
 
 impl NonTerminal {
     pub fn bind(&self, var: String) -> Option<Self> {
+        self.find_binding_ref(&var).cloned()
+    }
+
+    /// This node if it carries the binding `var`, else the first match below it
+    pub fn find_binding_ref(&self, var: &str) -> Option<&NonTerminal> {
         debug_trace!("bind::utils", "NonTerminal::bind: checking node {} for binding {}", self.value, var);
-        if let Some(binding) = self.binding() {
-            debug_trace!("bind::utils", "NonTerminal::bind: {} =? {}", binding, var);
-            if *binding == var {
-                debug_trace!("bind::utils", "NonTerminal::bind: MATCH! Returning node {}", self.value);
-                return Some(self.clone());
-            }
-        }
-        for child in &self.nonterminal_children() {
-            if let Some(binding) = child.bind(var.clone()) {
-                debug_trace!("bind::utils", "NonTerminal::bind: found {} in child, returning from {}", var, self.value);
-                return Some(binding);
-            }
+        if self.binding.as_deref() == Some(var) {
+            debug_trace!("bind::utils", "NonTerminal::bind: MATCH! Returning node {}", self.value);
+            return Some(self);
         }
-        None
+        nt_children(self).find_map(|child| child.find_binding_ref(var))
     }
 }
@@ -150,10 +150,10 @@ impl TypingContext {
 impl BoundType {
 
     pub fn resolve(&mut self, context: &TypingContext) -> () {
-        println!("Resolving type: {:?}", self);
+        crate::debug_trace!("typechecker", "Resolving type: {:?}", self);
         if let BoundType::ContextCall(_ctx, var) = self {
             if let Some(bound) = context.lookup(var).cloned() {
-                println!("Resolved context call {} to type {}", var, bound);
+                crate::debug_trace!("typechecker", "Resolved context call {} to type {}", var, bound);
                 *self = bound;
             }
         }
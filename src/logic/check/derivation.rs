@@ -0,0 +1,194 @@
+// Derivation trees recorded while checking: which bound rule typed which node and,
+// for each premise, the child it checked and the type found there.
+//
+// Premise checkers run on their own `TypeChecker`s, so the recorder is shared like
+// `RuleStats`. Rule applications nest: a child's derivation finishes while its
+// parent's premise is being checked, and is claimed by that premise when recorded.
+
+use crate::logic::ast::dot::{self, NONTERMINAL_FILL, TERMINAL_FILL};
+use crate::logic::ast::{NonTerminal, SourceSpan};
+use crate::logic::bind::utils::extract_terminals;
+use crate::logic::bind::{BoundPremise, BoundType, BoundTypingRule};
+
+const FAILED_FILL: &str = "#f6d5d5";
+
+/// One application of a bound typing rule
+#[derive(Debug, Clone, PartialEq)]
+pub struct Derivation {
+    pub rule: String,
+    /// Text of the node the rule is bound to
+    pub term: String,
+    pub span: Option<SourceSpan>,
+    pub premises: Vec<PremiseStep>,
+    /// The node's type, or why the rule rejected it
+    pub result: Result<BoundType, String>,
+}
+
+/// A premise of a rule application, as checked against one child
+#[derive(Debug, Clone, PartialEq)]
+pub struct PremiseStep {
+    /// The premise with terms filled in, e.g. `Γ[x:a] ⊢ x : a`
+    pub judgment: String,
+    /// Text of the child the premise checked
+    pub term: String,
+    /// Inferred type for `Γ ⊢ e : τ`, context type for `x ∈ Γ`; `None` if there was none
+    pub ty: Option<BoundType>,
+    pub ok: bool,
+    /// Derivations of the child
+    pub children: Vec<Derivation>,
+}
+
+#[derive(Debug, Default)]
+pub struct DerivationRecorder {
+    /// Applications in progress, each with finished derivations no premise claimed yet
+    open: Vec<(Derivation, Vec<Derivation>)>,
+    roots: Vec<Derivation>,
+}
+
+fn text(node: &NonTerminal) -> String {
+    extract_terminals(&node.as_node()).join("")
+}
+
+impl DerivationRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Derivations of the outermost rule applications, in checking order
+    pub fn roots(&self) -> &[Derivation] {
+        &self.roots
+    }
+
+    pub(crate) fn begin(&mut self, rule: &BoundTypingRule, node: &NonTerminal) {
+        let derivation = Derivation {
+            rule: rule.name.clone(),
+            term: text(node),
+            span: node.span.clone(),
+            premises: Vec::new(),
+            result: Err("not finished".into()),
+        };
+        self.open.push((derivation, Vec::new()));
+    }
+
+    /// Record `premise` of the rule bound to `owner`, checked against `child`
+    pub(crate) fn premise(&mut self, premise: &BoundPremise, owner: &NonTerminal, child: &NonTerminal, ty: Option<BoundType>, ok: bool) {
+        if let Some((derivation, finished)) = self.open.last_mut() {
+            derivation.premises.push(PremiseStep {
+                judgment: premise.display_in(owner),
+                term: text(child),
+                ty,
+                ok,
+                children: std::mem::take(finished),
+            });
+        }
+    }
+
+    pub(crate) fn end(&mut self, result: &Result<BoundType, String>) {
+        let Some((mut derivation, _)) = self.open.pop() else { return };
+        derivation.result = result.clone();
+        match self.open.last_mut() {
+            Some((_, finished)) => finished.push(derivation),
+            None => self.roots.push(derivation),
+        }
+    }
+
+    /// The recorded derivations as a Graphviz digraph: one box per rule application
+    /// labelled `rule`, term and type, with an edge per premise labelled with the
+    /// judgment and the type found. Failed applications and premises are red.
+    pub fn to_dot(&self) -> String {
+        let mut out = dot::header("derivation");
+        let mut next = 0usize;
+        for root in &self.roots {
+            write_derivation(root, &mut next, &mut out);
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+fn write_derivation(d: &Derivation, next: &mut usize, out: &mut String) -> usize {
+    let id = *next;
+    *next += 1;
+    let (outcome, fill) = match &d.result {
+        Ok(ty) => (format!(": {}", ty), NONTERMINAL_FILL),
+        Err(e) => (format!("✗ {}", e.lines().next().unwrap_or_default()), FAILED_FILL),
+    };
+    let label = format!("({})\n{}\n{}", d.rule, d.term, outcome);
+    out.push_str(&format!("  d{} [label=\"{}\", shape=box, fillcolor=\"{}\"];\n", id, dot::escape(&label), fill));
+    for step in &d.premises {
+        let found = match &step.ty {
+            Some(ty) => format!("{} {}", if step.ok { "✓" } else { "✗" }, ty),
+            None => (if step.ok { "✓" } else { "✗" }).to_string(),
+        };
+        let edge = format!("label=\"{}\"{}", dot::escape(&format!("{}\n{}", step.judgment, found)), if step.ok { "" } else { ", color=red" });
+        if step.children.is_empty() {
+            // nothing below the child (e.g. `x ∈ Γ`): show the term itself
+            let leaf = *next;
+            *next += 1;
+            out.push_str(&format!("  d{} [label=\"{}\", shape=ellipse, fillcolor=\"{}\"];\n", leaf, dot::escape(&step.term), TERMINAL_FILL));
+            out.push_str(&format!("  d{} -> d{} [{}];\n", id, leaf, edge));
+        }
+        for child in &step.children {
+            let c = write_derivation(child, next, out);
+            out.push_str(&format!("  d{} -> d{} [{}];\n", id, c, edge));
+        }
+    }
+    id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DerivationRecorder;
+    use crate::logic::bind::BoundType;
+    use crate::logic::check::TypeChecker;
+    use crate::logic::grammar::Grammar;
+    use crate::logic::grammar::tests::STLC_SPEC;
+    use crate::logic::parser::Parser;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn records_premises_with_their_types() {
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        let ast = Parser::new(grammar).parse("(λx:a->a.x) z").unwrap();
+        let recorder = Rc::new(RefCell::new(DerivationRecorder::new()));
+        let mut tc = TypeChecker::new().with_derivation(Rc::clone(&recorder));
+        tc.add("z".into(), BoundType::Atom("a".into()));
+        let a = BoundType::Atom("a".into());
+        assert_eq!(tc.check(&ast), Ok(Some(a.clone())));
+
+        let recorder = recorder.borrow();
+        let [app] = recorder.roots() else { panic!("one root derivation") };
+        assert_eq!((app.rule.as_str(), app.term.as_str()), ("app", "(λx:a->a.x)z"));
+        assert_eq!(app.result, Ok(a.clone()));
+        let [f, e] = app.premises.as_slice() else { panic!("app has two premises") };
+        assert!(f.ok && e.ok);
+        assert_eq!(f.ty, Some(BoundType::Arrow(Box::new(a.clone()), Box::new(a.clone()))));
+        assert_eq!(f.children[0].rule, "lambda");
+        assert_eq!(e.term, "z");
+        assert_eq!(e.children[0].rule, "var");
+
+        // the var rule's membership premise found z's type in the context
+        let var = &e.children[0];
+        assert!(var.premises[0].judgment.contains("∈ Γ"));
+        assert_eq!(var.premises[0].ty, Some(a));
+
+        let dot = recorder.to_dot();
+        assert!(dot.starts_with("digraph derivation {"));
+        assert!(dot.contains("(app)\\n(λx:a->a.x)z\\n: a"));
+        assert!(!dot.contains("color=red"));
+    }
+
+    #[test]
+    fn failed_premises_are_marked() {
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        let ast = Parser::new(grammar).parse("(λx:a->a.x) z").unwrap();
+        let recorder = Rc::new(RefCell::new(DerivationRecorder::new()));
+        let mut tc = TypeChecker::new().with_derivation(Rc::clone(&recorder));
+        tc.add("z".into(), BoundType::Atom("b".into()));
+        assert!(tc.check(&ast).is_err());
+        let dot = recorder.borrow().to_dot();
+        assert!(dot.contains("color=red"));
+        assert!(dot.contains("✗ b"));
+    }
+}
@@ -1,4 +1,4 @@
-use crate::logic::ast::NonTerminal;
+use crate::logic::ast::{NodeId, NonTerminal};
 
 
 use super::ast::ASTNode;
@@ -13,29 +13,83 @@ use super::bind::{
     BoundType
 };
 use crate::debug_trace; // added for trace-level context dumps
+use std::cell::RefCell;
+use std::rc::Rc;
 
 pub mod context;
 pub mod debug;
+pub mod derivation;
+pub mod stats;
+pub mod typed;
 
 use context::TypingContext;
 use debug::TypeCheckerDebug;
+use derivation::DerivationRecorder;
+use stats::{PremiseKind, RuleStats};
+use typed::{TypeRecorder, TypedAst};
 
 pub struct TypeChecker { 
     pub context: TypingContext,
     /// Debug helper for span information
     pub debug: TypeCheckerDebug,
+    /// Rule usage counters, if instrumentation is on (shared with premise checkers)
+    pub stats: Option<Rc<RefCell<RuleStats>>>,
+    /// Derivation tree being recorded, if any (shared with premise checkers)
+    pub derivation: Option<Rc<RefCell<DerivationRecorder>>>,
+    /// Type of each checked node, if recorded (shared with premise checkers)
+    pub types: Option<Rc<RefCell<TypeRecorder>>>,
+    /// Pre-order subtree sizes of the tree being checked, so bound nodes are found
+    /// without walking every node before them
+    sizes: Option<Rc<Vec<usize>>>,
+    /// Pre-order position of the node being checked in that tree
+    position: usize,
 }
 impl TypeChecker { 
     pub fn new() -> Self { 
         Self { 
             context: TypingContext::new(),
             debug: TypeCheckerDebug::new(None),
+            stats: None,
+            derivation: None,
+            types: None,
+            sizes: None,
+            position: 0,
         } 
     }
 
     /// Create a new TypeChecker with the given context
     pub fn with_context(context: TypingContext) -> Self {
-        Self { context , debug: TypeCheckerDebug::new(None) }
+        Self { context , debug: TypeCheckerDebug::new(None), stats: None, derivation: None, types: None, sizes: None, position: 0 }
+    }
+
+    /// Record rule applications and premise failures into `stats`
+    pub fn with_stats(mut self, stats: Rc<RefCell<RuleStats>>) -> Self {
+        self.stats = Some(stats);
+        self
+    }
+
+    /// Record which rule typed which node, premise by premise, into `derivation`
+    pub fn with_derivation(mut self, derivation: Rc<RefCell<DerivationRecorder>>) -> Self {
+        self.derivation = Some(derivation);
+        self
+    }
+
+    /// Record the type of every node checked, and the context of each binder, into `types`
+    pub fn with_types(mut self, types: Rc<RefCell<TypeRecorder>>) -> Self {
+        self.types = Some(types);
+        self
+    }
+
+    fn record_premise(&self, premise: &BoundPremise, owner: &NonTerminal, child: &NonTerminal, ty: Option<BoundType>, ok: bool) {
+        if let Some(derivation) = &self.derivation {
+            derivation.borrow_mut().premise(premise, owner, child, ty, ok);
+        }
+    }
+
+    fn record_failure(&self, rule: &BoundTypingRule, kind: PremiseKind) {
+        if let Some(stats) = &self.stats {
+            stats.borrow_mut().record_failure(&rule.name, kind);
+        }
     }
 
     /// Get a mutable reference to the context
@@ -65,8 +119,8 @@ impl TypeChecker {
     }
 
     /// Format bound typing rule information for error messages
-    pub fn format_bound_rule_info(&self, rule: &BoundTypingRule) -> String {
-        format!("\nBound typing rule [{}]: {}", rule.name, rule)
+    pub fn format_bound_rule_info(&self, rule: &BoundTypingRule, node: &NonTerminal) -> String {
+        format!("\nBound typing rule [{}]: {}", rule.name, rule.display_in(node))
     }
 
     /// Build a module tag that includes the current expression (nonterminal) name for tracing
@@ -77,7 +131,6 @@ impl TypeChecker {
 
 impl TypeChecker {
     pub fn check(&mut self, node: &ASTNode) -> Result<Option<BoundType>, String> { 
-
         debug_trace!("typechecker", "check:start node={}", node.show_simple());
         match node {
             ASTNode::Nonterminal(nt) => {
@@ -87,7 +140,62 @@ impl TypeChecker {
         }
     }
 
+    /// Check `node`, recording the type of each node in it and not just the root's
+    pub fn check_typed(&mut self, node: &ASTNode) -> TypedAst {
+        let recorder = Rc::new(RefCell::new(TypeRecorder::new()));
+        let outer = self.types.replace(recorder.clone());
+        let result = self.check_child(node, 0);
+        self.types = outer;
+        let recorder = Rc::try_unwrap(recorder).map(RefCell::into_inner).unwrap_or_else(|shared| shared.take());
+        TypedAst::new(node, recorder, result)
+    }
+
+    /// Check `child`, `offset` nodes after the node being checked in pre-order
+    fn check_child(&mut self, child: &ASTNode, offset: usize) -> Result<Option<BoundType>, String> {
+        self.at_child(offset, |checker| checker.check(child))
+    }
+
+    /// `check_child` for a nonterminal, without cloning it into an `ASTNode`
+    fn check_child_nt(&mut self, child: &NonTerminal, offset: usize) -> Result<Option<BoundType>, String> {
+        self.at_child(offset, |checker| checker.check_nt(child))
+    }
+
+    fn at_child(&mut self, offset: usize, check: impl FnOnce(&mut Self) -> Result<Option<BoundType>, String>) -> Result<Option<BoundType>, String> {
+        if let Some(types) = &self.types {
+            types.borrow_mut().enter(offset);
+        }
+        self.position += offset;
+        let result = check(self);
+        self.position -= offset;
+        if let Some(types) = &self.types {
+            types.borrow_mut().exit(&result);
+        }
+        result
+    }
+
+    /// Nonterminal children of `node` with their pre-order offsets from it
+    fn nonterminal_children(node: &NonTerminal) -> Vec<(usize, &ASTNode)> {
+        let mut offset = 1;
+        let mut out = Vec::new();
+        for child in &node.children {
+            if matches!(child, ASTNode::Nonterminal(_)) {
+                out.push((offset, child));
+            }
+            offset += child.node_count();
+        }
+        out
+    }
+
     pub fn check_nt(&mut self, node: &NonTerminal) -> Result<Option<BoundType>, String> {
+        // Entered from outside: index this subtree once for bound node lookups
+        if self.sizes.is_none() {
+            self.sizes = Some(Rc::new(node.subtree_sizes()));
+            let outer = std::mem::replace(&mut self.position, 0);
+            let result = self.check_nt(node);
+            self.sizes = None;
+            self.position = outer;
+            return result;
+        }
         let bound_typing_rule = &node.bound_typing_rule;
         if let Some(rule) = bound_typing_rule {
             // Use already-bound typing rules directly
@@ -95,25 +203,36 @@ impl TypeChecker {
             Ok(Some(ty))
         } else {
 
-            let nt_children = node.nonterminal_children();
-            if nt_children.len() == 1 {
-                debug_trace!("typechecker", "check_nt: single child, recursing into child node={}", nt_children[0].as_node().show_simple());
-                return self.check(&nt_children[0].as_node());
+            let nt_children = Self::nonterminal_children(node);
+            if let [(offset, child)] = nt_children.as_slice() {
+                debug_trace!("typechecker", "check_nt: single child, recursing into child node={}", child.show_simple());
+                return self.check_child(child, *offset);
             } else {
                 // No typing rule: just walk subtree to ensure descendants are processed; do not re-check child.
                 debug_trace!("typechecker", "check_nt: no bound_typing_rule for node={}", node.as_node().show_simple());
-                self.walk(&node.as_node())?;
+                self.walk_children(node)?;
                 Ok(None)
             }
         }
     }
 
+    /// Find the node a bound rule on `node` refers to by `id`
+    fn bound_node<'a>(&self, node: &'a NonTerminal, id: NodeId) -> Result<&'a NonTerminal, String> {
+        let found = match &self.sizes {
+            Some(sizes) => node.descendant_sized(id, &sizes[self.position..]),
+            None => node.descendant(id),
+        };
+        found.ok_or_else(|| self.format_error(&node.as_node(),
+            &format!("Bound node {} not found under {}", id, node.value)))
+    }
+
     /// Resolve bound type setting extensions into a vector of (name, type) pairs
-    fn resolve_bound_extensions(&self, extensions: &[BoundTypeAscription]) -> Result<Vec<(String, BoundType)>, String> {
+    fn resolve_bound_extensions(&self, extensions: &[BoundTypeAscription], node: &NonTerminal) -> Result<Vec<(String, BoundType)>, String> {
         let mut pairs: Vec<(String, BoundType)> = Vec::with_capacity(extensions.len());
         for ext in extensions {
-            let name = extract_terminal_value(&ext.node.as_node())
-                .ok_or_else(|| self.format_error(&ext.node.as_node(), "Could not extract variable name in type setting"))?;
+            let ext_node = self.bound_node(node, ext.node)?.as_node();
+            let name = extract_terminal_value(&ext_node)
+                .ok_or_else(|| self.format_error(&ext_node, "Could not extract variable name in type setting"))?;
             pairs.push((name, ext.ty.clone()));
         }
         Ok(pairs)
@@ -121,16 +240,36 @@ impl TypeChecker {
 
     /// Apply a bound typing rule where all meta-variables are already resolved
     pub fn apply_bound_rule(&mut self, rule: &BoundTypingRule, node: &NonTerminal) -> Result<BoundType, String> {
+        if let Some(derivation) = &self.derivation {
+            derivation.borrow_mut().begin(rule, node);
+        }
+        let result = self.apply_bound_rule_steps(rule, node);
+        if let Some(derivation) = &self.derivation {
+            derivation.borrow_mut().end(&result);
+        }
+        result
+    }
+
+    fn apply_bound_rule_steps(&mut self, rule: &BoundTypingRule, node: &NonTerminal) -> Result<BoundType, String> {
         let module_tag = self.trace_module_for(node);
-        debug_trace!(&module_tag, "apply_bound_rule:start rule={} node={}", rule.name, node.as_node().show_simple());
+        debug_trace!(&module_tag, "apply_bound_rule:start rule={} [{}] node={}", rule.name, rule.display_in(node), node.as_node().show_simple());
         debug_trace!(&module_tag, "context: BEFORE rule {}\n{}", rule.name, self.context.dump());
+        if let Some(stats) = &self.stats {
+            stats.borrow_mut().record_applied(&rule.name);
+        }
         // 1) Premise Validation: evaluate each premise in a temporary child context
         for premise in &rule.premises {
             // Build a child context for this premise, optionally seeded with setting extensions
             let mut premise_ctx = self.context.create_child();
             if let Some(setting) = &premise.setting {
-                let extensions = self.resolve_bound_extensions(&setting.extensions)?;
+                let extensions = self.resolve_bound_extensions(&setting.extensions, node)
+                    .inspect_err(|_| self.record_failure(rule, PremiseKind::Setting))?;
                 premise_ctx.extend(extensions);
+                if let Some(types) = &self.types
+                    && !setting.extensions.is_empty()
+                {
+                    types.borrow_mut().binder(&premise_ctx);
+                }
                 // debug dump the premise context if trace level
                 debug_trace!(&module_tag, "premise: created child context with setting extensions; context=\n{}", premise_ctx.dump());
             }
@@ -138,10 +277,20 @@ impl TypeChecker {
             // crate a new checker with the premise context
             let mut premise_checker = TypeChecker::with_context(premise_ctx);
             premise_checker.debug = self.debug.clone();
+            premise_checker.stats = self.stats.clone();
+            premise_checker.derivation = self.derivation.clone();
+            premise_checker.types = self.types.clone();
+            premise_checker.sizes = self.sizes.clone();
+            premise_checker.position = self.position;
 
             // Check the bound judgment in the child context
             if let Err(mut error) = premise_checker.check_bound_judgement(premise, node) {
-                error.push_str(&self.format_bound_rule_info(rule));
+                let kind = match &premise.judgment {
+                    Some(BoundTypingJudgment::Membership(..)) => PremiseKind::Membership,
+                    _ => PremiseKind::Ascription,
+                };
+                self.record_failure(rule, kind);
+                error.push_str(&self.format_bound_rule_info(rule, node));
                 debug_trace!(&module_tag, "apply_bound_rule:premise_error rule={} error={}", rule.name, error);
                 return Err(error);
             }
@@ -156,7 +305,8 @@ impl TypeChecker {
 
         // Commit output context transform to the current (ambient) context, if any
         if let Some(out) = &rule.conclusion.context.output {
-            let out_exts = self.resolve_bound_extensions(&out.extensions)?;
+            let out_exts = self.resolve_bound_extensions(&out.extensions, node)
+                .inspect_err(|_| self.record_failure(rule, PremiseKind::Setting))?;
             // if input context is same as output add to current, else create new child context
             if out.name != rule.conclusion.context.input {
                 self.context = self.context.create_child();
@@ -173,18 +323,22 @@ impl TypeChecker {
                 debug_trace!(&module_tag, "apply_bound_rule:end rule={} inferred_type={:?}", rule.name, ty);
                 Ok(ty.clone())
             }
-            BoundConclusionKind::ContextLookup(_context_name, var_node) => {
+            BoundConclusionKind::ContextLookup(_context_name, var_id) => {
+                let var_node = self.bound_node(node, *var_id)
+                    .inspect_err(|_| self.record_failure(rule, PremiseKind::Lookup))?;
                 if let Some(var_name) = extract_terminal_value(&var_node.as_node()) {
                     // debug node.show_simple()
-                    debug_trace!(&module_tag, "apply_bound_rule:start rule={} node={}", rule.name, node.as_node().show_simple());
+                    debug_trace!(&module_tag, "apply_bound_rule:start rule={} [{}] node={}", rule.name, rule.display_in(node), node.as_node().show_simple());
                     if let Some(ty) = self.context.lookup(&var_name) {
                         debug_trace!(&module_tag, "apply_bound_rule:end rule={} context_lookup {} => {:?}", rule.name, var_name, ty);
                         Ok(ty.clone())
                     } else {
+                        self.record_failure(rule, PremiseKind::Lookup);
                         Err(self.format_error(&var_node.as_node(),
                             &format!("Variable {} not found in context Γ for lookup", var_name)))
                     }
                 } else {
+                    self.record_failure(rule, PremiseKind::Lookup);
                     Err(self.format_error(&var_node.as_node(),
                         "Could not extract variable name for context lookup"))
                 }
@@ -197,14 +351,18 @@ impl TypeChecker {
         let module_tag = self.trace_module_for(current);
         match &premise.judgment {
             Some(BoundTypingJudgment::Ascription(ascr)) => {
-                let var_nt = &ascr.node;
+                let var_nt = self.bound_node(current, ascr.node)?;
                 let mut expected_ty = ascr.ty.clone();
 
-                if let Some(mut inferred_ty) = self.check(&var_nt.as_node())? {
+                let inferred = self.check_child_nt(var_nt, ascr.node.0)
+                    .inspect_err(|_| self.record_premise(premise, current, var_nt, None, false))?;
+                if let Some(mut inferred_ty) = inferred {
                     inferred_ty.resolve(&self.context);
                     expected_ty.resolve(&self.context);
                     // Use type compatibility checking instead of strict equality
-                    if inferred_ty.is_compatible_with(&expected_ty) {
+                    let ok = inferred_ty.is_compatible_with(&expected_ty);
+                    self.record_premise(premise, current, var_nt, Some(inferred_ty.clone()), ok);
+                    if ok {
                         debug_trace!(&module_tag, "ascription ok var={} expected={:?} inferred={:?}", self.extract_text(&var_nt.as_node()), expected_ty, inferred_ty);
                         Ok(())
                     } else {
@@ -215,14 +373,18 @@ impl TypeChecker {
                                     value, expected_ty, inferred_ty)))
                     }
                 } else {
+                    self.record_premise(premise, current, var_nt, None, false);
                     debug_trace!(&module_tag, "ascription no-infer var={} expected={:?} context=\n{}", var_nt.as_node().show_simple(), expected_ty, self.context.dump());
                     Err(self.format_error(&var_nt.as_node(),
                         &format!("No type inferred for node={}, required {:?}", var_nt.as_node().show_simple(), expected_ty)))
                 }
             }
-            Some(BoundTypingJudgment::Membership(var_node, ctx)) => {
+            Some(BoundTypingJudgment::Membership(var_id, ctx)) => {
+                let var_node = self.bound_node(current, *var_id)?;
                 if let Some(var_name) = extract_terminal_value(&var_node.as_node()) {
-                    if let Some(_t) = self.context.lookup(&var_name) {
+                    let found = self.context.lookup(&var_name).cloned();
+                    self.record_premise(premise, current, var_node, found.clone(), found.is_some());
+                    if found.is_some() {
                         debug_trace!(&module_tag, "membership ok {} in {}", var_name, ctx);
                         Ok(())
                     } else {
@@ -244,14 +406,16 @@ impl TypeChecker {
     pub fn walk(&mut self, node: &ASTNode) -> Result<(), String> {
         match node {
             ASTNode::Terminal(_) => Ok(()),
-            ASTNode::Nonterminal(nt) => {
-                // check() all children
-                for child in nt.nonterminal_children() {
-                    self.check(&child.as_node())?;
-                }
-                Ok(())
-            }
+            ASTNode::Nonterminal(nt) => self.walk_children(nt),
+        }
+    }
+
+    /// check() all nonterminal children
+    fn walk_children(&mut self, nt: &NonTerminal) -> Result<(), String> {
+        for (offset, child) in Self::nonterminal_children(nt) {
+            self.check_child(child, offset)?;
         }
+        Ok(())
     }
 }
 
@@ -0,0 +1,47 @@
+use std::collections::BTreeMap;
+
+/// Which part of a bound rule rejected a node
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PremiseKind {
+    /// `Γ ⊢ e : τ`
+    Ascription,
+    /// `x ∈ Γ`
+    Membership,
+    /// Extensions of a premise setting, e.g. the `x` in `Γ[x:τ]`
+    Setting,
+    /// `Γ(x)` in the conclusion
+    Lookup,
+}
+
+impl PremiseKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PremiseKind::Ascription => "ascription",
+            PremiseKind::Membership => "membership",
+            PremiseKind::Setting => "setting",
+            PremiseKind::Lookup => "lookup",
+        }
+    }
+}
+
+/// Per-rule counts collected while checking (shared with premise checkers)
+#[derive(Debug, Clone, Default)]
+pub struct RuleStats {
+    /// Times each rule was applied
+    pub applied: BTreeMap<String, usize>,
+    /// Times each rule rejected its node
+    pub failed: BTreeMap<String, usize>,
+    /// Failures by rule and premise kind
+    pub premise_failures: BTreeMap<(String, PremiseKind), usize>,
+}
+
+impl RuleStats {
+    pub(crate) fn record_applied(&mut self, rule: &str) {
+        *self.applied.entry(rule.to_string()).or_default() += 1;
+    }
+
+    pub(crate) fn record_failure(&mut self, rule: &str, kind: PremiseKind) {
+        *self.failed.entry(rule.to_string()).or_default() += 1;
+        *self.premise_failures.entry((rule.to_string(), kind)).or_default() += 1;
+    }
+}
@@ -10,7 +10,7 @@ use crate::logic::bind::{
     BoundTypeAscription,
     BoundType,
 };
-use crate::logic::ast::{ASTNode, NonTerminal, Terminal};
+use crate::logic::ast::{ASTNode, NodeId, NonTerminal, Terminal};
 use crate::logic::grammar::tests::STLC_SPEC;
 use crate::logic::grammar::Grammar;
 use crate::logic::parser::Parser;
@@ -22,13 +22,24 @@ fn mk_var_nt(name: &str) -> NonTerminal {
         value: "Variable".to_string(),
         span: None,
         binding: None,
-        children: vec![ASTNode::Terminal(Terminal { value: name.to_string(), span: None, binding: None })],
+        children: vec![ASTNode::Terminal(Terminal { value: name.to_string(), span: None, binding: None, trivia: None })],
         bound_typing_rule: None,
     }
 }
 
-fn ascr(name: &str, ty: BoundType) -> BoundTypeAscription {
-    BoundTypeAscription { node: mk_var_nt(name), ty }
+/// A `Dummy` node with one `Variable` child per name; the i-th variable is `NodeId(1 + 2 * i)`
+fn dummy_with(names: &[&str]) -> NonTerminal {
+    NonTerminal {
+        value: "Dummy".into(),
+        span: None,
+        children: names.iter().map(|n| mk_var_nt(n).as_node()).collect(),
+        binding: None,
+        bound_typing_rule: None,
+    }
+}
+
+fn ascr(id: usize, ty: BoundType) -> BoundTypeAscription {
+    BoundTypeAscription { node: NodeId(id), ty }
 }
 
 #[test] fn stlc_simple_ok() {
@@ -149,13 +160,13 @@ fn conclusion_identity_default() {
 
 #[test]
 fn conclusion_commits_output_extensions() {
-    let out = BoundTypeSetting { name: "Γ".into(), extensions: vec![ascr("x", BoundType::Atom("int".into()))] };
+    let out = BoundTypeSetting { name: "Γ".into(), extensions: vec![ascr(1, BoundType::Atom("int".into()))] };
     let rule = BoundTypingRule {
         name: "decl".into(),
         premises: vec![],
         conclusion: BoundConclusion { context: BoundConclusionContext { input: "Γ".into(), output: Some(out) }, kind: BoundConclusionKind::Type(BoundType::Atom("void".into())) },
     };
-    let dummy = NonTerminal { value: "Dummy".into(), span: None, children: vec![], binding: None, bound_typing_rule: None };
+    let dummy = dummy_with(&["x"]);
     let mut tc = TypeChecker::new();
     let _ = tc.apply_bound_rule(&rule, &dummy).expect("rule should apply");
     let got = tc.context.lookup("x").cloned();
@@ -164,13 +175,13 @@ fn conclusion_commits_output_extensions() {
 
 #[test]
 fn conclusion_input_only_does_not_commit() {
-    let input = BoundTypeSetting { name: "Γ".into(), extensions: vec![ascr("y", BoundType::Atom("Bool".into()))] };
+    let input = BoundTypeSetting { name: "Γ".into(), extensions: vec![ascr(1, BoundType::Atom("Bool".into()))] };
     let rule = BoundTypingRule {
         name: "expr".into(),
         premises: vec![],
         conclusion: BoundConclusion { context: BoundConclusionContext { input: "Γ".into(), output: None }, kind: BoundConclusionKind::Type(BoundType::Atom("unit".into())) },
     };
-    let dummy = NonTerminal { value: "Dummy".into(), span: None, children: vec![], binding: None, bound_typing_rule: None };
+    let dummy = dummy_with(&["y"]);
     let mut tc = TypeChecker::new();
     let _ = tc.apply_bound_rule(&rule, &dummy).expect("rule should apply");
     assert!(tc.context.lookup("y").is_none());
@@ -178,15 +189,25 @@ fn conclusion_input_only_does_not_commit() {
 
 #[test]
 fn conclusion_context_lookup_uses_ambient() {
-    let var_node = mk_var_nt("z");
     let rule = BoundTypingRule {
         name: "var".into(),
         premises: vec![],
-        conclusion: BoundConclusion { context: BoundConclusionContext::default(), kind: BoundConclusionKind::ContextLookup("Γ".into(), var_node) },
+        conclusion: BoundConclusion { context: BoundConclusionContext::default(), kind: BoundConclusionKind::ContextLookup("Γ".into(), NodeId(1)) },
     };
-    let dummy = NonTerminal { value: "Dummy".into(), span: None, children: vec![], binding: None, bound_typing_rule: None };
+    let dummy = dummy_with(&["z"]);
     let mut tc = TypeChecker::new();
     tc.add("z".into(), BoundType::Atom("T".into()));
     let ty = tc.apply_bound_rule(&rule, &dummy).expect("rule should apply");
     assert_eq!(ty, BoundType::Atom("T".into()));
-}
\ No newline at end of file
+}
+#[test]
+fn check_nt_and_walk_on_a_parsed_tree() {
+    let ast = Parser::new(Grammar::load(STLC_SPEC).unwrap()).parse("(λy:a->a.y)((λx:a->a.x)z)").unwrap();
+    let nt = ast.as_nonterminal().unwrap();
+    let mut tc = TypeChecker::new();
+    tc.add("z".to_string(), BoundType::Atom("a".to_string()));
+    let direct = tc.check_nt(nt).expect("check_nt on the root");
+    tc.walk(&ast).expect("walk the root's children");
+    // The same checker still checks whole trees afterwards
+    assert_eq!(tc.check(&ast).expect("check after check_nt"), direct);
+}
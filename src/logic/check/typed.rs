@@ -0,0 +1,232 @@
+// Typed ASTs: the type the checker found for every node, not just the root, and the
+// context each binder checks its premises in.
+//
+// Checking clones the nodes it descends into, so nodes are told apart by pre-order id
+// (the numbering bound rules use). `TypeRecorder` keeps the ids of the nodes being
+// checked; it is shared with premise checkers like `DerivationRecorder`.
+
+use std::collections::BTreeMap;
+
+use super::context::TypingContext;
+use crate::logic::ast::{ASTNode, AstArena, NodeId, SourceSpan};
+use crate::logic::bind::BoundType;
+
+#[derive(Debug, Default)]
+pub struct TypeRecorder {
+    /// Pre-order ids of the nodes being checked, innermost last
+    path: Vec<usize>,
+    types: BTreeMap<usize, BoundType>,
+    contexts: BTreeMap<usize, Vec<(String, BoundType)>>,
+}
+
+impl TypeRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start checking the node `offset` nodes after the current one in pre-order
+    pub(crate) fn enter(&mut self, offset: usize) {
+        let id = self.path.last().map_or(offset, |p| p + offset);
+        self.path.push(id);
+    }
+
+    pub(crate) fn exit(&mut self, result: &Result<Option<BoundType>, String>) {
+        if let (Some(id), Ok(Some(ty))) = (self.path.pop(), result) {
+            self.types.insert(id, ty.clone());
+        }
+    }
+
+    /// The current node extends the context with `context` for a premise
+    pub(crate) fn binder(&mut self, context: &TypingContext) {
+        if let Some(id) = self.path.last() {
+            let mut bindings: Vec<(String, BoundType)> = context.all_references().into_iter().map(|(k, v)| (k, v.clone())).collect();
+            bindings.sort_by(|a, b| a.0.cmp(&b.0));
+            self.contexts.insert(*id, bindings);
+        }
+    }
+}
+
+/// The type of one node
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub struct NodeType {
+    /// Pre-order id, 0 = root
+    pub node: NodeId,
+    pub span: Option<SourceSpan>,
+    pub ty: BoundType,
+}
+
+/// The context a binder checks its premises in, inner bindings shadowing outer ones
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub struct BinderContext {
+    pub node: NodeId,
+    pub span: Option<SourceSpan>,
+    pub bindings: Vec<(String, BoundType)>,
+}
+
+/// A checked tree with the type of each node that has one
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub struct TypedAst {
+    pub ast: ASTNode,
+    /// In pre-order; chains of single-child nodes share their child's type
+    pub types: Vec<NodeType>,
+    pub contexts: Vec<BinderContext>,
+    /// Type of the root, as `TypeChecker::check` returns it
+    pub ty: Option<BoundType>,
+    /// The type error, if checking failed; types found before it are kept
+    pub error: Option<String>,
+}
+
+impl TypedAst {
+    pub(crate) fn new(ast: &ASTNode, recorder: TypeRecorder, result: Result<Option<BoundType>, String>) -> Self {
+        let arena = AstArena::from(ast.clone());
+        let span = |id: usize| arena.get(NodeId(id)).and_then(|n| n.span.clone());
+        let types = recorder.types.into_iter().map(|(id, ty)| NodeType { node: NodeId(id), span: span(id), ty }).collect();
+        let contexts = recorder.contexts.into_iter().map(|(id, bindings)| BinderContext { node: NodeId(id), span: span(id), bindings }).collect();
+        let (ty, error) = match result {
+            Ok(ty) => (ty, None),
+            Err(e) => (None, Some(e)),
+        };
+        TypedAst { ast: ast.clone(), types, contexts, ty, error }
+    }
+
+    /// Type of the node with pre-order id `id`
+    pub fn type_of(&self, id: NodeId) -> Option<&BoundType> {
+        self.types.iter().find(|t| t.node == id).map(|t| &t.ty)
+    }
+
+    /// Context of the binder with pre-order id `id`
+    pub fn context_of(&self, id: NodeId) -> Option<&[(String, BoundType)]> {
+        self.contexts.iter().find(|c| c.node == id).map(|c| c.bindings.as_slice())
+    }
+
+    /// `ASTNode::pretty` with `(type "...")` on typed nodes and `(ctx "x: ...")` on binders
+    pub fn to_sexpr(&self) -> String {
+        fn esc(s: &str) -> String {
+            s.replace('\\', "\\\\").replace('"', "\\\"")
+        }
+        fn go(t: &TypedAst, node: &ASTNode, next: &mut usize, indent: usize, out: &mut String) {
+            let id = NodeId(*next);
+            *next += 1;
+            let pad = "  ".repeat(indent);
+            let mut notes = String::new();
+            if let Some(b) = node.binding() {
+                notes.push_str(&format!(" (b {})", b));
+            }
+            if let Some(ty) = t.type_of(id) {
+                notes.push_str(&format!(" (type \"{}\")", esc(&ty.to_string())));
+            }
+            if let Some(ctx) = t.context_of(id) {
+                let ctx = ctx.iter().map(|(x, ty)| format!("{}: {}", x, ty)).collect::<Vec<_>>().join(", ");
+                notes.push_str(&format!(" (ctx \"{}\")", esc(&ctx)));
+            }
+            match node {
+                ASTNode::Terminal(term) => out.push_str(&format!("{}(T \"{}\"{})", pad, esc(&term.value), notes)),
+                ASTNode::Nonterminal(nt) => {
+                    let rule = nt.rule_name().map(|r| format!(" (rule {})", r)).unwrap_or_default();
+                    out.push_str(&format!("{}(N {}{}{}", pad, nt.value, rule, notes));
+                    for child in &nt.children {
+                        out.push('\n');
+                        go(t, child, next, indent + 1, out);
+                    }
+                    out.push(')');
+                }
+            }
+        }
+        let mut out = String::new();
+        go(self, &self.ast, &mut 0, 0, &mut out);
+        out
+    }
+
+    /// `source` with each typed span underlined and its type next to it, outermost
+    /// first under every line; a chain of nodes with the same span is shown once
+    pub fn to_annotated_source(&self, source: &str) -> String {
+        let mut spans: Vec<(usize, usize, &BoundType)> = Vec::new();
+        for t in &self.types {
+            if let Some(s) = &t.span
+                && !spans.iter().any(|(a, b, _)| (*a, *b) == (s.start, s.end))
+            {
+                spans.push((s.start, s.end, &t.ty));
+            }
+        }
+        spans.sort_by(|x, y| x.0.cmp(&y.0).then(y.1.cmp(&x.1)));
+
+        let mut out = String::new();
+        let mut line_start = 0;
+        for line in source.split('\n') {
+            let line_end = line_start + line.chars().count();
+            out.push_str(line);
+            out.push('\n');
+            for (start, end, ty) in spans.iter().filter(|(s, _, _)| (line_start..line_end.max(line_start + 1)).contains(s)) {
+                let width = end.min(&line_end).saturating_sub(*start).max(1);
+                out.push_str(&format!("{}{} {}\n", " ".repeat(start - line_start), "^".repeat(width), ty));
+            }
+            line_start = line_end + 1;
+        }
+        if let Some(e) = &self.error {
+            out.push_str(&format!("error: {}\n", e));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::logic::ast::NodeId;
+    use crate::logic::bind::BoundType;
+    use crate::logic::check::TypeChecker;
+    use crate::logic::grammar::Grammar;
+    use crate::logic::grammar::tests::STLC_SPEC;
+    use crate::logic::parser::Parser;
+
+    fn atom(a: &str) -> BoundType {
+        BoundType::Atom(a.into())
+    }
+
+    #[test]
+    fn records_every_node_and_binder() {
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        let src = "(λx:a->a.x) z";
+        let ast = Parser::new(grammar).parse(src).unwrap();
+        let mut tc = TypeChecker::new();
+        tc.add("z".into(), atom("a"));
+        let typed = tc.check_typed(&ast);
+        assert_eq!(typed.error, None);
+        assert_eq!(typed.ty, Some(atom("a")));
+        assert_eq!(typed.type_of(NodeId(0)), Some(&atom("a")));
+
+        // every node of a rule, and the chains above it, has its type
+        let arena = crate::logic::ast::AstArena::from(ast.clone());
+        // the bound variable is only read by the lambda's premise, its use is checked
+        let vars: Vec<_> = arena.ids().filter(|id| arena[*id].value == "Variable").collect();
+        assert_eq!(vars.iter().map(|v| typed.type_of(*v)).collect::<Vec<_>>(), vec![None, Some(&atom("a")), Some(&atom("a"))]);
+        let lambda = arena.ids().find(|id| arena[*id].value == "Lambda").unwrap();
+        let arrow = BoundType::Arrow(Box::new(atom("a")), Box::new(atom("a")));
+        assert_eq!(typed.type_of(lambda), Some(&arrow));
+        assert_eq!(typed.type_of(arena.parent(lambda).unwrap()), Some(&arrow));
+
+        // the lambda checks its body with x in the context
+        let ctx = typed.context_of(lambda).unwrap();
+        assert!(ctx.iter().any(|(x, _)| x == "x") && ctx.iter().any(|(x, ty)| x == "z" && *ty == atom("a")));
+
+        let sexpr = typed.to_sexpr();
+        assert!(sexpr.starts_with("(N Term (type \"a\")"), "{}", sexpr);
+        assert!(sexpr.contains("(N Lambda (rule lambda) (type \"a → a\") (ctx \"x: a, z: a\")"), "{}", sexpr);
+        let annotated = typed.to_annotated_source(src);
+        assert!(annotated.starts_with(&format!("{}\n^^^^^^^^^^^^^ a\n", src)), "{}", annotated);
+        assert!(annotated.contains("\n            ^ a\n"), "{}", annotated);
+    }
+
+    #[test]
+    fn keeps_types_found_before_an_error() {
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        let ast = Parser::new(grammar).parse("(λx:a->a.x) z").unwrap();
+        let typed = TypeChecker::new().check_typed(&ast);
+        assert!(typed.error.as_deref().is_some_and(|e| e.contains("z")));
+        assert_eq!(typed.ty, None);
+        assert!(!typed.types.is_empty());
+        assert!(typed.to_annotated_source("(λx:a->a.x) z").contains("error: "));
+    }
+}
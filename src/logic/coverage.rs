@@ -0,0 +1,219 @@
+// Coverage of a spec by a corpus of programs: which production alternatives the
+// parser used, which typing rules were bound and applied, and which premises failed.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use crate::logic::ast::ASTNode;
+use crate::logic::check::stats::RuleStats;
+use crate::logic::check::TypeChecker;
+use crate::logic::grammar::Grammar;
+use crate::logic::grammar::desugar::SourceError;
+use crate::logic::parser::Parser;
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "json", derive(serde::Serialize))]
+pub struct RuleCoverage {
+    /// Nodes the rule was bound to
+    pub bound: usize,
+    /// Files with at least one node bound to the rule
+    pub files: usize,
+    /// Times the checker applied the rule
+    pub applied: usize,
+    /// Times the rule rejected its node
+    pub failed: usize,
+    /// Failures by premise kind (`ascription`, `membership`, `setting`, `lookup`)
+    pub premise_failures: BTreeMap<String, usize>,
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "json", derive(serde::Serialize))]
+pub struct Coverage {
+    pub files: usize,
+    pub parse_errors: usize,
+    pub desugar_errors: usize,
+    pub type_errors: usize,
+    /// Uses of each alternative, per nonterminal, in declaration order
+    pub alternatives: BTreeMap<String, Vec<usize>>,
+    pub rules: BTreeMap<String, RuleCoverage>,
+}
+
+impl Coverage {
+    /// Empty report listing every alternative and typing rule of `grammar`
+    pub fn new(grammar: &Grammar) -> Self {
+        let alternatives = grammar.productions.iter().map(|(nt, alts)| (nt.clone(), vec![0; alts.len()])).collect();
+        let rules = grammar.typing_rules.keys().map(|r| (r.clone(), RuleCoverage::default())).collect();
+        Self { alternatives, rules, ..Default::default() }
+    }
+
+    /// Parse and check one program, adding what it exercised
+    pub fn add_source(&mut self, grammar: &Grammar, source: &str) {
+        self.files += 1;
+        let mut parser = Parser::new(grammar.clone());
+        let ast = match parser.parse(source) {
+            Ok(ast) => ast,
+            Err(_) => {
+                self.parse_errors += 1;
+                return;
+            }
+        };
+        self.count_nodes(grammar, &ast);
+        for rule in ast.rules() {
+            self.rules.entry(rule).or_default().files += 1;
+        }
+
+        let stats = Rc::new(RefCell::new(RuleStats::default()));
+        let mut checker = TypeChecker::new().with_stats(Rc::clone(&stats));
+        match grammar.check_parsed(&ast, &mut checker) {
+            Err(SourceError::Desugar(_)) => self.desugar_errors += 1,
+            Err(_) => self.type_errors += 1,
+            Ok(_) => {}
+        }
+        let stats = stats.borrow();
+        for (rule, n) in &stats.applied {
+            self.rules.entry(rule.clone()).or_default().applied += n;
+        }
+        for (rule, n) in &stats.failed {
+            self.rules.entry(rule.clone()).or_default().failed += n;
+        }
+        for ((rule, kind), n) in &stats.premise_failures {
+            *self.rules.entry(rule.clone()).or_default().premise_failures.entry(kind.name().to_string()).or_default() += n;
+        }
+    }
+
+    fn count_nodes(&mut self, grammar: &Grammar, node: &ASTNode) {
+        let ASTNode::Nonterminal(nt) = node else { return };
+        if let Some(alt) = grammar.match_alternative(nt)
+            && let Some(counts) = self.alternatives.get_mut(&nt.value)
+        {
+            counts[alt] += 1;
+        }
+        if let Some(rule) = nt.rule_name() {
+            self.rules.entry(rule.to_string()).or_default().bound += 1;
+        }
+        for child in &nt.children {
+            self.count_nodes(grammar, child);
+        }
+    }
+
+    /// (covered, total) production alternatives
+    pub fn alternative_ratio(&self) -> (usize, usize) {
+        let all = self.alternatives.values().flatten();
+        (all.clone().filter(|n| **n > 0).count(), all.count())
+    }
+
+    /// (used, total) typing rules
+    pub fn rule_ratio(&self) -> (usize, usize) {
+        (self.rules.values().filter(|r| r.applied > 0).count(), self.rules.len())
+    }
+
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("coverage serializes")
+    }
+
+    /// Human-readable report; `grammar` gives the alternatives' source text
+    pub fn to_text(&self, grammar: &Grammar) -> String {
+        let mut out = String::new();
+        let percent = |(a, b): (usize, usize)| if b == 0 { 100.0 } else { a as f64 * 100.0 / b as f64 };
+        let desugar = if self.desugar_errors > 0 { format!(", {} desugar errors", self.desugar_errors) } else { String::new() };
+        out.push_str(&format!(
+            "{} files ({} parse errors{}, {} type errors)\n",
+            self.files, self.parse_errors, desugar, self.type_errors
+        ));
+
+        let alts = self.alternative_ratio();
+        out.push_str(&format!("\nProductions: {}/{} alternatives used ({:.1}%)\n", alts.0, alts.1, percent(alts)));
+        let mut order = grammar.production_order.clone();
+        order.extend(self.alternatives.keys().filter(|k| !grammar.production_order.contains(k)).cloned());
+        for nt in order {
+            let (Some(counts), Some(prods)) = (self.alternatives.get(&nt), grammar.productions.get(&nt)) else { continue };
+            for (i, (n, prod)) in counts.iter().zip(prods).enumerate() {
+                let rhs: Vec<String> = prod.rhs.iter().map(symbol_text).collect();
+                let mark = if *n == 0 { "  MISSING" } else { "" };
+                out.push_str(&format!("  {:>6}  {} #{}: {}{}\n", n, nt, i, rhs.join(" "), mark));
+            }
+        }
+
+        let rules = self.rule_ratio();
+        out.push_str(&format!("\nTyping rules: {}/{} applied ({:.1}%)\n", rules.0, rules.1, percent(rules)));
+        for (name, r) in &self.rules {
+            let mark = if r.applied == 0 { "  MISSING" } else { "" };
+            out.push_str(&format!(
+                "  {}: bound {} in {} files, applied {}, failed {}{}\n",
+                name, r.bound, r.files, r.applied, r.failed, mark
+            ));
+            for (kind, n) in &r.premise_failures {
+                out.push_str(&format!("      {} failures: {}\n", kind, n));
+            }
+        }
+        out
+    }
+}
+
+fn symbol_text(symbol: &crate::logic::grammar::Symbol) -> String {
+    use crate::logic::grammar::{RepetitionKind, Symbol};
+    let (base, rep) = match symbol {
+        Symbol::Simple { value, repetition, .. } => (value.clone(), repetition),
+        Symbol::Group { symbols, repetition } => {
+            (format!("({})", symbols.iter().map(symbol_text).collect::<Vec<_>>().join(" ")), repetition)
+        }
+    };
+    let suffix = match rep {
+        Some(RepetitionKind::ZeroOrMore) => "*",
+        Some(RepetitionKind::OneOrMore) => "+",
+        Some(RepetitionKind::ZeroOrOne) => "?",
+        None => "",
+    };
+    format!("{}{}", base, suffix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::grammar::tests::{let_spec, STLC_SPEC};
+
+    #[test]
+    fn counts_alternatives_rules_and_failures() {
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        let mut coverage = Coverage::new(&grammar);
+        for src in ["λx:a->a.x", "y", "λ.x"] {
+            coverage.add_source(&grammar, src);
+        }
+        assert_eq!((coverage.files, coverage.parse_errors, coverage.type_errors), (3, 1, 1));
+        // Term ::= Application | BaseTerm: only the second was used (two roots and a lambda body)
+        assert_eq!(coverage.alternatives["Term"], vec![0, 3]);
+        let (covered, total) = coverage.alternative_ratio();
+        assert!(covered > 0 && covered < total);
+
+        let lambda = &coverage.rules["lambda"];
+        assert_eq!((lambda.bound, lambda.files, lambda.applied, lambda.failed), (1, 1, 1, 0));
+        let var = &coverage.rules["var"];
+        assert_eq!(var.applied, 2);
+        assert_eq!(var.premise_failures.get("membership"), Some(&1));
+        assert_eq!(coverage.rules["app"].applied, 0);
+
+        let text = coverage.to_text(&grammar);
+        assert!(text.contains("app: bound 0 in 0 files, applied 0, failed 0  MISSING"), "{}", text);
+        #[cfg(feature = "json")]
+        {
+            let json: serde_json::Value = serde_json::from_str(&coverage.to_json()).unwrap();
+            assert_eq!(json["rules"]["var"]["premise_failures"]["membership"], 1);
+        }
+    }
+
+    #[test]
+    fn checks_programs_through_their_desugaring() {
+        let grammar = Grammar::load(&let_spec()).unwrap();
+        let mut coverage = Coverage::new(&grammar);
+        // types as `a->a` once desugared, but `g` is unbound in the sugared tree
+        coverage.add_source(&grammar, "λz:a->a.(let g : a->a = z in g)");
+        assert_eq!((coverage.type_errors, coverage.desugar_errors), (0, 0));
+        assert_eq!(coverage.rules["app"].applied, 1);
+
+        coverage.add_source(&grammar, "let g : a = λy:a->a.(y) in (g)");
+        assert_eq!((coverage.type_errors, coverage.desugar_errors), (0, 1));
+        assert!(coverage.to_text(&grammar).starts_with("2 files (0 parse errors, 1 desugar errors, 0 type errors)"));
+    }
+}
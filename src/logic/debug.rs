@@ -110,35 +110,35 @@ macro_rules! debug {
 /// Convenience macros for different debug levels
 macro_rules! debug_error {
     ($module:expr, $($arg:tt)*) => {
-        $crate::debug!($crate::logic::debug::DebugLevel::Error, $module, $($arg)*);
+        $crate::debug!($crate::logic::debug::DebugLevel::Error, $module, $($arg)*)
     };
 }
 
 #[macro_export]
 macro_rules! debug_warn {
     ($module:expr, $($arg:tt)*) => {
-        $crate::debug!($crate::logic::debug::DebugLevel::Warn, $module, $($arg)*);
+        $crate::debug!($crate::logic::debug::DebugLevel::Warn, $module, $($arg)*)
     };
 }
 
 #[macro_export]
 macro_rules! debug_info {
     ($module:expr, $($arg:tt)*) => {
-        $crate::debug!($crate::logic::debug::DebugLevel::Info, $module, $($arg)*);
+        $crate::debug!($crate::logic::debug::DebugLevel::Info, $module, $($arg)*)
     };
 }
 
 #[macro_export]
 macro_rules! debug_debug {
     ($module:expr, $($arg:tt)*) => {
-        $crate::debug!($crate::logic::debug::DebugLevel::Debug, $module, $($arg)*);
+        $crate::debug!($crate::logic::debug::DebugLevel::Debug, $module, $($arg)*)
     };
 }
 
 #[macro_export]
 macro_rules! debug_trace {
     ($module:expr, $($arg:tt)*) => {
-        $crate::debug!($crate::logic::debug::DebugLevel::Trace, $module, $($arg)*);
+        $crate::debug!($crate::logic::debug::DebugLevel::Trace, $module, $($arg)*)
     };
 }
 
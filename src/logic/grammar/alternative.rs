@@ -0,0 +1,91 @@
+use super::utils::is_regex;
+use super::{Grammar, Production, RepetitionKind, Symbol};
+use crate::logic::ast::{ASTNode, NonTerminal};
+
+impl Grammar {
+    /// Index of the production of `node.value` that produced `node`'s children.
+    /// Follows the parser: the first alternative whose right-hand side matches wins.
+    pub fn match_alternative(&self, node: &NonTerminal) -> Option<usize> {
+        let productions = self.productions.get(&node.value)?;
+        productions.iter().position(|p| self.matches_sequence(&p.rhs, &node.children))
+    }
+
+    /// A production of `outer` that is a lone `inner` between literal tokens, such as
+    /// `BaseTerm ::= '(' Term ')'`: how an `inner` node goes where an `outer` is expected
+    pub fn wrapping_production(&self, outer: &str, inner: &str) -> Option<&Production> {
+        self.productions.get(outer)?.iter().find(|p| {
+            let (nts, literals): (Vec<&Symbol>, Vec<&Symbol>) =
+                p.rhs.iter().partition(|s| s.is_group() || self.productions.contains_key(s.value()));
+            matches!(nts.as_slice(), [Symbol::Simple { value, repetition: None, .. }] if value == inner)
+                && !literals.is_empty()
+                && literals.iter().all(|s| s.repetition().is_none() && !is_regex(s.value()))
+        })
+    }
+
+    /// Do `children` form exactly the sequence `symbols` (repetitions flattened as the parser does)?
+    fn matches_sequence(&self, symbols: &[Symbol], children: &[ASTNode]) -> bool {
+        let Some((first, rest)) = symbols.split_first() else { return children.is_empty() };
+        match first.repetition() {
+            None => children.first().is_some_and(|c| self.matches_symbol(first, c)) && self.matches_sequence(rest, &children[1..]),
+            Some(RepetitionKind::ZeroOrOne) => {
+                (children.first().is_some_and(|c| self.matches_symbol(first, c)) && self.matches_sequence(rest, &children[1..]))
+                    || self.matches_sequence(rest, children)
+            }
+            Some(rep) => {
+                // Take the longest run first, like the parser's greedy loop
+                let run = children.iter().take_while(|c| self.matches_symbol(first, c)).count();
+                let min = if *rep == RepetitionKind::OneOrMore { 1 } else { 0 };
+                (min..=run).rev().any(|k| self.matches_sequence(rest, &children[k..]))
+            }
+        }
+    }
+
+    fn matches_symbol(&self, symbol: &Symbol, child: &ASTNode) -> bool {
+        match (symbol, child) {
+            (Symbol::Group { symbols, .. }, ASTNode::Nonterminal(nt)) => {
+                nt.value == "<group>" && self.matches_sequence(symbols, &nt.children)
+            }
+            (Symbol::Simple { value, .. }, ASTNode::Nonterminal(nt)) => {
+                self.productions.contains_key(value) && nt.value == *value
+            }
+            (Symbol::Simple { value, .. }, ASTNode::Terminal(t)) => {
+                if self.productions.contains_key(value) {
+                    false
+                } else if value.starts_with('/') && value.ends_with('/') && value.len() > 1 {
+                    !self.special_tokens.contains(&t.value)
+                        && self.regex_matches(value.trim_matches('/'), &t.value)
+                } else {
+                    value.trim_matches('\'') == t.value
+                }
+            }
+            (Symbol::Group { .. }, ASTNode::Terminal(_)) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::grammar::tests::STLC_SPEC;
+    use crate::logic::parser::Parser;
+
+    #[test]
+    fn finds_the_alternative_the_parser_used() {
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        let mut parser = Parser::new(grammar.clone());
+        // Term ::= Application[e] | BaseTerm[e]
+        let app = parser.parse("λf:a->a.f x").unwrap();
+        assert_eq!(app.value(), "Term");
+        assert_eq!(grammar.match_alternative(app.as_nonterminal().unwrap()), Some(0));
+        let var = parser.parse("x").unwrap();
+        assert_eq!(grammar.match_alternative(var.as_nonterminal().unwrap()), Some(1));
+
+        let spec = "A ::= 'a'\nB ::= 'b'\nS ::= A+ B? | B";
+        let grammar = Grammar::load(spec).unwrap();
+        let mut parser = Parser::new(grammar.clone());
+        let s = parser.parse("a a b").unwrap();
+        assert_eq!(grammar.match_alternative(s.as_nonterminal().unwrap()), Some(0));
+        let s = parser.parse("b").unwrap();
+        assert_eq!(grammar.match_alternative(s.as_nonterminal().unwrap()), Some(1));
+    }
+}
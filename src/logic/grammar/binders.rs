@@ -0,0 +1,92 @@
+// Binding structure: `Lambda binds x in e` says that the variable a Lambda binds to `x`
+// scopes over the subtree it binds to `e`. The operations built on it (free variables,
+// substitution, alpha-equivalence) live in `ast::scope`.
+
+use std::collections::{HashSet, VecDeque};
+
+use super::{Grammar, Symbol};
+
+/// A `binds` line of a spec
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub struct BinderDecl {
+    pub nonterminal: String,
+    /// Binding name of the variable the node introduces
+    pub var: String,
+    /// Binding names of the subtrees the variable is visible in
+    pub scopes: Vec<String>,
+    /// 1-based line in the spec
+    pub line: usize,
+}
+
+impl BinderDecl {
+    /// `Name binds x in e ...`
+    pub fn is_binder_line(line: &str) -> bool {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        words.len() >= 3 && words[1] == "binds" && !line.contains("::=")
+    }
+
+    /// Parse one `Name binds x in e1, e2` line
+    pub fn parse(line: &str, line_no: usize) -> Result<Self, String> {
+        let err = |msg: &str| format!("line {}: {}: {}", line_no, msg, line.trim());
+        let words: Vec<&str> = line.split(|c: char| c.is_whitespace() || c == ',').filter(|w| !w.is_empty()).collect();
+        match words.as_slice() {
+            [nonterminal, "binds", var, "in", scopes @ ..] if !scopes.is_empty() => Ok(BinderDecl {
+                nonterminal: nonterminal.to_string(),
+                var: var.to_string(),
+                scopes: scopes.iter().map(|s| s.to_string()).collect(),
+                line: line_no,
+            }),
+            _ => Err(err("expected 'Nonterminal binds x in e'")),
+        }
+    }
+
+    pub fn to_line(&self) -> String {
+        format!("{} binds {} in {}", self.nonterminal, self.var, self.scopes.join(", "))
+    }
+}
+
+impl Grammar {
+    /// The nearest symbol bound to `binding` below `nonterminal`, looking through the
+    /// productions of the nonterminals in between, as the binder does on trees
+    fn bound_symbol(&self, nonterminal: &str, binding: &str) -> Option<&Symbol> {
+        let mut queue = VecDeque::from([nonterminal]);
+        let mut seen = HashSet::from([nonterminal]);
+        while let Some(nt) = queue.pop_front() {
+            let mut symbols: Vec<&Symbol> = self.productions.get(nt)?.iter().flat_map(|p| &p.rhs).collect();
+            while let Some(symbol) = symbols.pop() {
+                if let Some(group) = symbol.group_symbols() {
+                    symbols.extend(group);
+                } else if symbol.binding().is_some_and(|b| b == binding) {
+                    return Some(symbol);
+                } else if self.productions.contains_key(symbol.value()) && seen.insert(symbol.value()) {
+                    queue.push_back(symbol.value());
+                }
+            }
+        }
+        None
+    }
+
+    /// Nonterminals whose nodes are variables: those `binds` declarations introduce
+    pub fn variable_nonterminals(&self) -> HashSet<&str> {
+        self.binders.iter().filter_map(|d| self.bound_symbol(&d.nonterminal, &d.var)).map(Symbol::value).collect()
+    }
+
+    /// Check every `binds` line against the productions
+    pub fn check_binders(&self) -> Result<(), String> {
+        for decl in &self.binders {
+            let err = |msg: String| format!("line {}: {}: {}", decl.line, msg, decl.to_line());
+            if !self.productions.contains_key(&decl.nonterminal) {
+                return Err(err(format!("unknown nonterminal '{}'", decl.nonterminal)));
+            }
+            let var = self.bound_symbol(&decl.nonterminal, &decl.var).ok_or_else(|| err(format!("{} binds nothing to '{}'", decl.nonterminal, decl.var)))?;
+            if !self.productions.contains_key(var.value()) {
+                return Err(err(format!("'{}' must be bound to a nonterminal, not {}", decl.var, var.value())));
+            }
+            if let Some(scope) = decl.scopes.iter().find(|s| self.bound_symbol(&decl.nonterminal, s).is_none()) {
+                return Err(err(format!("{} binds nothing to '{}'", decl.nonterminal, scope)));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,298 @@
+// Desugaring: `desugar Let ⇒ (λx:τ.e2) e1` defines a surface form by a template in
+// the language itself. The template's holes are the binding names of the sugared
+// production; each hole is replaced by the subtree bound to that name.
+//
+// A desugaring compiles to a `RewriteRule`. Its pattern is the production, one
+// metavariable per bound symbol. Its template is the parsed template text, where the
+// node spelling just a hole becomes that hole's metavariable, parenthesized if the bound
+// symbol does not fit there. New nodes
+// take the span of the sugared node, so type errors point into the original source.
+// Everything that type-checks programs goes through `Grammar::check_source` (or
+// `check_parsed`), so sugar is always checked through its expansion.
+
+use std::collections::HashMap;
+
+use super::Grammar;
+use crate::logic::ast::rewrite::{Pattern, RewriteRule, Rewriter};
+use crate::logic::ast::ASTNode;
+use crate::logic::bind::BoundType;
+use crate::logic::check::TypeChecker;
+use crate::logic::parser::Parser;
+
+/// A `desugar` line of a spec
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub struct Desugaring {
+    pub nonterminal: String,
+    /// Source text in the spec's language, with the production's bindings as holes
+    pub template: String,
+    /// 1-based line in the spec
+    pub line: usize,
+}
+
+impl Desugaring {
+    /// `desugar` followed by a nonterminal and `⇒` (or `=>`)
+    pub fn is_desugar_line(line: &str) -> bool {
+        line.trim_start().strip_prefix("desugar ").is_some_and(|rest| rest.contains('⇒') || rest.contains("=>"))
+    }
+
+    /// Parse one `desugar Name ⇒ template` line
+    pub fn parse(line: &str, line_no: usize) -> Result<Self, String> {
+        let err = |msg: &str| format!("line {}: {}: {}", line_no, msg, line.trim());
+        let rest = line.trim().strip_prefix("desugar").ok_or_else(|| err("expected 'desugar'"))?;
+        let (nonterminal, template) = rest.split_once('⇒').or_else(|| rest.split_once("=>")).ok_or_else(|| err("expected '⇒'"))?;
+        let (nonterminal, template) = (nonterminal.trim(), template.trim());
+        if nonterminal.is_empty() || nonterminal.contains(char::is_whitespace) {
+            return Err(err("expected one nonterminal before '⇒'"));
+        }
+        if template.is_empty() {
+            return Err(err("expected a template after '⇒'"));
+        }
+        Ok(Desugaring { nonterminal: nonterminal.to_string(), template: template.to_string(), line: line_no })
+    }
+
+    pub fn to_line(&self) -> String {
+        format!("desugar {} ⇒ {}", self.nonterminal, self.template)
+    }
+
+    /// One rule per production of the nonterminal
+    fn compile(&self, grammar: &Grammar) -> Result<Vec<RewriteRule>, String> {
+        let err = |msg: String| format!("line {}: desugar {}: {}", self.line, self.nonterminal, msg);
+        let productions = grammar.productions.get(&self.nonterminal).ok_or_else(|| err("unknown nonterminal".into()))?;
+        let tree = self.parse_template(grammar).map_err(err)?;
+        let mut rules = Vec::new();
+        for production in productions {
+            let mut holes = HashMap::new();
+            let mut children = Vec::new();
+            for symbol in &production.rhs {
+                if symbol.is_group() || symbol.repetition().is_some() {
+                    return Err(err("sugared productions cannot use groups or repetition".into()));
+                }
+                let binding = symbol.binding().cloned();
+                if let Some(name) = &binding {
+                    holes.insert(name.clone(), symbol.value().to_string());
+                }
+                children.push(Pattern::Var { name: binding.clone(), binding });
+            }
+            let pattern = Pattern::Nonterminal { name: Some(self.nonterminal.clone()), rule: None, binding: None, children };
+            rules.push(RewriteRule { pattern, template: with_holes(grammar, &tree, &holes).map_err(err)? });
+        }
+        Ok(rules)
+    }
+
+    /// Parse the template as a nonterminal the sugared one appears in, else as the start
+    /// symbol. Typing rules are left out: holes stand for terms the rules cannot see yet.
+    fn parse_template(&self, grammar: &Grammar) -> Result<ASTNode, String> {
+        let mut syntax = grammar.clone();
+        syntax.typing_rules.clear();
+        let mut targets: Vec<String> = grammar
+            .production_order
+            .iter()
+            .filter(|nt| grammar.productions[*nt].iter().flat_map(|p| &p.rhs).any(|s| s.value() == self.nonterminal))
+            .cloned()
+            .collect();
+        targets.extend(grammar.start_nonterminal().cloned());
+        let mut last = String::from("nothing to parse the template as");
+        for target in targets {
+            syntax.set_start(target);
+            match Parser::new(syntax.clone()).parse(&self.template) {
+                Ok(ast) => return Ok(ast),
+                Err(e) => last = e,
+            }
+        }
+        Err(format!("template does not parse: {}", last))
+    }
+}
+
+/// `node` as a template, where `holes` maps each hole to the symbol it captures: the
+/// outermost node whose only terminal is a hole becomes that hole
+fn with_holes(grammar: &Grammar, node: &ASTNode, holes: &HashMap<String, String>) -> Result<Pattern, String> {
+    let mut terminals = node.terminals();
+    if let (Some(t), None) = (terminals.next(), terminals.next())
+        && let Some(captured) = holes.get(&t.value)
+    {
+        return hole(grammar, node, &t.value, captured);
+    }
+    Ok(match node {
+        ASTNode::Terminal(t) => Pattern::Terminal { value: t.value.clone(), binding: t.binding.clone() },
+        ASTNode::Nonterminal(nt) => Pattern::Nonterminal {
+            name: Some(nt.value.clone()),
+            rule: None,
+            binding: nt.binding.clone(),
+            children: nt.children.iter().map(|c| with_holes(grammar, c, holes)).collect::<Result<_, _>>()?,
+        },
+    })
+}
+
+/// Template for hole `name`, spelled by the chain of single-child nodes from `node`.
+/// The captured node replaces the first node of the chain of its own kind, keeping that
+/// node's binding; if there is none, it goes inside a wrapping production of a chain
+/// node (`BaseTerm ::= '(' Term ')'`), so the result still prints as it parses.
+fn hole(grammar: &Grammar, node: &ASTNode, name: &str, captured: &str) -> Result<Pattern, String> {
+    let mut chain = vec![node];
+    while let Some([only]) = chain.last().and_then(|n| n.children().map(Vec::as_slice)) {
+        chain.push(only);
+    }
+    let fits = |n: &ASTNode| match n {
+        ASTNode::Terminal(_) => !grammar.productions.contains_key(captured),
+        ASTNode::Nonterminal(nt) => nt.value == captured,
+    };
+    let (at, inner) = if let Some(at) = chain.iter().position(|n| fits(n)) {
+        (at, Pattern::Var { name: Some(name.to_string()), binding: chain[at].binding().cloned() })
+    } else {
+        let (at, production) = chain
+            .iter()
+            .enumerate()
+            .find_map(|(i, n)| Some((i, grammar.wrapping_production(n.value(), captured)?)))
+            .ok_or_else(|| format!("hole '{}' holds a {}, which does not fit the template's {}", name, captured, node.value()))?;
+        let children = production
+            .rhs
+            .iter()
+            .map(|s| match s.value() == captured {
+                true => Pattern::Var { name: Some(name.to_string()), binding: s.binding().cloned() },
+                false => Pattern::Terminal { value: s.value().trim_matches('\'').to_string(), binding: s.binding().cloned() },
+            })
+            .collect();
+        let wrapper = chain[at];
+        (at, Pattern::Nonterminal { name: Some(wrapper.value().to_string()), rule: None, binding: wrapper.binding().cloned(), children })
+    };
+    Ok(chain[..at].iter().rev().fold(inner, |inner, n| Pattern::Nonterminal {
+        name: Some(n.value().to_string()),
+        rule: None,
+        binding: n.binding().cloned(),
+        children: vec![inner],
+    }))
+}
+
+/// Stage at which `Grammar::check_source` rejected a program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SourceError {
+    Parse(String),
+    Desugar(String),
+    Type(String),
+}
+
+impl std::fmt::Display for SourceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SourceError::Parse(m) => write!(f, "parse error: {}", m),
+            SourceError::Desugar(m) => write!(f, "desugar error: {}", m),
+            SourceError::Type(m) => write!(f, "type error: {}", m),
+        }
+    }
+}
+
+impl Grammar {
+    /// Parse `source` with `parser` (built from this grammar), then `check_parsed` it.
+    /// Returns the tree as parsed, sugar included, with its type.
+    pub fn check_source(&self, parser: &mut Parser, source: &str, checker: &mut TypeChecker) -> Result<(ASTNode, Option<BoundType>), SourceError> {
+        let ast = parser.parse(source).map_err(SourceError::Parse)?;
+        let ty = self.check_parsed(&ast, checker)?;
+        Ok((ast, ty))
+    }
+
+    /// Desugar a parsed tree and type-check the result with `checker`
+    pub fn check_parsed(&self, ast: &ASTNode, checker: &mut TypeChecker) -> Result<Option<BoundType>, SourceError> {
+        if self.desugarings.is_empty() {
+            return checker.check(ast).map_err(SourceError::Type);
+        }
+        let core = self.desugar(ast.clone()).map_err(SourceError::Desugar)?;
+        checker.check(&core).map_err(SourceError::Type)
+    }
+
+    /// The spec's desugarings as rewrite rules
+    pub fn desugar_rules(&self) -> Result<Vec<RewriteRule>, String> {
+        let mut rules = Vec::new();
+        for d in &self.desugarings {
+            rules.extend(d.compile(self)?);
+        }
+        Ok(rules)
+    }
+
+    /// Replace every sugared node of a parsed tree by its template, then re-bind typing
+    /// rules. Trees without sugar come back unchanged.
+    pub fn desugar(&self, ast: ASTNode) -> Result<ASTNode, String> {
+        if self.desugarings.is_empty() {
+            return Ok(ast);
+        }
+        Rewriter::new(self, self.desugar_rules()?).rewrite(ast)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::grammar::tests::{let_spec, STLC_SPEC};
+
+    #[test]
+    fn let_desugars_to_application() {
+        let grammar = Grammar::load(&let_spec()).unwrap();
+        assert_eq!(grammar.desugarings.len(), 1);
+        let src = "let g : a->a = z in g";
+        let ast = grammar.desugar(Parser::new(grammar.clone()).parse(src).unwrap()).unwrap();
+        assert!(ast.find_by_value("Let").is_empty());
+        assert_eq!(ast.find_by_rule("app").len(), 1);
+        assert_eq!(ast.find_by_rule("lambda").len(), 1);
+
+        let mut tc = TypeChecker::new();
+        tc.add("z".into(), BoundType::Atom("a".into()));
+        assert_eq!(tc.check(&ast), Ok(Some(BoundType::Atom("a".into()))));
+
+        // the synthesized nodes span the whole let; the body keeps its own span
+        let lambda = ast.find_by_rule("lambda")[0];
+        assert_eq!(lambda.span.as_ref().map(|s| (s.start, s.end)), Some((0, src.chars().count())));
+        let body = ast.find_by_value("g").into_iter().filter_map(|t| t.span()).map(|s| s.start).max();
+        assert_eq!(body, Some(20));
+
+        // errors inside the sugar point at the original source
+        let bad = grammar.desugar(Parser::new(grammar.clone()).parse("let g : a->a = z in y").unwrap()).unwrap();
+        let err = tc.check(&bad).unwrap_err();
+        assert!(err.contains("span 20..21"), "{}", err);
+
+        let reloaded = Grammar::load(&grammar.to_spec_string()).unwrap();
+        assert_eq!(reloaded.desugarings[0].to_line(), "desugar Let ⇒ (λx:τ.e2) e1");
+    }
+
+    #[test]
+    fn holes_are_parenthesized_to_fit_their_slot() {
+        let grammar = Grammar::load(&let_spec()).unwrap();
+        let mut parser = Parser::new(grammar.clone());
+        // e1 is a Term, but the template puts it where a BaseTerm goes
+        let ast = grammar.desugar(parser.parse("let g : a->a = (λy:a->a.y) z in g").unwrap()).unwrap();
+        let printed = grammar.unparse(&ast);
+        assert_eq!(printed, "(λg:a->a.g)((λy:a->a.y)z)");
+        let reparsed = parser.parse(&printed).unwrap();
+        assert_eq!(grammar.unparse(&reparsed), printed);
+        assert_eq!(reparsed.find_by_rule("app").len(), 2);
+        let mut tc = TypeChecker::new();
+        tc.add("z".into(), BoundType::Atom("a".into()));
+        assert_eq!(tc.check(&ast), Ok(Some(BoundType::Atom("a".into()))));
+        assert_eq!(tc.check(&reparsed), tc.check(&ast));
+    }
+
+    #[test]
+    fn checks_sources_through_their_desugaring() {
+        let grammar = Grammar::load(&let_spec()).unwrap();
+        let mut parser = Parser::new(grammar.clone());
+        let (ast, ty) = grammar.check_source(&mut parser, "λz:a->a.(let g : a->a = z in g)", &mut TypeChecker::new()).unwrap();
+        assert_eq!(ast.find_by_value("Let").len(), 1, "the parsed tree keeps its sugar");
+        // as `logic check` types it: the annotation `a->a` gives the binder `a`
+        assert_eq!(ty, Some(BoundType::Arrow(Box::new(BoundType::Atom("a".into())), Box::new(BoundType::Atom("a".into())))));
+
+        // the annotation rebinds `x` to a bare base type, which the lambda rule cannot bind
+        let err = grammar.check_source(&mut parser, "let g : a = λy:a->a.(y) in (g)", &mut TypeChecker::new()).unwrap_err();
+        assert!(matches!(err, SourceError::Desugar(_)), "{}", err);
+        let err = grammar.check_source(&mut parser, "let g", &mut TypeChecker::new()).unwrap_err();
+        assert!(matches!(err, SourceError::Parse(_)), "{}", err);
+    }
+
+    #[test]
+    fn rejects_bad_desugarings() {
+        assert!(Desugaring::is_desugar_line("desugar Let => x"));
+        assert!(!Desugaring::is_desugar_line("desugar ::= 'x'"));
+        let unknown = format!("{}\n\ndesugar Nope ⇒ x\n", STLC_SPEC);
+        assert!(Grammar::load(&unknown).unwrap_err().contains("unknown nonterminal"));
+        let unparsable = let_spec().replace("(λx:τ.e2) e1", "λ λ");
+        assert!(Grammar::load(&unparsable).unwrap_err().contains("template does not parse"));
+    }
+}
@@ -0,0 +1,116 @@
+// `%` lines that are not about layout: comment syntax for the tokenizer, and the shape
+// of the abstract tree (see `ASTNode::to_abstract`). They share the `%` syntax of layout
+// hints but are kept apart from them in `Grammar.directives`.
+
+use super::layout::parse_tokens;
+use super::Grammar;
+
+const KEYWORDS: [&str; 3] = ["comment", "inline", "hide"];
+
+/// A `%comment '//'`, `%comment '/*' '*/'`, `%inline Term` or `%hide '('` line
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json", serde(tag = "kind", rename_all = "snake_case"))]
+pub enum Directive {
+    /// Comment syntax, skipped by the tokenizer; without `close` the comment ends at the newline
+    Comment { open: String, close: Option<String> },
+    /// The nonterminal is replaced by its children in the abstract tree
+    Inline { nonterminal: String },
+    /// Terminals with this text are left out of the abstract tree
+    Hide { token: String },
+}
+
+impl Directive {
+    /// `%comment`, `%inline` and `%hide` lines (after indentation)
+    pub fn is_directive_line(line: &str) -> bool {
+        line.trim_start()
+            .strip_prefix('%')
+            .and_then(|body| body.split_whitespace().next())
+            .is_some_and(|keyword| KEYWORDS.contains(&keyword))
+    }
+
+    /// Parse one `%...` directive line; `%inline` and `%hide` may list several
+    pub fn parse(line: &str, line_no: usize) -> Result<Vec<Self>, String> {
+        let err = |msg: &str| format!("line {}: {}: {}", line_no, msg, line.trim());
+        let body = line.trim().strip_prefix('%').ok_or_else(|| err("expected '%'"))?;
+        let (keyword, rest) = body.split_once(char::is_whitespace).unwrap_or((body, ""));
+        let rest = rest.trim_start();
+        if keyword == "inline" {
+            // nonterminal names are bare, not quoted
+            if rest.is_empty() || rest.contains('\'') {
+                return Err(err("%inline takes nonterminal names"));
+            }
+            return Ok(rest.split_whitespace().map(|nt| Directive::Inline { nonterminal: nt.to_string() }).collect());
+        }
+        let tokens = parse_tokens(rest).ok_or_else(|| err("expected single-quoted tokens"))?;
+        match keyword {
+            "comment" => match tokens.len() {
+                1 | 2 => {
+                    let mut tokens = tokens.into_iter();
+                    Ok(vec![Directive::Comment { open: tokens.next().unwrap(), close: tokens.next() }])
+                }
+                _ => Err(err("%comment takes an opener and an optional closer")),
+            },
+            "hide" if !tokens.is_empty() => Ok(tokens.into_iter().map(|token| Directive::Hide { token }).collect()),
+            "hide" => Err(err("expected at least one token")),
+            _ => Err(err(&format!("unknown directive '%{}'", keyword))),
+        }
+    }
+
+    /// The directive as a spec line
+    pub fn to_line(&self) -> String {
+        match self {
+            Directive::Comment { open, close: Some(close) } => format!("%comment '{}' '{}'", open, close),
+            Directive::Comment { open, close: None } => format!("%comment '{}'", open),
+            Directive::Inline { nonterminal } => format!("%inline {}", nonterminal),
+            Directive::Hide { token } => format!("%hide '{}'", token),
+        }
+    }
+}
+
+impl Grammar {
+    /// `%inline` must name nonterminals of the grammar
+    pub(crate) fn check_directives(&self) -> Result<(), String> {
+        for directive in &self.directives {
+            if let Directive::Inline { nonterminal } = directive
+                && !self.productions.contains_key(nonterminal)
+            {
+                return Err(format!("{}: unknown nonterminal '{}'", directive.to_line(), nonterminal));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::grammar::tests::STLC_SPEC;
+
+    #[test]
+    fn parses_and_prints_directives() {
+        for line in ["%comment '//'", "%comment '/*' '*/'", "%inline Term", "%hide '('"] {
+            assert!(Directive::is_directive_line(line));
+            assert_eq!(Directive::parse(line, 1).unwrap()[0].to_line(), line);
+        }
+        assert!(!Directive::is_directive_line("%break after ';'"));
+        assert_eq!(Directive::parse("%inline Term BaseTerm", 1).unwrap().len(), 2);
+        assert!(Directive::parse("%inline 'Term'", 1).is_err());
+        assert!(Directive::parse("%hide Term", 1).is_err());
+        assert!(Directive::parse("%comment '/*' '*/' '!'", 1).is_err());
+    }
+
+    #[test]
+    fn directives_are_kept_apart_from_layout() {
+        let spec = format!("{}\n%break after '.'\n%comment '//'\n%inline Term\n", STLC_SPEC);
+        let grammar = Grammar::load(&spec).unwrap();
+        assert_eq!(grammar.layout.len(), 1);
+        assert_eq!(grammar.directives.len(), 2);
+        assert!(grammar.is_inline("Term"));
+        let reloaded = Grammar::load(&grammar.to_spec_string()).unwrap();
+        assert_eq!(reloaded.directives, grammar.directives);
+
+        let err = Grammar::load(&format!("{}\n%inline Nope\n", STLC_SPEC)).unwrap_err();
+        assert!(err.contains("unknown nonterminal 'Nope'"), "{}", err);
+    }
+}
@@ -0,0 +1,123 @@
+use super::directive::Directive;
+
+/// Where whitespace goes relative to a token in a layout hint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json", serde(rename_all = "snake_case"))]
+pub enum Side {
+    Before,
+    After,
+    Around,
+}
+
+impl Side {
+    pub fn before(&self) -> bool {
+        matches!(self, Side::Before | Side::Around)
+    }
+
+    pub fn after(&self) -> bool {
+        matches!(self, Side::After | Side::Around)
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Side::Before => "before",
+            Side::After => "after",
+            Side::Around => "around",
+        }
+    }
+}
+
+/// A `%` line of a spec describing the layout of source text:
+/// `%break after ';'`, `%space around '->'` or `%indent '{' '}'`. The other `%` lines
+/// are `Directive`s.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json", serde(tag = "kind", rename_all = "snake_case"))]
+pub enum LayoutHint {
+    /// Start a new line next to the token
+    Break { side: Side, token: String },
+    /// Put a space next to the token
+    Space { side: Side, token: String },
+    /// A new line follows the opener and precedes the closer; lines between are indented one level
+    Indent { open: String, close: String },
+}
+
+impl LayoutHint {
+    /// Lines starting with `%` (after indentation) that are not `Directive`s
+    pub fn is_hint_line(line: &str) -> bool {
+        line.trim_start().starts_with('%') && !Directive::is_directive_line(line)
+    }
+
+    /// Parse one `%...` line; `%break` and `%space` may list several
+    pub fn parse(line: &str, line_no: usize) -> Result<Vec<Self>, String> {
+        let err = |msg: &str| format!("line {}: {}: {}", line_no, msg, line.trim());
+        let body = line.trim().strip_prefix('%').ok_or_else(|| err("expected '%'"))?;
+        let (keyword, rest) = body.split_once(char::is_whitespace).ok_or_else(|| err("expected a layout directive"))?;
+        let mut rest = rest.trim_start();
+        let side = if matches!(keyword, "break" | "space") {
+            let (side, tail) = rest.split_once(char::is_whitespace).ok_or_else(|| err("expected 'before', 'after' or 'around'"))?;
+            rest = tail.trim_start();
+            Some(match side {
+                "before" => Side::Before,
+                "after" => Side::After,
+                "around" => Side::Around,
+                _ => return Err(err("expected 'before', 'after' or 'around'")),
+            })
+        } else {
+            None
+        };
+        let tokens = parse_tokens(rest).ok_or_else(|| err("expected single-quoted tokens"))?;
+        match (keyword, side) {
+            ("break", Some(side)) if !tokens.is_empty() => {
+                Ok(tokens.into_iter().map(|token| LayoutHint::Break { side, token }).collect())
+            }
+            ("space", Some(side)) if !tokens.is_empty() => {
+                Ok(tokens.into_iter().map(|token| LayoutHint::Space { side, token }).collect())
+            }
+            ("indent", None) => match <[String; 2]>::try_from(tokens) {
+                Ok([open, close]) => Ok(vec![LayoutHint::Indent { open, close }]),
+                Err(_) => Err(err("%indent takes an opening and a closing token")),
+            },
+            ("break" | "space", _) => Err(err("expected at least one token")),
+            _ => Err(err(&format!("unknown layout directive '%{}'", keyword))),
+        }
+    }
+
+    /// The hint as a spec line
+    pub fn to_line(&self) -> String {
+        match self {
+            LayoutHint::Break { side, token } => format!("%break {} '{}'", side.name(), token),
+            LayoutHint::Space { side, token } => format!("%space {} '{}'", side.name(), token),
+            LayoutHint::Indent { open, close } => format!("%indent '{}' '{}'", open, close),
+        }
+    }
+}
+
+/// Whitespace-separated `'tok'` list
+pub(super) fn parse_tokens(s: &str) -> Option<Vec<String>> {
+    s.split_whitespace()
+        .map(|t| t.strip_prefix('\'')?.strip_suffix('\'').filter(|t| !t.is_empty()).map(str::to_string))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_prints_hints() {
+        let hints = LayoutHint::parse("%break after ';' ','", 1).unwrap();
+        assert_eq!(hints.len(), 2);
+        assert_eq!(hints[1], LayoutHint::Break { side: Side::After, token: ",".into() });
+        for line in ["%space around '->'", "%indent '{' '}'", "%break before 'else'"] {
+            let hints = LayoutHint::parse(line, 1).unwrap();
+            assert_eq!(hints[0].to_line(), line);
+        }
+        assert!(LayoutHint::parse("%indent '{'", 1).is_err());
+        assert!(LayoutHint::parse("%break inside ';'", 1).is_err());
+        assert!(LayoutHint::parse("%wrap after ';'", 1).is_err());
+        assert!(LayoutHint::parse("%space after ;", 1).is_err());
+        assert!(LayoutHint::parse("%hide '('", 1).is_err());
+    }
+}
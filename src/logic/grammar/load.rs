@@ -1,4 +1,10 @@
 use crate::logic::grammar::{ Grammar, Production, TypingRule};
+use super::spec_test::SpecTest;
+use super::layout::LayoutHint;
+use super::directive::Directive;
+use super::desugar::Desugaring;
+use super::binders::BinderDecl;
+use crate::logic::attr::AttributeEquation;
 use super::utils::{parse_nonterminal, parse_production, special_tokens,parse_inference_rule, parse_rhs_with_groups};
 
 impl Grammar {
@@ -7,6 +13,22 @@ impl Grammar {
         let mut grammar = Grammar::new();
         // Track first-seen order of nonterminals to pick a deterministic start symbol
         let mut nt_order: Vec<String> = Vec::new();
+        // Test, layout, directive, desugar, attribute and binder lines may appear anywhere; collect them with their line numbers first
+        for (i, line) in input.lines().enumerate() {
+            if SpecTest::is_test_line(line) {
+                grammar.tests.push(SpecTest::parse(line, i + 1)?);
+            } else if Directive::is_directive_line(line) {
+                grammar.directives.extend(Directive::parse(line, i + 1)?);
+            } else if LayoutHint::is_hint_line(line) {
+                grammar.layout.extend(LayoutHint::parse(line, i + 1)?);
+            } else if Desugaring::is_desugar_line(line) {
+                grammar.desugarings.push(Desugaring::parse(line, i + 1)?);
+            } else if AttributeEquation::is_attribute_line(line) {
+                grammar.attributes.push(AttributeEquation::parse(line, i + 1)?);
+            } else if BinderDecl::is_binder_line(line) {
+                grammar.binders.push(BinderDecl::parse(line, i + 1)?);
+            }
+        }
         // Split input into blocks separated by blank lines
         let blocks: Vec<&str> = input.split("\n\n").filter(|b| !b.trim().is_empty()).collect();
         
@@ -14,7 +36,7 @@ impl Grammar {
             let lines: Vec<&str> = block
                 .lines()
                 .map(str::trim)
-                .filter(|line| !line.is_empty() && !line.starts_with("//"))
+                .filter(|line| !line.is_empty() && !line.starts_with("//") && !SpecTest::is_test_line(line) && !LayoutHint::is_hint_line(line) && !Directive::is_directive_line(line) && !Desugaring::is_desugar_line(line) && !AttributeEquation::is_attribute_line(line) && !BinderDecl::is_binder_line(line))
                 .collect();
                 
             if lines.is_empty() {
@@ -82,6 +104,11 @@ impl Grammar {
                 grammar.set_start(last.clone());
             }
         }
+        // Templates parse with the finished grammar
+        grammar.check_directives()?;
+        grammar.desugar_rules()?;
+        grammar.check_attributes()?;
+        grammar.check_binders()?;
         
         Ok(grammar)
     }
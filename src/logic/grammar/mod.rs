@@ -1,16 +1,27 @@
 pub mod utils;
 pub mod load;
 pub mod save;
+pub mod render;
+pub mod alternative;
+pub mod spec_test;
+pub mod layout;
+pub mod directive;
+pub mod desugar;
+pub mod binders;
 
 use std::collections::HashMap;
 
 #[derive(Debug, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json", serde(tag = "kind", rename_all = "snake_case"))]
 pub enum Symbol {
     Simple { value: String, binding: Option<String>, repetition: Option<RepetitionKind> },
     Group  { symbols: Vec<Symbol>, repetition: Option<RepetitionKind> },
 }
 
 #[derive(Debug, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json", serde(rename_all = "snake_case"))]
 pub enum RepetitionKind {
     ZeroOrMore,     // *
     OneOrMore,      // +
@@ -37,16 +48,23 @@ impl Symbol {
 pub type Nonterminal = String;
 /// A single production rule `left ::= right₀ right₁ …`.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
 pub struct Production {
     pub rule: Option<String>,
     pub rhs: Vec<Symbol>,
 }
 
+use crate::logic::tokenizer::Tokenizer;
 use crate::logic::typing::TypingRule;
 
+/// Characters that separate tokens without being tokens themselves
+pub const TOKEN_DELIMITERS: [char; 4] = [' ', '\t', '\n', '\r'];
+
 /// A complete grammar consisting of context-free productions and
 /// inference-style typing rules.
+// Serialize is written by hand in json.rs to keep productions in declaration order
 #[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Deserialize))]
 pub struct Grammar {
     pub productions: HashMap<Nonterminal, Vec<Production>>,
     pub typing_rules: HashMap<String, TypingRule>, // name -> rule
@@ -55,6 +73,25 @@ pub struct Grammar {
     pub start: Option<Nonterminal>,
     // Preserve declaration order of productions as they appear in the spec
     pub production_order: Vec<Nonterminal>,
+    // `test` lines declared in the spec, in order
+    pub tests: Vec<spec_test::SpecTest>,
+    // `%` layout hints for the unparser, in order
+    pub layout: Vec<layout::LayoutHint>,
+    // `%comment`, `%inline` and `%hide` directives, in order
+    #[cfg_attr(feature = "json", serde(default))]
+    pub directives: Vec<directive::Directive>,
+    // `desugar` lines, in order
+    #[cfg_attr(feature = "json", serde(default))]
+    pub desugarings: Vec<desugar::Desugaring>,
+    // `syn` and `inh` attribute equations, in order
+    #[cfg_attr(feature = "json", serde(default))]
+    pub attributes: Vec<crate::logic::attr::AttributeEquation>,
+    // `binds` declarations, in order
+    #[cfg_attr(feature = "json", serde(default))]
+    pub binders: Vec<binders::BinderDecl>,
+    // Compiled regex terminals, shared by everything that matches tokens against this grammar
+    #[cfg_attr(feature = "json", serde(skip))]
+    regex_cache: utils::RegexCache,
 }
 
 impl Grammar {
@@ -80,6 +117,60 @@ impl Grammar {
         self.start = Some(start.into());
     }
 
+    /// The tokenizer the parser uses: special tokens, whitespace delimiters and `%comment` syntax
+    pub fn tokenizer(&self) -> Tokenizer {
+        Tokenizer::new(self.special_tokens.clone(), TOKEN_DELIMITERS.to_vec()).with_comments(self.comments())
+    }
+
+    /// Does the whole of `token` match the regex terminal `pattern` (given without its slashes)?
+    pub fn regex_matches(&self, pattern: &str, token: &str) -> bool {
+        self.regex_cache.is_match(pattern, token)
+    }
+
+    /// Literal terminals of every production, unquoted, sorted and without duplicates
+    pub fn literals(&self) -> Vec<String> {
+        fn collect(grammar: &Grammar, symbols: &[Symbol], out: &mut Vec<String>) {
+            for symbol in symbols {
+                match symbol {
+                    Symbol::Group { symbols, .. } => collect(grammar, symbols, out),
+                    Symbol::Simple { value, .. } => {
+                        if !grammar.productions.contains_key(value) && !utils::is_regex(value) {
+                            out.push(value.trim_matches('\'').to_string());
+                        }
+                    }
+                }
+            }
+        }
+        let mut out = Vec::new();
+        for production in self.productions.values().flatten() {
+            collect(self, &production.rhs, &mut out);
+        }
+        out.sort();
+        out.dedup();
+        out
+    }
+
+    /// Comment syntax declared with `%comment`, as (opener, closer) pairs
+    pub fn comments(&self) -> Vec<(String, Option<String>)> {
+        self.directives
+            .iter()
+            .filter_map(|d| match d {
+                directive::Directive::Comment { open, close } => Some((open.clone(), close.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Whether `%inline` names the nonterminal
+    pub fn is_inline(&self, nonterminal: &str) -> bool {
+        self.directives.iter().any(|d| matches!(d, directive::Directive::Inline { nonterminal: n } if n == nonterminal))
+    }
+
+    /// Whether `%hide` names the token
+    pub fn is_hidden(&self, token: &str) -> bool {
+        self.directives.iter().any(|d| matches!(d, directive::Directive::Hide { token: t } if t == token))
+    }
+
     /// Get the start nonterminal if available.
     pub fn start_nonterminal(&self) -> Option<&Nonterminal> {
         self.start.as_ref()
@@ -138,6 +229,14 @@ pub(crate) mod tests {
     τ₂
     "#;
 
+    /// STLC plus `let x : τ = e1 in e2`, defined by desugaring to an application
+    pub fn let_spec() -> String {
+        STLC_SPEC.replace(
+            "BaseTerm ::= Variable | Lambda | '(' Term ')'",
+            "Let ::= 'let' Variable[x] ':' Type[τ] '=' Term[e1] 'in' Term[e2]\n    BaseTerm ::= Variable | Lambda | Let | '(' Term ')'",
+        ) + "\n\ndesugar Let ⇒ (λx:τ.e2) e1\n"
+    }
+
     #[test]
     fn parses_unified_stlc_grammar() {
         let grammar = Grammar::load(STLC_SPEC).expect("Parser failed");
@@ -310,6 +409,12 @@ pub(crate) mod tests {
         assert_eq!(block[0].rhs[1].repetition(), Some(&RepetitionKind::ZeroOrMore));
     }
 
+    #[test]
+    fn literals_are_unquoted_and_deduplicated() {
+        let g = Grammar::load(STLC_SPEC).unwrap();
+        assert_eq!(g.literals(), vec!["(", ")", "->", ".", ":", "λ"]);
+    }
+
     #[test]
     fn test_parse_empty_block() {
         use crate::logic::parser::Parser;
@@ -395,4 +500,21 @@ pub(crate) mod tests {
         run(&g,"x, y");
         run(&g,"x, y, z");
     }
+
+    #[test]
+    fn test_regex_terminals_match_whole_tokens() {
+        use crate::logic::parser::Parser;
+
+        let mut p = Parser::new(Grammar::load("Name ::= /[a-z]+/").unwrap());
+        assert!(p.parse("xy").is_ok());
+        // A match inside the token is not enough
+        assert!(p.parse("x1").is_err());
+        assert!(p.parse("1x").is_err());
+
+        // Alternatives are anchored as a group, not just the first and last one
+        let mut p = Parser::new(Grammar::load("Op ::= /a|ab/").unwrap());
+        assert!(p.parse("ab").is_ok());
+        assert!(p.parse("abc").is_err());
+        assert!(p.parse("cab").is_err());
+    }
 }
@@ -0,0 +1,112 @@
+use super::Grammar;
+use super::layout::LayoutHint;
+use crate::logic::ast::ASTNode;
+use crate::logic::tokenizer::Tokenizer;
+
+impl Grammar {
+    /// Join a token sequence into source text the parser tokenizes back into the same tokens.
+    /// A space is only inserted between two tokens that would otherwise merge or split
+    /// differently (e.g. two identifiers, or `-` followed by `>` when `->` is special).
+    pub fn render_tokens<S: AsRef<str>>(&self, tokens: &[S]) -> String {
+        let mut tokenizer = self.tokenizer();
+        let mut out = String::new();
+        let mut prev: Option<&str> = None;
+        for tok in tokens {
+            let tok = tok.as_ref();
+            if let Some(p) = prev && !splits_back(&mut tokenizer, p, tok) {
+                out.push(' ');
+            }
+            out.push_str(tok);
+            prev = Some(tok);
+        }
+        out
+    }
+
+    /// Print `ast` back as source text, following the spec's `%` layout hints.
+    /// Whitespace is only ever added between tokens, so the output re-parses to the same tree.
+    pub fn unparse(&self, ast: &ASTNode) -> String {
+        let mut tokenizer = self.tokenizer();
+        let hints = |pred: &dyn Fn(&LayoutHint) -> bool| self.layout.iter().any(pred);
+        let mut out = String::new();
+        let mut depth = 0usize;
+        let mut prev: Option<&str> = None;
+        for tok in ast.terminals().map(|t| t.value.as_str()).filter(|t| !t.is_empty()) {
+            let closes = hints(&|h| matches!(h, LayoutHint::Indent { close, .. } if close == tok));
+            if closes {
+                depth = depth.saturating_sub(1);
+            }
+            if let Some(p) = prev {
+                let newline = closes
+                    || hints(&|h| match h {
+                        LayoutHint::Break { side, token } => (side.after() && token == p) || (side.before() && token == tok),
+                        LayoutHint::Indent { open, .. } => open == p,
+                        _ => false,
+                    });
+                let space = hints(&|h| match h {
+                    LayoutHint::Space { side, token } => (side.after() && token == p) || (side.before() && token == tok),
+                    _ => false,
+                });
+                if newline {
+                    out.push('\n');
+                    out.push_str(&"    ".repeat(depth));
+                } else if space || !splits_back(&mut tokenizer, p, tok) {
+                    out.push(' ');
+                }
+            }
+            out.push_str(tok);
+            if hints(&|h| matches!(h, LayoutHint::Indent { open, .. } if open == tok)) {
+                depth += 1;
+            }
+            prev = Some(tok);
+        }
+        out
+    }
+}
+
+/// Does `ab` tokenize back into exactly `[a, b]`?
+fn splits_back(tokenizer: &mut Tokenizer, a: &str, b: &str) -> bool {
+    let joined = format!("{}{}", a, b);
+    let Ok(occ) = tokenizer.tokenize_with_spans(&joined) else { return false };
+    let toks: Vec<String> = occ.iter().filter_map(|(id, _, _)| tokenizer.str(*id)).collect();
+    toks.len() == 2 && toks[0] == a && toks[1] == b
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::grammar::tests::STLC_SPEC;
+    use crate::logic::parser::Parser;
+
+    #[test]
+    fn render_inserts_only_needed_spaces() {
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        let toks = ["λ", "x", ":", "a", "->", "a", ".", "f", "x"];
+        assert_eq!(grammar.render_tokens(&toks), "λx:a->a.f x");
+        assert_eq!(grammar.render_tokens(&["(", "f", ")", "(", "g", ")"]), "(f)(g)");
+    }
+
+    #[test]
+    fn unparse_follows_layout_and_round_trips() {
+        let spec = format!("{}\n\n%space around '->' ':'\n%break after '.'\n%indent '(' ')'\n", STLC_SPEC);
+        let grammar = Grammar::load(&spec).unwrap();
+        let mut parser = Parser::new(grammar.clone());
+        let ast = parser.parse("(λf:a->a.f)x").unwrap();
+        let text = grammar.unparse(&ast);
+        assert_eq!(text, "(\n    λf : a -> a.\n    f\n)x");
+        assert!(parser.parse(&text).unwrap().syneq(&ast));
+
+        // without hints only the spaces needed to keep tokens apart are added
+        let plain = Grammar::load(STLC_SPEC).unwrap();
+        let ast = Parser::new(plain.clone()).parse("λf : a->a . f  x").unwrap();
+        assert_eq!(plain.unparse(&ast), "λf:a->a.f x");
+    }
+
+    #[test]
+    fn render_keeps_tokens_from_forming_comments() {
+        let grammar = Grammar::load("Term ::= 'x' | '-' Term\n%comment '--'\n").unwrap();
+        let text = grammar.render_tokens(&["-", "-", "x"]);
+        assert_eq!(text, "- -x");
+        let ast = Parser::new(grammar.clone()).parse(&text).unwrap();
+        assert_eq!(grammar.unparse(&ast), text);
+    }
+}
@@ -58,6 +58,65 @@ impl Grammar {
             }
         }
 
+        // ---------- Layout ----------
+        if !self.layout.is_empty() {
+            out.push_str("// --- Layout ---\n");
+            for hint in &self.layout {
+                out.push_str(&hint.to_line());
+                out.push('\n');
+            }
+            out.push('\n');
+        }
+
+        // ---------- Directives ----------
+        if !self.directives.is_empty() {
+            out.push_str("// --- Directives ---\n");
+            for directive in &self.directives {
+                out.push_str(&directive.to_line());
+                out.push('\n');
+            }
+            out.push('\n');
+        }
+
+        // ---------- Desugaring ----------
+        if !self.desugarings.is_empty() {
+            out.push_str("// --- Desugaring ---\n");
+            for d in &self.desugarings {
+                out.push_str(&d.to_line());
+                out.push('\n');
+            }
+            out.push('\n');
+        }
+
+        // ---------- Binders ----------
+        if !self.binders.is_empty() {
+            out.push_str("// --- Binders ---\n");
+            for decl in &self.binders {
+                out.push_str(&decl.to_line());
+                out.push('\n');
+            }
+            out.push('\n');
+        }
+
+        // ---------- Attributes ----------
+        if !self.attributes.is_empty() {
+            out.push_str("// --- Attributes ---\n");
+            for eq in &self.attributes {
+                out.push_str(&eq.to_line());
+                out.push('\n');
+            }
+            out.push('\n');
+        }
+
+        // ---------- Tests ----------
+        if !self.tests.is_empty() {
+            out.push_str("// --- Tests ---\n");
+            for test in &self.tests {
+                out.push_str(&test.to_line());
+                out.push('\n');
+            }
+        }
+
         out
     }
 
@@ -90,6 +149,19 @@ impl Grammar {
         format!("'{}'", value)
     }
 
+    /// Hash of the canonical productions and typing rules, used to tie saved ASTs to the
+    /// grammar that produced them. Formatting of the original spec and the other sections
+    /// (layout, desugarings, binders, attributes, tests) do not affect it.
+    pub fn fingerprint(&self) -> String {
+        let grammar = Grammar {
+            productions: self.productions.clone(),
+            typing_rules: self.typing_rules.clone(),
+            production_order: self.production_order.clone(),
+            ..Grammar::default()
+        };
+        crate::logic::ast::serialize::content_hash(&grammar.to_spec_string())
+    }
+
     /// Write the textual specification to a file on disk.
     pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
         std::fs::write(path, self.to_spec_string())
@@ -0,0 +1,256 @@
+use super::Grammar;
+use super::desugar::SourceError;
+use crate::logic::bind::BoundType;
+use crate::logic::check::TypeChecker;
+use crate::logic::parser::Parser;
+
+/// A `test` line from a spec or its sidecar `.tests` file:
+/// `test "src"`, `test "src" : T`, `test "src" fails "msg"` or `test parse-error "src"`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub struct SpecTest {
+    pub input: String,
+    pub expect: Expectation,
+    /// 1-based line in the file the test was read from
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json", serde(tag = "kind", content = "args", rename_all = "snake_case"))]
+pub enum Expectation {
+    /// Parses and type checks, type not recorded yet
+    Checks,
+    /// Parses and type checks to this type
+    Type(String),
+    /// Parses, but type checking fails with an error containing this text
+    Fails(String),
+    /// Does not parse
+    ParseError,
+}
+
+impl std::fmt::Display for Expectation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expectation::Checks => write!(f, "any type"),
+            Expectation::Type(ty) => write!(f, "type {}", ty),
+            Expectation::Fails(msg) => write!(f, "type error containing \"{}\"", msg),
+            Expectation::ParseError => write!(f, "parse error"),
+        }
+    }
+}
+
+/// What actually happened to a test input
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    ParseError(String),
+    /// Parsed, but the spec's desugarings failed on it; no expectation accepts this
+    DesugarError(String),
+    TypeError(String),
+    Type(Option<BoundType>),
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Outcome::ParseError(e) => write!(f, "parse error: {}", e),
+            Outcome::DesugarError(e) => write!(f, "desugar error: {}", e),
+            Outcome::TypeError(e) => write!(f, "type error: {}", e),
+            Outcome::Type(Some(ty)) => write!(f, "type {}", ty),
+            Outcome::Type(None) => write!(f, "no type"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TestResult {
+    pub test: SpecTest,
+    pub outcome: Outcome,
+    pub passed: bool,
+}
+
+impl SpecTest {
+    /// `test` followed by a quoted input or `parse-error` (so a nonterminal named `test` still works)
+    pub fn is_test_line(line: &str) -> bool {
+        line.trim_start()
+            .strip_prefix("test ")
+            .is_some_and(|rest| rest.trim_start().starts_with('"') || rest.trim_start().starts_with("parse-error"))
+    }
+
+    /// Parse one `test ...` line
+    pub fn parse(line: &str, line_no: usize) -> Result<Self, String> {
+        let err = |msg: &str| format!("line {}: {}: {}", line_no, msg, line.trim());
+        let rest = line.trim().strip_prefix("test ").ok_or_else(|| err("expected 'test'"))?.trim_start();
+        let (parse_error, rest) = match rest.strip_prefix("parse-error") {
+            Some(r) => (true, r.trim_start()),
+            None => (false, rest),
+        };
+        let (input, rest) = parse_quoted(rest).ok_or_else(|| err("expected a quoted input"))?;
+        let rest = rest.trim();
+        let expect = if parse_error {
+            if !rest.is_empty() { return Err(err("unexpected text after parse-error test")); }
+            Expectation::ParseError
+        } else if rest.is_empty() {
+            Expectation::Checks
+        } else if let Some(ty) = rest.strip_prefix(':') {
+            let ty = ty.trim();
+            BoundType::parse(ty).map_err(|e| err(&format!("invalid type '{}' ({})", ty, e)))?;
+            Expectation::Type(ty.to_string())
+        } else if let Some(msg) = rest.strip_prefix("fails") {
+            let (msg, tail) = parse_quoted(msg.trim_start()).ok_or_else(|| err("expected a quoted error message"))?;
+            if !tail.trim().is_empty() { return Err(err("unexpected text after error message")); }
+            Expectation::Fails(msg)
+        } else {
+            return Err(err("expected ': TYPE', 'fails \"msg\"' or nothing after the input"));
+        };
+        Ok(Self { input, expect, line: line_no })
+    }
+
+    /// The test as a spec line
+    pub fn to_line(&self) -> String {
+        match &self.expect {
+            Expectation::Checks => format!("test {}", quote(&self.input)),
+            Expectation::Type(ty) => format!("test {} : {}", quote(&self.input), ty),
+            Expectation::Fails(msg) => format!("test {} fails {}", quote(&self.input), quote(msg)),
+            Expectation::ParseError => format!("test parse-error {}", quote(&self.input)),
+        }
+    }
+
+    /// Does `outcome` satisfy this test?
+    pub fn accepts(&self, outcome: &Outcome) -> bool {
+        match (&self.expect, outcome) {
+            (Expectation::ParseError, Outcome::ParseError(_)) => true,
+            (Expectation::Fails(msg), Outcome::TypeError(e)) => e.contains(msg.as_str()),
+            (Expectation::Checks, Outcome::Type(ty)) => ty.is_some(),
+            (Expectation::Type(expected), Outcome::Type(Some(actual))) => {
+                BoundType::parse(expected).is_ok_and(|ty| ty == *actual)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Collect the tests of a sidecar file (other non-comment lines are errors)
+pub fn parse_tests(text: &str) -> Result<Vec<SpecTest>, String> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty() && !l.trim_start().starts_with("//"))
+        .map(|(i, l)| SpecTest::parse(l, i + 1))
+        .collect()
+}
+
+impl Grammar {
+    /// Run one input through the parser, desugaring and type checker
+    pub fn run_input(&self, input: &str) -> Outcome {
+        let mut parser = Parser::new(self.clone());
+        match self.check_source(&mut parser, input, &mut TypeChecker::new()) {
+            Ok((_, ty)) => Outcome::Type(ty),
+            Err(SourceError::Parse(e)) => Outcome::ParseError(e),
+            Err(SourceError::Desugar(e)) => Outcome::DesugarError(e),
+            Err(SourceError::Type(e)) => Outcome::TypeError(e),
+        }
+    }
+
+    /// Run `tests` (e.g. `self.tests` plus a sidecar file's)
+    pub fn run_tests(&self, tests: &[SpecTest]) -> Vec<TestResult> {
+        tests
+            .iter()
+            .map(|test| {
+                let outcome = self.run_input(&test.input);
+                TestResult { passed: test.accepts(&outcome), test: test.clone(), outcome }
+            })
+            .collect()
+    }
+}
+
+/// Read a double-quoted string with `\"`, `\\`, `\n` and `\t` escapes; returns it and the rest
+fn parse_quoted(s: &str) -> Option<(String, &str)> {
+    let body = s.strip_prefix('"')?;
+    let mut out = String::new();
+    let mut chars = body.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((out, &body[i + 1..])),
+            '\\' => match chars.next()?.1 {
+                'n' => out.push('\n'),
+                't' => out.push('\t'),
+                other => out.push(other),
+            },
+            c => out.push(c),
+        }
+    }
+    None
+}
+
+fn quote(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::grammar::tests::{let_spec, STLC_SPEC};
+
+    #[test]
+    fn parses_and_prints_test_lines() {
+        let cases = [
+            ("test \"λx:a->a.x\" : a → a", Expectation::Type("a → a".into())),
+            ("test \"x\" fails \"not found in context\"", Expectation::Fails("not found in context".into())),
+            ("test parse-error \"λ.x\"", Expectation::ParseError),
+            ("test \"say \\\"hi\\\"\"", Expectation::Checks),
+        ];
+        for (line, expect) in cases {
+            let test = SpecTest::parse(line, 1).unwrap();
+            assert_eq!(test.expect, expect);
+            assert_eq!(test.to_line(), line);
+        }
+        assert!(SpecTest::parse("test \"x\" : →", 1).is_err());
+        assert!(SpecTest::parse("test x", 1).is_err());
+    }
+
+    #[test]
+    fn checks_needs_an_inferred_type() {
+        let test = SpecTest::parse("test \"x\"", 1).unwrap();
+        assert!(test.accepts(&Outcome::Type(Some(BoundType::Atom("a".into())))));
+        assert!(!test.accepts(&Outcome::Type(None)));
+    }
+
+    #[test]
+    fn spec_tests_load_run_and_round_trip() {
+        let spec = format!(
+            "{}\n\ntest \"λx:a->a.x\" : a → a\ntest \"λx:a->a.x\" : b\ntest \"y\" fails \"not found\"\ntest parse-error \"λ.x\"\n",
+            STLC_SPEC
+        );
+        let grammar = Grammar::load(&spec).unwrap();
+        assert_eq!(grammar.tests.len(), 4);
+        assert_eq!(grammar.typing_rules.len(), 3);
+        let results = grammar.run_tests(&grammar.tests);
+        let passed: Vec<bool> = results.iter().map(|r| r.passed).collect();
+        assert_eq!(passed, vec![true, false, true, true]);
+        assert_eq!(results[1].outcome.to_string(), "type a → a");
+
+        let reloaded = Grammar::load(&grammar.to_spec_string()).unwrap();
+        let lines = |g: &Grammar| g.tests.iter().map(|t| t.to_line()).collect::<Vec<_>>();
+        assert_eq!(lines(&reloaded), lines(&grammar));
+    }
+
+    #[test]
+    fn desugaring_failures_are_not_parse_errors() {
+        let spec = format!("{}\ntest parse-error \"let g : a = λy:a->a.(y) in (g)\"\n", let_spec());
+        let grammar = Grammar::load(&spec).unwrap();
+        let results = grammar.run_tests(&grammar.tests);
+        assert!(matches!(results[0].outcome, Outcome::DesugarError(_)), "{}", results[0].outcome);
+        assert!(!results[0].passed);
+    }
+}
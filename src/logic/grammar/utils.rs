@@ -1,6 +1,33 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
 use regex::Regex;
 use super::{Symbol, RepetitionKind};
 
+/// Compiled regex terminals of one grammar, keyed by pattern and filled on first use.
+/// Callers only look up the grammar's own terminals, so it never outgrows the grammar.
+/// Clones share nothing; equality ignores the cache.
+#[derive(Debug, Default, Clone)]
+pub struct RegexCache(RefCell<HashMap<String, Option<Regex>>>);
+
+impl RegexCache {
+    /// Does all of `token` match `pattern`? Patterns that do not compile match nothing.
+    pub fn is_match(&self, pattern: &str, token: &str) -> bool {
+        self.0
+            .borrow_mut()
+            .entry(pattern.to_string())
+            .or_insert_with(|| Regex::new(&format!("^(?:{})$", pattern)).ok())
+            .as_ref()
+            .is_some_and(|re| re.is_match(token))
+    }
+}
+
+impl PartialEq for RegexCache {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
 // collection of utils for working with grammar definitions
 pub fn is_regex(pattern: &str) -> bool {
     // Only slash-delimited patterns: /regex/
@@ -0,0 +1,131 @@
+// JSON import/export for tooling that cannot read S-expression ASTs or spec text.
+//
+// The shape comes from serde derives on the types themselves (enabled by the `json`
+// feature) and is documented in `json.md`: structs are objects keyed by their field
+// names, enums carry a snake_case `"kind"` tag with tuple payloads under `"args"`.
+// `Grammar` is the exception: it serializes by hand so its maps come out in a stable
+// order, productions as declared and typing rules by name.
+
+use serde::ser::{SerializeMap, SerializeStruct, Serializer};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+
+use crate::logic::ast::ASTNode;
+use crate::logic::bind::{BoundType, BoundTypingRule};
+use crate::logic::check::typed::TypedAst;
+use crate::logic::grammar::Grammar;
+use crate::logic::typing::TypingRule;
+
+pub trait Json: Serialize + DeserializeOwned {
+    /// Pretty-printed JSON
+    fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("JSON export cannot fail for these types")
+    }
+
+    fn from_json(input: &str) -> Result<Self, String> {
+        serde_json::from_str(input).map_err(|e| format!("invalid JSON: {}", e))
+    }
+}
+
+impl Serialize for Grammar {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Grammar", 11)?;
+        state.serialize_field("productions", &Productions(self))?;
+        state.serialize_field("typing_rules", &self.typing_rules.iter().collect::<BTreeMap<_, _>>())?;
+        state.serialize_field("special_tokens", &self.special_tokens)?;
+        state.serialize_field("start", &self.start)?;
+        state.serialize_field("production_order", &self.production_order)?;
+        state.serialize_field("tests", &self.tests)?;
+        state.serialize_field("layout", &self.layout)?;
+        state.serialize_field("directives", &self.directives)?;
+        state.serialize_field("desugarings", &self.desugarings)?;
+        state.serialize_field("attributes", &self.attributes)?;
+        state.serialize_field("binders", &self.binders)?;
+        state.end()
+    }
+}
+
+/// Productions keyed by nonterminal in `production_order`, then any others by name
+struct Productions<'a>(&'a Grammar);
+
+impl Serialize for Productions<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let grammar = self.0;
+        let mut rest: Vec<_> = grammar.productions.keys().filter(|nt| !grammar.production_order.contains(nt)).collect();
+        rest.sort();
+        let mut map = serializer.serialize_map(Some(grammar.productions.len()))?;
+        for nt in grammar.production_order.iter().filter(|nt| grammar.productions.contains_key(*nt)).chain(rest) {
+            map.serialize_entry(nt, &grammar.productions[nt])?;
+        }
+        map.end()
+    }
+}
+
+impl Json for ASTNode {}
+impl Json for Grammar {}
+impl Json for TypingRule {}
+impl Json for BoundTypingRule {}
+impl Json for BoundType {}
+impl Json for TypedAst {}
+
+#[cfg(test)]
+mod tests {
+    use super::Json;
+    use crate::logic::ast::ASTNode;
+    use crate::logic::bind::BoundType;
+    use crate::logic::check::TypeChecker;
+    use crate::logic::check::typed::TypedAst;
+    use crate::logic::grammar::Grammar;
+    use crate::logic::grammar::tests::STLC_SPEC;
+    use crate::logic::parser::Parser;
+    use crate::logic::typing::TypingRule;
+
+    #[test]
+    fn round_trips() {
+        let spec = format!("{}\n%break after '.'\ntest \"x\" fails \"not found\"\n", STLC_SPEC);
+        let grammar = Grammar::load(&spec).unwrap();
+        assert_eq!(Grammar::from_json(&grammar.to_json()).unwrap(), grammar);
+        let lambda = &grammar.typing_rules["lambda"];
+        assert_eq!(&TypingRule::from_json(&lambda.to_json()).unwrap(), lambda);
+
+        let ast = Parser::new(grammar).parse("(λx:a->a.x) z").unwrap();
+        assert_eq!(ASTNode::from_json(&ast.to_json()).unwrap(), ast);
+        let typed = TypeChecker::new().check_typed(&ast);
+        assert_eq!(TypedAst::from_json(&typed.to_json()).unwrap(), typed);
+
+        let ty = BoundType::Arrow(Box::new(BoundType::Atom("a".into())), Box::new(BoundType::Universe));
+        assert_eq!(BoundType::from_json(&ty.to_json()).unwrap(), ty);
+    }
+
+    #[test]
+    fn productions_keep_declaration_order() {
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        let json = grammar.to_json();
+        let positions: Vec<usize> = grammar.production_order.iter().map(|nt| json.find(&format!("\"{}\": [", nt)).unwrap()).collect();
+        assert!(positions.windows(2).all(|w| w[0] < w[1]), "{:?}", grammar.production_order);
+        assert_eq!(Grammar::from_json(&json).unwrap(), grammar);
+    }
+
+    #[test]
+    fn documented_shape() {
+        let ty = BoundType::Arrow(Box::new(BoundType::Atom("a".into())), Box::new(BoundType::Universe));
+        let value: serde_json::Value = serde_json::from_str(&ty.to_json()).unwrap();
+        assert_eq!(value, serde_json::json!({ "kind": "arrow", "args": [{ "kind": "atom", "args": "a" }, { "kind": "universe" }] }));
+
+        let grammar = Grammar::load(STLC_SPEC).unwrap();
+        let ast = Parser::new(grammar).parse("z").unwrap();
+        let value: serde_json::Value = serde_json::from_str(&ast.to_json()).unwrap();
+        assert_eq!(value["kind"], "nonterminal");
+        assert_eq!(value["span"], serde_json::json!({ "start": 0, "end": 1 }));
+        let var = ast.find_by_rule("var")[0];
+        let leaf = ast.terminals().next().unwrap();
+        assert_eq!(serde_json::to_value(leaf).unwrap(), serde_json::json!({ "value": "z", "span": { "start": 0, "end": 1 } }));
+
+        let rule = serde_json::to_value(var.bound_typing_rule.as_ref().unwrap()).unwrap();
+        assert_eq!(rule["name"], "var");
+        assert_eq!(rule["conclusion"]["kind"], serde_json::json!({ "kind": "context_lookup", "args": ["Γ", 1] }));
+
+        assert!(ASTNode::from_json("{\"kind\": \"leaf\"}").unwrap_err().starts_with("invalid JSON"));
+    }
+}
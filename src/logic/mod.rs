@@ -9,6 +9,10 @@ pub mod typing;
 pub mod bind;
 pub mod debug;
 pub mod recursion;
+pub mod coverage;
+pub mod attr;
+#[cfg(feature = "json")]
+pub mod json;
 
 pub mod tests;
 
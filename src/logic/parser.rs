@@ -1,9 +1,9 @@
 use crate::debug_debug;
 use crate::logic::grammar::{Grammar, Nonterminal, Production, Symbol, RepetitionKind};
 use crate::logic::ast::{ASTNode, SourceSpan, Terminal, NonTerminal};
+use crate::logic::ast::trivia::attach_trivia;
 use crate::logic::tokenizer::Tokenizer;
 use crate::logic::recursion::RecursionTracker;
-use regex;
 use crate::logic::bind::{BindingResolver, DefaultBindingResolver, BoundTypingRule};
 use crate::logic::typing::TypingRule;
 
@@ -15,15 +15,13 @@ pub struct Parser {
     pub pos: usize,
     pub recursion_tracker: RecursionTracker,
     pub token_spans: Vec<(usize, usize)>,
+    /// Attach whitespace and comments to terminals (see `with_trivia`)
+    trivia: bool,
 }
 
 impl Parser {
     pub fn new(grammar: Grammar) -> Self {
-        // Create tokenizer with special tokens from grammar and common delimiters
-        let tokenizer = Tokenizer::new(
-            grammar.special_tokens.clone(),
-            vec![' ', '\t', '\n', '\r'] // Common whitespace delimiters
-        );
+        let tokenizer = grammar.tokenizer();
         
         Parser {
             grammar,
@@ -32,9 +30,17 @@ impl Parser {
             pos: 0,
             recursion_tracker: RecursionTracker::new(),
             token_spans: vec![],
+            trivia: false,
         }
     }
 
+    /// Keep each terminal's surrounding whitespace and comments, so `ASTNode::to_source`
+    /// reproduces the input exactly
+    pub fn with_trivia(mut self) -> Self {
+        self.trivia = true;
+        self
+    }
+
     /// Initialize the parser with input, performing tokenization and setting up internal state
     pub fn init(&mut self, input: &str) -> Result<(), String> {
         crate::debug_info!("parser", "Initializing parser with input: '{}'", input);
@@ -48,7 +54,7 @@ impl Parser {
         // Use tokenizer with spans
         let token_occ = match self.tokenizer.tokenize_with_spans(input) {
             Ok(v) => v,
-            Err(_) => return Err("Tokenization failed".to_string()),
+            Err(e) => return Err(format!("Tokenization failed: {}", e)),
         };
         
         // Convert token IDs back to strings for the parser and collect spans
@@ -76,7 +82,11 @@ impl Parser {
 
     pub fn parse(&mut self, input: &str) -> Result<ASTNode, String> {
         self.init(input)?;
-        self.parse_with_tokens()
+        let mut ast = self.parse_with_tokens()?;
+        if self.trivia {
+            attach_trivia(&mut ast, input, &self.token_spans, &self.tokenizer);
+        }
+        Ok(ast)
     }
 
 
@@ -323,11 +333,11 @@ impl Parser {
         let matches = if val.starts_with('\'') && val.ends_with('\'') {
             val.trim_matches('\'') == token
         } else if val.starts_with('/') && val.ends_with('/') {
-            if self.grammar.special_tokens.contains(token) { false } else { regex::Regex::new(val.trim_matches('/')).map(|re| re.is_match(token)).unwrap_or(false) }
+            if self.grammar.special_tokens.contains(token) { false } else { self.grammar.regex_matches(val.trim_matches('/'), token) }
         } else { val == token };
         if matches {
             let (s,e)=self.token_spans[self.pos];
-            let node = ASTNode::Terminal(Terminal { span: Some(SourceSpan{start:s,end:e}), value: token.clone(), binding: symbol.binding().cloned() });
+            let node = ASTNode::Terminal(Terminal { span: Some(SourceSpan{start:s,end:e}), value: token.clone(), binding: symbol.binding().cloned(), trivia: None });
             self.pos += 1; Ok(node)
         } else { Err(format!("Expected '{}', found '{}'", val, token)) }
     }
@@ -337,9 +347,9 @@ impl Parser {
         // Only for nonterminals
         let nt = if let Some(nt) = node.as_nonterminal() { nt } else { return Ok(None) };
 
-        // Construct a temporary NonTerminal (already have it) and resolve
+        // Resolve against the node in place, without copying its subtree
         let resolver = DefaultBindingResolver;
-        match resolver.resolve_rule(rule, &nt) {
+        match resolver.resolve_rule(rule, nt) {
             Ok(bound) => {
                 if !bound.is_well_formed() {
                     return Err(format!("Resolved bound rule '{}' is not well-formed for node {}", rule_name, nt.value));
@@ -352,6 +362,7 @@ impl Parser {
 
     /// Expose internal grammar immutably for sibling modules (partial parsing etc.).
     pub fn grammar(&self) -> &Grammar { &self.grammar }
+
     /// Expose internal grammar mutably if future partial parsing wants to augment analysis state.
     pub fn grammar_mut(&mut self) -> &mut Grammar { &mut self.grammar }
 
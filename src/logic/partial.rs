@@ -283,7 +283,7 @@ impl Parser {
             if matches {
                 debug_debug!("partial", "Terminal '{}' matched token '{}'", sym_val, token);
                 let span = self.token_span(self.position());
-                let node = ASTNode::Terminal(Terminal { value: token.clone(), span, binding: symbol.binding().cloned() });
+                let node = ASTNode::Terminal(Terminal { value: token.clone(), span, binding: symbol.binding().cloned(), trivia: None });
                 self.advance_position(1);
                 State::Complete(node)
             } else {
@@ -318,7 +318,7 @@ impl Parser {
         } else if sym_val.starts_with('\'') && sym_val.ends_with('\'') {
             sym_val.trim_matches('\'') == token
         } else if sym_val.starts_with('/') && sym_val.ends_with('/') {
-            if self.grammar.special_tokens.contains(&token.to_string()) { false } else { regex::Regex::new(sym_val.trim_matches('/')).map(|re| re.is_match(token)).unwrap_or(false) }
+            if self.grammar.special_tokens.contains(&token.to_string()) { false } else { self.grammar.regex_matches(sym_val.trim_matches('/'), token) }
         } else {
             sym_val == token
         }
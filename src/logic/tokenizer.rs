@@ -1,5 +1,3 @@
-use std::fmt::Error;
-
 use bimap::BiMap;
 
 pub type TokenId = usize;
@@ -8,6 +6,8 @@ pub struct Tokenizer {
     tokens: BiMap<String, TokenId>,
     special_tokens: Vec<String>,
     delimiters: Vec<char>,
+    /// Comment syntax skipped like delimiters: (opener, closer); no closer = to end of line
+    comments: Vec<(String, Option<String>)>,
 }
 
 impl Tokenizer {
@@ -18,6 +18,7 @@ impl Tokenizer {
             tokens,
             special_tokens: special_tokens.clone(),
             delimiters: delimiters.clone(),
+            comments: vec![],
         };
         for token in special_tokens {
             tokenizer.token(token);
@@ -40,18 +41,51 @@ impl Tokenizer {
         }
     }
 
+    /// Skip comments with these (opener, closer) pairs; a missing closer ends at the newline
+    pub fn with_comments(mut self, comments: Vec<(String, Option<String>)>) -> Self {
+        self.comments = comments;
+        self
+    }
+
+    /// If a comment starts at `i`, the index just past it, or an error if it is never closed
+    pub fn comment_end(&self, chars: &[char], i: usize) -> Option<Result<usize, String>> {
+        let starts_with = |at: usize, s: &str| {
+            let s: Vec<char> = s.chars().collect();
+            at + s.len() <= chars.len() && chars[at..at + s.len()] == s[..]
+        };
+        let (open, close) = self.comments.iter().find(|(open, _)| starts_with(i, open))?;
+        let mut j = i + open.chars().count();
+        match close {
+            Some(close) => {
+                while j < chars.len() && !starts_with(j, close) { j += 1; }
+                if j == chars.len() {
+                    return Some(Err(format!("unterminated comment starting at {}", i)));
+                }
+                Some(Ok(j + close.chars().count()))
+            }
+            None => {
+                while j < chars.len() && chars[j] != '\n' { j += 1; }
+                Some(Ok(j))
+            }
+        }
+    }
+
     pub fn str(&self, id:TokenId) -> Option<String> {
         self.tokens.get_by_right(&id).cloned()
     }
 
     /// Tokenize the input string into a vector of tokens, handling special tokens
-    pub fn tokenize(&mut self, input: String) -> Result<Vec<TokenId>,Error> {
+    pub fn tokenize(&mut self, input: String) -> Result<Vec<TokenId>, String> {
         let mut split = Vec::<String>::new();
         let mut i = 0;
         let chars: Vec<char> = input.chars().collect();
         let input_len = chars.len();
 
         while i < input_len {
+            if let Some(end) = self.comment_end(&chars, i) {
+                i = end?;
+                continue;
+            }
             // Try to match a special token at the current position
             let mut matched = None;
             for special in &self.special_tokens {
@@ -79,6 +113,7 @@ impl Tokenizer {
             let mut current = String::new();
             while i < input_len
                 && !self.delimiters.contains(&chars[i])
+                && self.comment_end(&chars, i).is_none()
                 && !self.special_tokens.iter().any(|s| {
                     let s_chars: Vec<char> = s.chars().collect();
                     i + s_chars.len() <= input_len &&
@@ -100,13 +135,17 @@ impl Tokenizer {
     }
 
     /// Tokenize the input string and return token ids with character spans (start,end)
-    pub fn tokenize_with_spans(&mut self, input: &str) -> Result<Vec<(TokenId, usize, usize)>, Error> {
+    pub fn tokenize_with_spans(&mut self, input: &str) -> Result<Vec<(TokenId, usize, usize)>, String> {
         let mut out: Vec<(TokenId, usize, usize)> = Vec::new();
         let mut i = 0;
         let chars: Vec<char> = input.chars().collect();
         let input_len = chars.len();
 
         while i < input_len {
+            if let Some(end) = self.comment_end(&chars, i) {
+                i = end?;
+                continue;
+            }
             // Try to match a special token at the current position
             let mut matched: Option<(String, usize)> = None; // (token, len)
             for special in &self.special_tokens {
@@ -138,6 +177,7 @@ impl Tokenizer {
             let mut current = String::new();
             while i < input_len
                 && !self.delimiters.contains(&chars[i])
+                && self.comment_end(&chars, i).is_none()
                 && !self.special_tokens.iter().any(|s| {
                     let s_chars: Vec<char> = s.chars().collect();
                     i + s_chars.len() <= input_len &&
@@ -192,4 +232,15 @@ pub(crate) mod tests {
         let pieces: Vec<_> = occ.iter().map(|(_, s, e)| &input[*s..*e]).collect();
         assert_eq!(pieces, vec!["int", "x", "=", "5", ";"]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn unterminated_block_comment_is_an_error() {
+        let comments = vec![("/*".to_string(), Some("*/".to_string())), ("//".to_string(), None)];
+        let mut tokenizer = Tokenizer::new(vec![], vec![' ', '\n']).with_comments(comments);
+        let err = tokenizer.tokenize("a b /* oops c d".to_string()).unwrap_err();
+        assert!(err.contains("unterminated comment"), "{}", err);
+        assert!(tokenizer.tokenize_with_spans("a b /* oops c d").is_err());
+        let ok = tokenizer.tokenize("a /* c */ b // d".to_string()).unwrap();
+        assert_eq!(ok.len(), 2);
+    }
+}
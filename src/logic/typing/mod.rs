@@ -8,6 +8,8 @@ pub mod rule;   // typing rules (inference rule structures & parsing)
 ///---------------
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json", serde(tag = "kind", content = "args", rename_all = "snake_case"))]
 pub enum Type {
     // Base types
     Atom(String),
@@ -9,6 +9,7 @@ pub type TypeAscription = (Term, Type);
 
 /// Typing context (setting) possibly extended with new bindings.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
 pub struct TypeSetting {
     pub name: String,                 // e.g. Γ
     pub extensions: Vec<TypeAscription>, // e.g. [x:τ]
@@ -16,6 +17,8 @@ pub struct TypeSetting {
 
 /// A typing judgment Γ ⊢ e : τ or membership x ∈ Γ
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json", serde(tag = "kind", content = "args", rename_all = "snake_case"))]
 pub enum TypingJudgment {
     Ascription(TypeAscription), // (term, type)
     Membership(String, String), // (variable, context) for x ∈ Γ
@@ -23,6 +26,7 @@ pub enum TypingJudgment {
 
 /// Premises in a typing rule (currently only typing judgments are supported for the "simple" phase).
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
 pub struct Premise {
     pub setting: Option<TypeSetting>,
     pub judgment: Option<TypingJudgment>,
@@ -30,6 +34,7 @@ pub struct Premise {
 
 /// Context specification for a conclusion (optional input/output context transforms)
 #[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
 pub struct ConclusionContext {
     pub input: String,                 // context variable name (previously Option<TypeSetting>)
     pub output: Option<TypeSetting>,   // possibly enriched context after transformation
@@ -41,6 +46,8 @@ impl ConclusionContext {
 
 /// The kind of conclusion: either a type or a context lookup Γ(x)
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json", serde(tag = "kind", content = "args", rename_all = "snake_case"))]
 pub enum ConclusionKind {
     Type(Type),
     ContextLookup(String, String), // (context, var) for Γ(x)
@@ -48,6 +55,7 @@ pub enum ConclusionKind {
 
 /// A conclusion consisting of an optional context transform and a concrete kind
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
 pub struct Conclusion {
     pub context: ConclusionContext,
     pub kind: ConclusionKind,
@@ -67,6 +75,7 @@ impl Conclusion {
 
 /// A typing rule (inference rule) with premises and a conclusion.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
 pub struct TypingRule {
     pub name: String,
     pub premises: Vec<Premise>,